@@ -0,0 +1,107 @@
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use felisp::{Interpreter, ast::Expr};
+
+const FIB: &str = "(def! fib (fn* (n) (if (<= n 1) n (+ (fib (- n 1)) (fib (- n 2))))))";
+const ACK: &str = "(def! ack (fn* (m n) (if (<= m 0) (+ n 1) (if (<= n 0) (ack (- m 1) 1) (ack (- m 1) (ack m (- n 1)))))))";
+const COUNT_TO: &str = "(def! count-to (fn* (v n) (if (<= n 0) v (count-to (conj v n) (- n 1)))))";
+
+/// interpreter with the given definitions evaluated
+fn interpreter(defs: &[&str]) -> Interpreter {
+    let interpreter = Interpreter::new();
+    for def in defs {
+        interpreter.eval_str(def).unwrap();
+    }
+    interpreter
+}
+
+/// code read, once checked to evaluate to what prints as expected, as cargo test
+/// does running each benchmark once, so that what is measured is also right
+fn checked(interpreter: &Interpreter, code: &str, expected: &str) -> Expr {
+    let expr = Expr::parse(code.to_string()).unwrap();
+    let value = interpreter.eval(&expr).unwrap();
+    assert_eq!(value.to_string(), expected, "evaluating {}", code);
+    expr
+}
+
+fn parsing(c: &mut Criterion) {
+    c.bench_function("parse ackermann", |b| {
+        b.iter(|| Expr::parse(black_box(ACK.to_string())).unwrap())
+    });
+    let nested = format!("{}1{}", "(+ 1 ".repeat(100), ")".repeat(100));
+    c.bench_function("parse nested", |b| {
+        b.iter(|| Expr::parse(black_box(nested.clone())).unwrap())
+    });
+}
+
+fn lookup(c: &mut Criterion) {
+    let interpreter = interpreter(&["(def! x 1)"]);
+    let globals = checked(&interpreter, "(+ x x x x x x x x x x)", "10");
+    c.bench_function("global lookup", |b| {
+        b.iter(|| interpreter.eval(black_box(&globals)).unwrap())
+    });
+    let locals = checked(
+        &interpreter,
+        "(let* (x 1 y 2) (+ x y x y x y x y x y))",
+        "15",
+    );
+    c.bench_function("local lookup", |b| {
+        b.iter(|| interpreter.eval(black_box(&locals)).unwrap())
+    });
+}
+
+fn arithmetic(c: &mut Criterion) {
+    let interpreter = interpreter(&[]);
+    let code = "(let* (x 3 y 4) (+ (* x x) (* y y) (- x y) (* 2 x y)))";
+    let small = checked(&interpreter, code, "48");
+    c.bench_function("small int arithmetic", |b| {
+        b.iter(|| interpreter.eval(black_box(&small)).unwrap())
+    });
+}
+
+fn closures(c: &mut Criterion) {
+    let interpreter = interpreter(&["(def! add (fn* (x y) (+ x y)))"]);
+    let call = checked(&interpreter, "(add (add 1 2) (add 3 4))", "10");
+    c.bench_function("closure call", |b| {
+        b.iter(|| interpreter.eval(black_box(&call)).unwrap())
+    });
+    let make = checked(&interpreter, "(fn* (x) (fn* (y) (+ x y)))", "<fun>");
+    c.bench_function("closure creation", |b| {
+        b.iter(|| interpreter.eval(black_box(&make)).unwrap())
+    });
+}
+
+fn recursion(c: &mut Criterion) {
+    let interpreter = interpreter(&[FIB, ACK]);
+    let fib = checked(&interpreter, "(fib 15)", "610");
+    c.bench_function("fib 15", |b| {
+        b.iter(|| interpreter.eval(black_box(&fib)).unwrap())
+    });
+    let ack = checked(&interpreter, "(ack 2 3)", "9");
+    c.bench_function("ackermann 2 3", |b| {
+        b.iter(|| interpreter.eval(black_box(&ack)).unwrap())
+    });
+}
+
+fn sequences(c: &mut Criterion) {
+    let interpreter = interpreter(&[COUNT_TO, "(def! big (count-to [] 1000))"]);
+    let counted: Vec<String> = (1..=100).rev().map(|n| n.to_string()).collect();
+    let build = checked(
+        &interpreter,
+        "(count-to [] 100)",
+        &format!("[{}]", counted.join(" ")),
+    );
+    c.bench_function("conj 100", |b| {
+        b.iter(|| interpreter.eval(black_box(&build)).unwrap())
+    });
+    let update = checked(&interpreter, "(count (assoc big 500 0))", "1000");
+    c.bench_function("assoc into 1000", |b| {
+        b.iter(|| interpreter.eval(black_box(&update)).unwrap())
+    });
+}
+
+criterion_group!(
+    benches, parsing, lookup, arithmetic, closures, recursion, sequences
+);
+criterion_main!(benches);
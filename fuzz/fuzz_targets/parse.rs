@@ -0,0 +1,22 @@
+#![no_main]
+
+// reads arbitrary source, which must either fail with an error that can be
+// reported or print back to source that reads the same
+
+use felisp::{ast::Expr, span::Source};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    match Expr::parse_all(&Source::new("<fuzz>", text.to_string())) {
+        Ok(exprs) => {
+            for expr in exprs {
+                let printed = expr.to_string();
+                let reread = Expr::parse(printed.clone()).expect("printed exprs read back");
+                assert_eq!(reread, expr, "reading {}", printed);
+            }
+        }
+        Err(err) => {
+            err.report();
+        }
+    }
+});
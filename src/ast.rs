@@ -1,71 +1,644 @@
-use std::{fmt, iter::Peekable, str::Chars};
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+use crate::{
+    error::FelispError,
+    numeric,
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::Rc,
+};
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub enum Expr {
-    Atom(String),
-    List(Vec<Expr>),
+    // literals, recognized by the reader
+    Nil(Span),
+    Bool(bool, Span),
+    Number(i64, Span),
+    // bigints, ratios and floats, as written, see numeric::parse
+    Numeric(Rc<str>, Span),
+    String(Rc<str>, Span),
+    // :name, without its colon
+    Keyword(Symbol, Span),
+    // def!, let*, ...
+    Special(Special, Span),
+    Atom(Symbol, Span),
+    List(Rc<[Expr]>, Span),
+    // [a b c]
+    Vector(Rc<[Expr]>, Span),
+    // {k1 v1 k2 v2}, flattened
+    Map(Rc<[Expr]>, Span),
+}
+
+/// forms with their own evaluation rules, which can't be rebound
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub enum Special {
+    Def,
+    Let,
+    Do,
+    If,
+    Fn,
+    Quote,
+    Deftest,
+    Is,
+    Ns,
+    Require,
+    CallEc,
+    Generator,
+    Yield,
+    Defprotocol,
+    ExtendType,
+    WithHandler,
+    Retry,
+    Defonce,
+    Defconst,
+    Case,
+    WithOutStr,
+    Binding,
+    WithOpen,
+    When,
+    Unless,
+    IfNot,
+    // -> and ->>
+    ThreadFirst,
+    ThreadLast,
+}
+
+impl Special {
+    pub const ALL: [Special; 28] = [
+        Special::Def,
+        Special::Let,
+        Special::Do,
+        Special::If,
+        Special::Fn,
+        Special::Quote,
+        Special::Deftest,
+        Special::Is,
+        Special::Ns,
+        Special::Require,
+        Special::CallEc,
+        Special::Generator,
+        Special::Yield,
+        Special::Defprotocol,
+        Special::ExtendType,
+        Special::WithHandler,
+        Special::Retry,
+        Special::Defonce,
+        Special::Defconst,
+        Special::Case,
+        Special::WithOutStr,
+        Special::Binding,
+        Special::WithOpen,
+        Special::When,
+        Special::Unless,
+        Special::IfNot,
+        Special::ThreadFirst,
+        Special::ThreadLast,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Special> {
+        let special = match name {
+            "def!" => Special::Def,
+            "let*" => Special::Let,
+            "do" => Special::Do,
+            "if" => Special::If,
+            "fn*" => Special::Fn,
+            "quote" => Special::Quote,
+            "deftest" => Special::Deftest,
+            "is" => Special::Is,
+            "ns" => Special::Ns,
+            "require" => Special::Require,
+            "call/ec" => Special::CallEc,
+            "generator" => Special::Generator,
+            "yield" => Special::Yield,
+            "defprotocol" => Special::Defprotocol,
+            "extend-type" => Special::ExtendType,
+            "with-handler" => Special::WithHandler,
+            "retry" => Special::Retry,
+            "defonce" => Special::Defonce,
+            "defconst" => Special::Defconst,
+            "case" => Special::Case,
+            "with-out-str" => Special::WithOutStr,
+            "binding" => Special::Binding,
+            "with-open" => Special::WithOpen,
+            "when" => Special::When,
+            "unless" => Special::Unless,
+            "if-not" => Special::IfNot,
+            "->" => Special::ThreadFirst,
+            "->>" => Special::ThreadLast,
+            _ => return None,
+        };
+        Some(special)
+    }
+
+    /// whether the form binds the global named by its first argument
+    pub fn defines(self) -> bool {
+        matches!(
+            self,
+            Special::Def | Special::Defonce | Special::Defconst | Special::Deftest
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Special::Def => "def!",
+            Special::Let => "let*",
+            Special::Do => "do",
+            Special::If => "if",
+            Special::Fn => "fn*",
+            Special::Quote => "quote",
+            Special::Deftest => "deftest",
+            Special::Is => "is",
+            Special::Ns => "ns",
+            Special::Require => "require",
+            Special::CallEc => "call/ec",
+            Special::Generator => "generator",
+            Special::Yield => "yield",
+            Special::Defprotocol => "defprotocol",
+            Special::ExtendType => "extend-type",
+            Special::WithHandler => "with-handler",
+            Special::Retry => "retry",
+            Special::Case => "case",
+            Special::WithOutStr => "with-out-str",
+            Special::Binding => "binding",
+            Special::WithOpen => "with-open",
+            Special::When => "when",
+            Special::Unless => "unless",
+            Special::IfNot => "if-not",
+            Special::ThreadFirst => "->",
+            Special::ThreadLast => "->>",
+            Special::Defonce => "defonce",
+            Special::Defconst => "defconst",
+        }
+    }
 }
 
 impl Expr {
-    pub fn parse(source: String) -> Result<Expr, String> {
-        let mut chars = source.chars().peekable();
-        let expr = parse_expression(&mut chars)?;
-        if !chars.peek().is_none() {
-            return Err("Unexpected EOF".to_string());
+    pub fn parse(source: String) -> Result<Expr, FelispError> {
+        Self::parse_source(&Source::new("<repl>", source))
+    }
+
+    pub fn parse_source(source: &Rc<Source>) -> Result<Expr, FelispError> {
+        let mut reader = Reader {
+            source,
+            chars: source.text.char_indices().peekable(),
+            depth: 0,
+        };
+        let expr = parse_expression(&mut reader)?;
+        if reader.chars.peek().is_some() {
+            return Err(reader.error("unexpected trailing input"));
         }
         Ok(expr)
     }
+
+    /// reads every form of source, as found in a script
+    pub fn parse_all(source: &Rc<Source>) -> Result<Vec<Expr>, FelispError> {
+        let mut reader = Reader {
+            source,
+            chars: source.text.char_indices().peekable(),
+            depth: 0,
+        };
+        let mut exprs = vec![];
+        skip_whitespace(&mut reader);
+        while reader.peek().is_some() {
+            exprs.push(parse_expression(&mut reader)?);
+        }
+        Ok(exprs)
+    }
+
+    /// the symbol an atom stands for, or the printed form of anything else
+    pub fn name(&self) -> Symbol {
+        match self {
+            Expr::Atom(symbol, _) => *symbol,
+            _ => Symbol::intern(&self.to_string()),
+        }
+    }
+
+    /// the tag and form of a tagged literal like #date "2024-01-01",
+    /// which reads as (#date "2024-01-01")
+    pub fn tagged(&self) -> Option<(Symbol, &Expr)> {
+        match self {
+            Expr::List(exprs, _) => match &exprs[..] {
+                [Expr::Atom(tag, _), form] => {
+                    let tag = tag.name().strip_prefix('#').filter(|tag| !tag.is_empty())?;
+                    Some((Symbol::intern(tag), form))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// whether this is the . of a dotted list
+    pub fn is_dot(&self) -> bool {
+        matches!(self, Expr::Atom(name, _) if name.name() == ".")
+    }
+
+    pub fn span(&self) -> &Span {
+        match self {
+            Expr::Nil(span)
+            | Expr::Bool(_, span)
+            | Expr::Number(_, span)
+            | Expr::Numeric(_, span)
+            | Expr::String(_, span)
+            | Expr::Keyword(_, span)
+            | Expr::Special(_, span)
+            | Expr::Atom(_, span)
+            | Expr::List(_, span)
+            | Expr::Vector(_, span)
+            | Expr::Map(_, span) => span,
+        }
+    }
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expr::Atom(s) => write!(f, "{}", s),
-            Expr::List(exprs) => {
-                write!(f, "(")?;
-                let n = exprs.len();
-                if n > 0 {
-                    for expr in &exprs[..n - 1] {
-                        write!(f, "{} ", expr)?;
-                    }
-                    write!(f, "{})", exprs[n - 1])
-                } else {
-                    write!(f, ")")
-                }
-            }
+            Expr::Nil(_) => write!(f, "nil"),
+            Expr::Bool(b, _) => write!(f, "{}", b),
+            Expr::Number(n, _) => write!(f, "{}", n),
+            Expr::Numeric(n, _) => write!(f, "{}", n),
+            Expr::String(s, _) => write_str(f, s),
+            Expr::Keyword(name, _) => write!(f, ":{}", name),
+            Expr::Special(special, _) => write!(f, "{}", special.name()),
+            Expr::Atom(s, _) => write!(f, "{}", s),
+            Expr::List(exprs, _) => match self.tagged() {
+                Some((tag, form)) => write!(f, "#{} {}", tag, form),
+                None => write_seq(f, "(", exprs.iter(), ")"),
+            },
+            Expr::Vector(exprs, _) => write_seq(f, "[", exprs.iter(), "]"),
+            Expr::Map(exprs, _) => write_seq(f, "{", exprs.iter(), "}"),
+        }
+    }
+}
+
+/// writes items separated by spaces between open and close
+pub fn write_seq<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    open: &str,
+    items: impl IntoIterator<Item = T>,
+    close: &str,
+) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    write!(f, "{}", close)
+}
+
+/// writes s between double quotes, escaping them as the reader expects
+pub fn write_str(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub fn is_delimiter(c: char) -> bool {
+    matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | '"' | ';')
+}
+
+/// the symbol the function reading tagged literals #tag form is bound to
+/// (reader_macro("date")) -> #date
+pub fn reader_macro(tag: &str) -> Symbol {
+    Symbol::intern(&format!("#{}", tag))
+}
+
+/// how many #s come between the r and the quote of the raw string text starts with,
+/// none if it doesn't start with one
+/// (raw_string_hashes("r#\"a\"#")) -> Some(1)
+pub fn raw_string_hashes(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('r')?;
+    let hashes = rest.len() - rest.trim_start_matches('#').len();
+    rest[hashes..].starts_with('"').then_some(hashes)
+}
+
+/// the byte length of the raw string text starts with, None if it isn't terminated
+pub fn raw_string_len(text: &str) -> Option<usize> {
+    let hashes = raw_string_hashes(text)?;
+    let closing = format!("\"{}", "#".repeat(hashes));
+    let body = 2 + hashes;
+    let len = text[body..].find(&closing)?;
+    Some(body + len + closing.len())
+}
+
+/// the ; comments of text found between two forms, which the reader skips,
+/// without their line breaks, None if text has more than whitespace and comments
+/// (comments(" ; a\n  ;; b\n")) -> Some(["; a", ";; b"])
+pub fn comments(text: &str) -> Option<Vec<&str>> {
+    let mut comments = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if !rest.starts_with(';') {
+            return None;
+        }
+        let (comment, after) = rest.split_once('\n').unwrap_or((rest, ""));
+        comments.push(comment.trim_end());
+        rest = after.trim_start();
+    }
+    Some(comments)
+}
+
+/// how deep lists, vectors and maps can nest in source code, so that reading,
+/// analyzing or printing it can't overflow the stack
+pub const MAX_NESTING: usize = 512;
+
+struct Reader<'a> {
+    source: &'a Rc<Source>,
+    chars: Peekable<CharIndices<'a>>,
+    // how many sequences the reader is in
+    depth: usize,
+}
+
+impl Reader<'_> {
+    /// byte offset of the next character
+    fn pos(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.text.len(), |&(i, _)| i)
+    }
+
+    fn next_if(&mut self, pred: impl Fn(char) -> bool) -> Option<char> {
+        self.chars.next_if(|&(_, c)| pred(c)).map(|(_, c)| c)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn error(&mut self, msg: &str) -> FelispError {
+        let pos = self.pos();
+        let end = self
+            .chars
+            .peek()
+            .map_or(pos + 1, |&(i, c)| i + c.len_utf8());
+        FelispError::ParseError(msg.to_string()).at(Span::new(self.source, pos, end))
+    }
+}
+
+/// skips whitespace and ; comments, up to the end of their line
+fn skip_whitespace(reader: &mut Reader) {
+    loop {
+        if reader.next_if(|c| c.is_whitespace()).is_some() {
+            continue;
+        }
+        if reader.next_if(|c| c == ';').is_some() {
+            while reader.next_if(|c| c != '\n').is_some() {}
+            continue;
         }
+        break;
     }
 }
 
-fn parse_expression(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
-    while let Some(_) = chars.next_if(|&c| c.is_whitespace()) {}
-    let out = match chars.peek() {
-        Some('(') => parse_list(chars),
-        _ => parse_atom(chars),
+fn parse_expression(reader: &mut Reader) -> Result<Expr, FelispError> {
+    skip_whitespace(reader);
+    let out = match reader.peek() {
+        Some('(') => parse_list(reader),
+        Some('[') => parse_seq(reader, '[', ']').map(|(exprs, span)| Expr::Vector(exprs, span)),
+        Some('{') => parse_map(reader),
+        Some('"') => parse_string(reader),
+        Some('r') if raw_string_hashes(&reader.source.text[reader.pos()..]).is_some() => {
+            parse_raw_string(reader)
+        }
+        Some('\'') => parse_quote(reader),
+        Some('#') => parse_tagged(reader),
+        _ => parse_atom(reader),
     };
-    while let Some(_) = chars.next_if(|&c| c.is_whitespace()) {}
+    skip_whitespace(reader);
     out
 }
 
-fn parse_atom(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
+fn parse_atom(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let start = reader.pos();
     let mut result = String::new();
-    while let Some(c) = chars.next_if(|&c| c != ')' && c.is_ascii_graphic()) {
+    while let Some(c) = reader.next_if(|c| !is_delimiter(c) && c.is_ascii_graphic()) {
         result.push(c);
     }
     if result.is_empty() {
-        return Err("empty atom".to_string());
+        return Err(reader.error("empty atom"));
+    }
+    let span = Span::new(reader.source, start, reader.pos());
+    let expr = match result.as_str() {
+        "nil" => Expr::Nil(span),
+        "true" => Expr::Bool(true, span),
+        "false" => Expr::Bool(false, span),
+        ":" => return Err(FelispError::ParseError("empty keyword".to_string()).at(span)),
+        _ => {
+            if let Ok(n) = result.parse::<i64>() {
+                Expr::Number(n, span)
+            } else if numeric::is_literal(&result) {
+                Expr::Numeric(result.into(), span)
+            } else if let Some(name) = result.strip_prefix(':') {
+                Expr::Keyword(Symbol::intern(name), span)
+            } else if let Some(special) = Special::from_name(&result) {
+                Expr::Special(special, span)
+            } else {
+                Expr::Atom(Symbol::intern(&result), span)
+            }
+        }
+    };
+    Ok(expr)
+}
+
+/// 'form -> (quote form)
+fn parse_quote(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let start = reader.pos();
+    if reader.depth == MAX_NESTING {
+        return Err(reader.error(&format!("nested deeper than {}", MAX_NESTING)));
+    }
+    reader.next_if(|c| c == '\'');
+    let quote = Expr::Special(Special::Quote, Span::new(reader.source, start, start + 1));
+    reader.depth += 1;
+    let expr = parse_expression(reader)?;
+    reader.depth -= 1;
+    let span = Span::new(reader.source, start, expr.span().end);
+    Ok(Expr::List(Rc::new([quote, expr]), span))
+}
+
+/// #tag form -> (#tag form), which calls the reader macro of tag on the form
+fn parse_tagged(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let start = reader.pos();
+    if reader.depth == MAX_NESTING {
+        return Err(reader.error(&format!("nested deeper than {}", MAX_NESTING)));
+    }
+    reader.next_if(|c| c == '#');
+    if reader.peek() == Some('"') {
+        return parse_interpolated(reader, start);
+    }
+    let mut tag = String::from("#");
+    while let Some(c) = reader.next_if(|c| !is_delimiter(c) && c.is_ascii_graphic()) {
+        tag.push(c);
+    }
+    if tag == "#" {
+        return Err(reader.error("expected a tag after '#'"));
+    }
+    let tag = Expr::Atom(
+        Symbol::intern(&tag),
+        Span::new(reader.source, start, reader.pos()),
+    );
+    skip_whitespace(reader);
+    if reader.peek().is_none_or(|c| matches!(c, ')' | ']' | '}')) {
+        return Err(reader.error(&format!("expected a form after '{}'", tag)));
     }
-    Ok(Expr::Atom(result))
+    reader.depth += 1;
+    let form = parse_expression(reader)?;
+    reader.depth -= 1;
+    let span = Span::new(reader.source, start, form.span().end);
+    Ok(Expr::List(Rc::new([tag, form]), span))
 }
 
-fn parse_list(chars: &mut Peekable<Chars>) -> Result<Expr, String> {
-    chars.next_if_eq(&'(').ok_or("parse_list expected '('")?;
+/// "a \"quoted\" string\n", with \" \\ and \n escapes
+fn parse_string(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let start = reader.pos();
+    reader.next_if(|c| c == '"');
+    let mut result = String::new();
+    loop {
+        match reader.chars.next() {
+            Some((_, '"')) => break,
+            Some((_, '\\')) => result.push(parse_escape(reader, "")?),
+            Some((_, c)) => result.push(c),
+            None => return Err(reader.error("unterminated string")),
+        }
+    }
+    let span = Span::new(reader.source, start, reader.pos());
+    Ok(Expr::String(result.into(), span))
+}
+
+/// r"C:\dir\file" or r#"say "hi""#, as written between the quotes, without escapes,
+/// ending at a quote followed by as many #s as there are after the r
+fn parse_raw_string(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let start = reader.pos();
+    let text = &reader.source.text[start..];
+    let Some(len) = raw_string_len(text) else {
+        while reader.chars.next().is_some() {}
+        return Err(reader.error("unterminated string"));
+    };
+    let hashes = raw_string_hashes(text).expect("raw strings start with one");
+    let result = &text[2 + hashes..len - 1 - hashes];
+    while reader.chars.next_if(|&(i, _)| i < start + len).is_some() {}
+    let span = Span::new(reader.source, start, start + len);
+    Ok(Expr::String(result.into(), span))
+}
+
+/// #"x is {x}, y+1 is {(+ y 1)}" -> (str "x is " x ", y+1 is " (+ y 1)), the form
+/// between each pair of braces printed in its place, with \{ for a brace
+fn parse_interpolated(reader: &mut Reader, start: usize) -> Result<Expr, FelispError> {
+    reader.next_if(|c| c == '"');
+    let str = Expr::Atom(
+        Symbol::intern("str"),
+        Span::new(reader.source, start, reader.pos()),
+    );
+    let mut parts = vec![str];
+    let mut text = String::new();
+    let mut text_start = reader.pos();
+    loop {
+        let pos = reader.pos();
+        match reader.chars.next() {
+            Some((_, '"')) => {
+                if !text.is_empty() {
+                    let span = Span::new(reader.source, text_start, pos);
+                    parts.push(Expr::String(text.into(), span));
+                }
+                break;
+            }
+            Some((_, '\\')) => text.push(parse_escape(reader, "{")?),
+            Some((_, '{')) => {
+                if !text.is_empty() {
+                    let span = Span::new(reader.source, text_start, pos);
+                    parts.push(Expr::String(std::mem::take(&mut text).into(), span));
+                }
+                skip_whitespace(reader);
+                if reader.peek().is_none_or(|c| c == '}') {
+                    return Err(reader.error("expected a form between '{' and '}'"));
+                }
+                if reader.depth == MAX_NESTING {
+                    return Err(reader.error(&format!("nested deeper than {}", MAX_NESTING)));
+                }
+                reader.depth += 1;
+                parts.push(parse_expression(reader)?);
+                reader.depth -= 1;
+                reader
+                    .next_if(|c| c == '}')
+                    .ok_or_else(|| reader.error("expected '}' after the form"))?;
+                text_start = reader.pos();
+            }
+            Some((_, c)) => text.push(c),
+            None => return Err(reader.error("unterminated string")),
+        }
+    }
+    let span = Span::new(reader.source, start, reader.pos());
+    Ok(Expr::List(parts.into(), span))
+}
+
+/// the character written by the escape after a \, one of \" \\ \n or the extra ones
+fn parse_escape(reader: &mut Reader, extra: &str) -> Result<char, FelispError> {
+    match reader.chars.next() {
+        Some((_, '"')) => Ok('"'),
+        Some((_, '\\')) => Ok('\\'),
+        Some((_, 'n')) => Ok('\n'),
+        Some((_, c)) if extra.contains(c) => Ok(c),
+        Some((i, c)) => Err(FelispError::ParseError(format!("unknown escape '\\{}'", c))
+            .at(Span::new(reader.source, i - 1, i + c.len_utf8()))),
+        None => Err(reader.error("unterminated string")),
+    }
+}
+
+/// reads the forms between open and close
+fn parse_seq(
+    reader: &mut Reader,
+    open: char,
+    close: char,
+) -> Result<(Rc<[Expr]>, Span), FelispError> {
+    let start = reader.pos();
+    if reader.depth == MAX_NESTING {
+        return Err(reader.error(&format!("nested deeper than {}", MAX_NESTING)));
+    }
+    reader
+        .next_if(|c| c == open)
+        .ok_or_else(|| reader.error(&format!("expected '{}'", open)))?;
+    reader.depth += 1;
     let mut result = vec![];
-    while chars.peek().is_some_and(|&c| c != ')') {
-        let expr = parse_expression(chars)?;
+    while reader.peek().is_some_and(|c| c != close) {
+        let expr = parse_expression(reader)?;
         result.push(expr);
     }
-    chars.next_if_eq(&')').ok_or("parse_list expected ')'")?;
+    reader
+        .next_if(|c| c == close)
+        .ok_or_else(|| reader.error(&format!("expected '{}'", close)))?;
+    reader.depth -= 1;
+
+    let span = Span::new(reader.source, start, reader.pos());
+    Ok((result.into(), span))
+}
 
-    Ok(Expr::List(result))
+/// (a b), or (a b . c) with a dot before the last form, which quoted makes an improper list
+fn parse_list(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let (exprs, span) = parse_seq(reader, '(', ')')?;
+    if let Some(i) = exprs.iter().position(Expr::is_dot)
+        && (i == 0 || i + 2 != exprs.len())
+    {
+        return Err(FelispError::ParseError(
+            "expected one form after '.', and one before it".to_string(),
+        )
+        .at(exprs[i].span().clone()));
+    }
+    Ok(Expr::List(exprs, span))
+}
+
+fn parse_map(reader: &mut Reader) -> Result<Expr, FelispError> {
+    let (exprs, span) = parse_seq(reader, '{', '}')?;
+    if !exprs.len().is_multiple_of(2) {
+        return Err(
+            FelispError::ParseError("map literal requires key-value pairs".to_string()).at(span),
+        );
+    }
+    Ok(Expr::Map(exprs, span))
 }
@@ -0,0 +1,224 @@
+// what let* and fn* bind: each symbol of their patterns names a slot of the scope
+// they make, a vector pattern destructuring a vector into the slots of its items
+// (let* ([a b & more] [1 2 3 4]) more) -> [3 4]
+// ((fn* (x & rest) rest) 1 2 3) -> [2 3]
+
+use crate::{ast::Expr, env::Value, error::FelispError, printer, symbol::Symbol, sync::Rc};
+
+/// a symbol or vector in binding position
+#[derive(Clone)]
+pub enum Pattern {
+    // binds the value itself
+    Name(Symbol),
+    // binds the items of a vector in turn, nil to those it lacks,
+    // and a vector of the ones left to the rest, if any
+    Vector(Rc<[Pattern]>, Option<Rc<Pattern>>),
+}
+
+/// the patterns of a let* or fn*, and the names of the slots they bind, in order
+pub struct Bindings {
+    // one for each value of a let*, or each parameter of a fn*
+    pub patterns: Rc<[Pattern]>,
+    // (fn* (a & rest) ...) binds the arguments after the patterns as a vector
+    pub rest: Option<Pattern>,
+    pub names: Rc<[Symbol]>,
+}
+
+impl Pattern {
+    /// how many slots it binds
+    pub fn len(&self) -> usize {
+        match self {
+            Pattern::Name(_) => 1,
+            Pattern::Vector(items, rest) => {
+                items.iter().map(Pattern::len).sum::<usize>() + rest.as_ref().map_or(0, |r| r.len())
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn names(&self, out: &mut Vec<Symbol>) {
+        match self {
+            Pattern::Name(name) => out.push(*name),
+            Pattern::Vector(items, rest) => {
+                for item in items.iter() {
+                    item.names(out);
+                }
+                if let Some(rest) = rest {
+                    rest.names(out);
+                }
+            }
+        }
+    }
+
+    /// the values of the slots it binds for value, in order, pushed on slots
+    /// [a b] binds [1] to 1 and nil, and nil to nil and nil
+    pub fn bind(&self, value: Value, slots: &mut Vec<Value>) -> Result<(), FelispError> {
+        let items = match (self, &value) {
+            (Pattern::Name(_), _) => {
+                slots.push(value);
+                return Ok(());
+            }
+            (Pattern::Vector(..), Value::Vector(items)) => items.clone(),
+            (Pattern::Vector(..), Value::Nil) => Default::default(),
+            (Pattern::Vector(..), value) => {
+                return Err(FelispError::TypeError {
+                    expected: "Vector to destructure".to_string(),
+                    got: printer::abbreviate(value),
+                    arg: None,
+                    function: None,
+                });
+            }
+        };
+        let Pattern::Vector(patterns, rest) = self else {
+            unreachable!("names returned above")
+        };
+        for (i, pattern) in patterns.iter().enumerate() {
+            pattern.bind(items.get(i).cloned().unwrap_or(Value::Nil), slots)?;
+        }
+        if let Some(rest) = rest {
+            let left = items.iter().skip(patterns.len()).cloned().collect();
+            rest.bind(Value::Vector(left), slots)?;
+        }
+        Ok(())
+    }
+}
+
+impl Bindings {
+    fn new(patterns: Vec<Pattern>, rest: Option<Pattern>) -> Self {
+        let mut names = vec![];
+        for pattern in patterns.iter().chain(&rest) {
+            pattern.names(&mut names);
+        }
+        Self {
+            patterns: patterns.into(),
+            rest,
+            names: names.into(),
+        }
+    }
+
+    /// the parameters of (fn* (a [b c] & rest) ...)
+    pub fn params(params: &Expr) -> Result<Self, FelispError> {
+        let Expr::List(params, _) = params else {
+            return Err(FelispError::SyntaxError(format!(
+                "fn* expected bindings got '{}'",
+                params
+            ))
+            .at(params.span().clone()));
+        };
+        let (patterns, rest) = sequence(params, "fn*")?;
+        Ok(Self::new(patterns, rest))
+    }
+
+    /// the patterns of (let* (a 1 [b c] v) ...), and the forms of their values
+    pub fn pairs(pairs: &Expr) -> Result<(Self, Vec<&Expr>), FelispError> {
+        let pairs_list = match pairs {
+            Expr::List(pairs, _) if pairs.len().is_multiple_of(2) => pairs,
+            _ => {
+                return Err(FelispError::SyntaxError(format!(
+                    "let* expected key-value pairs got '{}'",
+                    pairs
+                ))
+                .at(pairs.span().clone()));
+            }
+        };
+        let patterns = pairs_list
+            .chunks(2)
+            .map(|pair| pattern(&pair[0], "let*"))
+            .collect::<Result<Vec<Pattern>, FelispError>>()?;
+        let values = pairs_list.chunks(2).map(|pair| &pair[1]).collect();
+        Ok((Self::new(patterns, None), values))
+    }
+
+    /// whether each argument binds a slot of its own, as is, which calls needn't destructure
+    pub fn is_flat(&self) -> bool {
+        self.rest.is_none() && self.names.len() == self.patterns.len()
+    }
+
+    /// the slots of the scope of a call with args, checking there are as many as it takes
+    pub fn bind_args(&self, args: Vec<Value>) -> Result<Vec<Value>, FelispError> {
+        let required = self.patterns.len();
+        if self.rest.is_none() && args.len() != required {
+            return Err(FelispError::ArityError(format!(
+                "fn required {} args but given {}",
+                required,
+                args.len()
+            )));
+        }
+        if args.len() < required {
+            return Err(FelispError::ArityError(format!(
+                "fn required at least {} args but given {}",
+                required,
+                args.len()
+            )));
+        }
+        if self.is_flat() {
+            return Ok(args);
+        }
+        let mut slots = Vec::with_capacity(self.names.len());
+        let mut args = args.into_iter();
+        for pattern in self.patterns.iter() {
+            pattern.bind(args.next().unwrap_or(Value::Nil), &mut slots)?;
+        }
+        if let Some(rest) = &self.rest {
+            rest.bind(Value::Vector(args.collect()), &mut slots)?;
+        }
+        Ok(slots)
+    }
+}
+
+/// the symbols a pattern binds, for tools working on forms rather than values
+/// [a [b] & c] -> a b c
+pub fn pattern_names(pattern: &Expr) -> Vec<&Expr> {
+    match pattern {
+        Expr::Atom(name, _) if name.name() == "&" => vec![],
+        Expr::Atom(..) => vec![pattern],
+        Expr::Vector(items, _) => items.iter().flat_map(pattern_names).collect(),
+        _ => vec![],
+    }
+}
+
+/// the patterns of items, up to a & followed by that of the rest
+fn sequence(items: &[Expr], form: &str) -> Result<(Vec<Pattern>, Option<Pattern>), FelispError> {
+    let (items, rest) = match items.iter().position(is_ampersand) {
+        Some(i) => match &items[i + 1..] {
+            [rest] => (&items[..i], Some(pattern(rest, form)?)),
+            _ => {
+                return Err(FelispError::SyntaxError(format!(
+                    "{} expected one pattern after &",
+                    form
+                ))
+                .at(items[i].span().clone()));
+            }
+        },
+        None => (items, None),
+    };
+    let patterns = items
+        .iter()
+        .map(|item| pattern(item, form))
+        .collect::<Result<Vec<Pattern>, FelispError>>()?;
+    Ok((patterns, rest))
+}
+
+fn pattern(expr: &Expr, form: &str) -> Result<Pattern, FelispError> {
+    match expr {
+        Expr::Atom(name, _) if !is_ampersand(expr) && name.qualified().is_none() => {
+            Ok(Pattern::Name(*name))
+        }
+        Expr::Vector(items, _) => {
+            let (items, rest) = sequence(items, form)?;
+            Ok(Pattern::Vector(items.into(), rest.map(Rc::new)))
+        }
+        _ => Err(FelispError::SyntaxError(format!(
+            "{} expected a symbol or vector to bind got '{}'",
+            form, expr
+        ))
+        .at(expr.span().clone())),
+    }
+}
+
+fn is_ampersand(expr: &Expr) -> bool {
+    matches!(expr, Expr::Atom(name, _) if name.name() == "&")
+}
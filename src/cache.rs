@@ -0,0 +1,256 @@
+// parsed forms of files, kept on disk so that loading big files again needn't parse them:
+// an entry is named after a hash of the path of the file, and is used while the file
+// keeps the modification time and length it had when cached
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    ast::{Expr, MAX_NESTING, Special},
+    error::FelispError,
+    numeric,
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::Rc,
+};
+
+// starts every entry, ending with the version of the encoding
+const MAGIC: &[u8; 8] = b"felisp\x00\x02";
+
+/// the forms of the file at path, from its entry in the cache directory if still valid,
+/// parsed then cached otherwise
+/// failing to use the cache is not an error, the file is parsed instead
+pub fn read_forms(path: &Path, dir: Option<&Path>) -> Result<Vec<Expr>, FelispError> {
+    // taken before reading, so that a file changed meanwhile is parsed again next time
+    let modified = modified(path);
+    let source = Source::read(path)?;
+    let (Some(dir), Some(modified)) = (dir, modified) else {
+        return Expr::parse_all(&source);
+    };
+    let entry = dir.join(entry_name(path));
+    let stamp = (modified, source.text.len() as u64);
+    if let Ok(bytes) = fs::read(&entry)
+        && let Some(exprs) = decode(&bytes, stamp, &source)
+    {
+        return Ok(exprs);
+    }
+    let exprs = Expr::parse_all(&source)?;
+    let _ = write_entry(dir, &entry, &encode(&exprs, stamp));
+    Ok(exprs)
+}
+
+/// nanoseconds from the epoch to the last modification of path
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let since = modified.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since.as_nanos()).ok()
+}
+
+fn entry_name(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    format!("{:016x}.forms", hasher.finish())
+}
+
+/// writes the entry through a temporary file, so that readers never see half of it
+fn write_entry(dir: &Path, entry: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let temporary = entry.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, entry)
+}
+
+fn encode(exprs: &[Expr], (modified, len): (u64, u64)) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend(modified.to_le_bytes());
+    out.extend(len.to_le_bytes());
+    write_varint(&mut out, exprs.len() as u64);
+    let mut symbols = HashMap::new();
+    for expr in exprs {
+        encode_expr(expr, &mut symbols, &mut out);
+    }
+    out
+}
+
+/// a tag byte, the span, then what the tag calls for
+/// symbols are numbered in the order they first appear, their name following
+/// only their first appearance, so that decoding interns each name once
+fn encode_expr(expr: &Expr, symbols: &mut HashMap<Symbol, usize>, out: &mut Vec<u8>) {
+    let tag = match expr {
+        Expr::Nil(_) => 0,
+        Expr::Bool(false, _) => 1,
+        Expr::Bool(true, _) => 2,
+        Expr::Number(..) => 3,
+        Expr::String(..) => 4,
+        Expr::Special(..) => 5,
+        Expr::Atom(..) => 6,
+        Expr::List(..) => 7,
+        Expr::Vector(..) => 8,
+        Expr::Map(..) => 9,
+        Expr::Keyword(..) => 10,
+        Expr::Numeric(..) => 11,
+    };
+    out.push(tag);
+    let span = expr.span();
+    write_varint(out, span.start as u64);
+    write_varint(out, span.end as u64);
+    match expr {
+        Expr::Nil(_) | Expr::Bool(..) => {}
+        Expr::Number(n, _) => out.extend(n.to_le_bytes()),
+        Expr::String(s, _) | Expr::Numeric(s, _) => write_str(out, s),
+        Expr::Special(special, _) => {
+            out.push(Special::ALL.iter().position(|s| s == special).unwrap_or(0) as u8)
+        }
+        Expr::Atom(symbol, _) | Expr::Keyword(symbol, _) => {
+            let next = symbols.len();
+            let index = *symbols.entry(*symbol).or_insert(next);
+            write_varint(out, index as u64);
+            if index == next {
+                write_str(out, symbol.name());
+            }
+        }
+        Expr::List(exprs, _) | Expr::Vector(exprs, _) | Expr::Map(exprs, _) => {
+            write_varint(out, exprs.len() as u64);
+            for expr in exprs.iter() {
+                encode_expr(expr, symbols, out);
+            }
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_varint(out, s.len() as u64);
+    out.extend(s.as_bytes());
+}
+
+/// the forms of an entry, if it was made for source as it is now and is well formed
+fn decode(bytes: &[u8], (modified, len): (u64, u64), source: &Rc<Source>) -> Option<Vec<Expr>> {
+    let mut decoder = Decoder {
+        bytes,
+        pos: 0,
+        source,
+        symbols: vec![],
+    };
+    if decoder.take(MAGIC.len())? != MAGIC || decoder.u64()? != modified || decoder.u64()? != len {
+        return None;
+    }
+    let count = decoder.varint()?;
+    let exprs = (0..count)
+        .map(|_| decoder.expr(0))
+        .collect::<Option<Vec<Expr>>>()?;
+    (decoder.pos == bytes.len()).then_some(exprs)
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    source: &'a Rc<Source>,
+    // symbols met so far, by number
+    symbols: Vec<Symbol>,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= u64::from(byte & 0x7f).checked_shl(shift)?;
+            if byte < 0x80 {
+                return Some(n);
+            }
+        }
+        None
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.varint()?).ok()
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let len = self.usize()?;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+
+    fn symbol(&mut self) -> Option<Symbol> {
+        let index = self.usize()?;
+        if index == self.symbols.len() {
+            let symbol = Symbol::intern(self.str()?);
+            self.symbols.push(symbol);
+        }
+        self.symbols.get(index).copied()
+    }
+
+    /// a span of the source, which it must fit in
+    fn span(&mut self) -> Option<Span> {
+        let (start, end) = (self.usize()?, self.usize()?);
+        let text = &self.source.text;
+        (start <= end && text.is_char_boundary(start) && text.is_char_boundary(end))
+            .then(|| Span::new(self.source, start, end))
+    }
+
+    fn expr(&mut self, depth: usize) -> Option<Expr> {
+        let tag = self.byte()?;
+        let span = self.span()?;
+        let expr = match tag {
+            0 => Expr::Nil(span),
+            1 => Expr::Bool(false, span),
+            2 => Expr::Bool(true, span),
+            3 => Expr::Number(i64::from_le_bytes(self.take(8)?.try_into().ok()?), span),
+            4 => Expr::String(self.str()?.into(), span),
+            5 => Expr::Special(*Special::ALL.get(usize::from(self.byte()?))?, span),
+            6 => Expr::Atom(self.symbol()?, span),
+            10 => Expr::Keyword(self.symbol()?, span),
+            11 => Expr::Numeric(
+                Some(self.str()?).filter(|n| numeric::is_literal(n))?.into(),
+                span,
+            ),
+            7..=9 if depth < MAX_NESTING => {
+                // each item takes 3 bytes at least, more can't be there
+                let count = self.usize()?;
+                if count > (self.bytes.len() - self.pos) / 3
+                    || (tag == 9 && !count.is_multiple_of(2))
+                {
+                    return None;
+                }
+                let exprs = (0..count)
+                    .map(|_| self.expr(depth + 1))
+                    .collect::<Option<Rc<[Expr]>>>()?;
+                match tag {
+                    7 => Expr::List(exprs, span),
+                    8 => Expr::Vector(exprs, span),
+                    _ => Expr::Map(exprs, span),
+                }
+            }
+            _ => return None,
+        };
+        Some(expr)
+    }
+}
@@ -0,0 +1,408 @@
+use im::HashMap;
+
+use crate::{
+    ast::Expr,
+    deterministic,
+    env::Value,
+    error::FelispError,
+    numeric,
+    span::{Source, Span},
+    sync::Rc,
+};
+
+// vectors and maps are persistent: updating one shares structure with the
+// original rather than copying it, so both stay cheap to keep around
+
+fn arity(name: &str, args: &[Value], min: usize, max: Option<usize>) -> Result<(), FelispError> {
+    if args.len() < min || max.is_some_and(|max| args.len() > max) {
+        let expected = match max {
+            Some(max) if max == min => format!("{}", min),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+        return Err(FelispError::ArityError(format!(
+            "{} requires {} arguments but given {}",
+            name,
+            expected,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+fn index(args: &[Value], i: usize) -> Result<usize, FelispError> {
+    match &args[i] {
+        Value::Number(n) if *n >= 0 => Ok(*n as usize),
+        arg => Err(FelispError::bad_arg(i, "index", arg)),
+    }
+}
+
+/// value at args[i], checked to be usable as a map key
+fn key(args: &[Value], i: usize) -> Result<Value, FelispError> {
+    match &args[i] {
+        key if key.is_hashable() => Ok(key.clone()),
+        key => Err(FelispError::bad_arg(i, "hashable key", key)),
+    }
+}
+
+/// items of the sequence at args[i]: vectors, proper lists, nil,
+/// and maps as [k v] entries
+fn items(args: &[Value], i: usize) -> Result<Vec<Value>, FelispError> {
+    match &args[i] {
+        Value::Vector(items) => Ok(items.iter().cloned().collect()),
+        Value::Map(entries) => Ok(deterministic::entries(entries)
+            .into_iter()
+            .map(|(k, v)| Value::Vector([k.clone(), v.clone()].into_iter().collect()))
+            .collect()),
+        coll => coll
+            .list_items()
+            .ok_or_else(|| FelispError::bad_arg(i, "collection", coll)),
+    }
+}
+
+/// (vector 1 2 3) -> [1 2 3]
+pub fn vector(args: &[Value]) -> Result<Value, FelispError> {
+    Ok(Value::Vector(args.iter().cloned().collect()))
+}
+
+/// (hash-map k1 v1 k2 v2) -> {k1 v1 k2 v2}
+pub fn hash_map(args: &[Value]) -> Result<Value, FelispError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(FelispError::ArityError(
+            "hash-map requires key-value pairs".to_string(),
+        ));
+    }
+    (0..args.len())
+        .step_by(2)
+        .map(|i| Ok((key(args, i)?, args[i + 1].clone())))
+        .collect::<Result<_, _>>()
+        .map(Value::Map)
+}
+
+/// value of coll at key (or index), default (nil if absent) when missing
+/// (get {1 2} 1) -> 2
+/// (get [1 2] 5 0) -> 0
+pub fn get(args: &[Value]) -> Result<Value, FelispError> {
+    arity("get", args, 2, Some(3))?;
+    let default = args.get(2).cloned().unwrap_or(Value::Nil);
+    let found = match (&args[0], &args[1]) {
+        (Value::Map(entries), key) => entries.get(key).cloned(),
+        (Value::Vector(items), Value::Number(n)) if *n >= 0 => items.get(*n as usize).cloned(),
+        (Value::Vector(_), _) | (Value::Nil, _) => None,
+        (coll, _) => return Err(FelispError::bad_arg(0, "collection", coll)),
+    };
+    Ok(found.unwrap_or(default))
+}
+
+/// the function apply calls and its arguments: those given before the last,
+/// then the items of the last
+/// (spread [+ 1 [2 3]]) -> (+, [1 2 3])
+pub fn spread(args: &[Value]) -> Result<(Value, Vec<Value>), FelispError> {
+    arity("apply", args, 2, None)?;
+    let last = args.len() - 1;
+    let mut spread = args[1..last].to_vec();
+    spread.extend(items(args, last)?);
+    Ok((args[0].clone(), spread))
+}
+
+/// the items of coll in a vector, the [k v] entries of maps,
+/// for functions walking any collection by index
+/// (vec '(1 2)) -> [1 2], (vec nil) -> []
+pub fn vec(args: &[Value]) -> Result<Value, FelispError> {
+    arity("vec", args, 1, Some(1))?;
+    Ok(match &args[0] {
+        Value::Vector(_) => args[0].clone(),
+        _ => Value::Vector(items(args, 0)?.into()),
+    })
+}
+
+/// item at index n of coll, which lists are walked only as far as,
+/// default (nil if absent) outside of it
+/// (nth '(1 2 3) 1) -> 2, (nth [1 2] 5 0) -> 0
+pub fn nth(args: &[Value]) -> Result<Value, FelispError> {
+    arity("nth", args, 2, Some(3))?;
+    let Value::Number(n) = args[1] else {
+        return Err(FelispError::bad_arg(1, "Number", &args[1]));
+    };
+    let default = args.get(2).cloned().unwrap_or(Value::Nil);
+    let Ok(n) = usize::try_from(n) else {
+        return Ok(default);
+    };
+    let found = match &args[0] {
+        Value::Vector(items) => items.get(n).cloned(),
+        Value::Pair(_) => {
+            let mut rest = &args[0];
+            for _ in 0..n {
+                match rest {
+                    Value::Pair(pair) => rest = &pair.1,
+                    _ => break,
+                }
+            }
+            match rest {
+                Value::Pair(pair) => Some(pair.0.clone()),
+                _ => None,
+            }
+        }
+        _ => items(args, 0)?.into_iter().nth(n),
+    };
+    Ok(found.unwrap_or(default))
+}
+
+/// a keyword or map called as a function: (:a m) and (m :a) get :a from the map m,
+/// default, nil unless given, when it has no such key
+/// (:a {:a 1}) -> 1, ({:a 1} :b 0) -> 0, (:a nil) -> nil
+pub fn lookup(f: &Value, args: &[Value]) -> Result<Value, FelispError> {
+    let name = match f {
+        Value::Keyword(_) => f.to_string(),
+        _ => "map".to_string(),
+    };
+    arity(&name, args, 1, Some(2))?;
+    let default = args.get(1).cloned().unwrap_or(Value::Nil);
+    let found = match (f, &args[0]) {
+        (Value::Map(entries), key) => entries.get(key).cloned(),
+        (Value::Keyword(_), Value::Map(entries)) => entries.get(f).cloned(),
+        (Value::Keyword(_), Value::Nil) => None,
+        (_, arg) => return Err(FelispError::bad_arg(0, "Map", arg)),
+    };
+    Ok(found.unwrap_or(default))
+}
+
+/// coll with each key (or index) associated to the value following it
+/// (assoc {} 1 2) -> {1 2}
+/// (assoc [1 2] 0 3) -> [3 2]
+pub fn assoc(args: &[Value]) -> Result<Value, FelispError> {
+    arity("assoc", args, 3, None)?;
+    if args.len().is_multiple_of(2) {
+        return Err(FelispError::ArityError(
+            "assoc requires key-value pairs".to_string(),
+        ));
+    }
+    match &args[0] {
+        Value::Map(entries) => {
+            let mut entries = entries.clone();
+            for i in (1..args.len()).step_by(2) {
+                entries.insert(key(args, i)?, args[i + 1].clone());
+            }
+            Ok(Value::Map(entries))
+        }
+        Value::Vector(items) => {
+            let mut items = items.clone();
+            for i in (1..args.len()).step_by(2) {
+                let n = index(args, i)?;
+                match n {
+                    n if n < items.len() => {
+                        items.set(n, args[i + 1].clone());
+                    }
+                    n if n == items.len() => items.push_back(args[i + 1].clone()),
+                    _ => return Err(FelispError::bad_arg(i, "index in bounds", &args[i])),
+                }
+            }
+            Ok(Value::Vector(items))
+        }
+        coll => Err(FelispError::bad_arg(0, "Map or Vector", coll)),
+    }
+}
+
+/// map without the given keys
+/// (dissoc {1 2 3 4} 1) -> {3 4}
+pub fn dissoc(args: &[Value]) -> Result<Value, FelispError> {
+    arity("dissoc", args, 1, None)?;
+    match &args[0] {
+        Value::Map(entries) => {
+            let mut entries = entries.clone();
+            for key in &args[1..] {
+                entries.remove(key);
+            }
+            Ok(Value::Map(entries))
+        }
+        coll => Err(FelispError::bad_arg(0, "Map", coll)),
+    }
+}
+
+/// coll with items added: at the end of vectors, as [k v] entries of maps
+/// (conj [1] 2 3) -> [1 2 3]
+/// (conj {} [1 2]) -> {1 2}
+pub fn conj(args: &[Value]) -> Result<Value, FelispError> {
+    arity("conj", args, 1, None)?;
+    match &args[0] {
+        Value::Vector(items) => {
+            let mut items = items.clone();
+            items.extend(args[1..].iter().cloned());
+            Ok(Value::Vector(items))
+        }
+        Value::Map(entries) => {
+            let mut entries = entries.clone();
+            for (i, entry) in args.iter().enumerate().skip(1) {
+                match entry {
+                    Value::Vector(pair) if pair.len() == 2 && pair[0].is_hashable() => {
+                        entries.insert(pair[0].clone(), pair[1].clone());
+                    }
+                    _ => return Err(FelispError::bad_arg(i, "[key value] entry", entry)),
+                }
+            }
+            Ok(Value::Map(entries))
+        }
+        Value::Nil => Ok(Value::Vector(args[1..].iter().cloned().collect())),
+        coll => Err(FelispError::bad_arg(0, "Map or Vector", coll)),
+    }
+}
+
+/// a pair of car and cdr, a list when cdr is one, an improper list otherwise
+/// (cons 1 '(2)) -> (1 2)
+/// (cons 1 2) -> (1 . 2)
+pub fn cons(args: &[Value]) -> Result<Value, FelispError> {
+    arity("cons", args, 2, Some(2))?;
+    Ok(Value::Pair(Rc::new((args[0].clone(), args[1].clone()))))
+}
+
+/// (list 1 2 3) -> (1 2 3), (list) -> ()
+pub fn list(args: &[Value]) -> Result<Value, FelispError> {
+    Ok(list_of(args.to_vec()))
+}
+
+/// the items followed by tail, an improper list unless tail is a list
+/// (list* 1 2 '(3)) -> (1 2 3), (list* 1 2 3) -> (1 2 . 3)
+pub fn list_star(args: &[Value]) -> Result<Value, FelispError> {
+    arity("list*", args, 1, None)?;
+    let (tail, items) = args.split_last().unwrap();
+    Ok(items.iter().rev().fold(tail.clone(), |rest, item| {
+        Value::Pair(Rc::new((item.clone(), rest)))
+    }))
+}
+
+/// the first item of a pair, nil for nil and ()
+/// (car '(1 2)) -> 1
+pub fn car(args: &[Value]) -> Result<Value, FelispError> {
+    arity("car", args, 1, Some(1))?;
+    half(&args[0]).map(|pair| pair.map_or(Value::Nil, |pair| pair.0.clone()))
+}
+
+/// the rest of a pair, nil for nil and ()
+/// (cdr '(1 2)) -> (2)
+/// (cdr '(1 . 2)) -> 2
+pub fn cdr(args: &[Value]) -> Result<Value, FelispError> {
+    arity("cdr", args, 1, Some(1))?;
+    half(&args[0]).map(|pair| pair.map_or(Value::Nil, |pair| pair.1.clone()))
+}
+
+/// the pair car or cdr takes from, None for the empty lists
+fn half(value: &Value) -> Result<Option<&(Value, Value)>, FelispError> {
+    match value {
+        Value::Pair(pair) => Ok(Some(pair)),
+        Value::Nil => Ok(None),
+        Value::Quoted(Expr::List(items, _)) if items.is_empty() => Ok(None),
+        _ => Err(FelispError::bad_arg(0, "Pair", value)),
+    }
+}
+
+/// number of items in coll
+/// (count [1 2]) -> 2
+pub fn count(args: &[Value]) -> Result<Value, FelispError> {
+    arity("count", args, 1, Some(1))?;
+    let n = match &args[0] {
+        Value::Vector(items) => items.len(),
+        Value::Map(entries) => entries.len(),
+        Value::Quoted(Expr::List(items, _)) => items.len(),
+        Value::Pair(_) => match args[0].list_items() {
+            Some(items) => items.len(),
+            None => return Err(FelispError::bad_arg(0, "proper list", &args[0])),
+        },
+        Value::Nil => 0,
+        coll => return Err(FelispError::bad_arg(0, "collection", coll)),
+    };
+    Ok(Value::Number(n as i64))
+}
+
+/// items of coll in ascending order, which is total over data, equal ones
+/// keeping theirs: a vector of those of vectors and maps, a list of those of lists
+/// (sort [3 nil "a" 1]) -> [nil 1 3 "a"], (sort '(2 1)) -> (1 2)
+pub fn sort(args: &[Value]) -> Result<Value, FelispError> {
+    arity("sort", args, 1, Some(1))?;
+    let mut items = items(args, 0)?;
+    if !items.iter().all(Value::is_ordered) {
+        return Err(FelispError::bad_arg(
+            0,
+            "collection of comparable items",
+            &args[0],
+        ));
+    }
+    items.sort();
+    Ok(match &args[0] {
+        Value::Vector(_) | Value::Map(_) => Value::Vector(items.into()),
+        _ => list_of(items),
+    })
+}
+
+/// -1, 0 or 1 as a comes before, with or after b in the order sort puts them in,
+/// numbers compared as <= does, though sort puts the exact one of equal numbers first
+/// (compare 1 2) -> -1, (compare "a" nil) -> 1, (compare 1 1.0) -> 0
+pub fn compare(args: &[Value]) -> Result<Value, FelispError> {
+    arity("compare", args, 2, Some(2))?;
+    if let Some(i) = args.iter().position(|arg| !arg.is_ordered()) {
+        return Err(FelispError::bad_arg(i, "comparable value", &args[i]));
+    }
+    let ordering = numeric::order(&args[0], &args[1]).unwrap_or_else(|| args[0].cmp(&args[1]));
+    Ok(Value::Number(ordering as i64))
+}
+
+/// a proper list of items
+fn list_of(items: Vec<Value>) -> Value {
+    let source = Source::new("<list>", String::new());
+    let empty = Value::Quoted(Expr::List(Rc::from([]), Span::new(&source, 0, 0)));
+    items
+        .into_iter()
+        .rev()
+        .fold(empty, |rest, item| Value::Pair(Rc::new((item, rest))))
+}
+
+/// the items of coll in vectors of n, leaving out the last ones if fewer than n
+/// (partition 2 [1 2 3 4 5]) -> [[1 2] [3 4]]
+pub fn partition(args: &[Value]) -> Result<Value, FelispError> {
+    arity("partition", args, 2, Some(2))?;
+    let n = match &args[0] {
+        Value::Number(n) if *n > 0 => *n as usize,
+        n => return Err(FelispError::bad_arg(0, "positive Number", n)),
+    };
+    Ok(Value::Vector(
+        items(args, 1)?
+            .chunks_exact(n)
+            .map(|chunk| Value::Vector(chunk.iter().cloned().collect()))
+            .collect(),
+    ))
+}
+
+/// vectors of the items of each coll at the same index, as many as the shortest has
+/// (zip [1 2 3] ["a" "b"]) -> [[1 "a"] [2 "b"]]
+pub fn zip(args: &[Value]) -> Result<Value, FelispError> {
+    let colls = (0..args.len())
+        .map(|i| items(args, i))
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = colls.iter().map(Vec::len).min().unwrap_or(0);
+    Ok(Value::Vector(
+        (0..len)
+            .map(|i| Value::Vector(colls.iter().map(|coll| coll[i].clone()).collect()))
+            .collect(),
+    ))
+}
+
+/// how many times each item of coll appears in it
+/// (frequencies [1 2 1]) -> {1 2 2 1}
+pub fn frequencies(args: &[Value]) -> Result<Value, FelispError> {
+    arity("frequencies", args, 1, Some(1))?;
+    let mut counts = HashMap::new();
+    for item in items(args, 0)? {
+        if !item.is_hashable() {
+            return Err(FelispError::bad_arg(
+                0,
+                "collection of hashable items",
+                &args[0],
+            ));
+        }
+        let n = match counts.get(&item) {
+            Some(Value::Number(n)) => *n,
+            _ => 0,
+        };
+        counts.insert(item, Value::Number(n + 1));
+    }
+    Ok(Value::Map(counts))
+}
@@ -0,0 +1,127 @@
+// conversions between rust types and values, for host functions
+// to take their arguments and build their results without matching on Value
+
+use crate::{env::Value, error::FelispError, numeric, printer};
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Nil
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        if b { Value::True } else { Value::False }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+/// None -> nil
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(option: Option<T>) -> Self {
+        option.map_or(Value::Nil, Into::into)
+    }
+}
+
+/// vec![1, 2] -> [1 2]
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::Vector(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<Value>, V: Into<Value>> FromIterator<(K, V)> for Value {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+fn expected(what: &str, value: &Value) -> FelispError {
+    FelispError::TypeError {
+        expected: what.to_string(),
+        got: printer::abbreviate(value),
+        arg: None,
+        function: None,
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = FelispError;
+
+    /// nil and false are false, anything else is true, as for if
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Ok(!matches!(value, Value::Nil | Value::False))
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = FelispError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            _ => Err(expected("Number", &value)),
+        }
+    }
+}
+
+/// any number, rounded to the nearest float
+impl TryFrom<Value> for f64 {
+    type Error = FelispError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        numeric::to_f64(&value).ok_or_else(|| expected("Number", &value))
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = FelispError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match &value {
+            Value::String(s) => Ok(s.to_string()),
+            _ => Err(expected("String", &value)),
+        }
+    }
+}
+
+/// [1 2] -> vec![1, 2], nil -> vec![]
+impl<T: TryFrom<Value, Error = FelispError>> TryFrom<Value> for Vec<T> {
+    type Error = FelispError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match &value {
+            Value::Vector(items) => items.iter().cloned().map(T::try_from).collect(),
+            Value::Nil => Ok(vec![]),
+            _ => Err(expected("Vector", &value)),
+        }
+    }
+}
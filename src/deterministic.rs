@@ -0,0 +1,54 @@
+// deterministic runs (--deterministic), whose output depends on nothing but the script
+// and the seed: random values come from a generator seeded with it, (now) reads a
+// logical clock advancing a millisecond per call from 1970, and maps are walked in
+// the order of their keys rather than that of their hashes, which changes every run
+
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+use im::HashMap;
+
+use crate::env::Value;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// state of the splitmix64 generator
+static STATE: AtomicU64 = AtomicU64::new(0);
+// milliseconds since 1970 of the logical clock
+static CLOCK: AtomicI64 = AtomicI64::new(0);
+
+/// makes the runs of every interpreter of the process deterministic from now on
+pub fn enable(seed: u64) {
+    STATE.store(seed, Ordering::Relaxed);
+    CLOCK.store(0, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// the next number of the seeded generator, None unless enabled
+pub fn random() -> Option<u64> {
+    if !is_enabled() {
+        return None;
+    }
+    let mut z = STATE
+        .fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed)
+        .wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    Some(z ^ (z >> 31))
+}
+
+/// the time of the logical clock, advancing it, None unless enabled
+pub fn now_ms() -> Option<i64> {
+    is_enabled().then(|| CLOCK.fetch_add(1, Ordering::Relaxed))
+}
+
+/// the entries of a map, sorted by key when enabled
+pub fn entries(map: &HashMap<Value, Value>) -> Vec<(&Value, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    if is_enabled() {
+        entries.sort_by_key(|&(key, _)| key);
+    }
+    entries
+}
@@ -0,0 +1,64 @@
+// identifiers and digests of strings, for scripts around content addressing:
+// digests are written in lower-case hex, of the UTF-8 bytes of the string
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use uuid::{Builder, Uuid};
+
+use crate::{
+    deterministic,
+    env::{Builtin, Value},
+    error::FelispError,
+};
+
+/// builtins of the digest feature, safe to give to any code
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[
+    ("uuid", "", uuid),
+    ("sha256", "s", sha256),
+    ("md5", "s", md5),
+];
+
+/// a random (version 4) UUID
+/// (uuid) -> "67e55044-10b1-426f-9247-bb680e5fe0c8"
+fn uuid(args: &[Value]) -> Result<Value, FelispError> {
+    if !args.is_empty() {
+        return Err(FelispError::ArityError(format!(
+            "uuid requires 0 arguments but given {}",
+            args.len()
+        )));
+    }
+    let uuid = match (deterministic::random(), deterministic::random()) {
+        (Some(high), Some(low)) => {
+            let bytes = (u128::from(high) << 64 | u128::from(low)).to_be_bytes();
+            Builder::from_random_bytes(bytes).into_uuid()
+        }
+        _ => Uuid::new_v4(),
+    };
+    Ok(Value::from(uuid.to_string()))
+}
+
+/// (sha256 "abc") -> "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+fn sha256(args: &[Value]) -> Result<Value, FelispError> {
+    hex_digest::<Sha256>("sha256", args)
+}
+
+/// (md5 "abc") -> "900150983cd24fb0d6963f7d28e17f72"
+fn md5(args: &[Value]) -> Result<Value, FelispError> {
+    hex_digest::<Md5>("md5", args)
+}
+
+fn hex_digest<D: Digest>(name: &str, args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "{} requires 1 argument but given {}",
+            name,
+            args.len()
+        )));
+    }
+    let Value::String(s) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    let digest = D::digest(s.as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(Value::from(hex))
+}
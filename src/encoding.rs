@@ -0,0 +1,94 @@
+// base64 and hex encodings of bytes, which felisp has no type of:
+// bytes are vectors of numbers from 0 to 255, or strings for their UTF-8 bytes,
+// and decoding malformed input gives nil rather than an error
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::{
+    env::{Builtin, Value},
+    error::FelispError,
+};
+
+/// builtins of the encoding feature, safe to give to any code
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[
+    ("base64-encode", "data", base64_encode),
+    ("base64-decode", "s", base64_decode),
+    ("hex-encode", "data", hex_encode),
+    ("hex-decode", "s", hex_decode),
+    ("bytes->string", "bytes", bytes_to_string),
+];
+
+/// (base64-encode "felisp") -> "ZmVsaXNw"
+fn base64_encode(args: &[Value]) -> Result<Value, FelispError> {
+    let bytes = bytes_arg("base64-encode", args)?;
+    Ok(Value::from(STANDARD.encode(bytes)))
+}
+
+/// (base64-decode "ZmVsaXNw") -> [102 101 108 105 115 112]
+fn base64_decode(args: &[Value]) -> Result<Value, FelispError> {
+    let s = string_arg("base64-decode", args)?;
+    Ok(STANDARD.decode(s).map_or(Value::Nil, to_vector))
+}
+
+/// (hex-encode [1 255]) -> "01ff"
+fn hex_encode(args: &[Value]) -> Result<Value, FelispError> {
+    let bytes = bytes_arg("hex-encode", args)?;
+    Ok(Value::from(hex::encode(bytes)))
+}
+
+/// (hex-decode "01ff") -> [1 255]
+fn hex_decode(args: &[Value]) -> Result<Value, FelispError> {
+    let s = string_arg("hex-decode", args)?;
+    Ok(hex::decode(s).map_or(Value::Nil, to_vector))
+}
+
+/// the string of the UTF-8 bytes, nil if they aren't
+/// (bytes->string (base64-decode "ZmVsaXNw")) -> "felisp"
+fn bytes_to_string(args: &[Value]) -> Result<Value, FelispError> {
+    let bytes = bytes_arg("bytes->string", args)?;
+    Ok(String::from_utf8(bytes).map_or(Value::Nil, Value::from))
+}
+
+fn arity(name: &str, args: &[Value]) -> Result<(), FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "{} requires 1 argument but given {}",
+            name,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+fn string_arg<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, FelispError> {
+    arity(name, args)?;
+    match &args[0] {
+        Value::String(s) => Ok(s),
+        arg => Err(FelispError::bad_arg(0, "String", arg)),
+    }
+}
+
+fn bytes_arg(name: &str, args: &[Value]) -> Result<Vec<u8>, FelispError> {
+    arity(name, args)?;
+    match &args[0] {
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Vector(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Number(n) => u8::try_from(*n).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| FelispError::bad_arg(0, "bytes", &args[0])),
+        arg => Err(FelispError::bad_arg(0, "bytes", arg)),
+    }
+}
+
+fn to_vector(bytes: Vec<u8>) -> Value {
+    Value::Vector(
+        bytes
+            .into_iter()
+            .map(|b| Value::Number(i64::from(b)))
+            .collect(),
+    )
+}
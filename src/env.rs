@@ -1,154 +1,1445 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+use std::{
+    any::{Any, type_name},
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::HashSet,
+    convert::Infallible,
+    fmt::Display,
+    future::Future,
+    hash::{Hash, Hasher},
+    mem,
+    pin::Pin,
+};
 
-use crate::ast::Expr;
+use im::{HashMap, Vector};
+use num_bigint::BigInt;
+use num_rational::BigRational;
 
-pub type Callback = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+use crate::{
+    ast::{Expr, reader_macro, write_str},
+    collections, deterministic,
+    error::FelispError,
+    eval::{Curried, Generator, Memo},
+    ir::FnDef,
+    logging,
+    namespace::{self, Namespaces},
+    native::IntoNativeFn,
+    numbers, numeric,
+    output::{self, Output, Stream},
+    printer::PrettyPrinter,
+    protocol, resource,
+    symbol::{Symbol, SymbolMap},
+    sync::{Lock, MaybeSend, MaybeSync, Rc},
+};
+
+#[cfg(not(feature = "sync"))]
+type Callback = Box<dyn Fn(&[Value]) -> Result<Value, FelispError>>;
+#[cfg(feature = "sync")]
+type Callback = Box<dyn Fn(&[Value]) -> Result<Value, FelispError> + Send + Sync>;
+
+/// closure the host can turn into a NativeFn
+#[cfg(not(feature = "sync"))]
+pub trait HostFn: Fn(&[Value]) -> Result<Value, FelispError> + 'static {}
+#[cfg(not(feature = "sync"))]
+impl<F: Fn(&[Value]) -> Result<Value, FelispError> + 'static> HostFn for F {}
+#[cfg(feature = "sync")]
+pub trait HostFn: Fn(&[Value]) -> Result<Value, FelispError> + Send + Sync + 'static {}
+#[cfg(feature = "sync")]
+impl<F: Fn(&[Value]) -> Result<Value, FelispError> + Send + Sync + 'static> HostFn for F {}
+
+/// plain function builtin, without captured state
+pub type Builtin = fn(&[Value]) -> Result<Value, FelispError>;
+
+/// value eventually returned by an async host function
+#[cfg(not(feature = "sync"))]
+pub type ValueFuture = Pin<Box<dyn Future<Output = Result<Value, FelispError>>>>;
+#[cfg(feature = "sync")]
+pub type ValueFuture = Pin<Box<dyn Future<Output = Result<Value, FelispError>> + Send>>;
+
+/// host function awaited by the evaluator, which only eval_async can call
+#[cfg(not(feature = "sync"))]
+pub type AsyncCallback = Rc<dyn Fn(Vec<Value>) -> ValueFuture>;
+#[cfg(feature = "sync")]
+pub type AsyncCallback = Rc<dyn Fn(Vec<Value>) -> ValueFuture + Send + Sync>;
 
 #[derive(Clone)]
 pub enum Value {
-    // nil, ()
+    // nil, the only value false is besides false itself
     Nil,
     True,
     False,
-    // special forms
-    Def,
-    Let,
-    Do,
-    If,
-    Fn,
-    Quote,
     // data types
     Number(i64),
+    // the rest of the number tower, see src/numeric.rs: ints too large for Number,
+    // exact fractions in lowest terms, and floats
+    BigInt(Rc<BigInt>),
+    Ratio(Rc<BigRational>),
+    Float(f64),
+    String(Rc<str>),
+    // :name, evaluating to itself
+    Keyword(Symbol),
     Quoted(Expr),
-    Function(Callback),
+    // (car . cdr), lists being chains of them ending in () or nil
+    Pair(Rc<(Value, Value)>),
+    Vector(Vector<Value>),
+    Map(HashMap<Value, Value>),
+    // builtins and host functions
+    Function(Rc<NativeFn>),
+    AsyncFunction(AsyncCallback),
+    Lambda(Rc<Lambda>),
+    // (memoize f), caching what f returns for each list of arguments
+    Memoized(Rc<Memo>),
+    // (curry f), taking the arguments of f a few at a time
+    Curried(Rc<Curried>),
+    // host handle, opaque to felisp code
+    Native(Rc<Native>),
+    // (generator ...), resumed by calling it
+    Generator(Rc<Generator>),
+}
+
+/// a value of the host, e.g. a socket or a game entity, that felisp code
+/// can pass around and back to host functions but not look into
+pub struct Native {
+    pub type_name: &'static str,
+    #[cfg(not(feature = "sync"))]
+    value: Box<dyn Any>,
+    #[cfg(feature = "sync")]
+    value: Box<dyn Any + Send + Sync>,
+    // releases what it holds, for resources, see src/resource.rs
+    close: Option<fn(&dyn Any) -> bool>,
+}
+
+impl Native {
+    /// whether it holds a resource, which close releases
+    pub fn is_resource(&self) -> bool {
+        self.close.is_some()
+    }
+
+    /// releases what it holds, returning false if it already was, None if it can't be
+    pub fn close(&self) -> Option<bool> {
+        self.close.map(|close| close(self.value.as_ref()))
+    }
+}
+
+impl Value {
+    /// wraps a host value, printed as <TypeName>
+    pub fn native<T: Any + MaybeSync>(value: T) -> Value {
+        let type_name = type_name::<T>();
+        Value::Native(Rc::new(Native {
+            type_name: type_name.rsplit("::").next().unwrap_or(type_name),
+            value: Box::new(value),
+            close: None,
+        }))
+    }
+
+    /// wraps a host value printed as <type_name>, which (close! v) calls close with
+    pub(crate) fn closable<T: Any + MaybeSync>(
+        value: T,
+        type_name: &'static str,
+        close: fn(&dyn Any) -> bool,
+    ) -> Value {
+        Value::Native(Rc::new(Native {
+            type_name,
+            value: Box::new(value),
+            close: Some(close),
+        }))
+    }
+
+    /// the host value of type T wrapped by Value::native, if this is one
+    pub fn downcast_native<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::Native(native) => native.value.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// a builtin or host function, named and with the arguments it takes,
+/// which are checked before it is called
+pub struct NativeFn {
+    pub name: Symbol,
+    // written as fn* parameters: [x] for optional ones, & xs for the rest
+    pub params: Rc<str>,
+    pub min_arity: usize,
+    // None when it takes any number of arguments from min_arity on
+    pub max_arity: Option<usize>,
+    // one of the builtins envs start with, under its own name
+    builtin: bool,
+    // apply, whose calls the evaluator makes itself, see NativeFn::spreads
+    spreads: bool,
+    f: Callback,
+}
+
+impl NativeFn {
+    /// f named name, taking the arguments params describes
+    /// (NativeFn::new("get", "coll key [default]", collections::get))
+    pub fn new(name: impl Into<Symbol>, params: &str, f: impl HostFn) -> Self {
+        let mut min_arity = 0;
+        let mut max_arity = Some(0);
+        for param in params.split_whitespace() {
+            match param {
+                "&" => {
+                    max_arity = None;
+                    break;
+                }
+                optional if optional.starts_with('[') => {}
+                _ => min_arity += 1,
+            }
+            max_arity = max_arity.map(|n| n + 1);
+        }
+        Self {
+            name: name.into(),
+            params: params.into(),
+            min_arity,
+            max_arity,
+            builtin: false,
+            spreads: false,
+            f: Box::new(f),
+        }
+    }
+
+    /// whether this is the builtin envs bind to name when created,
+    /// rather than a host function or a builtin bound to another name
+    pub fn is_builtin(&self, name: Symbol) -> bool {
+        self.builtin && self.name == name
+    }
+
+    /// whether this is apply, which the evaluator calls f for itself, so that lambdas
+    /// can be applied too, spreading the last argument as apply does
+    pub fn spreads(&self) -> bool {
+        self.spreads
+    }
+
+    /// how calls are written, shown by (doc f)
+    /// (get coll key [default])
+    pub fn signature(&self) -> String {
+        match self.params.is_empty() {
+            true => format!("({})", self.name),
+            false => format!("({} {})", self.name, self.params),
+        }
+    }
+
+    /// applies f to args once checked to be as many as it takes,
+    /// naming it in the type errors it raises
+    pub fn call(&self, args: &[Value]) -> Result<Value, FelispError> {
+        let n = args.len();
+        if n < self.min_arity || self.max_arity.is_some_and(|max| n > max) {
+            let expected = match self.max_arity {
+                Some(max) if max == self.min_arity => arguments(max),
+                Some(max) => format!("{} to {}", self.min_arity, arguments(max)),
+                None => format!("at least {}", arguments(self.min_arity)),
+            };
+            return Err(FelispError::ArityError(format!(
+                "{} requires {} but given {}",
+                self.name, expected, n
+            )));
+        }
+        (self.f)(args).map_err(|err| err.raised_by(self.name))
+    }
+}
+
+fn arguments(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
+
+impl From<NativeFn> for Value {
+    fn from(f: NativeFn) -> Self {
+        Value::Function(Rc::new(f))
+    }
+}
+
+/// closure created by fn*, applied by the evaluator
+pub struct Lambda {
+    pub def: Rc<FnDef>,
+    pub env: Rc<Env>,
+    pub scope: Option<Rc<Scope>>,
+}
+
+// the impls below go through their values from worklists rather than recursively,
+// as a program can build lists and vectors nested too deep for the stack
+
+/// what is left to print of a value, in the order it is printed
+enum Print<'a> {
+    Value(&'a Value),
+    // what follows the items of a list printed so far: more, a dotted tail or nothing
+    Rest(&'a Value),
+    Text(&'static str),
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.is_collection() {
+            return self.fmt_atom(f);
+        }
+        let mut pending = vec![Print::Value(self)];
+        while let Some(print) = pending.pop() {
+            match print {
+                Print::Text(text) => f.write_str(text)?,
+                // (1 2 3), or (1 2 . 3) when the last cdr isn't a list
+                Print::Value(Value::Pair(pair)) => {
+                    f.write_str("(")?;
+                    pending.extend([Print::Rest(&pair.1), Print::Value(&pair.0)]);
+                }
+                Print::Rest(Value::Pair(pair)) => {
+                    f.write_str(" ")?;
+                    pending.extend([Print::Rest(&pair.1), Print::Value(&pair.0)]);
+                }
+                Print::Rest(rest) if rest.is_empty_list() => f.write_str(")")?,
+                Print::Rest(last) => {
+                    f.write_str(" . ")?;
+                    pending.extend([Print::Text(")"), Print::Value(last)]);
+                }
+                Print::Value(Value::Vector(items)) => {
+                    f.write_str("[")?;
+                    pending.push(Print::Text("]"));
+                    for (i, item) in items.iter().enumerate().rev() {
+                        pending.push(Print::Value(item));
+                        if i > 0 {
+                            pending.push(Print::Text(" "));
+                        }
+                    }
+                }
+                Print::Value(Value::Map(entries)) => {
+                    f.write_str("{")?;
+                    pending.push(Print::Text("}"));
+                    let entries = deterministic::entries(entries);
+                    for (i, (k, v)) in entries.into_iter().enumerate().rev() {
+                        pending.extend([Print::Value(v), Print::Text(" "), Print::Value(k)]);
+                        if i > 0 {
+                            pending.push(Print::Text(" "));
+                        }
+                    }
+                }
+                Print::Value(atom) => atom.fmt_atom(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    fn is_collection(&self) -> bool {
+        matches!(self, Value::Pair(_) | Value::Vector(_) | Value::Map(_))
+    }
+
+    /// nil or (), which end proper lists
+    fn is_empty_list(&self) -> bool {
+        match self {
+            Value::Nil => true,
+            Value::Quoted(Expr::List(items, _)) => items.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn fmt_atom(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Nil => write!(f, "nil"),
             Value::True => write!(f, "true"),
             Value::False => write!(f, "false"),
-            Value::Def => write!(f, "def!"),
-            Value::Let => write!(f, "let*"),
-            Value::If => write!(f, "if"),
-            Value::Do => write!(f, "do"),
-            Value::Quote => write!(f, "quote"),
-            Value::Fn => write!(f, "fn*"),
             Value::Number(n) => write!(f, "{}", n),
+            Value::BigInt(n) => write!(f, "{}", n),
+            Value::Ratio(r) => write!(f, "{}", r),
+            // 1.0 rather than 1, so that it reads back as a float
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => write_str(f, s),
+            Value::Keyword(name) => write!(f, ":{}", name),
             Value::Quoted(expr) => write!(f, "{}", expr),
-            Value::Function(_) => write!(f, "<fun>"),
+            Value::Pair(_) | Value::Vector(_) | Value::Map(_) => write!(f, "{}", self),
+            Value::Lambda(lambda) if let Some(name) = lambda.def.name => {
+                write!(f, "<fun {}>", name)
+            }
+            Value::Function(_)
+            | Value::AsyncFunction(_)
+            | Value::Lambda(_)
+            | Value::Memoized(_)
+            | Value::Curried(_) => write!(f, "<fun>"),
+            Value::Native(native) => write!(f, "<{}>", native.type_name),
+            Value::Generator(_) => write!(f, "<generator>"),
+        }
+    }
+}
+
+/// a value of a walk, or the end of the items of a vector or map
+enum Step<'a> {
+    Value(&'a Value),
+    End,
+}
+
+/// the values making up a value, depth first: the value itself then, for collections,
+/// their items: car then cdr, those of vectors then End, the entries of maps by key then End
+struct Walk<'a> {
+    pending: Vec<Step<'a>>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(value: &'a Value) -> Self {
+        Walk {
+            pending: vec![Step::Value(value)],
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Step<'a>;
+
+    fn next(&mut self) -> Option<Step<'a>> {
+        let step = self.pending.pop()?;
+        match step {
+            Step::Value(Value::Pair(pair)) => self
+                .pending
+                .extend([Step::Value(&pair.1), Step::Value(&pair.0)]),
+            Step::Value(Value::Vector(items)) => {
+                self.pending.push(Step::End);
+                self.pending.extend(items.iter().rev().map(Step::Value));
+            }
+            Step::Value(Value::Map(entries)) => {
+                let mut entries: Vec<_> = entries.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+                self.pending.push(Step::End);
+                for (k, v) in entries.into_iter().rev() {
+                    self.pending.extend([Step::Value(v), Step::Value(k)]);
+                }
+            }
+            _ => {}
+        }
+        Some(step)
+    }
+}
+
+/// how a and b compare, walking them side by side: the first values of their walks
+/// that atom tells apart decide, a walk ending first coming before
+/// atom compares the other values, collections only by type, as their items come next
+fn compare_walks<E>(
+    a: &Value,
+    b: &Value,
+    mut atom: impl FnMut(&Value, &Value) -> Result<Ordering, E>,
+) -> Result<Ordering, E> {
+    if !a.is_collection() || !b.is_collection() {
+        return atom(a, b);
+    }
+    let (mut a, mut b) = (Walk::new(a), Walk::new(b));
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ok(Ordering::Equal),
+            (Some(Step::End), Some(Step::End)) => Ordering::Equal,
+            (None | Some(Step::End), _) => Ordering::Less,
+            (_, None | Some(Step::End)) => Ordering::Greater,
+            (Some(Step::Value(x)), Some(Step::Value(y))) => atom(x, y)?,
+        };
+        if ordering != Ordering::Equal {
+            return Ok(ordering);
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        let atoms_eq = |x: &Value, y: &Value| {
+            let eq = match (x, y) {
+                (Value::Nil, Value::Nil)
+                | (Value::True, Value::True)
+                | (Value::False, Value::False) => true,
+                (Value::Number(x), Value::Number(y)) => x == y,
+                (Value::BigInt(x), Value::BigInt(y)) => x == y,
+                (Value::Ratio(x), Value::Ratio(y)) => x == y,
+                // by bits, as they are hashed, so NaN equals itself and 0.0 isn't -0.0
+                (Value::Float(x), Value::Float(y)) => x.to_bits() == y.to_bits(),
+                (Value::String(x), Value::String(y)) => x == y,
+                (Value::Keyword(x), Value::Keyword(y)) => x == y,
+                (Value::Quoted(x), Value::Quoted(y)) => x == y,
+                // compared by their items, which come next
+                (Value::Pair(_), Value::Pair(_)) => true,
+                (Value::Vector(x), Value::Vector(y)) => x.len() == y.len(),
+                (Value::Map(x), Value::Map(y)) => x.len() == y.len(),
+                // functions are only equal to themselves
+                (Value::Function(x), Value::Function(y)) => Rc::ptr_eq(x, y),
+                (Value::AsyncFunction(x), Value::AsyncFunction(y)) => Rc::ptr_eq(x, y),
+                (Value::Lambda(x), Value::Lambda(y)) => Rc::ptr_eq(x, y),
+                (Value::Native(x), Value::Native(y)) => Rc::ptr_eq(x, y),
+                (Value::Memoized(x), Value::Memoized(y)) => Rc::ptr_eq(x, y),
+                (Value::Curried(x), Value::Curried(y)) => Rc::ptr_eq(x, y),
+                (Value::Generator(x), Value::Generator(y)) => Rc::ptr_eq(x, y),
+                _ => false,
+            };
+            Ok::<_, Infallible>(if eq { Ordering::Equal } else { Ordering::Less })
+        };
+        let Ok(ordering) = compare_walks(self, other, atoms_eq);
+        ordering == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut pending = vec![];
+        let mut next = Some(self);
+        while let Some(value) = next {
+            mem::discriminant(value).hash(state);
+            match value {
+                Value::Nil | Value::True | Value::False => {}
+                Value::Number(n) => n.hash(state),
+                Value::BigInt(n) => n.hash(state),
+                Value::Ratio(r) => r.hash(state),
+                Value::Float(x) => x.to_bits().hash(state),
+                Value::String(s) => s.hash(state),
+                Value::Keyword(name) => name.hash(state),
+                Value::Quoted(expr) => expr.hash(state),
+                Value::Pair(pair) => pending.extend([&pair.1, &pair.0]),
+                Value::Vector(items) => {
+                    items.len().hash(state);
+                    pending.extend(items.iter().rev());
+                }
+                Value::Map(entries) => {
+                    entries.len().hash(state);
+                    // in key order, as equal maps can iterate in different orders
+                    let mut entries: Vec<_> = entries.iter().collect();
+                    entries.sort_by_key(|&(key, _)| key);
+                    pending.extend(
+                        entries
+                            .into_iter()
+                            .rev()
+                            .flat_map(|(key, value)| [value, key]),
+                    );
+                }
+                Value::Function(f) => Rc::as_ptr(f).cast::<()>().hash(state),
+                Value::AsyncFunction(f) => Rc::as_ptr(f).cast::<()>().hash(state),
+                Value::Lambda(f) => Rc::as_ptr(f).hash(state),
+                Value::Native(native) => Rc::as_ptr(native).hash(state),
+                Value::Memoized(memo) => Rc::as_ptr(memo).hash(state),
+                Value::Curried(curried) => Rc::as_ptr(curried).hash(state),
+                Value::Generator(generator) => Rc::as_ptr(generator).hash(state),
+            }
+            next = pending.pop();
+        }
+    }
+}
+
+/// values of different types are ordered nil < false < true < numbers < strings
+/// < keywords < quoted forms < pairs < vectors < maps < functions < native values < generators,
+/// functions, native values and generators among themselves by address, for map keys
+/// to have an order: compare and sort reject them, see Value::is_ordered
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let Ok(ordering) = compare_walks(self, other, |x, y| {
+            Ok::<_, Infallible>(match (x, y) {
+                (Value::Number(x), Value::Number(y)) => x.cmp(y),
+                (x, y) if x.rank() == 3 && y.rank() == 3 => numeric::total_cmp(x, y),
+                (Value::String(x), Value::String(y)) => x.cmp(y),
+                (Value::Keyword(x), Value::Keyword(y)) => x.name().cmp(y.name()),
+                (Value::Quoted(x), Value::Quoted(y)) => x.to_string().cmp(&y.to_string()),
+                (Value::Function(x), Value::Function(y)) => {
+                    Rc::as_ptr(x).cast::<()>().cmp(&Rc::as_ptr(y).cast())
+                }
+                (Value::AsyncFunction(x), Value::AsyncFunction(y)) => {
+                    Rc::as_ptr(x).cast::<()>().cmp(&Rc::as_ptr(y).cast())
+                }
+                (Value::Lambda(x), Value::Lambda(y)) => Rc::as_ptr(x).cmp(&Rc::as_ptr(y)),
+                (Value::Native(x), Value::Native(y)) => Rc::as_ptr(x).cmp(&Rc::as_ptr(y)),
+                (Value::Memoized(x), Value::Memoized(y)) => Rc::as_ptr(x).cmp(&Rc::as_ptr(y)),
+                (Value::Curried(x), Value::Curried(y)) => Rc::as_ptr(x).cmp(&Rc::as_ptr(y)),
+                (Value::Generator(x), Value::Generator(y)) => Rc::as_ptr(x).cmp(&Rc::as_ptr(y)),
+                // collections of the same type by their items, which come next
+                (x, y) => x.rank().cmp(&y.rank()),
+            })
+        });
+        ordering
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// values nested deeper than this are dropped once the outermost drop is done with
+// the others, rather than recursively, so that dropping a list of a million cells
+// or a vector nested as deep doesn't overflow the stack
+const MAX_DROP_DEPTH: usize = 128;
+
+thread_local! {
+    // how many values being dropped on this thread are nested in one another
+    static DROP_DEPTH: Cell<usize> = const { Cell::new(0) };
+    // the values found too deep to drop, left to the outermost drop
+    static DEFERRED: RefCell<Vec<Value>> = const { RefCell::new(Vec::new()) };
+    // left in the place of the entries of a map being dropped
+    static NO_ENTRIES: HashMap<Value, Value> = HashMap::new();
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        match self {
+            // a cell freed along with the value, rather than shared, frees its car and cdr
+            Value::Pair(pair) => {
+                if let Some((car, cdr)) = Rc::get_mut(pair) {
+                    for value in [car, cdr] {
+                        if value.is_collection() {
+                            drop_nested(mem::replace(value, Value::Nil), |value| value);
+                        }
+                    }
+                }
+            }
+            Value::Vector(items) if !items.is_empty() => {
+                drop_nested(mem::take(items), Value::Vector);
+            }
+            Value::Map(entries) if !entries.is_empty() => {
+                if let Ok(none) = NO_ENTRIES.try_with(HashMap::clone) {
+                    drop_nested(mem::replace(entries, none), Value::Map);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// drops items, or leaves them to the outermost drop, as a value, if nested too deep
+fn drop_nested<T>(items: T, value: fn(T) -> Value) {
+    let depth = DROP_DEPTH.get();
+    if depth >= MAX_DROP_DEPTH {
+        // dropped right away if the thread is exiting and there's nowhere to leave them
+        let _ = DEFERRED.try_with(move |deferred| deferred.borrow_mut().push(value(items)));
+        return;
+    }
+    DROP_DEPTH.set(depth + 1);
+    drop(items);
+    if depth == 0 {
+        // each going as deep again, deferring what is deeper still
+        while let Some(value) = DEFERRED
+            .try_with(|deferred| deferred.borrow_mut().pop())
+            .ok()
+            .flatten()
+        {
+            drop(value);
         }
     }
+    DROP_DEPTH.set(depth);
 }
 
+/// the types type-of tells apart
+pub const TYPE_KEYWORDS: &[&str] = &[
+    "nil",
+    "bool",
+    "int",
+    "ratio",
+    "float",
+    "string",
+    "keyword",
+    "symbol",
+    "list",
+    "pair",
+    "vector",
+    "map",
+    "fn",
+    "native-fn",
+    "generator",
+    "native",
+];
+
+impl Value {
+    /// name of the value's type, as used in type errors
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "Nil",
+            Value::True | Value::False => "Bool",
+            Value::Number(_) | Value::BigInt(_) => "Number",
+            Value::Ratio(_) => "Ratio",
+            Value::Float(_) => "Float",
+            Value::String(_) => "String",
+            Value::Keyword(_) => "Keyword",
+            Value::Quoted(_) => "Quoted",
+            Value::Pair(_) => "Pair",
+            Value::Vector(_) => "Vector",
+            Value::Map(_) => "Map",
+            Value::Function(_)
+            | Value::AsyncFunction(_)
+            | Value::Lambda(_)
+            | Value::Memoized(_)
+            | Value::Curried(_) => "Function",
+            Value::Native(native) => native.type_name,
+            Value::Generator(_) => "Generator",
+        }
+    }
+
+    /// name of the keyword type-of returns for the value, one of TYPE_KEYWORDS:
+    /// list is for proper lists, () included, and pair for improper ones,
+    /// fn for lambdas and memoized functions, native-fn for builtins and host functions,
+    /// and native for host values
+    pub fn type_keyword(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::True | Value::False => "bool",
+            Value::Number(_) | Value::BigInt(_) => "int",
+            Value::Ratio(_) => "ratio",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Keyword(_) => "keyword",
+            Value::Quoted(Expr::List(..)) => "list",
+            Value::Quoted(_) => "symbol",
+            Value::Pair(_) if self.list_items().is_some() => "list",
+            Value::Pair(_) => "pair",
+            Value::Vector(_) => "vector",
+            Value::Map(_) => "map",
+            Value::Lambda(_) | Value::Memoized(_) | Value::Curried(_) => "fn",
+            Value::Function(_) | Value::AsyncFunction(_) => "native-fn",
+            Value::Native(_) => "native",
+            Value::Generator(_) => "generator",
+        }
+    }
+
+    /// the items of a proper list: nil, () or pairs ending in either
+    /// (cons 1 (cons 2 nil)) -> [1 2], (cons 1 2) -> None
+    pub fn list_items(&self) -> Option<Vec<Value>> {
+        let mut items = vec![];
+        let mut rest = self;
+        loop {
+            match rest {
+                Value::Pair(pair) => {
+                    items.push(pair.0.clone());
+                    rest = &pair.1;
+                }
+                Value::Nil => return Some(items),
+                Value::Quoted(Expr::List(exprs, _)) if exprs.is_empty() => return Some(items),
+                _ => return None,
+            }
+        }
+    }
+
+    /// position of the value's type in the ordering of values
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Nil => 0,
+            Value::False => 1,
+            Value::True => 2,
+            Value::Number(_) | Value::BigInt(_) | Value::Ratio(_) | Value::Float(_) => 3,
+            Value::String(_) => 4,
+            Value::Keyword(_) => 5,
+            Value::Quoted(_) => 6,
+            Value::Pair(_) => 7,
+            Value::Vector(_) => 8,
+            Value::Map(_) => 9,
+            Value::Function(_) => 10,
+            Value::AsyncFunction(_) => 11,
+            Value::Lambda(_) => 12,
+            Value::Memoized(_) => 13,
+            Value::Native(_) => 14,
+            Value::Generator(_) => 15,
+            Value::Curried(_) => 16,
+        }
+    }
+
+    /// whether the value can be a map key: data, but not functions nor generators
+    pub fn is_hashable(&self) -> bool {
+        self.all(|value| {
+            !matches!(
+                value,
+                Value::Function(_)
+                    | Value::AsyncFunction(_)
+                    | Value::Lambda(_)
+                    | Value::Memoized(_)
+                    | Value::Curried(_)
+                    | Value::Generator(_)
+            )
+        })
+    }
+
+    /// whether compare and sort can order the value: data ordered by its content,
+    /// unlike functions, generators and native values, which only have an address
+    pub fn is_ordered(&self) -> bool {
+        self.all(|value| {
+            !matches!(
+                value,
+                Value::Function(_)
+                    | Value::AsyncFunction(_)
+                    | Value::Lambda(_)
+                    | Value::Memoized(_)
+                    | Value::Curried(_)
+                    | Value::Generator(_)
+                    | Value::Native(_)
+            )
+        })
+    }
+
+    /// whether atom holds for the value and every value nested in it
+    fn all(&self, atom: impl Fn(&Value) -> bool) -> bool {
+        // from a worklist, as lists can be too long to recurse down
+        let mut pending = vec![self];
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::Pair(pair) => pending.extend([&pair.0, &pair.1]),
+                Value::Vector(items) => pending.extend(items),
+                Value::Map(entries) => pending.extend(entries.iter().flat_map(|(k, v)| [k, v])),
+                value if !atom(value) => return false,
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// local variables of a let* or function call,
+/// addressed by the (depth, index) the analyzer resolved them to
+pub struct Scope {
+    // names of the slots, including those of a let* not bound yet
+    names: Rc<[Symbol]>,
+    slots: Lock<Vec<Value>>,
+    outer: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(names: Rc<[Symbol]>, slots: Vec<Value>, outer: Option<Rc<Scope>>) -> Rc<Self> {
+        Rc::new(Self {
+            names,
+            slots: Lock::new(slots),
+            outer,
+        })
+    }
+
+    /// the variables bound in this scope, without those of the outer ones
+    pub fn bindings(&self) -> Vec<(Symbol, Value)> {
+        self.names
+            .iter()
+            .copied()
+            .zip(self.slots.borrow().iter().cloned())
+            .collect()
+    }
+
+    pub fn outer(&self) -> Option<&Rc<Scope>> {
+        self.outer.as_ref()
+    }
+
+    /// rebinds the innermost variable called name, false if there is none
+    pub fn set(&self, name: Symbol, value: Value) -> bool {
+        let mut slots = self.slots.borrow_mut();
+        match self.names[..slots.len()].iter().rposition(|&n| n == name) {
+            Some(index) => {
+                slots[index] = value;
+                true
+            }
+            None => {
+                drop(slots);
+                self.outer
+                    .as_ref()
+                    .is_some_and(|outer| outer.set(name, value))
+            }
+        }
+    }
+
+    /// value of the slot index of the scope depth levels up, if bound yet
+    pub fn get(&self, depth: usize, index: usize) -> Option<Value> {
+        match depth {
+            0 => self.slots.borrow().get(index).cloned(),
+            _ => self.outer.as_ref()?.get(depth - 1, index),
+        }
+    }
+
+    pub fn push(&self, value: Value) {
+        self.slots.borrow_mut().push(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.borrow().is_empty()
+    }
+
+    /// how many scopes this one is nested in
+    pub fn depth(&self) -> usize {
+        self.outer.as_ref().map_or(0, |outer| outer.depth() + 1)
+    }
+}
+
+/// global bindings, made by def! or the host
 pub struct Env {
-    data: RefCell<HashMap<String, Value>>,
+    data: Lock<SymbolMap<Value>>,
     outer: Option<Rc<Env>>,
+    // looked up in for what this env doesn't bind, see EnvBuilder::parent,
+    // its namespaces, ports and settings being this env's own
+    parent: Option<Rc<Env>>,
+    // without I/O builtins, nor a way to register them
+    sandboxed: bool,
+    // made by ns and require, only those of the outermost env are used
+    namespaces: Lock<Namespaces>,
+    // bound by defconst, which def! can't rebind
+    constants: Lock<HashSet<Symbol>>,
+    // ports printing goes to, only those of the outermost env are used
+    output: Rc<Output>,
+    // whether lambdas given too few arguments are curried, see Env::set_auto_curry
+    auto_curry: Lock<bool>,
 }
 
+// builtins reaching outside the interpreter: files, processes, network
+// left out of sandboxed envs
+const IO_BUILTINS: &[&[(&str, &str, Builtin)]] = &[
+    #[cfg(feature = "net")]
+    crate::net::BUILTINS,
+    #[cfg(feature = "io")]
+    output::BUILTINS,
+];
+
 impl Env {
     pub fn new(outer: Option<Rc<Env>>) -> Self {
         Self {
-            data: RefCell::new(HashMap::new()),
+            data: Lock::new(SymbolMap::default()),
             outer,
+            parent: None,
+            sandboxed: false,
+            namespaces: Lock::default(),
+            constants: Lock::default(),
+            output: Rc::default(),
+            auto_curry: Lock::default(),
         }
     }
-    pub fn get(&self, symbol: &str) -> Result<Value, String> {
-        let val = match symbol {
-            "nil" => Value::Nil,
-            "true" => Value::True,
-            "false" => Value::False,
-            "def!" => Value::Def,
-            "let*" => Value::Let,
-            "do" => Value::Do,
-            "if" => Value::If,
-            "quote" => Value::Quote,
-            "fn*" => Value::Fn,
-            _ => {
-                if let Ok(n) = symbol.parse::<i64>() {
-                    Value::Number(n)
-                } else {
-                    self.get_from_map(symbol)?
-                }
-            }
-        };
-        Ok(val)
+
+    /// (Env::builder().builtin("hash", f).value("config", v).parent(env).build())
+    pub fn builder() -> EnvBuilder {
+        EnvBuilder::default()
     }
 
-    fn get_from_map(&self, symbol: &str) -> Result<Value, String> {
-        if let Some(value) = self.data.borrow().get(symbol) {
+    /// the default env without I/O builtins, for running untrusted code
+    /// the code can't bind any, having no way to make host functions, but the host
+    /// still can: register_fn, register_mut and set bind whatever they are given,
+    /// only set_io refuses, for hosts binding I/O functions in any env they make
+    pub fn sandboxed() -> Self {
+        let output = Rc::default();
+        Self {
+            data: Lock::new(builtins(&output)),
+            outer: None,
+            parent: None,
+            sandboxed: true,
+            namespaces: Lock::default(),
+            constants: Lock::default(),
+            output,
+            auto_curry: Lock::default(),
+        }
+    }
+
+    /// whether this env, one it is nested in or its parent is sandboxed
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandboxed
+            || self
+                .outer
+                .iter()
+                .chain(&self.parent)
+                .any(|env| env.is_sandboxed())
+    }
+
+    pub fn get(&self, symbol: impl Into<Symbol>) -> Result<Value, FelispError> {
+        let symbol = symbol.into();
+        if let Some(value) = self.data.borrow().get(&symbol) {
             return Ok(value.clone());
         }
         if let Some(outer) = &self.outer {
-            return outer.get_from_map(symbol);
+            return outer.get(symbol);
+        }
+        if let Some(parent) = &self.parent
+            && let Ok(value) = parent.get(symbol)
+        {
+            return Ok(value);
+        }
+        namespace::lookup(self, symbol)
+            .ok_or_else(|| FelispError::UnknownSymbol(symbol.to_string()))
+    }
+
+    /// value of symbol in this env, without looking in the ones it is nested in
+    pub fn get_own(&self, symbol: impl Into<Symbol>) -> Option<Value> {
+        self.data.borrow().get(&symbol.into()).cloned()
+    }
+
+    /// the outermost env this one is nested in, or itself
+    pub fn root(self: &Rc<Self>) -> Rc<Env> {
+        match &self.outer {
+            Some(outer) => outer.root(),
+            None => self.clone(),
+        }
+    }
+
+    /// namespaces of the outermost env, see src/namespace.rs
+    pub(crate) fn namespaces(&self) -> &Lock<Namespaces> {
+        match &self.outer {
+            Some(outer) => outer.namespaces(),
+            None => &self.namespaces,
+        }
+    }
+
+    /// ports of the outermost env, see src/output.rs
+    pub(crate) fn output(&self) -> &Rc<Output> {
+        match &self.outer {
+            Some(outer) => outer.output(),
+            None => &self.output,
+        }
+    }
+
+    /// writes text where *out* or *err* goes, as println and eprintln do,
+    /// for host functions printing like the code calling them
+    pub fn print(&self, stream: Stream, text: &str) -> Result<(), FelispError> {
+        self.output().write(stream, text)
+    }
+
+    /// makes lambdas of the outermost env given fewer arguments than they require
+    /// return a function taking the rest, as (curry f) does, instead of failing
+    pub fn set_auto_curry(&self, on: bool) {
+        match &self.outer {
+            Some(outer) => outer.set_auto_curry(on),
+            None => *self.auto_curry.borrow_mut() = on,
+        }
+    }
+
+    pub(crate) fn auto_curries(&self) -> bool {
+        match &self.outer {
+            Some(outer) => outer.auto_curries(),
+            None => *self.auto_curry.borrow(),
+        }
+    }
+
+    /// symbols bound in this env or the ones it is nested in
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self.data.borrow().keys().copied().collect();
+        for env in self.outer.iter().chain(&self.parent) {
+            symbols.extend(env.symbols());
         }
-        Err(format!("unknown symbol '{}'", symbol))
+        symbols
     }
 
-    pub fn set(&self, symbol: &str, value: Value) {
-        self.data.borrow_mut().insert(symbol.to_string(), value);
+    pub fn set(&self, symbol: impl Into<Symbol>, value: Value) {
+        self.data.borrow_mut().insert(symbol.into(), value);
+    }
+
+    /// makes f read the tagged literals #tag form of the code evaluated from now on,
+    /// called with the form unevaluated, as set-reader-macro! does
+    /// (env.set_reader_macro("date", parse_date))
+    pub fn set_reader_macro(&self, tag: &str, f: Value) {
+        self.set(reader_macro(tag), f);
+    }
+
+    /// binds symbol as def! does, or as a constant for defconst,
+    /// unless it is bound to a constant, which only the host can rebind with set
+    /// defconst may bind a constant again to the same value, as when reloading a file
+    pub fn define(
+        &self,
+        symbol: impl Into<Symbol>,
+        value: Value,
+        constant: bool,
+    ) -> Result<(), FelispError> {
+        let symbol = symbol.into();
+        if self.is_constant(symbol)
+            && !(constant && self.get_own(symbol).is_some_and(|old| old == value))
+        {
+            return Err(FelispError::Constant(symbol.to_string()));
+        }
+        if constant {
+            self.constants.borrow_mut().insert(symbol);
+        }
+        self.set(symbol, value);
+        Ok(())
+    }
+
+    /// unbinds symbol in this env, returning the value it had, if any
+    /// bindings of defconst can't be removed
+    pub fn remove(&self, symbol: impl Into<Symbol>) -> Result<Option<Value>, FelispError> {
+        let symbol = symbol.into();
+        if self.is_constant(symbol) {
+            return Err(FelispError::Constant(symbol.to_string()));
+        }
+        Ok(self.data.borrow_mut().remove(&symbol))
+    }
+
+    /// whether symbol is bound by defconst in this env
+    pub fn is_constant(&self, symbol: impl Into<Symbol>) -> bool {
+        self.constants.borrow().contains(&symbol.into())
+    }
+
+    /// the bindings of this env as they are now, for diff to compare against
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(self.data.borrow().clone())
+    }
+
+    /// bindings of this env made or rebound since snapshot was taken, sorted by name,
+    /// not those of the envs it is nested in
+    pub fn diff(&self, snapshot: &Snapshot) -> Vec<(Symbol, Value)> {
+        let mut bindings: Vec<(Symbol, Value)> = self
+            .data
+            .borrow()
+            .iter()
+            .filter(|&(symbol, value)| snapshot.0.get(symbol) != Some(value))
+            .map(|(symbol, value)| (*symbol, value.clone()))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+        bindings
+    }
+
+    /// binds a host function keeping state between calls, e.g. a counter
+    /// or a connection it owns, which evaluations then take turns using
+    pub fn register_mut(
+        &self,
+        symbol: impl Into<Symbol>,
+        f: impl FnMut(&[Value]) -> Result<Value, FelispError> + MaybeSend + 'static,
+    ) {
+        let symbol = symbol.into();
+        self.set(symbol, stateful(symbol, f));
+    }
+
+    /// binds a rust function, converting its arguments and result
+    /// (env.register_fn("add", |a: i64, b: i64| a + b))
+    pub fn register_fn<Args>(&self, symbol: impl Into<Symbol>, f: impl IntoNativeFn<Args>) {
+        let symbol = symbol.into();
+        self.set(symbol, f.into_native_fn(symbol).into());
+    }
+
+    /// binds a host function doing I/O, which sandboxed envs refuse
+    pub fn set_io(&self, symbol: impl Into<Symbol>, value: Value) -> Result<(), FelispError> {
+        let symbol = symbol.into();
+        if self.is_sandboxed() {
+            return Err(FelispError::Sandboxed(symbol.to_string()));
+        }
+        self.set(symbol, value);
+        Ok(())
     }
 }
 
+/// the bindings of an env at some point, see Env::snapshot
+/// the default one is empty, so that diffing against it gives every binding
+#[derive(Clone, Default)]
+pub struct Snapshot(SymbolMap<Value>);
+
+/// bindings of an env being put together, see Env::builder
+#[derive(Default)]
+pub struct EnvBuilder {
+    data: SymbolMap<Value>,
+    parent: Option<Rc<Env>>,
+    // that the printing builtins of defaults write to
+    output: Rc<Output>,
+}
+
+impl EnvBuilder {
+    /// binds the builtins of the default env, including I/O ones
+    pub fn defaults(mut self) -> Self {
+        let env = Env::default();
+        self.data.extend(env.data.borrow().clone());
+        self.output = env.output;
+        self
+    }
+
+    /// binds a host function taking any number of arguments
+    pub fn builtin(self, symbol: impl Into<Symbol>, f: impl HostFn) -> Self {
+        let symbol = symbol.into();
+        self.value(symbol, NativeFn::new(symbol, "& args", f).into())
+    }
+
+    /// binds a stateful host function, see Env::register_mut
+    pub fn builtin_mut(
+        self,
+        symbol: impl Into<Symbol>,
+        f: impl FnMut(&[Value]) -> Result<Value, FelispError> + MaybeSend + 'static,
+    ) -> Self {
+        let symbol = symbol.into();
+        self.value(symbol, stateful(symbol, f))
+    }
+
+    /// binds a rust function, see Env::register_fn
+    pub fn register_fn<Args>(self, symbol: impl Into<Symbol>, f: impl IntoNativeFn<Args>) -> Self {
+        let symbol = symbol.into();
+        self.value(symbol, f.into_native_fn(symbol).into())
+    }
+
+    pub fn value(mut self, symbol: impl Into<Symbol>, value: Value) -> Self {
+        self.data.insert(symbol.into(), value);
+        self
+    }
+
+    /// env to look symbols up in when they are not bound in this one
+    /// which def! doesn't bind in, nor ns make namespaces in
+    pub fn parent(mut self, env: Rc<Env>) -> Self {
+        self.parent = Some(env);
+        self
+    }
+
+    pub fn build(self) -> Env {
+        Env {
+            data: Lock::new(self.data),
+            outer: None,
+            parent: self.parent,
+            sandboxed: false,
+            namespaces: Lock::default(),
+            constants: Lock::default(),
+            output: self.output,
+            auto_curry: Lock::default(),
+        }
+    }
+}
+
+/// a function value named name calling f, which only one call can use at a time
+fn stateful(
+    name: Symbol,
+    f: impl FnMut(&[Value]) -> Result<Value, FelispError> + MaybeSend + 'static,
+) -> Value {
+    let f = Lock::new(f);
+    NativeFn::new(name, "& args", move |args: &[Value]| (f.borrow_mut())(args)).into()
+}
+
 impl Default for Env {
     fn default() -> Self {
+        let output = Rc::default();
+        let mut data = builtins(&output);
+        for &builtins in IO_BUILTINS {
+            insert_builtins(&mut data, builtins);
+        }
         Self {
-            data: RefCell::new(HashMap::from([
-                ("+".to_string(), Value::Function(Rc::new(add))),
-                ("-".to_string(), Value::Function(Rc::new(sub))),
-                ("*".to_string(), Value::Function(Rc::new(mul))),
-                ("<=".to_string(), Value::Function(Rc::new(leq))),
-            ])),
+            data: Lock::new(data),
             outer: None,
+            parent: None,
+            sandboxed: false,
+            namespaces: Lock::default(),
+            constants: Lock::default(),
+            output,
+            auto_curry: Lock::default(),
         }
     }
 }
 
-fn add(args: &[Value]) -> Result<Value, String> {
-    let mut total = 0;
-    for arg in args {
-        if let Value::Number(n) = arg {
-            total += n;
-        } else {
-            return Err(format!("invalid type expected Number but got '{}'", arg));
-        }
+// builtins of the optional features, as safe to give to any code as the others
+const FEATURE_BUILTINS: &[&[(&str, &str, Builtin)]] = &[
+    #[cfg(feature = "time")]
+    crate::time::BUILTINS,
+    #[cfg(feature = "digest")]
+    crate::digest::BUILTINS,
+    #[cfg(feature = "encoding")]
+    crate::encoding::BUILTINS,
+    #[cfg(any(feature = "toml", feature = "yaml", feature = "csv"))]
+    crate::formats::BUILTINS,
+];
+
+// names, parameters and functions of the builtins of every env
+const CORE_BUILTINS: &[(&str, &str, Builtin)] = &[
+    ("+", "& numbers", numeric::add),
+    ("-", "number & numbers", numeric::sub),
+    ("*", "& numbers", numeric::mul),
+    ("/", "number & numbers", numeric::div),
+    ("<", "number & numbers", numeric::lt),
+    ("<=", "number & numbers", numeric::leq),
+    (">", "number & numbers", numeric::gt),
+    (">=", "number & numbers", numeric::geq),
+    ("=", "number & numbers", numeric::eq),
+    ("==", "number & numbers", numeric::eq),
+    ("quot", "a b", numeric::quot),
+    ("parse-int", "s [base]", numbers::parse_int),
+    ("parse-float", "s", numbers::parse_float),
+    ("number->string", "n [base]", numbers::number_to_string),
+    ("nil?", "x", is_nil),
+    ("type-of", "x", type_of),
+    ("str", "& values", str),
+    ("doc", "f", doc),
+    ("memoize", "f", memoize),
+    ("curry", "f [n]", curry),
+    ("throw", "value", throw),
+    ("breakpoint", "", breakpoint),
+    ("vector", "& items", collections::vector),
+    ("hash-map", "& keys-and-values", collections::hash_map),
+    ("get", "coll key [default]", collections::get),
+    ("nth", "coll n [default]", collections::nth),
+    ("vec", "coll", collections::vec),
+    (
+        "assoc",
+        "coll key value & keys-and-values",
+        collections::assoc,
+    ),
+    ("dissoc", "map & keys", collections::dissoc),
+    ("conj", "coll & items", collections::conj),
+    ("count", "coll", collections::count),
+    ("cons", "car cdr", collections::cons),
+    ("list", "& items", collections::list),
+    ("list*", "& items tail", collections::list_star),
+    ("car", "pair", collections::car),
+    ("cdr", "pair", collections::cdr),
+    ("sort", "coll", collections::sort),
+    ("compare", "a b", collections::compare),
+    ("partition", "n coll", collections::partition),
+    ("zip", "& colls", collections::zip),
+    ("frequencies", "coll", collections::frequencies),
+    ("log-error", "& values", logging::log_error),
+    ("log-warn", "& values", logging::log_warn),
+    ("log-info", "& values", logging::log_info),
+    ("log-debug", "& values", logging::log_debug),
+];
+
+/// builtins safe to give to any code
+fn builtins(output: &Rc<Output>) -> SymbolMap<Value> {
+    let mut data = SymbolMap::default();
+    for (name, f) in output::builtins(output) {
+        data.insert(name.into(), f);
+    }
+    insert_builtins(&mut data, CORE_BUILTINS);
+    let apply = NativeFn {
+        builtin: true,
+        spreads: true,
+        ..NativeFn::new("apply", "f & args", apply)
+    };
+    data.insert("apply".into(), apply.into());
+    insert_builtins(&mut data, protocol::BUILTINS);
+    insert_builtins(&mut data, resource::BUILTINS);
+    for &builtins in FEATURE_BUILTINS {
+        insert_builtins(&mut data, builtins);
     }
-    Ok(Value::Number(total))
+    data
 }
 
-fn sub(args: &[Value]) -> Result<Value, String> {
-    if let Value::Number(x) = args[0] {
-        if let Value::Number(y) = args[1] {
-            return Ok(Value::Number(x - y));
-        }
+fn insert_builtins(data: &mut SymbolMap<Value>, builtins: &[(&str, &str, Builtin)]) {
+    for &(name, params, f) in builtins {
+        let f = NativeFn {
+            builtin: true,
+            ..NativeFn::new(name, params, f)
+        };
+        data.insert(name.into(), f.into());
+    }
+}
+
+/// f called with args, the items of the last one spread, which the evaluator does
+/// itself, so host code calling apply can only apply other builtins
+/// (apply + 1 [2 3]) -> 6
+fn apply(args: &[Value]) -> Result<Value, FelispError> {
+    let (f, args) = collections::spread(args)?;
+    match &f {
+        Value::Function(f) => f.call(&args),
+        f => Err(FelispError::bad_arg(0, "builtin function", f)),
+    }
+}
+
+/// does nothing, but debuggers attached with Interpreter::on_step
+/// redefine it to pause at the form evaluated after it
+/// (breakpoint) -> nil
+fn breakpoint(_: &[Value]) -> Result<Value, FelispError> {
+    Ok(Value::Nil)
+}
+
+/// the type of its argument, as a keyword, see Value::type_keyword
+/// (type-of [1]) -> :vector
+fn type_of(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "type-of requires 1 argument but given {}",
+            args.len()
+        )));
     }
-    Err(format!(
-        "invalid type expected Numbers but got '{}, {}'",
-        args[0], args[1]
-    ))
+    Ok(Value::Keyword(Symbol::intern(args[0].type_keyword())))
 }
 
-fn mul(args: &[Value]) -> Result<Value, String> {
-    let mut total = 1;
-    for arg in args {
-        if let Value::Number(n) = arg {
-            total *= n;
-        } else {
-            return Err(format!("invalid type expected Number but got '{}'", arg));
+/// its arguments one after the other, printed as println does, strings as they are
+/// (str "x is " 1 nil) -> "x is 1nil"
+fn str(args: &[Value]) -> Result<Value, FelispError> {
+    let s: String = args
+        .iter()
+        .map(|arg| match arg {
+            Value::String(s) => s.to_string(),
+            arg => arg.to_string(),
+        })
+        .collect();
+    Ok(Value::from(s))
+}
+
+/// how calls of a builtin are written, or the first line of the fn* of a function,
+/// nil for other values
+/// (doc get) -> "(get coll key [default])"
+fn doc(args: &[Value]) -> Result<Value, FelispError> {
+    Ok(match &args[0] {
+        Value::Function(f) => Value::from(f.signature()),
+        Value::Lambda(lambda) => {
+            let source = PrettyPrinter::default().print_expr(&lambda.def.source);
+            Value::from(source.lines().next().unwrap_or_default())
         }
+        _ => Value::Nil,
+    })
+}
+
+/// whether its argument is nil, rather than false or empty
+/// (nil? ()) -> false
+fn is_nil(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "nil? requires 1 argument but given {}",
+            args.len()
+        )));
     }
-    Ok(Value::Number(total))
+    Ok(if let Value::Nil = args[0] {
+        Value::True
+    } else {
+        Value::False
+    })
 }
 
-fn leq(args: &[Value]) -> Result<Value, String> {
-    if let Value::Number(x) = args[0] {
-        if let Value::Number(y) = args[1] {
-            return Ok(if x <= y { Value::True } else { Value::False });
+/// f wrapped to return what it did the first time it was called with the same arguments,
+/// without calling it again, which suits functions without side effects
+/// (def! fib (memoize (fn* (n) (if (<= n 1) n (+ (fib (- n 1)) (fib (- n 2))))))) -> <fun>
+fn memoize(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "memoize requires 1 argument but given {}",
+            args.len()
+        )));
+    }
+    match &args[0] {
+        Value::Function(_) | Value::AsyncFunction(_) | Value::Lambda(_) => {
+            Ok(Value::Memoized(Rc::new(Memo::new(args[0].clone()))))
         }
+        Value::Memoized(_) => Ok(args[0].clone()),
+        f => Err(FelispError::bad_arg(0, "Function", f)),
+    }
+}
+
+/// f taking its arguments a few at a time: called with fewer than n, by default the
+/// least f takes, it returns a function taking the rest
+/// (((curry (fn* (a b c) (+ a b c))) 1) 2 3) -> 6, ((curry + 2) 1) -> <fun>
+fn curry(args: &[Value]) -> Result<Value, FelispError> {
+    let arity = match (&args[0], args.get(1)) {
+        (_, Some(Value::Number(n))) if *n >= 0 => *n as usize,
+        (_, Some(n)) => return Err(FelispError::bad_arg(1, "non-negative Number", n)),
+        (f, None) => min_arity(f).ok_or_else(|| FelispError::bad_arg(0, "Function", f))?,
+    };
+    match &args[0] {
+        Value::Function(_)
+        | Value::AsyncFunction(_)
+        | Value::Lambda(_)
+        | Value::Memoized(_)
+        | Value::Curried(_)
+        | Value::Keyword(_)
+        | Value::Map(_) => Ok(Value::Curried(Rc::new(Curried::new(
+            args[0].clone(),
+            arity,
+        )))),
+        f => Err(FelispError::bad_arg(0, "Function", f)),
+    }
+}
+
+/// the fewest arguments f can be called with, None when not known, as for
+/// async functions
+fn min_arity(f: &Value) -> Option<usize> {
+    match f {
+        Value::Function(f) => Some(f.min_arity),
+        Value::Lambda(lambda) => Some(lambda.def.min_arity()),
+        Value::Memoized(memo) => min_arity(memo.f()),
+        Value::Keyword(_) | Value::Map(_) => Some(1),
+        _ => None,
+    }
+}
+
+/// raises its argument as an error
+/// (throw 1) -> uncaught exception: 1
+fn throw(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "throw requires 1 argument but given {}",
+            args.len()
+        )));
     }
-    Err(format!(
-        "invalid type expected Numbers but got '{}, {}'",
-        args[0], args[1]
-    ))
+    Err(FelispError::Thrown(args[0].clone()))
 }
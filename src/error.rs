@@ -0,0 +1,446 @@
+use std::{convert::Infallible, error::Error, fmt, io, time::Duration};
+
+use im::HashMap;
+
+use crate::{
+    ast::Expr,
+    env::Value,
+    printer::{self, PrettyPrinter},
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::Rc,
+};
+
+/// error of the host's own, raised by a host function
+#[cfg(not(feature = "sync"))]
+pub type HostError = Rc<dyn Error>;
+#[cfg(feature = "sync")]
+pub type HostError = Rc<dyn Error + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub enum FelispError {
+    // the reader could not make sense of the source
+    ParseError(String),
+    // symbol is not bound in any enclosing env
+    UnknownSymbol(String),
+    // wrong number of arguments given to a form or function
+    ArityError(String),
+    // argument of the wrong type, arg is its 0-based position if known,
+    // function the builtin it was given to, once it returned the error
+    TypeError {
+        expected: String,
+        got: String,
+        arg: Option<usize>,
+        function: Option<Symbol>,
+    },
+    // malformed special form, e.g. (let* x 1)
+    SyntaxError(String),
+    // value in call position is not callable
+    NotCallable(String),
+    // async host function called by a synchronous evaluation
+    AsyncCall,
+    // I/O builtin registered in a sandboxed env
+    Sandboxed(String),
+    // def! of a symbol bound by defconst
+    Constant(String),
+    // evaluation nested deeper than the evaluator allows
+    DepthExceeded(usize),
+    // evaluation took more steps than allowed
+    StepsExceeded(u64),
+    // evaluation ran for longer than allowed
+    Timeout(Duration),
+    // evaluation aborted by setting its interrupt flag, e.g. on Ctrl-C
+    Interrupted,
+    // user value raised with (throw v)
+    Thrown(Value),
+    // (is form) on a form that evaluated to nil or false
+    AssertionFailed(String),
+    // (k v) of the continuation k of a call/ec, which returns v from it
+    // unless it already returned, leaving the error to be reported
+    Escape(u64, Value),
+    // module that require can't find or load, e.g. because it requires itself
+    ModuleError(String),
+    // generator called while running, or yield outside of one
+    GeneratorError(String),
+    // retry outside of a handler of with-handler
+    HandlerError(String),
+    // error of a host function, e.g. a failed database query
+    Host(HostError),
+    // error annotated with the source it was raised from
+    Located(Box<FelispError>, Span),
+    // error annotated with the form it was raised by, e.g. the call a builtin raised it in,
+    // and the local variables visible there, innermost first
+    Raised(Box<FelispError>, Span, Rc<[Symbol]>),
+    // error annotated with the calls it unwound through, innermost first
+    Traced(Box<FelispError>, Vec<Frame>),
+}
+
+/// a function call the evaluator was in when an error was raised
+#[derive(Debug, Clone)]
+pub struct Frame {
+    // named after the expression in call position
+    pub name: Symbol,
+    pub span: Span,
+}
+
+// deep recursions only show their outermost and innermost frames
+const MAX_FRAMES: usize = 16;
+
+/// the kinds of errors with-handler can handle, see FelispError::keyword
+pub const KEYWORDS: &[&str] = &[
+    "parse-error",
+    "unknown-symbol",
+    "arity-error",
+    "type-error",
+    "syntax-error",
+    "not-callable",
+    "async-call",
+    "sandboxed",
+    "constant",
+    "thrown",
+    "assertion-failed",
+    "module-error",
+    "generator-error",
+    "handler-error",
+    "depth-exceeded",
+    "io-error",
+    "host-error",
+];
+
+impl FelispError {
+    /// wraps an error of the host, as its source
+    #[cfg(not(feature = "sync"))]
+    pub fn host(err: impl Error + 'static) -> Self {
+        FelispError::Host(Rc::new(err))
+    }
+
+    #[cfg(feature = "sync")]
+    pub fn host(err: impl Error + Send + Sync + 'static) -> Self {
+        FelispError::Host(Rc::new(err))
+    }
+
+    /// type error about the arg-th argument of a function call
+    pub fn bad_arg(arg: usize, expected: &str, got: &Value) -> Self {
+        FelispError::TypeError {
+            expected: expected.to_string(),
+            got: printer::abbreviate(got),
+            arg: Some(arg),
+            function: None,
+        }
+    }
+
+    /// names the builtin function in a type error it raised, see NativeFn::call
+    pub fn raised_by(self, function: Symbol) -> Self {
+        match self {
+            FelispError::TypeError {
+                expected,
+                got,
+                arg,
+                function: None,
+            } => FelispError::TypeError {
+                expected,
+                got,
+                arg,
+                function: Some(function),
+            },
+            err => err,
+        }
+    }
+
+    /// attaches a span to the error, unless it already carries a more precise one
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            FelispError::Located(_, _) => self,
+            FelispError::Traced(err, frames) => FelispError::Traced(Box::new(err.at(span)), frames),
+            FelispError::Raised(err, form, names) => {
+                FelispError::Raised(Box::new(err.at(span)), form, names)
+            }
+            _ => FelispError::Located(Box::new(self), span),
+        }
+    }
+
+    /// records that the error unwound through the given call
+    pub fn traced(self, frame: Frame) -> Self {
+        match self {
+            FelispError::Traced(err, mut frames) => {
+                frames.push(frame);
+                FelispError::Traced(err, frames)
+            }
+            _ => FelispError::Traced(Box::new(self), vec![frame]),
+        }
+    }
+
+    /// records the form the error was raised by and the local variables visible there,
+    /// unless it already carries those of a form it was raised deeper in
+    pub fn raised(self, form: Span, names: impl FnOnce() -> Rc<[Symbol]>) -> Self {
+        match self {
+            _ if self.form().is_some() => self,
+            // kept outermost, where backtrace looks for the frames
+            FelispError::Traced(err, frames) => {
+                FelispError::Traced(Box::new(err.raised(form, names)), frames)
+            }
+            _ => FelispError::Raised(Box::new(self), form, names()),
+        }
+    }
+
+    /// the form the error was raised by and the local variables visible there, if recorded
+    pub fn form(&self) -> Option<(&Span, &[Symbol])> {
+        match self {
+            FelispError::Raised(_, form, names) => Some((form, names)),
+            FelispError::Located(err, _) | FelispError::Traced(err, _) => err.form(),
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            FelispError::Located(_, span) => Some(span),
+            FelispError::Traced(err, _) | FelispError::Raised(err, ..) => err.span(),
+            _ => None,
+        }
+    }
+
+    /// the error itself, without the location, form and backtrace wrapped around it
+    /// e.g. to match on its variant
+    pub fn cause(&self) -> &FelispError {
+        match self {
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => FelispError::cause(err),
+            err => err,
+        }
+    }
+
+    /// what kind of error it is, which messages start with when they name it
+    /// e.g. "type error" for "type error: expected Number but got 'nil'"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FelispError::ParseError(_) => "parse error",
+            FelispError::UnknownSymbol(_) => "unknown symbol",
+            FelispError::ArityError(_) => "arity error",
+            FelispError::TypeError { .. } => "type error",
+            FelispError::SyntaxError(_) => "syntax error",
+            FelispError::NotCallable(_) => "not callable",
+            FelispError::AsyncCall => "async call",
+            FelispError::Sandboxed(_) => "sandboxed",
+            FelispError::Constant(_) => "constant",
+            FelispError::DepthExceeded(_) => "max depth exceeded",
+            FelispError::StepsExceeded(_) => "max steps exceeded",
+            FelispError::Timeout(_) => "timed out",
+            FelispError::Interrupted => "interrupted",
+            FelispError::Thrown(_) => "uncaught exception",
+            FelispError::AssertionFailed(_) => "assertion failed",
+            FelispError::ModuleError(_) => "module error",
+            FelispError::Escape(..) => "escape",
+            FelispError::GeneratorError(_) => "generator error",
+            FelispError::HandlerError(_) => "handler error",
+            FelispError::Host(_) => "host error",
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => err.kind(),
+        }
+    }
+
+    /// the keyword with-handler names the kind of the error by: its kind with dashes,
+    /// io-error for I/O errors of the host, thrown for values given to throw and
+    /// depth-exceeded for runaway recursion, which handlers run once it has unwound,
+    /// or none for those of the step and time limits, interrupts and call/ec,
+    /// which handlers can't stop
+    /// see KEYWORDS
+    pub fn keyword(&self) -> Option<&'static str> {
+        let keyword = match self {
+            FelispError::ParseError(_) => "parse-error",
+            FelispError::UnknownSymbol(_) => "unknown-symbol",
+            FelispError::ArityError(_) => "arity-error",
+            FelispError::TypeError { .. } => "type-error",
+            FelispError::SyntaxError(_) => "syntax-error",
+            FelispError::NotCallable(_) => "not-callable",
+            FelispError::AsyncCall => "async-call",
+            FelispError::Sandboxed(_) => "sandboxed",
+            FelispError::Constant(_) => "constant",
+            FelispError::Thrown(_) => "thrown",
+            FelispError::AssertionFailed(_) => "assertion-failed",
+            FelispError::ModuleError(_) => "module-error",
+            FelispError::GeneratorError(_) => "generator-error",
+            FelispError::HandlerError(_) => "handler-error",
+            FelispError::DepthExceeded(_) => "depth-exceeded",
+            FelispError::Host(err) if err.downcast_ref::<io::Error>().is_some() => "io-error",
+            FelispError::Host(_) => "host-error",
+            FelispError::StepsExceeded(_)
+            | FelispError::Timeout(_)
+            | FelispError::Interrupted
+            | FelispError::Escape(..) => return None,
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => return err.keyword(),
+        };
+        Some(keyword)
+    }
+
+    /// the error as the handlers of with-handler are given it, a map of
+    /// its :kind keyword and :message, the :value given to throw if it was,
+    /// the :form it was raised from, pretty-printed, and its :location,
+    /// and the names of the :locals visible there, innermost first
+    /// {:kind :type-error :message "type error: expected Number but got 'nil' (arg 2)"
+    ///  :form "(+ n nil)" :location "<repl>:1:12" :locals [n]}
+    pub fn value(&self) -> Value {
+        let field = |name| Value::Keyword(Symbol::intern(name));
+        let mut fields = HashMap::new();
+        if let Some(keyword) = self.keyword() {
+            fields.insert(field("kind"), field(keyword));
+        }
+        fields.insert(field("message"), Value::from(self.to_string()));
+        if let Some(value) = self.thrown() {
+            fields.insert(field("value"), value.clone());
+        }
+        if let Some((span, names)) = self.form() {
+            let form = match Expr::parse(span.text().to_string()) {
+                Ok(expr) => PrettyPrinter::default().print_expr(&expr),
+                Err(_) => span.text().to_string(),
+            };
+            let (line, col) = span.source.line_col(span.start);
+            let location = format!("{}:{}:{}", span.source.name, line, col);
+            fields.insert(field("form"), Value::from(form));
+            fields.insert(field("location"), Value::from(location));
+            let source = Source::new("<locals>", String::new());
+            let names = names
+                .iter()
+                .map(|&name| Value::Quoted(Expr::Atom(name, Span::new(&source, 0, 0))));
+            fields.insert(field("locals"), Value::Vector(names.collect()));
+        }
+        Value::Map(fields)
+    }
+
+    /// the value given to throw, if that's what the error is
+    pub fn thrown(&self) -> Option<&Value> {
+        match self {
+            FelispError::Thrown(value) => Some(value),
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => err.thrown(),
+            _ => None,
+        }
+    }
+
+    /// the call/ec and value an escape continuation was called with, if it was
+    pub fn escaped(&self) -> Option<(u64, &Value)> {
+        match self {
+            FelispError::Escape(id, value) => Some((*id, value)),
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => err.escaped(),
+            _ => None,
+        }
+    }
+
+    pub fn backtrace(&self) -> &[Frame] {
+        match self {
+            FelispError::Traced(_, frames) => frames,
+            _ => &[],
+        }
+    }
+
+    /// the error message followed by a snippet of the offending source, if known
+    /// and the backtrace of calls it unwound through
+    pub fn report(&self) -> String {
+        let mut out = self.to_string();
+        if let Some(span) = self.span() {
+            out += &format!("\n{}", span.snippet());
+        }
+        let frames = self.backtrace();
+        if !frames.is_empty() {
+            out += "\nbacktrace:";
+        }
+        for (i, frame) in frames.iter().enumerate() {
+            if frames.len() > MAX_FRAMES && i == MAX_FRAMES / 2 {
+                out += &format!("\n  ... {} more", frames.len() - MAX_FRAMES);
+            }
+            if frames.len() <= MAX_FRAMES
+                || i < MAX_FRAMES / 2
+                || i >= frames.len() - MAX_FRAMES / 2
+            {
+                let (line, col) = frame.span.source.line_col(frame.span.start);
+                out += &format!(
+                    "\n  at {} ({}:{}:{})",
+                    frame.name, frame.span.source.name, line, col
+                );
+            }
+        }
+        out
+    }
+}
+
+impl From<Infallible> for FelispError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+impl fmt::Display for FelispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FelispError::ParseError(msg) => write!(f, "parse error: {}", msg),
+            FelispError::UnknownSymbol(symbol) => write!(f, "unknown symbol '{}'", symbol),
+            FelispError::ArityError(msg) => write!(f, "arity error: {}", msg),
+            FelispError::TypeError {
+                expected,
+                got,
+                arg,
+                function,
+            } => {
+                write!(f, "type error: ")?;
+                if let Some(function) = function {
+                    write!(f, "{}: ", function)?;
+                }
+                write!(f, "expected {} but got '{}'", expected, got)?;
+                match arg {
+                    Some(arg) => write!(f, " (arg {})", arg + 1),
+                    None => Ok(()),
+                }
+            }
+            FelispError::SyntaxError(msg) => write!(f, "syntax error: {}", msg),
+            FelispError::NotCallable(what) => write!(f, "'{}' is not callable", what),
+            FelispError::Constant(name) => {
+                write!(f, "'{}' is a constant, which can't be bound again", name)
+            }
+            FelispError::Sandboxed(name) => {
+                write!(f, "'{}' does I/O, which is not allowed in a sandbox", name)
+            }
+            FelispError::AsyncCall => {
+                write!(f, "async functions can only be called by eval_async")
+            }
+            FelispError::DepthExceeded(depth) => write!(f, "max depth exceeded ({})", depth),
+            FelispError::StepsExceeded(steps) => write!(f, "max steps exceeded ({})", steps),
+            FelispError::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+            FelispError::Interrupted => write!(f, "interrupted"),
+            FelispError::Thrown(value) => {
+                write!(f, "uncaught exception: {}", printer::abbreviate(value))
+            }
+            FelispError::AssertionFailed(form) => write!(f, "assertion failed: {}", form),
+            FelispError::ModuleError(msg) => write!(f, "module error: {}", msg),
+            FelispError::Escape(_, value) => write!(
+                f,
+                "escape: continuation called with {} after its call/ec returned",
+                printer::abbreviate(value)
+            ),
+            FelispError::GeneratorError(msg) => write!(f, "generator error: {}", msg),
+            FelispError::HandlerError(msg) => write!(f, "handler error: {}", msg),
+            FelispError::Host(err) => write!(f, "{}", err),
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for FelispError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FelispError::Host(err) => Some(&**err),
+            FelispError::Located(err, _)
+            | FelispError::Traced(err, _)
+            | FelispError::Raised(err, ..) => err.source(),
+            _ => None,
+        }
+    }
+}
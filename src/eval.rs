@@ -0,0 +1,1363 @@
+use std::{
+    array,
+    collections::HashMap,
+    mem,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::{Expr, Special},
+    bindings::{Bindings, Pattern},
+    collections,
+    env::{Env, Lambda, NativeFn, Scope, Value, ValueFuture},
+    error::{FelispError, Frame},
+    hooks::{FrameEvent, Hooks, StepContext, Trace},
+    ir::{CaseTable, Definition, Node, NodeKind, analyze},
+    namespace,
+    output::{Output, Port, Stream},
+    printer,
+    span::Span,
+    symbol::Symbol,
+    sync::{Lock, Rc},
+};
+
+/// default bound on the evaluator's explicit stack, so runaway recursion
+/// fails with an error instead of exhausting memory
+pub const MAX_DEPTH: usize = 100_000;
+
+// the clock is only read every so many steps
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+// most arguments of the builtin calls call_inline makes
+const INLINE_ARGS: usize = 4;
+
+/// budget a single evaluation must stay within
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    // set from another thread or a signal handler to abort the evaluation,
+    // which clears it
+    pub interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: MAX_DEPTH,
+            max_steps: None,
+            timeout: None,
+            interrupt: None,
+        }
+    }
+}
+
+/// what is left of the limits while evaluating
+struct Budget {
+    limits: Limits,
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    fn new(limits: &Limits) -> Self {
+        Self {
+            limits: limits.clone(),
+            steps: 0,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// accounts for one more step of the evaluator at the given depth
+    fn spend(&mut self, depth: usize) -> Result<(), FelispError> {
+        self.steps += 1;
+        if depth > self.limits.max_depth {
+            return Err(FelispError::DepthExceeded(self.limits.max_depth));
+        }
+        if let Some(max_steps) = self.limits.max_steps
+            && self.steps > max_steps
+        {
+            return Err(FelispError::StepsExceeded(max_steps));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout)
+            && self.steps.is_multiple_of(STEPS_PER_CLOCK_CHECK)
+            && Instant::now() >= deadline
+        {
+            return Err(FelispError::Timeout(timeout));
+        }
+        if let Some(interrupt) = &self.limits.interrupt
+            && interrupt.load(Ordering::Relaxed)
+        {
+            interrupt.store(false, Ordering::Relaxed);
+            return Err(FelispError::Interrupted);
+        }
+        Ok(())
+    }
+}
+
+/// where the node being evaluated looks up its variables
+#[derive(Clone)]
+struct Context {
+    env: Rc<Env>,
+    scope: Option<Rc<Scope>>,
+}
+
+/// the next thing for the evaluator to do
+enum Step {
+    Eval(Node, Context),
+    Return(Value),
+    // result of the async function called by exprs, whose frame is on the stack
+    Await(ValueFuture, Rc<[Node]>),
+    // generator called, whose frame is on the stack
+    Resume(Rc<Generator>),
+    // (retry) in a handler of with-handler
+    Retry(Span),
+}
+
+/// body of a (generator ...), evaluated on the machine's stack a yield at a time:
+/// yielding moves the continuations above the call resuming it into the generator,
+/// and the next call pushes them back
+pub struct Generator {
+    state: Lock<GeneratorState>,
+    // the forms of the (generator ...) it was made by, and the variables they saw
+    body: Rc<[Node]>,
+    scope: Option<Rc<Scope>>,
+}
+
+enum GeneratorState {
+    // not called yet
+    Start {
+        body: Rc<[Node]>,
+        ctx: Context,
+        span: Span,
+    },
+    // continuations of the yield it is suspended at, outermost first
+    Suspended(Vec<Cont>),
+    // on the stack, between a call and a yield
+    Running,
+    // its body returned or raised an error, calls return nil
+    Done,
+}
+
+impl Generator {
+    /// the forms of the (generator ...) it was made by
+    pub fn body(&self) -> &[Node] {
+        &self.body
+    }
+
+    /// the local variables in scope where it was made
+    pub fn scope(&self) -> Option<&Rc<Scope>> {
+        self.scope.as_ref()
+    }
+
+    fn finish(&self) {
+        *self.state.borrow_mut() = GeneratorState::Done;
+    }
+}
+
+/// the function wrapped by (memoize f) and what its calls returned, by their arguments
+pub struct Memo {
+    f: Value,
+    cache: Lock<HashMap<Vec<Value>, Value>>,
+}
+
+impl Memo {
+    pub fn new(f: Value) -> Self {
+        Self {
+            f,
+            cache: Lock::default(),
+        }
+    }
+
+    pub fn f(&self) -> &Value {
+        &self.f
+    }
+
+    /// the arguments and values cached
+    pub fn cached(&self) -> Vec<Value> {
+        self.cache
+            .borrow()
+            .iter()
+            .flat_map(|(args, value)| args.iter().chain([value]).cloned())
+            .collect()
+    }
+}
+
+/// f and the arguments given so far, called once it has arity of them
+pub struct Curried {
+    f: Value,
+    args: Vec<Value>,
+    arity: usize,
+}
+
+impl Curried {
+    pub fn new(f: Value, arity: usize) -> Self {
+        Self {
+            f,
+            args: vec![],
+            arity,
+        }
+    }
+
+    /// the function and the arguments given so far
+    pub fn parts(&self) -> (&Value, &[Value]) {
+        (&self.f, &self.args)
+    }
+}
+
+/// pending work waiting on the value currently being computed
+enum Cont {
+    // (f x1 x2 ...) waiting on exprs[values.len()]
+    Call {
+        exprs: Rc<[Node]>,
+        values: Vec<Value>,
+        name: Symbol,
+        ctx: Context,
+        span: Span,
+    },
+    // (def! key _), or defconst
+    Def(Symbol, Rc<Env>, Definition, Span),
+    // (let* (... p v ...) body) waiting on values[index], to bind by patterns[index]
+    // in the next slots of ctx.scope, or with-open, closing the values
+    Let {
+        bindings: Rc<Bindings>,
+        values: Rc<[Node]>,
+        index: usize,
+        body: Rc<Node>,
+        closing: bool,
+        ctx: Context,
+        span: Span,
+    },
+    // (do ...) waiting on exprs[index]
+    Do {
+        exprs: Rc<[Node]>,
+        index: usize,
+        ctx: Context,
+        span: Span,
+    },
+    // (if _ then else?) waiting on the condition
+    If {
+        then: Rc<Node>,
+        otherwise: Option<Rc<Node>>,
+        ctx: Context,
+        span: Span,
+    },
+    // (case _ ...) waiting on the value looked up in table
+    Case {
+        table: Rc<CaseTable>,
+        ctx: Context,
+        span: Span,
+    },
+    // (binding (...) body...) waiting on the port of ports[values.len()]
+    Binding {
+        ports: Rc<[(Stream, Node)]>,
+        values: Vec<Value>,
+        body: Rc<[Node]>,
+        ctx: Context,
+        span: Span,
+    },
+    // (binding ...) or (with-out-str ...) waiting on its body, with the streams
+    // redirected to the ports; with-out-str returns what its port was written
+    Redirect {
+        output: Rc<Output>,
+        ports: Vec<(Stream, Value)>,
+        capture: bool,
+        span: Span,
+    },
+    // (call/ec f) waiting on f
+    CallEc {
+        exprs: Rc<[Node]>,
+        span: Span,
+    },
+    // call/ec the escape continuation numbered so returns from
+    Escape(u64),
+    // (yield _)
+    Yield(Span),
+    // call of a generator, which yield returns from
+    Resume(Rc<Generator>),
+    // (with-handler (kind handler ...) body...) waiting on its body, or on the handler
+    // it called once running, which retry evaluates the body again from
+    Handler {
+        handlers: Rc<[(Symbol, Node)]>,
+        body: Rc<[Node]>,
+        running: bool,
+        ctx: Context,
+        span: Span,
+    },
+    // (with-open ...) waiting on its body, to close the resource then
+    Close(Value, Span),
+    // call of a memoized function, whose value is cached for args
+    Memoize(Rc<Memo>, Vec<Value>),
+    // body of a lambda being evaluated
+    Return(Frame),
+    // node being evaluated while tracing, to report its value
+    Traced(Node),
+}
+
+impl Cont {
+    fn span(&self) -> Option<&Span> {
+        match self {
+            Cont::Call { span, .. }
+            | Cont::Def(_, _, _, span)
+            | Cont::Let { span, .. }
+            | Cont::Do { span, .. }
+            | Cont::If { span, .. }
+            | Cont::Case { span, .. }
+            | Cont::Binding { span, .. }
+            | Cont::Redirect { span, .. }
+            | Cont::CallEc { span, .. }
+            | Cont::Handler { span, .. }
+            | Cont::Close(_, span)
+            | Cont::Yield(span) => Some(span),
+            Cont::Escape(_)
+            | Cont::Resume(_)
+            | Cont::Memoize(..)
+            | Cont::Return(_)
+            | Cont::Traced(_) => None,
+        }
+    }
+}
+
+/// analyzes expr then evaluates it
+pub fn eval(expr: &Expr, env: Rc<Env>, limits: &Limits) -> Result<Value, FelispError> {
+    run(analyze(expr)?, env, limits, &Hooks::default())
+}
+
+/// evaluates node, failing on calls to async functions
+pub fn run(node: Node, env: Rc<Env>, limits: &Limits, hooks: &Hooks) -> Result<Value, FelispError> {
+    let mut machine = Machine::new(limits, hooks);
+    match machine.run(Step::Eval(node, Context { env, scope: None }))? {
+        Suspend::Done(value) => Ok(value),
+        Suspend::Await(_, exprs) => {
+            let span = exprs[0].span.clone();
+            Err(machine.unwind(FelispError::AsyncCall.at(span)))
+        }
+    }
+}
+
+/// evaluates node, awaiting the results of async functions it calls
+/// the timeout is only checked between steps, not while awaiting
+pub async fn run_async(
+    node: Node,
+    env: Rc<Env>,
+    limits: &Limits,
+    hooks: &Hooks,
+) -> Result<Value, FelispError> {
+    let mut machine = Machine::new(limits, hooks);
+    let mut step = Step::Eval(node, Context { env, scope: None });
+    loop {
+        step = match machine.run(step)? {
+            Suspend::Done(value) => return Ok(value),
+            Suspend::Await(future, exprs) => match future.await {
+                Ok(value) => Step::Return(value),
+                Err(err) => machine.recover(locate_arg(err, &exprs))?,
+            },
+        };
+    }
+}
+
+/// why the machine stopped stepping
+enum Suspend {
+    Done(Value),
+    Await(ValueFuture, Rc<[Node]>),
+}
+
+/// evaluates without recursing on the rust stack:
+/// pending work is kept on an explicit stack of continuations
+/// aborting with an error as soon as limits are exceeded
+struct Machine<'a> {
+    budget: Budget,
+    stack: Vec<Cont>,
+    hooks: &'a Hooks,
+    // Traced continuations on the stack
+    traced: usize,
+}
+
+impl<'a> Machine<'a> {
+    fn new(limits: &Limits, hooks: &'a Hooks) -> Self {
+        Self {
+            budget: Budget::new(limits),
+            stack: vec![],
+            hooks,
+            traced: 0,
+        }
+    }
+
+    /// steps until the evaluation is done or waits on a future
+    fn run(&mut self, mut step: Step) -> Result<Suspend, FelispError> {
+        loop {
+            if let Err(err) = self.budget.spend(self.stack.len()) {
+                // only running too deep has handlers, which unwinding makes room for
+                step = self.recover(err)?;
+                continue;
+            }
+            let next = match step {
+                Step::Eval(node, ctx) => {
+                    self.hooks.eval(&node, &ctx.env);
+                    self.hooks.counters.eval(self.stack.len());
+                    self.hooks.step(&StepContext {
+                        node: &node,
+                        env: &ctx.env,
+                        scope: ctx.scope.as_ref(),
+                        depth: self.stack.len(),
+                    });
+                    if self.hooks.on_trace.is_some() {
+                        self.trace(&node, &ctx);
+                    }
+                    eval_node(node, ctx, &mut self.stack, &mut self.budget, self.hooks)
+                }
+                Step::Return(value) => match self.stack.pop() {
+                    Some(Cont::Traced(node)) => {
+                        self.traced -= 1;
+                        self.hooks.trace(&Trace::Return {
+                            node: &node,
+                            value: &value,
+                            depth: self.traced,
+                        });
+                        Ok(Step::Return(value))
+                    }
+                    Some(Cont::Yield(span)) => self.suspend(value).map_err(|err| err.at(span)),
+                    Some(cont) => resume(cont, value, &mut self.stack, self.hooks),
+                    None => return Ok(Suspend::Done(value)),
+                },
+                Step::Await(future, exprs) => return Ok(Suspend::Await(future, exprs)),
+                Step::Resume(generator) => self.resume_generator(generator),
+                Step::Retry(span) => self.retry().map_err(|err| err.at(span)),
+            };
+            step = match next {
+                Ok(step) => step,
+                Err(err) => self.recover(err)?,
+            };
+        }
+    }
+
+    /// the step going on from err: returning from the call/ec it escapes to,
+    /// or calling the handler with-handler has for it, else err unwound
+    fn recover(&mut self, err: FelispError) -> Result<Step, FelispError> {
+        match self.escape(&err) {
+            Some(value) => Ok(Step::Return(value)),
+            None => self.handle(err).map_err(|err| self.unwind(err)),
+        }
+    }
+
+    /// pops the stack down to the call/ec err escapes to, if it is on it,
+    /// returning the value to return from it
+    fn escape(&mut self, err: &FelispError) -> Option<Value> {
+        let (id, value) = err.escaped()?;
+        let index = self
+            .stack
+            .iter()
+            .rposition(|cont| matches!(cont, Cont::Escape(i) if *i == id))?;
+        self.pop_to(index);
+        Some(value.clone())
+    }
+
+    /// pops the stack down to the innermost with-handler with a handler for err
+    /// and calls it with the error, or gives err back if there is none
+    fn handle(&mut self, err: FelispError) -> Result<Step, FelispError> {
+        let Some(keyword) = err.keyword() else {
+            return Err(err);
+        };
+        let found = self.stack.iter().rposition(|cont| match cont {
+            Cont::Handler {
+                handlers,
+                running: false,
+                ..
+            } => handlers
+                .iter()
+                .any(|(kind, _)| kind.name() == keyword || kind.name() == "error"),
+            _ => false,
+        });
+        let Some(index) = found else {
+            return Err(err);
+        };
+        let Cont::Handler {
+            handlers,
+            body,
+            ctx,
+            span,
+            ..
+        } = self.pop_to(index)
+        else {
+            unreachable!("found at index")
+        };
+        let (_, handler) = handlers
+            .iter()
+            .find(|(kind, _)| kind.name() == keyword || kind.name() == "error")
+            .cloned()
+            .expect("found among the handlers");
+        let error = Node {
+            kind: NodeKind::Const(err.value()),
+            span: span.clone(),
+        };
+        self.stack.push(Cont::Handler {
+            handlers,
+            body,
+            running: true,
+            ctx: ctx.clone(),
+            span: span.clone(),
+        });
+        let name = Symbol::intern(Special::WithHandler.name());
+        eval_args(
+            Rc::new([handler, error]),
+            vec![],
+            name,
+            ctx,
+            span,
+            &mut self.stack,
+            self.hooks,
+        )
+    }
+
+    /// pops the stack down to the with-handler whose handler is running
+    /// and evaluates its body again, with its handlers back in place
+    fn retry(&mut self) -> Result<Step, FelispError> {
+        let index = self
+            .stack
+            .iter()
+            .rposition(|cont| matches!(cont, Cont::Handler { running: true, .. }))
+            .ok_or_else(|| {
+                FelispError::HandlerError("retry outside of a handler of with-handler".to_string())
+            })?;
+        let Cont::Handler {
+            handlers,
+            body,
+            ctx,
+            span,
+            ..
+        } = self.pop_to(index)
+        else {
+            unreachable!("found at index")
+        };
+        self.stack.push(Cont::Handler {
+            handlers,
+            body: body.clone(),
+            running: false,
+            ctx: ctx.clone(),
+            span: span.clone(),
+        });
+        do_next(body, 0, ctx, span, &mut self.stack)
+    }
+
+    /// pops the continuations above index, as if they had returned, then the one at it
+    fn pop_to(&mut self, index: usize) -> Cont {
+        for cont in self.stack.drain(index + 1..).rev() {
+            match cont {
+                Cont::Return(frame) => self.hooks.frame(&FrameEvent::Exit { frame: &frame }),
+                Cont::Traced(_) => self.traced -= 1,
+                Cont::Resume(generator) => generator.finish(),
+                Cont::Redirect { output, ports, .. } => release(&output, &ports),
+                Cont::Close(resource, _) => close(&resource),
+                _ => {}
+            }
+        }
+        self.stack.pop().expect("index is on the stack")
+    }
+
+    /// pushes back the continuations of the generator, on top of a marker for yield
+    /// to suspend it at, or starts evaluating its body
+    /// apply only calls generators neither running nor done
+    fn resume_generator(&mut self, generator: Rc<Generator>) -> Result<Step, FelispError> {
+        let state = mem::replace(&mut *generator.state.borrow_mut(), GeneratorState::Running);
+        self.stack.push(Cont::Resume(generator));
+        match state {
+            GeneratorState::Start { body, ctx, span } => {
+                do_next(body, 0, ctx, span, &mut self.stack)
+            }
+            GeneratorState::Suspended(stack) => {
+                self.traced += count_traced(&stack);
+                // printing goes again to the ports of the binding it yielded in
+                for cont in &stack {
+                    if let Cont::Redirect { output, ports, .. } = cont {
+                        for (stream, port) in ports {
+                            output.redirect(*stream, port.clone());
+                        }
+                    }
+                }
+                self.stack.extend(stack);
+                // what the yield it was suspended at returns
+                Ok(Step::Return(Value::Nil))
+            }
+            GeneratorState::Running | GeneratorState::Done => {
+                unreachable!("generators are only resumed when suspended")
+            }
+        }
+    }
+
+    /// moves the continuations above the innermost generator call into the generator,
+    /// returning value from that call
+    fn suspend(&mut self, value: Value) -> Result<Step, FelispError> {
+        let index = self
+            .stack
+            .iter()
+            .rposition(|cont| matches!(cont, Cont::Resume(_)))
+            .ok_or_else(|| {
+                FelispError::GeneratorError("yield outside of a generator".to_string())
+            })?;
+        let stack = self.stack.split_off(index + 1);
+        let Some(Cont::Resume(generator)) = self.stack.pop() else {
+            unreachable!("found at index")
+        };
+        self.traced -= count_traced(&stack);
+        for cont in &stack {
+            if let Cont::Redirect { output, ports, .. } = cont {
+                release(output, ports);
+            }
+        }
+        *generator.state.borrow_mut() = GeneratorState::Suspended(stack);
+        Ok(Step::Return(value))
+    }
+
+    /// reports node to the trace hook, and keeps it to report its value
+    fn trace(&mut self, node: &Node, ctx: &Context) {
+        self.hooks.trace(&Trace::Eval {
+            node,
+            depth: self.traced,
+            scopes: ctx.scope.as_ref().map_or(0, |scope| scope.depth() + 1),
+        });
+        self.stack.push(Cont::Traced(node.clone()));
+        self.traced += 1;
+    }
+
+    /// annotates an error with the forms and calls it escapes from
+    fn unwind(&mut self, mut err: FelispError) -> FelispError {
+        for cont in mem::take(&mut self.stack).into_iter().rev() {
+            err = match cont {
+                Cont::Return(frame) => err.traced(frame),
+                Cont::Resume(generator) => {
+                    generator.finish();
+                    err
+                }
+                Cont::Redirect { output, ports, .. } => {
+                    release(&output, &ports);
+                    err
+                }
+                Cont::Close(resource, _) => {
+                    close(&resource);
+                    err
+                }
+                _ => match cont.span() {
+                    Some(span) => err.at(span.clone()),
+                    None => err,
+                },
+            };
+        }
+        err
+    }
+}
+
+/// err, raised by the form at span, with the names of the variables bound where it was,
+/// innermost first, each once
+fn raised(err: FelispError, span: Span, ctx: &Context) -> FelispError {
+    err.raised(span, || local_names(ctx.scope.as_ref()))
+}
+
+fn local_names(scope: Option<&Rc<Scope>>) -> Rc<[Symbol]> {
+    let mut names: Vec<Symbol> = vec![];
+    let mut scope = scope;
+    while let Some(inner) = scope {
+        for (name, _) in inner.bindings().into_iter().rev() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        scope = inner.outer();
+    }
+    names.into()
+}
+
+fn eval_node(
+    node: Node,
+    ctx: Context,
+    stack: &mut Vec<Cont>,
+    budget: &mut Budget,
+    hooks: &Hooks,
+) -> Result<Step, FelispError> {
+    if let Some(value) = eval_leaf(&node, &ctx) {
+        return value
+            .map(Step::Return)
+            .map_err(|err| raised(err, node.span, &ctx));
+    }
+    let span = node.span;
+    match node.kind {
+        NodeKind::Const(_) | NodeKind::Local { .. } | NodeKind::Global(_) => {
+            unreachable!("evaluated by eval_leaf")
+        }
+        NodeKind::Def(key, value, definition) => {
+            if definition == Definition::Once
+                && let Some(bound) = ctx.env.get_own(key)
+            {
+                return Ok(Step::Return(bound));
+            }
+            stack.push(Cont::Def(key, ctx.env.clone(), definition, span));
+            Ok(Step::Eval((*value).clone(), ctx))
+        }
+        NodeKind::Let(bindings, values, body) => {
+            hooks.counters.env();
+            let ctx = Context {
+                scope: Some(Scope::new(bindings.names.clone(), vec![], ctx.scope)),
+                env: ctx.env,
+            };
+            bind_next(bindings, values, 0, body, false, ctx, span, stack)
+        }
+        NodeKind::WithOpen(bindings, values, body) => {
+            hooks.counters.env();
+            let ctx = Context {
+                scope: Some(Scope::new(bindings.names.clone(), vec![], ctx.scope)),
+                env: ctx.env,
+            };
+            bind_next(bindings, values, 0, body, true, ctx, span, stack)
+        }
+        NodeKind::Do(exprs) => do_next(exprs, 0, ctx, span, stack),
+        NodeKind::If(cond, then, otherwise) => {
+            stack.push(Cont::If {
+                then,
+                otherwise,
+                ctx: ctx.clone(),
+                span,
+            });
+            Ok(Step::Eval((*cond).clone(), ctx))
+        }
+        NodeKind::Stream(stream) => Ok(Step::Return(ctx.env.output().current(stream))),
+        NodeKind::WithOutStr(body) => {
+            let ports = vec![(Stream::Out, Port::string())];
+            redirect(ports, true, body, ctx, span, stack)
+        }
+        NodeKind::Binding(ports, body) => bind_port(ports, vec![], body, ctx, span, stack),
+        NodeKind::Case(value, table) => {
+            stack.push(Cont::Case {
+                table,
+                ctx: ctx.clone(),
+                span,
+            });
+            Ok(Step::Eval((*value).clone(), ctx))
+        }
+        NodeKind::Fn(def) => Ok(Step::Return(Value::Lambda(Rc::new(Lambda {
+            def,
+            env: ctx.env,
+            scope: ctx.scope,
+        })))),
+        NodeKind::Call(exprs, name) => {
+            match call_inline(&exprs, name, &ctx, stack, budget, hooks) {
+                // located at the call, as continuing its Cont::Call would
+                Some(step) => step.map_err(|err| raised(err.at(span.clone()), span, &ctx)),
+                None => eval_args(exprs, vec![], name, ctx, span, stack, hooks),
+            }
+        }
+        NodeKind::Ns(name) => {
+            namespace::enter(&ctx.env, name);
+            Ok(Step::Return(Value::Nil))
+        }
+        NodeKind::CallEc(exprs) => {
+            let f = exprs[0].clone();
+            stack.push(Cont::CallEc { exprs, span });
+            Ok(Step::Eval(f, ctx))
+        }
+        NodeKind::Generator(body) => Ok(Step::Return(Value::Generator(Rc::new(Generator {
+            scope: ctx.scope.clone(),
+            state: Lock::new(GeneratorState::Start {
+                body: body.clone(),
+                ctx,
+                span,
+            }),
+            body,
+        })))),
+        NodeKind::Yield(value) => {
+            stack.push(Cont::Yield(span));
+            Ok(Step::Eval((*value).clone(), ctx))
+        }
+        NodeKind::WithHandler(handlers, body) => {
+            stack.push(Cont::Handler {
+                handlers,
+                body: body.clone(),
+                running: false,
+                ctx: ctx.clone(),
+                span: span.clone(),
+            });
+            do_next(body, 0, ctx, span, stack)
+        }
+        NodeKind::Retry => Ok(Step::Retry(span)),
+        NodeKind::Require(name) => namespace::require(&ctx.env, name, &budget.limits, hooks)
+            .map(|()| Step::Return(Value::Nil))
+            .map_err(|err| err.at(span)),
+    }
+}
+
+/// feeds value to the continuation that was waiting on it
+fn resume(
+    cont: Cont,
+    value: Value,
+    stack: &mut Vec<Cont>,
+    hooks: &Hooks,
+) -> Result<Step, FelispError> {
+    let span = cont.span().cloned();
+    let step = match cont {
+        Cont::Call {
+            exprs,
+            mut values,
+            name,
+            ctx,
+            span,
+        } => {
+            values.push(value);
+            eval_args(exprs, values, name, ctx, span, stack, hooks)
+        }
+        Cont::Def(key, env, definition, _) => env
+            .define(key, value.clone(), definition == Definition::Const)
+            .map(|()| Step::Return(value)),
+        Cont::Let {
+            bindings,
+            values,
+            index,
+            body,
+            closing,
+            ctx,
+            span,
+        } => {
+            if closing {
+                if !matches!(&value, Value::Native(native) if native.is_resource()) {
+                    return Err(FelispError::TypeError {
+                        expected: "resource to close".to_string(),
+                        got: printer::abbreviate(&value),
+                        arg: None,
+                        function: Some(Symbol::intern(Special::WithOpen.name())),
+                    }
+                    .at(span));
+                }
+                stack.push(Cont::Close(value.clone(), span.clone()));
+            }
+            if let Some(scope) = &ctx.scope {
+                match &bindings.patterns[index] {
+                    Pattern::Name(_) => scope.push(value),
+                    pattern => {
+                        let mut slots = vec![];
+                        if let Err(err) = pattern.bind(value, &mut slots) {
+                            return Err(raised(err.at(span.clone()), span, &ctx));
+                        }
+                        slots.into_iter().for_each(|slot| scope.push(slot));
+                    }
+                }
+            }
+            bind_next(bindings, values, index + 1, body, closing, ctx, span, stack)
+        }
+        Cont::Do {
+            exprs,
+            index,
+            ctx,
+            span,
+        } => do_next(exprs, index + 1, ctx, span, stack),
+        Cont::If {
+            then,
+            otherwise,
+            ctx,
+            ..
+        } => match (value, otherwise) {
+            (Value::Nil | Value::False, None) => Ok(Step::Return(Value::Nil)),
+            (Value::Nil | Value::False, Some(otherwise)) => {
+                Ok(Step::Eval((*otherwise).clone(), ctx))
+            }
+            _ => Ok(Step::Eval((*then).clone(), ctx)),
+        },
+        Cont::Binding {
+            ports,
+            mut values,
+            body,
+            ctx,
+            span,
+        } => {
+            if value.downcast_native::<Port>().is_none() {
+                let (stream, _) = ports[values.len()];
+                return Err(FelispError::TypeError {
+                    expected: format!("Port for {}", stream.name()),
+                    got: printer::abbreviate(&value),
+                    arg: None,
+                    function: Some(Symbol::intern(Special::Binding.name())),
+                }
+                .at(span));
+            }
+            values.push(value);
+            bind_port(ports, values, body, ctx, span, stack)
+        }
+        Cont::Redirect {
+            output,
+            ports,
+            capture,
+            ..
+        } => {
+            release(&output, &ports);
+            match (capture, ports.first()) {
+                (true, Some((_, port))) => {
+                    let port = port
+                        .downcast_native::<Port>()
+                        .expect("redirected to a port");
+                    Ok(Step::Return(Value::from(
+                        port.contents().unwrap_or_default(),
+                    )))
+                }
+                _ => Ok(Step::Return(value)),
+            }
+        }
+        Cont::Case { table, ctx, .. } => {
+            let branch = match table.keys.get(&value) {
+                Some(&index) => &table.branches[index],
+                None => match &table.default {
+                    Some(default) => default,
+                    None => return Ok(Step::Return(Value::Nil)),
+                },
+            };
+            Ok(Step::Eval(branch.clone(), ctx))
+        }
+        Cont::CallEc { exprs, .. } => {
+            static ESCAPES: AtomicU64 = AtomicU64::new(0);
+            let id = ESCAPES.fetch_add(1, Ordering::Relaxed);
+            stack.push(Cont::Escape(id));
+            let values = vec![value, escape_continuation(id)];
+            apply(&exprs, values, Symbol::intern("call/ec"), stack, hooks)
+        }
+        Cont::Close(resource, _) => {
+            close(&resource);
+            Ok(Step::Return(value))
+        }
+        Cont::Memoize(memo, args) => {
+            memo.cache.borrow_mut().insert(args, value.clone());
+            Ok(Step::Return(value))
+        }
+        Cont::Return(frame) => {
+            hooks.frame(&FrameEvent::Exit { frame: &frame });
+            Ok(Step::Return(value))
+        }
+        // the body of the generator returned
+        Cont::Resume(generator) => {
+            generator.finish();
+            Ok(Step::Return(Value::Nil))
+        }
+        // the body returned, or the handler in its place
+        Cont::Handler { .. } | Cont::Escape(_) | Cont::Traced(_) => Ok(Step::Return(value)),
+        Cont::Yield(_) => unreachable!("the machine suspends generators"),
+    };
+    match span {
+        Some(span) => step.map_err(|err| err.at(span)),
+        None => step,
+    }
+}
+
+/// evaluates the value to bind by the pattern index of the let* or with-open,
+/// or the body once all are bound
+#[allow(clippy::too_many_arguments)]
+fn bind_next(
+    bindings: Rc<Bindings>,
+    values: Rc<[Node]>,
+    index: usize,
+    body: Rc<Node>,
+    closing: bool,
+    ctx: Context,
+    span: Span,
+    stack: &mut Vec<Cont>,
+) -> Result<Step, FelispError> {
+    if index >= values.len() {
+        return Ok(Step::Eval((*body).clone(), ctx));
+    }
+    let value = values[index].clone();
+    stack.push(Cont::Let {
+        bindings,
+        values,
+        index,
+        body,
+        closing,
+        ctx: ctx.clone(),
+        span,
+    });
+    Ok(Step::Eval(value, ctx))
+}
+
+/// evaluates the port of ports[values.len()], or redirects the streams to the ports
+/// evaluated once there are no more
+fn bind_port(
+    ports: Rc<[(Stream, Node)]>,
+    values: Vec<Value>,
+    body: Rc<[Node]>,
+    ctx: Context,
+    span: Span,
+    stack: &mut Vec<Cont>,
+) -> Result<Step, FelispError> {
+    let Some((_, port)) = ports.get(values.len()) else {
+        let ports = ports
+            .iter()
+            .map(|(stream, _)| *stream)
+            .zip(values)
+            .collect();
+        return redirect(ports, false, body, ctx, span, stack);
+    };
+    let port = port.clone();
+    stack.push(Cont::Binding {
+        ports,
+        values,
+        body,
+        ctx: ctx.clone(),
+        span,
+    });
+    Ok(Step::Eval(port, ctx))
+}
+
+/// evaluates body with the streams redirected to the ports, until it returns
+fn redirect(
+    ports: Vec<(Stream, Value)>,
+    capture: bool,
+    body: Rc<[Node]>,
+    ctx: Context,
+    span: Span,
+    stack: &mut Vec<Cont>,
+) -> Result<Step, FelispError> {
+    let output = ctx.env.output().clone();
+    for (stream, port) in &ports {
+        output.redirect(*stream, port.clone());
+    }
+    stack.push(Cont::Redirect {
+        output,
+        ports,
+        capture,
+        span: span.clone(),
+    });
+    do_next(body, 0, ctx, span, stack)
+}
+
+/// closes a resource bound by with-open, which it may already be
+fn close(resource: &Value) {
+    if let Value::Native(native) = resource {
+        native.close();
+    }
+}
+
+/// undoes the redirections of a binding
+fn release(output: &Output, ports: &[(Stream, Value)]) {
+    for (stream, port) in ports.iter().rev() {
+        output.release(*stream, port);
+    }
+}
+
+/// evaluates all arguments sequentially, returning the last
+/// (do 1 2 3) -> 3
+fn do_next(
+    exprs: Rc<[Node]>,
+    index: usize,
+    ctx: Context,
+    span: Span,
+    stack: &mut Vec<Cont>,
+) -> Result<Step, FelispError> {
+    if exprs.is_empty() {
+        return Ok(Step::Return(Value::Nil));
+    }
+    let expr = exprs[index].clone();
+    if index + 1 < exprs.len() {
+        stack.push(Cont::Do {
+            exprs,
+            index,
+            ctx: ctx.clone(),
+            span,
+        });
+    }
+    Ok(Step::Eval(expr, ctx))
+}
+
+/// the value of a constant or variable, None for the nodes that need steps of their own
+fn eval_leaf(node: &Node, ctx: &Context) -> Option<Result<Value, FelispError>> {
+    let value = match &node.kind {
+        NodeKind::Const(value) => Ok(value.clone()),
+        NodeKind::Local { depth, index, name } => ctx
+            .scope
+            .as_ref()
+            .and_then(|scope| scope.get(*depth, *index))
+            .ok_or_else(|| FelispError::UnknownSymbol(name.to_string()).at(node.span.clone())),
+        NodeKind::Global(symbol) => ctx
+            .env
+            .get(*symbol)
+            .map_err(|err| err.at(node.span.clone())),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// (f x y) of a builtin f on constants and variables, as most arithmetic is:
+/// calls f right away, its arguments in an array rather than continuations and
+/// a vector, so that (+ n 1) allocates nothing, spending a step of budget
+/// for each argument as evaluating them would
+/// None for other calls, and when hooks watch every node, which it skips
+fn call_inline(
+    exprs: &Rc<[Node]>,
+    name: Symbol,
+    ctx: &Context,
+    stack: &[Cont],
+    budget: &mut Budget,
+    hooks: &Hooks,
+) -> Option<Result<Step, FelispError>> {
+    if exprs.len() > INLINE_ARGS + 1
+        || hooks.watches_nodes()
+        || !exprs.iter().all(|expr| {
+            matches!(
+                expr.kind,
+                NodeKind::Const(_) | NodeKind::Local { .. } | NodeKind::Global(_)
+            )
+        })
+    {
+        return None;
+    }
+    let f = match eval_leaf(&exprs[0], ctx)? {
+        Ok(Value::Function(ref f)) if f.spreads() => return None,
+        Ok(f @ Value::Function(_)) => f,
+        Ok(_) => return None,
+        Err(err) => return Some(Err(err)),
+    };
+    let mut args: [Value; INLINE_ARGS] = array::from_fn(|_| Value::Nil);
+    for (arg, expr) in args.iter_mut().zip(&exprs[1..]) {
+        hooks.counters.eval(stack.len());
+        if let Err(err) = budget.spend(stack.len()) {
+            return Some(Err(err));
+        }
+        *arg = match eval_leaf(expr, ctx)? {
+            Ok(value) => value,
+            Err(err) => return Some(Err(err)),
+        };
+    }
+    let args = &args[..exprs.len() - 1];
+    let frame = Frame {
+        name,
+        span: exprs[0].span.clone(),
+    };
+    hooks.call(&f, args);
+    hooks.frame(&FrameEvent::Enter {
+        frame: &frame,
+        depth: stack.len(),
+    });
+    let Value::Function(f) = &f else {
+        unreachable!("matched above")
+    };
+    Some(call_native(f, args, exprs, frame, hooks))
+}
+
+/// calls the builtin f, the frame hook told of it returning
+fn call_native(
+    f: &NativeFn,
+    args: &[Value],
+    exprs: &[Node],
+    frame: Frame,
+    hooks: &Hooks,
+) -> Result<Step, FelispError> {
+    match f.call(args) {
+        Ok(value) => {
+            hooks.frame(&FrameEvent::Exit { frame: &frame });
+            Ok(Step::Return(value))
+        }
+        Err(err) => Err(locate_arg(err, exprs).traced(frame)),
+    }
+}
+
+/// (f x1 x2 ...) -> (apply f x1 x2 ...)
+/// evaluates the next of f, x1, x2 ..., or applies f once all are evaluated
+fn eval_args(
+    exprs: Rc<[Node]>,
+    values: Vec<Value>,
+    name: Symbol,
+    ctx: Context,
+    span: Span,
+    stack: &mut Vec<Cont>,
+    hooks: &Hooks,
+) -> Result<Step, FelispError> {
+    if values.len() == exprs.len() {
+        return apply(&exprs, values, name, stack, hooks).map_err(|err| raised(err, span, &ctx));
+    }
+    let expr = exprs[values.len()].clone();
+    stack.push(Cont::Call {
+        exprs,
+        values,
+        name,
+        ctx: ctx.clone(),
+        span,
+    });
+    Ok(Step::Eval(expr, ctx))
+}
+
+fn apply(
+    exprs: &Rc<[Node]>,
+    mut values: Vec<Value>,
+    name: Symbol,
+    stack: &mut Vec<Cont>,
+    hooks: &Hooks,
+) -> Result<Step, FelispError> {
+    // (apply f x [y z]) -> (f x y z), errors raised by f pointing at the call to apply
+    if let Some(Value::Function(native)) = values.first()
+        && native.spreads()
+    {
+        let (f, args) = collections::spread(&values[1..]).map_err(|err| {
+            let frame = Frame {
+                name,
+                span: exprs[0].span.clone(),
+            };
+            locate_arg(err, exprs).traced(frame)
+        })?;
+        let name = match &f {
+            Value::Function(f) => f.name,
+            _ => name,
+        };
+        let head: Rc<[Node]> = exprs[..1].into();
+        return apply(
+            &head,
+            [f].into_iter().chain(args).collect(),
+            name,
+            stack,
+            hooks,
+        );
+    }
+    // named lambdas are reported by their own name, whatever they were called by
+    let name = match values.first() {
+        Some(Value::Lambda(lambda)) => lambda.def.name.unwrap_or(name),
+        _ => name,
+    };
+    let frame = Frame {
+        name,
+        span: exprs[0].span.clone(),
+    };
+    let args = values.split_off(1);
+    let f = values.pop().expect("calls have a head");
+    let (f, args) = match &f {
+        // under-applied, it takes the arguments and waits for the rest
+        Value::Curried(curried) => {
+            let mut all = curried.args.clone();
+            all.extend(args);
+            if all.len() < curried.arity {
+                return Ok(Step::Return(Value::Curried(Rc::new(Curried {
+                    f: curried.f.clone(),
+                    args: all,
+                    arity: curried.arity,
+                }))));
+            }
+            (curried.f.clone(), all)
+        }
+        // with auto-curry on, lambdas given too few arguments are curried
+        Value::Lambda(lambda)
+            if args.len() < lambda.def.min_arity() && lambda.env.auto_curries() =>
+        {
+            let arity = lambda.def.min_arity();
+            return Ok(Step::Return(Value::Curried(Rc::new(Curried {
+                f: f.clone(),
+                args,
+                arity,
+            }))));
+        }
+        _ => (f, args),
+    };
+    let f = match &f {
+        Value::Memoized(memo) => {
+            if let Some(value) = memo.cache.borrow().get(&args) {
+                return Ok(Step::Return(value.clone()));
+            }
+            stack.push(Cont::Memoize(memo.clone(), args.clone()));
+            memo.f.clone()
+        }
+        _ => f,
+    };
+    // a call in tail position returns what its caller does, so it takes the caller's
+    // frame, and loops written as tail calls run in constant space
+    if let Value::Lambda(_) = f
+        && let Some(Cont::Return(_)) = stack.last()
+        && let Some(Cont::Return(caller)) = stack.pop()
+    {
+        hooks.frame(&FrameEvent::Exit { frame: &caller });
+    }
+    hooks.call(&f, &args);
+    hooks.frame(&FrameEvent::Enter {
+        frame: &frame,
+        depth: stack.len(),
+    });
+    match &f {
+        Value::Function(f) => call_native(f, &args, exprs, frame, hooks),
+        Value::AsyncFunction(f) => {
+            stack.push(Cont::Return(frame));
+            Ok(Step::Await(f(args), exprs.clone()))
+        }
+        Value::Lambda(lambda) => {
+            let bound = lambda
+                .def
+                .arity(args.len())
+                .and_then(|arity| Ok((arity, arity.params.bind_args(args)?)));
+            let (arity, slots) = match bound {
+                Ok(bound) => bound,
+                Err(err) => return Err(err.traced(frame)),
+            };
+            hooks.counters.env();
+            let ctx = Context {
+                env: lambda.env.clone(),
+                scope: Some(Scope::new(
+                    arity.params.names.clone(),
+                    slots,
+                    lambda.scope.clone(),
+                )),
+            };
+            stack.push(Cont::Return(frame));
+            Ok(Step::Eval(arity.body.clone(), ctx))
+        }
+        Value::Generator(generator) => {
+            if !args.is_empty() {
+                return Err(FelispError::ArityError(format!(
+                    "generator requires 0 args but given {}",
+                    args.len()
+                ))
+                .traced(frame));
+            }
+            match *generator.state.borrow() {
+                GeneratorState::Running => {
+                    return Err(FelispError::GeneratorError(
+                        "generator called while it is running".to_string(),
+                    )
+                    .traced(frame));
+                }
+                GeneratorState::Done => {
+                    hooks.frame(&FrameEvent::Exit { frame: &frame });
+                    return Ok(Step::Return(Value::Nil));
+                }
+                GeneratorState::Start { .. } | GeneratorState::Suspended(_) => {}
+            }
+            stack.push(Cont::Return(frame));
+            Ok(Step::Resume(generator.clone()))
+        }
+        // (:a m) and (m :a), looking :a up in m
+        Value::Keyword(_) | Value::Map(_) => match collections::lookup(&f, &args) {
+            Ok(value) => {
+                hooks.frame(&FrameEvent::Exit { frame: &frame });
+                Ok(Step::Return(value))
+            }
+            Err(err) => Err(locate_arg(err, exprs).traced(frame)),
+        },
+        _ => Err(FelispError::NotCallable(printer::abbreviate(&f)).at(frame.span)),
+    }
+}
+
+/// the continuation of the call/ec numbered id, which returns its argument from it
+fn escape_continuation(id: u64) -> Value {
+    NativeFn::new("continuation", "value", move |args: &[Value]| {
+        Err(FelispError::Escape(id, args[0].clone()))
+    })
+    .into()
+}
+
+fn count_traced(stack: &[Cont]) -> usize {
+    stack
+        .iter()
+        .filter(|cont| matches!(cont, Cont::Traced(_)))
+        .count()
+}
+
+/// points a type error raised by a host function at the argument it is about
+fn locate_arg(err: FelispError, exprs: &[Node]) -> FelispError {
+    match err {
+        FelispError::TypeError { arg: Some(i), .. } if i + 1 < exprs.len() => {
+            let span = exprs[i + 1].span.clone();
+            err.at(span)
+        }
+        _ => err,
+    }
+}
@@ -0,0 +1,230 @@
+// calls into shared libraries, without rust glue for each function:
+// arguments and results are converted to and from C after the types
+// the script gives, which it must get right, as nothing checks them
+
+use std::{
+    error::Error,
+    ffi::{CStr, CString, c_char, c_int, c_long, c_void},
+    io,
+};
+
+use libffi::middle::{Arg, Cif, CodePtr, Type, arg};
+use libloading::Library;
+
+use crate::{
+    env::{Env, NativeFn, Value},
+    error::FelispError,
+    native::IntoNativeFn,
+    numeric,
+    resource::{self, Resource},
+    symbol::Symbol,
+};
+
+/// a shared library opened by ffi-open, printed as <SharedLibrary>,
+/// unloaded once closed
+pub struct SharedLibrary {
+    library: Library,
+}
+
+/// C types of arguments and results, named by keywords or strings
+#[derive(Clone, Copy, PartialEq)]
+enum CType {
+    Int,
+    Long,
+    // floats, or other numbers as floats for arguments
+    Double,
+    // a nul-terminated char *, nil for NULL results
+    String,
+    // results only
+    Void,
+}
+
+/// a converted argument, which Arg points to during the call
+enum CValue {
+    Int(c_int),
+    Long(c_long),
+    Double(f64),
+    Pointer(*const c_char),
+}
+
+/// binds (ffi-open path) and (ffi-call lib name arg-types result-type args...) in env
+/// refused by sandboxed envs, as the functions called could do anything
+pub fn register(env: &Env) -> Result<(), FelispError> {
+    let symbol = Symbol::intern("ffi-open");
+    env.set_io(symbol, open.into_native_fn(symbol).into())?;
+    let f = NativeFn::new("ffi-call", "lib name arg-types result-type & args", call);
+    env.set_io("ffi-call", f.into())
+}
+
+/// loads the shared library at path, or found by the system's loader
+/// (ffi-open "libm.so.6") -> <SharedLibrary>
+fn open(path: String) -> Result<Value, FelispError> {
+    // SAFETY: the initializers of the library run, which the script asks for
+    let library = unsafe { Library::new(&path) }.map_err(|err| loading_error(&path, err))?;
+    Ok(Resource::value(SharedLibrary { library }))
+}
+
+/// calls the C function name of lib with args, converted to arg-types, a list or vector,
+/// its result converted from result-type
+/// types are :int, :long, :double, :string and, for results, :void, or their names
+/// (ffi-call libm "pow" '(:double :double) :double 2 10) -> 1024.0
+/// (ffi-call libm "cos" ["double"] "double" 0) -> 1.0
+fn call(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() < 4 {
+        return Err(FelispError::ArityError(format!(
+            "ffi-call requires at least 4 arguments but given {}",
+            args.len()
+        )));
+    }
+    // the library stays open until the call returns
+    resource::with_arg(args, 0, "SharedLibrary", |lib| call_in(lib, args))?
+}
+
+fn call_in(lib: &SharedLibrary, args: &[Value]) -> Result<Value, FelispError> {
+    let [_, name, types, result, args @ ..] = args else {
+        unreachable!("call checks there are at least 4")
+    };
+    let name =
+        String::try_from(name.clone()).map_err(|_| FelispError::bad_arg(1, "String", name))?;
+    let types = match types {
+        Value::Vector(types) => types.iter().cloned().collect(),
+        _ => types
+            .list_items()
+            .ok_or_else(|| FelispError::bad_arg(2, "list or Vector of C types", types))?,
+    };
+    let types = types
+        .iter()
+        .map(|t| match c_type(t, 2)? {
+            CType::Void => Err(FelispError::bad_arg(2, "argument C type", t)),
+            t => Ok(t),
+        })
+        .collect::<Result<Vec<CType>, FelispError>>()?;
+    let result = c_type(result, 3)?;
+    if args.len() != types.len() {
+        return Err(FelispError::ArityError(format!(
+            "{} requires {} args but given {}",
+            name,
+            types.len(),
+            args.len()
+        )));
+    }
+    // kept alive until the call returns, as the arguments point into them
+    let mut strings = vec![];
+    let values = args
+        .iter()
+        .zip(&types)
+        .enumerate()
+        .map(|(i, (value, &t))| c_value(value, t, i + 4, &mut strings))
+        .collect::<Result<Vec<CValue>, FelispError>>()?;
+    let ffi_args: Vec<Arg> = values
+        .iter()
+        .map(|value| match value {
+            CValue::Int(n) => arg(n),
+            CValue::Long(n) => arg(n),
+            CValue::Double(x) => arg(x),
+            CValue::Pointer(p) => arg(p),
+        })
+        .collect();
+    // SAFETY: the script vouches for the signature, the library outlives the call
+    let f = unsafe { lib.library.get::<unsafe extern "C" fn()>(name.as_str()) }
+        .map_err(|err| loading_error(&name, err))?;
+    let code = CodePtr(*f as *mut c_void);
+    let cif = Cif::new(types.iter().map(|&t| ffi_type(t)), ffi_type(result));
+    // SAFETY: as above, the arguments match the types the cif was made of
+    let value = unsafe {
+        match result {
+            CType::Void => {
+                cif.call::<()>(code, &ffi_args);
+                Value::Nil
+            }
+            CType::Int => Value::Number(i64::from(cif.call::<c_int>(code, &ffi_args))),
+            // c_long is only i64 on some platforms
+            #[allow(clippy::useless_conversion)]
+            CType::Long => Value::Number(i64::from(cif.call::<c_long>(code, &ffi_args))),
+            CType::Double => Value::Float(cif.call::<f64>(code, &ffi_args)),
+            CType::String => {
+                let p = cif.call::<*const c_char>(code, &ffi_args);
+                if p.is_null() {
+                    Value::Nil
+                } else {
+                    Value::from(CStr::from_ptr(p).to_string_lossy().into_owned())
+                }
+            }
+        }
+    };
+    Ok(value)
+}
+
+/// an error of libloading, with the message of the loader it comes from
+fn loading_error(what: &str, err: libloading::Error) -> FelispError {
+    let message = match err.source() {
+        Some(source) => format!("{}: {}: {}", what, err, source),
+        None => format!("{}: {}", what, err),
+    };
+    FelispError::host(io::Error::other(message))
+}
+
+/// the C type :double or "double" names
+fn c_type(value: &Value, arg: usize) -> Result<CType, FelispError> {
+    let name = match value {
+        Value::Keyword(name) => name.name(),
+        Value::String(name) => name,
+        _ => return Err(FelispError::bad_arg(arg, "C type", value)),
+    };
+    let t = match name {
+        "int" => CType::Int,
+        "long" => CType::Long,
+        "double" => CType::Double,
+        "string" => CType::String,
+        "void" => CType::Void,
+        _ => return Err(FelispError::bad_arg(arg, "C type", value)),
+    };
+    Ok(t)
+}
+
+fn ffi_type(t: CType) -> Type {
+    match t {
+        CType::Int => Type::c_int(),
+        CType::Long => Type::c_long(),
+        CType::Double => Type::f64(),
+        CType::String => Type::pointer(),
+        CType::Void => Type::void(),
+    }
+}
+
+/// value converted to t, strings kept in strings
+fn c_value(
+    value: &Value,
+    t: CType,
+    arg: usize,
+    strings: &mut Vec<CString>,
+) -> Result<CValue, FelispError> {
+    let number = || match value {
+        Value::Number(n) => Ok(*n),
+        _ => Err(FelispError::bad_arg(arg, "Number", value)),
+    };
+    let c_value = match (t, value) {
+        (CType::Int, _) => CValue::Int(
+            c_int::try_from(number()?)
+                .map_err(|_| FelispError::bad_arg(arg, "Number fitting a C int", value))?,
+        ),
+        (CType::Long, _) => CValue::Long(
+            c_long::try_from(number()?)
+                .map_err(|_| FelispError::bad_arg(arg, "Number fitting a C long", value))?,
+        ),
+        (CType::Double, _) => CValue::Double(
+            numeric::to_f64(value).ok_or_else(|| FelispError::bad_arg(arg, "Number", value))?,
+        ),
+        (CType::String, Value::String(s)) => {
+            let s = CString::new(s.as_bytes())
+                .map_err(|_| FelispError::bad_arg(arg, "String without nul bytes", value))?;
+            // the bytes stay where they are as the CString moves
+            let p = s.as_ptr();
+            strings.push(s);
+            CValue::Pointer(p)
+        }
+        (CType::String, _) => return Err(FelispError::bad_arg(arg, "String", value)),
+        (CType::Void, _) => unreachable!("checked by call"),
+    };
+    Ok(c_value)
+}
@@ -0,0 +1,205 @@
+// data formats read into values: tables and mappings as maps with string keys,
+// arrays as vectors, dates and times as the strings they are written as
+// csv rows are vectors of strings, or maps keyed by the header row
+
+#[cfg(feature = "yaml")]
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::{
+    env::{Builtin, Value},
+    error::FelispError,
+};
+
+/// builtins of the toml, yaml and csv features, safe to give to any code
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[
+    #[cfg(feature = "toml")]
+    ("toml-parse", "s", toml_parse),
+    #[cfg(feature = "yaml")]
+    ("yaml-parse", "s", yaml_parse),
+    #[cfg(feature = "csv")]
+    ("csv-parse", "s [opts]", csv_parse),
+    #[cfg(feature = "csv")]
+    ("csv-str", "rows", csv_str),
+];
+
+#[cfg(any(feature = "toml", feature = "yaml"))]
+fn string_arg<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "{} requires 1 argument but given {}",
+            name,
+            args.len()
+        )));
+    }
+    match &args[0] {
+        Value::String(s) => Ok(s),
+        arg => Err(FelispError::bad_arg(0, "String", arg)),
+    }
+}
+
+/// (toml-parse "name = \"felisp\"\n[deps]\nim = 15") -> {"name" "felisp" "deps" {"im" 15}}
+#[cfg(feature = "toml")]
+fn toml_parse(args: &[Value]) -> Result<Value, FelispError> {
+    let s = string_arg("toml-parse", args)?;
+    let table = s
+        .parse::<toml::Table>()
+        .map_err(|err| FelispError::ParseError(format!("toml: {}", err.message())))?;
+    from_toml(toml::Value::Table(table))
+}
+
+#[cfg(feature = "toml")]
+fn from_toml(value: toml::Value) -> Result<Value, FelispError> {
+    Ok(match value {
+        toml::Value::String(s) => Value::from(s),
+        toml::Value::Integer(n) => Value::Number(n),
+        toml::Value::Float(x) => Value::Float(x),
+        toml::Value::Boolean(b) => Value::from(b),
+        toml::Value::Datetime(datetime) => Value::from(datetime.to_string()),
+        toml::Value::Array(items) => {
+            Value::Vector(items.into_iter().map(from_toml).collect::<Result<_, _>>()?)
+        }
+        toml::Value::Table(table) => Value::Map(
+            table
+                .into_iter()
+                .map(|(k, v)| Ok((Value::from(k), from_toml(v)?)))
+                .collect::<Result<_, FelispError>>()?,
+        ),
+    })
+}
+
+/// the first document of s, nil if it has none
+/// (yaml-parse "name: felisp\ntags: [lisp, toy]") -> {"name" "felisp" "tags" ["lisp" "toy"]}
+#[cfg(feature = "yaml")]
+fn yaml_parse(args: &[Value]) -> Result<Value, FelispError> {
+    let s = string_arg("yaml-parse", args)?;
+    let documents = YamlLoader::load_from_str(s)
+        .map_err(|err| FelispError::ParseError(format!("yaml: {}", err)))?;
+    documents
+        .into_iter()
+        .next()
+        .map_or(Ok(Value::Nil), from_yaml)
+}
+
+#[cfg(feature = "yaml")]
+fn from_yaml(value: Yaml) -> Result<Value, FelispError> {
+    Ok(match value {
+        Yaml::Null => Value::Nil,
+        Yaml::Boolean(b) => Value::from(b),
+        Yaml::Integer(n) => Value::Number(n),
+        // .inf and .nan included
+        Yaml::Real(ref x) => match value.as_f64() {
+            Some(x) => Value::Float(x),
+            None => return Err(FelispError::ParseError(format!("yaml: bad float {}", x))),
+        },
+        Yaml::String(s) => Value::from(s),
+        Yaml::Array(items) => {
+            Value::Vector(items.into_iter().map(from_yaml).collect::<Result<_, _>>()?)
+        }
+        Yaml::Hash(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((from_yaml(k)?, from_yaml(v)?)))
+                .collect::<Result<_, FelispError>>()?,
+        ),
+        Yaml::Alias(_) | Yaml::BadValue => {
+            return Err(FelispError::ParseError(
+                "yaml: unsupported value".to_string(),
+            ));
+        }
+    })
+}
+
+/// the rows of s, a vector of vectors of strings, or of maps keyed by the first row
+/// when opts has :headers true; :delimiter is a one character string, "," by default
+/// (csv-parse "a,b\n1,2") -> [["a" "b"] ["1" "2"]]
+/// (csv-parse "a,b\n1,2" {:headers true}) -> [{"a" "1" "b" "2"}]
+#[cfg(feature = "csv")]
+fn csv_parse(args: &[Value]) -> Result<Value, FelispError> {
+    use crate::symbol::Symbol;
+
+    if !(1..=2).contains(&args.len()) {
+        return Err(FelispError::ArityError(format!(
+            "csv-parse requires 1 to 2 arguments but given {}",
+            args.len()
+        )));
+    }
+    let Value::String(s) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    let opt = |name: &str| match args.get(1) {
+        Some(Value::Map(opts)) => opts.get(&Value::Keyword(Symbol::intern(name))).cloned(),
+        _ => None,
+    };
+    if let Some(opts) = args.get(1)
+        && !matches!(opts, Value::Map(_) | Value::Nil)
+    {
+        return Err(FelispError::bad_arg(1, "Map of options", opts));
+    }
+    let headers = !matches!(opt("headers"), None | Some(Value::Nil | Value::False));
+    let delimiter = match &opt("delimiter") {
+        None => b',',
+        Some(Value::String(d)) if d.len() == 1 => d.as_bytes()[0],
+        Some(d) => return Err(FelispError::bad_arg(1, "one character :delimiter", d)),
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(headers)
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(s.as_bytes());
+    let parse_error = |err: csv::Error| FelispError::ParseError(format!("csv: {}", err));
+    let names: Vec<Value> = if headers {
+        let names = reader.headers().map_err(parse_error)?;
+        names.iter().map(Value::from).collect()
+    } else {
+        vec![]
+    };
+    let mut rows = vec![];
+    for record in reader.records() {
+        let fields = record.map_err(parse_error)?;
+        let fields = fields.iter().map(Value::from);
+        rows.push(if headers {
+            Value::Map(names.iter().cloned().zip(fields).collect())
+        } else {
+            Value::Vector(fields.collect())
+        });
+    }
+    Ok(Value::Vector(rows.into()))
+}
+
+/// rows, vectors or lists of values, written as csv, strings as they are,
+/// nil as an empty field and other values as they print
+/// (csv-str [["a" "b"] [1 "x,y"]]) -> "a,b\n1,\"x,y\"\n"
+#[cfg(feature = "csv")]
+fn csv_str(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 1 {
+        return Err(FelispError::ArityError(format!(
+            "csv-str requires 1 argument but given {}",
+            args.len()
+        )));
+    }
+    let rows = match &args[0] {
+        Value::Vector(rows) => rows.iter().cloned().collect(),
+        rows => rows
+            .list_items()
+            .ok_or_else(|| FelispError::bad_arg(0, "rows", rows))?,
+    };
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+    for row in rows {
+        let fields = match &row {
+            Value::Vector(fields) => fields.iter().cloned().collect(),
+            row => row
+                .list_items()
+                .ok_or_else(|| FelispError::bad_arg(0, "rows of vectors", &args[0]))?,
+        };
+        let fields = fields.iter().map(|field| match field {
+            Value::String(s) => s.to_string(),
+            Value::Nil => String::new(),
+            field => field.to_string(),
+        });
+        writer.write_record(fields).map_err(FelispError::host)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|err| FelispError::host(err.into_error()))?;
+    Ok(Value::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
@@ -0,0 +1,217 @@
+// callbacks the host can have the evaluator call, for profilers, debuggers
+// or audit logs, each of them costing a branch per step when not set,
+// and the counters of what it did, which are always kept
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::{
+    env::{Env, Scope, Value},
+    error::{FelispError, Frame},
+    ir::Node,
+    symbol::Symbol,
+    sync::Rc,
+};
+
+#[cfg(not(feature = "sync"))]
+pub type EvalHook = Rc<dyn Fn(&Node, &Env)>;
+#[cfg(feature = "sync")]
+pub type EvalHook = Rc<dyn Fn(&Node, &Env) + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+pub type CallHook = Rc<dyn Fn(&Value, &[Value])>;
+#[cfg(feature = "sync")]
+pub type CallHook = Rc<dyn Fn(&Value, &[Value]) + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+pub type TraceHook = Rc<dyn Fn(&Trace)>;
+#[cfg(feature = "sync")]
+pub type TraceHook = Rc<dyn Fn(&Trace) + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+pub type StepHook = Rc<dyn Fn(&StepContext)>;
+#[cfg(feature = "sync")]
+pub type StepHook = Rc<dyn Fn(&StepContext) + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+pub type FrameHook = Rc<dyn Fn(&FrameEvent)>;
+#[cfg(feature = "sync")]
+pub type FrameHook = Rc<dyn Fn(&FrameEvent) + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+pub type ErrorHook = Rc<dyn Fn(&FelispError)>;
+#[cfg(feature = "sync")]
+pub type ErrorHook = Rc<dyn Fn(&FelispError) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    // before evaluating each node, with the global env
+    pub on_eval: Option<EvalHook>,
+    // before applying each function to its evaluated arguments
+    pub on_call: Option<CallHook>,
+    // with the error an evaluation fails with
+    pub on_error: Option<ErrorHook>,
+    // as each node is entered then left, see Trace
+    // setting it keeps every node on the stack until it has a value, so tail calls
+    // stop running in constant space and count towards the max depth
+    pub on_trace: Option<TraceHook>,
+    // before evaluating each node, with its local variables, see StepContext
+    pub on_step: Option<StepHook>,
+    // as each function call starts then returns, see FrameEvent
+    pub on_frame: Option<FrameHook>,
+    // of the evaluations using these hooks, see Stats
+    pub counters: Rc<Counters>,
+}
+
+/// what the evaluator did since its counters were last reset, see Interpreter::stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    // nodes evaluated
+    pub evals: u64,
+    // functions applied, builtins included
+    pub calls: u64,
+    // scopes made for the variables of let* and function calls
+    pub envs: u64,
+    // most continuations pending at once
+    pub peak_depth: usize,
+}
+
+/// the counters Stats is read from, shared by the evaluations of an interpreter
+#[derive(Default, Debug)]
+pub struct Counters {
+    evals: AtomicU64,
+    calls: AtomicU64,
+    envs: AtomicU64,
+    peak_depth: AtomicUsize,
+}
+
+/// (stats) -> {:evals 120 :calls 14 :envs 9 :peak-depth 23}
+impl From<Stats> for Value {
+    fn from(stats: Stats) -> Self {
+        let fields = [
+            ("evals", stats.evals),
+            ("calls", stats.calls),
+            ("envs", stats.envs),
+            ("peak-depth", stats.peak_depth as u64),
+        ];
+        Value::Map(
+            fields
+                .into_iter()
+                .map(|(name, n)| {
+                    (
+                        Value::Keyword(Symbol::intern(name)),
+                        Value::Number(n as i64),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Counters {
+    pub fn stats(&self) -> Stats {
+        Stats {
+            evals: self.evals.load(Ordering::Relaxed),
+            calls: self.calls.load(Ordering::Relaxed),
+            envs: self.envs.load(Ordering::Relaxed),
+            peak_depth: self.peak_depth.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.evals.store(0, Ordering::Relaxed);
+        self.calls.store(0, Ordering::Relaxed);
+        self.envs.store(0, Ordering::Relaxed);
+        self.peak_depth.store(0, Ordering::Relaxed);
+    }
+
+    /// counts a node evaluated with depth continuations pending
+    pub(crate) fn eval(&self, depth: usize) {
+        self.evals.fetch_add(1, Ordering::Relaxed);
+        self.peak_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn env(&self) {
+        self.envs.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// what the trace hook is told: each node as its evaluation starts, nested in
+/// depth others, then the value it evaluated to, unless its evaluation failed
+pub enum Trace<'a> {
+    // node about to be evaluated, in a local scope nested in scopes others
+    Eval {
+        node: &'a Node,
+        depth: usize,
+        scopes: usize,
+    },
+    // value node evaluated to
+    Return {
+        node: &'a Node,
+        value: &'a Value,
+        depth: usize,
+    },
+}
+
+/// what the step hook is told of the node about to be evaluated, for debuggers
+/// to show it, read or rebind its variables, and step over it
+pub struct StepContext<'a> {
+    pub node: &'a Node,
+    pub env: &'a Rc<Env>,
+    // innermost scope of the let* or function the node is in, if any
+    pub scope: Option<&'a Rc<Scope>>,
+    // forms waiting on the node's value, which its own subforms are deeper than
+    pub depth: usize,
+}
+
+/// what the frame hook is told: each function as it is applied, then as it
+/// returns, unless it fails, for profilers to time it
+pub enum FrameEvent<'a> {
+    // function about to be applied, with depth continuations pending,
+    // which the calls it makes are deeper than
+    Enter { frame: &'a Frame, depth: usize },
+    Exit { frame: &'a Frame },
+}
+
+impl Hooks {
+    /// whether a hook is told of every node, which evaluating some inline would skip
+    pub(crate) fn watches_nodes(&self) -> bool {
+        self.on_eval.is_some() || self.on_step.is_some() || self.on_trace.is_some()
+    }
+
+    pub fn eval(&self, node: &Node, env: &Env) {
+        if let Some(hook) = &self.on_eval {
+            hook(node, env);
+        }
+    }
+
+    pub fn call(&self, f: &Value, args: &[Value]) {
+        self.counters.calls.fetch_add(1, Ordering::Relaxed);
+        if let Some(hook) = &self.on_call {
+            hook(f, args);
+        }
+    }
+
+    pub fn trace(&self, trace: &Trace) {
+        if let Some(hook) = &self.on_trace {
+            hook(trace);
+        }
+    }
+
+    pub fn step(&self, step: &StepContext) {
+        if let Some(hook) = &self.on_step {
+            hook(step);
+        }
+    }
+
+    pub fn frame(&self, event: &FrameEvent) {
+        if let Some(hook) = &self.on_frame {
+            hook(event);
+        }
+    }
+
+    pub fn error(&self, err: &FelispError) {
+        if let Some(hook) = &self.on_error {
+            hook(err);
+        }
+    }
+}
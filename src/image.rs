@@ -0,0 +1,160 @@
+// images of a session: the bindings of an env written as felisp code re-creating them,
+// which felisp --image, or :load, evaluates to resume the session
+
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use crate::{
+    ast::{Expr, Special},
+    deterministic,
+    env::{Env, Value},
+    error::FelispError,
+    native::IntoNativeFn,
+    symbol::Symbol,
+    sync::Rc,
+};
+
+// source name of the forms of Interpreter::load_prelude, which needn't be saved
+const PRELUDE: &str = "<prelude>";
+
+/// binds (save-image path) in env, which saves it to path, see save
+/// refused by sandboxed envs, as it writes files
+pub fn register(env: &Rc<Env>) -> Result<(), FelispError> {
+    let symbol = Symbol::intern("save-image");
+    let saved = env.clone();
+    let save_image = move |path: String| save(&saved, Path::new(&path)).map(|n| n as i64);
+    env.set_io(symbol, save_image.into_native_fn(symbol).into())
+}
+
+/// writes a def! of each binding of env, without those of the envs it is nested in,
+/// returning how many it wrote
+/// builtins and other host functions are left out, as the host binds them again,
+/// as are the functions of the prelude, host values and closures over local variables
+/// (save-image "session.flp") -> 12
+pub fn save(env: &Rc<Env>, path: &Path) -> Result<usize, FelispError> {
+    let mut symbols: Vec<Symbol> = env.symbols();
+    symbols.sort_by_key(|symbol| symbol.name());
+    symbols.dedup();
+    let mut out = String::from("; felisp image, see felisp --image\n");
+    let mut saved = 0;
+    for symbol in symbols {
+        if let Some(form) = env.get_own(symbol).and_then(|value| readable(&value, env)) {
+            let special = if env.is_constant(symbol) {
+                Special::Defconst
+            } else {
+                Special::Def
+            };
+            let _ = writeln!(out, "({} {} {})", special.name(), symbol, form);
+            saved += 1;
+        }
+    }
+    fs::write(path, out).map_err(|err| {
+        FelispError::host(io::Error::new(
+            err.kind(),
+            format!("can't write {}: {}", path.display(), err),
+        ))
+    })?;
+    Ok(saved)
+}
+
+/// what is left to write of a value: values, and the text between them
+enum Piece<'v> {
+    Value(&'v Value),
+    Text(&'static str),
+}
+
+/// code evaluating to value in env, if there is any
+/// lists are written (list a b c), or (list* a b c tail) when they don't end in (),
+/// as nil is a different end; values are walked with a stack of their pieces rather
+/// than recursively, so that lists and collections of any length or nesting are saved
+/// without overflowing
+fn readable(value: &Value, env: &Rc<Env>) -> Option<String> {
+    let mut out = String::new();
+    let mut pending = vec![Piece::Value(value)];
+    while let Some(piece) = pending.pop() {
+        let value = match piece {
+            Piece::Text(text) => {
+                out += text;
+                continue;
+            }
+            Piece::Value(value) => value,
+        };
+        match value {
+            Value::Nil
+            | Value::True
+            | Value::False
+            | Value::Number(_)
+            | Value::BigInt(_)
+            | Value::Ratio(_)
+            | Value::String(_)
+            | Value::Keyword(_) => write!(out, "{}", value).ok()?,
+            // NaN and the infinities have no literal
+            Value::Float(x) if x.is_finite() => write!(out, "{}", value).ok()?,
+            // (quote (a b)) -> (a b)
+            Value::Quoted(expr) => write!(out, "({} {})", Special::Quote.name(), expr).ok()?,
+            Value::Pair(_) => {
+                let mut items = vec![];
+                let mut rest = value;
+                while let Value::Pair(pair) = rest {
+                    items.push(&pair.0);
+                    rest = &pair.1;
+                }
+                match rest {
+                    Value::Quoted(Expr::List(exprs, _)) if exprs.is_empty() => {
+                        push_seq(&mut pending, "(list", &items, ")")
+                    }
+                    tail => {
+                        items.push(tail);
+                        push_seq(&mut pending, "(list*", &items, ")")
+                    }
+                }
+            }
+            Value::Vector(items) => {
+                let items: Vec<&Value> = items.iter().collect();
+                push_seq(&mut pending, "[", &items, "]")
+            }
+            Value::Map(entries) => {
+                let entries: Vec<&Value> = deterministic::entries(entries)
+                    .into_iter()
+                    .flat_map(|(k, v)| [k, v])
+                    .collect();
+                push_seq(&mut pending, "{", &entries, "}")
+            }
+            Value::Lambda(lambda)
+                if lambda.scope.is_none()
+                    && Rc::ptr_eq(&lambda.env, env)
+                    && lambda.def.source.span().source.name != PRELUDE =>
+            {
+                out += &lambda.def.source.to_string()
+            }
+            Value::Float(_)
+            | Value::Lambda(_)
+            | Value::Memoized(_)
+            | Value::Curried(_)
+            | Value::Function(_)
+            | Value::AsyncFunction(_)
+            | Value::Native(_)
+            | Value::Generator(_) => return None,
+        }
+    }
+    Some(out)
+}
+
+/// pushes the pieces of open items close, separated by spaces, in reverse as the
+/// last piece pushed is written first
+fn push_seq<'v>(
+    pending: &mut Vec<Piece<'v>>,
+    open: &'static str,
+    items: &[&'v Value],
+    close: &'static str,
+) {
+    pending.push(Piece::Text(close));
+    for &item in items.iter().rev() {
+        pending.push(Piece::Value(item));
+        pending.push(Piece::Text(" "));
+    }
+    // no space after an opening bracket
+    if open.ends_with(['[', '{']) && !items.is_empty() {
+        pending.pop();
+    }
+    pending.push(Piece::Text(open));
+}
@@ -0,0 +1,219 @@
+// the navigable view of (inspect v): v as a tree with a numbered line per value,
+// its type and count, which expands into the values it holds or collapses back
+
+use std::collections::HashSet;
+
+use rustyline::{DefaultEditor, error::ReadlineError};
+
+use crate::{deterministic, env::Value, printer::PrettyPrinter};
+
+// how long the summary of a value on its line gets before it is cut
+const SUMMARY_WIDTH: usize = 60;
+
+const HELP: &str = "\
+N       expand or collapse line N
+*       expand every line
+-       collapse every line but the first
+q       leave, returning the value";
+
+/// the tree of a value, with which of its lines are expanded
+pub struct Inspector {
+    value: Value,
+    // paths of the expanded lines, as the indexes of the children taken from the root
+    expanded: HashSet<Vec<usize>>,
+    printer: PrettyPrinter,
+}
+
+/// a line of the tree
+struct Line {
+    path: Vec<usize>,
+    // the index or key the value is under in its parent
+    label: Option<String>,
+    value: Value,
+}
+
+impl Inspector {
+    /// value with its first level expanded
+    pub fn new(value: Value) -> Self {
+        Self {
+            value,
+            expanded: HashSet::from([vec![]]),
+            printer: PrettyPrinter {
+                width: usize::MAX,
+                max_depth: Some(2),
+                max_items: Some(8),
+            },
+        }
+    }
+
+    /// prompts for commands below the tree until q or the end of input
+    pub fn run(mut self) -> rustyline::Result<Value> {
+        let mut editor = DefaultEditor::new()?;
+        println!("{}", self.render());
+        loop {
+            let line = match editor.readline("inspect> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                Err(err) => return Err(err),
+            };
+            match line.trim() {
+                "q" | ":q" | "quit" => break,
+                "" => continue,
+                "*" => self.expand_all(),
+                "-" => self.expanded = HashSet::from([vec![]]),
+                "?" | "help" => {
+                    println!("{}", HELP);
+                    continue;
+                }
+                n => match n.parse() {
+                    Ok(n) if self.toggle(n) => {}
+                    _ => {
+                        println!("expected a line number, see ?");
+                        continue;
+                    }
+                },
+            }
+            println!("{}", self.render());
+        }
+        Ok(self.value)
+    }
+
+    /// the visible lines, numbered, indented by depth and marked + when
+    /// they can be expanded and - when they can be collapsed
+    /// 0 - :vector (2) [1 [2 3]]
+    /// 1     0 :int 1
+    /// 2   + 1 :vector (2) [2 3]
+    pub fn render(&self) -> String {
+        let lines = self.lines();
+        let number_width = (lines.len() - 1).to_string().len();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(n, line)| {
+                let marker = match children(&line.value).is_empty() {
+                    true => ' ',
+                    false if self.expanded.contains(&line.path) => '-',
+                    false => '+',
+                };
+                let label = line
+                    .label
+                    .as_ref()
+                    .map_or(String::new(), |label| format!("{} ", label));
+                let count = count(&line.value).map_or(String::new(), |n| format!(" ({})", n));
+                format!(
+                    "{:>number_width$} {:indent$}{} {}:{}{} {}",
+                    n,
+                    "",
+                    marker,
+                    label,
+                    line.value.type_keyword(),
+                    count,
+                    self.summary(&line.value),
+                    indent = 2 * line.path.len(),
+                )
+                .trim_end()
+                .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// expands line n if it is collapsed, collapses it if it is expanded,
+    /// returning false when there is no such line with children
+    pub fn toggle(&mut self, n: usize) -> bool {
+        let Some(line) = self.lines().into_iter().nth(n) else {
+            return false;
+        };
+        if children(&line.value).is_empty() {
+            return false;
+        }
+        if !self.expanded.remove(&line.path) {
+            self.expanded.insert(line.path);
+        }
+        true
+    }
+
+    fn expand_all(&mut self) {
+        let mut paths = vec![vec![]];
+        let mut values = vec![self.value.clone()];
+        while let (Some(path), Some(value)) = (paths.pop(), values.pop()) {
+            for (i, (_, child)) in children(&value).into_iter().enumerate() {
+                let mut path = path.clone();
+                path.push(i);
+                paths.push(path);
+                values.push(child);
+            }
+            self.expanded.insert(path);
+        }
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let mut lines = vec![];
+        let mut stack = vec![Line {
+            path: vec![],
+            label: None,
+            value: self.value.clone(),
+        }];
+        while let Some(line) = stack.pop() {
+            if self.expanded.contains(&line.path) {
+                // reversed, so the first child is popped first
+                for (i, (label, value)) in children(&line.value).into_iter().enumerate().rev() {
+                    let mut path = line.path.clone();
+                    path.push(i);
+                    stack.push(Line {
+                        path,
+                        label: Some(label),
+                        value,
+                    });
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// value on one line, cut after SUMMARY_WIDTH characters
+    fn summary(&self, value: &Value) -> String {
+        let summary = self.printer.print(value);
+        match summary.char_indices().nth(SUMMARY_WIDTH) {
+            Some((end, _)) => format!("{}...", &summary[..end]),
+            None => summary,
+        }
+    }
+}
+
+/// the values value holds, labelled by their index or key
+fn children(value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::Vector(items) => indexed(items.iter().cloned()),
+        Value::Map(entries) => deterministic::entries(entries)
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect(),
+        Value::Pair(pair) => match value.list_items() {
+            Some(items) => indexed(items),
+            None => vec![
+                ("car".to_string(), pair.0.clone()),
+                ("cdr".to_string(), pair.1.clone()),
+            ],
+        },
+        _ => vec![],
+    }
+}
+
+fn indexed(items: impl IntoIterator<Item = Value>) -> Vec<(String, Value)> {
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| (i.to_string(), item))
+        .collect()
+}
+
+/// how many values value holds, for collections and strings
+fn count(value: &Value) -> Option<usize> {
+    match value {
+        Value::String(s) => Some(s.chars().count()),
+        Value::Vector(_) | Value::Map(_) | Value::Pair(_) => Some(children(value).len()),
+        _ => None,
+    }
+}
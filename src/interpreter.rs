@@ -0,0 +1,327 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use crate::{
+    ast::Expr,
+    env::{Env, Value},
+    error::FelispError,
+    eval::{Limits, run, run_async},
+    hooks::{FrameEvent, Hooks, Stats, StepContext, Trace},
+    introspect,
+    ir::{Node, analyze},
+    namespace,
+    optimize::optimize,
+    output::{Port, Stream},
+    span::Source,
+    sync::{MaybeSend, MaybeSync, Rc},
+};
+
+// felisp definitions of the functions that need no builtin of their own
+pub(crate) const PRELUDE: &str = include_str!("prelude.lisp");
+
+/// entry point for embedding felisp: a root env and the limits
+/// every evaluation in it runs under
+pub struct Interpreter {
+    env: Rc<Env>,
+    limits: Limits,
+    optimize: bool,
+    hooks: Hooks,
+    // the child env of scoped, evaluated in instead of the current namespace
+    scope: Option<Rc<Env>>,
+}
+
+// with the sync feature, interpreters can be moved to other threads
+#[cfg(feature = "sync")]
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Interpreter>();
+};
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::with_env(Env::default())
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// aborts evaluations nesting deeper than max_depth, taking more than
+    /// max_steps evaluation steps or running for longer than timeout
+    pub fn with_limits(max_depth: usize, max_steps: u64, timeout: Duration) -> Self {
+        Self::new().limits(Limits {
+            max_depth,
+            max_steps: Some(max_steps),
+            timeout: Some(timeout),
+            interrupt: None,
+        })
+    }
+
+    /// evaluates in env instead of the default one,
+    /// binding env-keys, bound?, resolve, undef!, set-reader-macro! and stats in it
+    pub fn with_env(env: Env) -> Self {
+        let env = Rc::new(env);
+        introspect::register(&env);
+        // the env holds it, which must not keep it alive
+        let weak = Rc::downgrade(&env);
+        env.register_fn("set-reader-macro!", move |tag: Value, f: Value| {
+            let Value::Quoted(Expr::Atom(tag, _)) = tag else {
+                return Err(FelispError::bad_arg(0, "Symbol", &tag));
+            };
+            if let Some(env) = weak.upgrade() {
+                env.set_reader_macro(tag.name(), f);
+            }
+            Ok(Value::Nil)
+        });
+        let hooks = Hooks::default();
+        let counters = hooks.counters.clone();
+        env.register_fn("stats", move || Value::from(counters.stats()));
+        Self {
+            env,
+            limits: Limits::default(),
+            optimize: false,
+            hooks,
+            scope: None,
+        }
+    }
+
+    /// an interpreter without I/O builtins, for running untrusted code,
+    /// which should be given limits too, so that it can't loop forever
+    /// (Interpreter::sandboxed().limits(Limits { max_steps: Some(1_000_000), ..Limits::default() }))
+    /// what it guards against is the code, not the host: see Env::sandboxed
+    pub fn sandboxed() -> Self {
+        Self::with_env(Env::sandboxed())
+    }
+
+    /// runs the optimizer over every expression before evaluating it
+    /// see [`optimize`] for what it does
+    pub fn optimized(mut self) -> Self {
+        self.optimize = true;
+        self
+    }
+
+    /// makes lambdas called with fewer arguments than they require return a function
+    /// taking the rest, as if they were curried, instead of failing
+    pub fn auto_curry(self) -> Self {
+        self.env.set_auto_curry(true);
+        self
+    }
+
+    /// aborts evaluations going past limits, replacing those given before,
+    /// the flag of interruptible included
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// aborts evaluations with an Interrupted error once flag is set,
+    /// from a Ctrl-C handler for instance, clearing it
+    pub fn interruptible(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.limits.interrupt = Some(flag);
+        self
+    }
+
+    /// directories (require 'my.utils) looks for my/utils.lisp in, in turn,
+    /// instead of the current one
+    pub fn module_path(self, dirs: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        namespace::set_path(&self.env, dirs.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// keeps the parsed forms of the files eval_file and require load in dir,
+    /// so that loading them again needn't parse them, see src/cache.rs
+    pub fn module_cache(self, dir: impl Into<PathBuf>) -> Self {
+        namespace::set_cache(&self.env, Some(dir.into()));
+        self
+    }
+
+    /// calls f with what the code prints to *out*, which goes to stdout otherwise,
+    /// unless it redirects *out* itself with binding or with-out-str
+    pub fn redirect_out(self, f: impl FnMut(&str) + MaybeSend + 'static) -> Self {
+        self.env.output().set(Stream::Out, Port::host(f));
+        self
+    }
+
+    /// calls f with what the code prints to *err*, which goes to stderr otherwise
+    pub fn redirect_err(self, f: impl FnMut(&str) + MaybeSend + 'static) -> Self {
+        self.env.output().set(Stream::Err, Port::host(f));
+        self
+    }
+
+    /// calls f before evaluating each node
+    pub fn on_eval(mut self, f: impl Fn(&Node, &Env) + MaybeSync + 'static) -> Self {
+        self.hooks.on_eval = Some(Rc::new(f));
+        self
+    }
+
+    /// calls f before applying each function to its arguments
+    pub fn on_call(mut self, f: impl Fn(&Value, &[Value]) + MaybeSync + 'static) -> Self {
+        self.hooks.on_call = Some(Rc::new(f));
+        self
+    }
+
+    /// calls f with the error of each failed evaluation
+    pub fn on_error(mut self, f: impl Fn(&FelispError) + MaybeSync + 'static) -> Self {
+        self.hooks.on_error = Some(Rc::new(f));
+        self
+    }
+
+    /// calls f as each node is evaluated and with the value it evaluated to,
+    /// at the cost of tail calls, see Hooks::on_trace
+    pub fn on_trace(mut self, f: impl Fn(&Trace) + MaybeSync + 'static) -> Self {
+        self.hooks.on_trace = Some(Rc::new(f));
+        self
+    }
+
+    /// calls f before evaluating each node, with the variables in scope,
+    /// which debuggers can pause evaluation in, see StepContext
+    pub fn on_step(mut self, f: impl Fn(&StepContext) + MaybeSync + 'static) -> Self {
+        self.hooks.on_step = Some(Rc::new(f));
+        self
+    }
+
+    /// calls f as each function call starts and returns, see FrameEvent
+    pub fn on_frame(mut self, f: impl Fn(&FrameEvent) + MaybeSync + 'static) -> Self {
+        self.hooks.on_frame = Some(Rc::new(f));
+        self
+    }
+
+    /// what the evaluations of this interpreter did since the last reset_stats,
+    /// for performance work or quotas
+    pub fn stats(&self) -> Stats {
+        self.hooks.counters.stats()
+    }
+
+    pub fn reset_stats(&self) {
+        self.hooks.counters.reset();
+    }
+
+    /// the outermost env, that of the user namespace
+    pub fn env(&self) -> &Rc<Env> {
+        &self.env
+    }
+
+    /// the env forms are evaluated in: the scope within scoped,
+    /// or else the env of the current namespace
+    pub fn current_env(&self) -> Rc<Env> {
+        match &self.scope {
+            Some(scope) => scope.clone(),
+            None => namespace::current(&self.env),
+        }
+    }
+
+    /// calls f with an interpreter evaluating in a child env of the current one,
+    /// which def! binds in, leaving the current env as it was once f returns
+    /// (interpreter.scoped(|i| { i.eval_str(code)?; Ok(i.current_env().diff(&Snapshot::default())) }))
+    pub fn scoped<R>(&self, f: impl FnOnce(&Interpreter) -> R) -> R {
+        let scope = Env::new(Some(self.current_env()));
+        f(&Interpreter {
+            env: self.env.clone(),
+            limits: self.limits.clone(),
+            optimize: self.optimize,
+            hooks: self.hooks.clone(),
+            scope: Some(Rc::new(scope)),
+        })
+    }
+
+    /// the limits every evaluation runs under
+    pub fn current_limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// evaluates expr in the current namespace, see (ns name)
+    pub fn eval(&self, expr: &Expr) -> Result<Value, FelispError> {
+        self.eval_within(expr, &self.limits)
+    }
+
+    /// evaluates expr like eval, failing with a Timeout error once timeout has passed,
+    /// or sooner if the interpreter's own timeout is shorter, for servers evaluating
+    /// expressions of their users
+    /// the clock is checked between evaluation steps, not during a builtin call
+    pub fn eval_with_deadline(&self, expr: &Expr, timeout: Duration) -> Result<Value, FelispError> {
+        let limits = Limits {
+            timeout: Some(self.limits.timeout.map_or(timeout, |own| own.min(timeout))),
+            ..self.limits.clone()
+        };
+        self.eval_within(expr, &limits)
+    }
+
+    fn eval_within(&self, expr: &Expr, limits: &Limits) -> Result<Value, FelispError> {
+        self.analyze(expr)
+            .and_then(|node| run(node, self.current_env(), limits, &self.hooks))
+            .inspect_err(|err| self.hooks.error(err))
+    }
+
+    /// evaluates expr, awaiting the async functions it calls
+    /// instead of failing like eval
+    pub async fn eval_async(&self, expr: &Expr) -> Result<Value, FelispError> {
+        let node = self
+            .analyze(expr)
+            .inspect_err(|err| self.hooks.error(err))?;
+        run_async(node, self.current_env(), &self.limits, &self.hooks)
+            .await
+            .inspect_err(|err| self.hooks.error(err))
+    }
+
+    fn analyze(&self, expr: &Expr) -> Result<Node, FelispError> {
+        analyze(expr).map(|node| self.prepare(node))
+    }
+
+    /// node optimized, if the interpreter optimizes
+    fn prepare(&self, node: Node) -> Node {
+        if self.optimize {
+            optimize(node, &self.current_env())
+        } else {
+            node
+        }
+    }
+
+    /// evaluates every form of the file at path in turn, returning the value of the last
+    /// see Source::read for how it is read
+    pub fn eval_file(&self, path: impl AsRef<Path>) -> Result<Value, FelispError> {
+        let exprs = namespace::read_forms(&self.env, path.as_ref())
+            .inspect_err(|err| self.hooks.error(err))?;
+        self.eval_all(&exprs)
+    }
+
+    /// evaluates every form of source in turn, returning the value of the last
+    pub fn eval_source(&self, source: &Rc<Source>) -> Result<Value, FelispError> {
+        let exprs = Expr::parse_all(source).inspect_err(|err| self.hooks.error(err))?;
+        self.eval_all(&exprs)
+    }
+
+    /// every form is analyzed before any is evaluated, so that a malformed one
+    /// rejects the file without the effects of the forms above it
+    fn eval_all(&self, exprs: &[Expr]) -> Result<Value, FelispError> {
+        let nodes = exprs
+            .iter()
+            .map(analyze)
+            .collect::<Result<Vec<Node>, FelispError>>()
+            .inspect_err(|err| self.hooks.error(err))?;
+        let mut value = Value::Nil;
+        for node in nodes {
+            // optimized only now, as it looks up what the forms above defined
+            let node = self.prepare(node);
+            value = run(node, self.current_env(), &self.limits, &self.hooks)
+                .inspect_err(|err| self.hooks.error(err))?;
+        }
+        Ok(value)
+    }
+
+    /// defines not, inc, dec, first, second and the other functions of
+    /// src/prelude.lisp in the env, which a bare interpreter goes without
+    pub fn load_prelude(&self) -> Result<Value, FelispError> {
+        self.eval_source(&Source::new("<prelude>", PRELUDE.to_string()))
+    }
+
+    /// parses and evaluates source
+    pub fn eval_str(&self, source: &str) -> Result<Value, FelispError> {
+        let expr = Expr::parse(source.to_string()).inspect_err(|err| self.hooks.error(err))?;
+        self.eval(&expr)
+    }
+}
@@ -0,0 +1,83 @@
+// builtins looking into the env code runs in, for tooling written in felisp:
+// completers listing what is bound, doc browsers resolving names to values,
+// and undef! for tidying it up
+// they see the globals of the current namespace, not local variables
+
+use crate::{
+    ast::Expr,
+    env::{Env, Value},
+    error::FelispError,
+    namespace,
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::{Rc, Weak},
+};
+
+/// binds env-keys, bound?, resolve and undef! in env, looking into it when called
+pub fn register(env: &Rc<Env>) {
+    // the env holds them, which must not keep it alive
+    let weak = Rc::downgrade(env);
+    env.register_fn("env-keys", move || env_keys(&weak));
+    let weak = Rc::downgrade(env);
+    env.register_fn("bound?", move |symbol: Value| {
+        Ok::<_, FelispError>(resolve(&weak, &symbol)?.is_some())
+    });
+    let weak = Rc::downgrade(env);
+    env.register_fn("resolve", move |symbol: Value| {
+        Ok::<_, FelispError>(resolve(&weak, &symbol)?.unwrap_or(Value::Nil))
+    });
+    let weak = Rc::downgrade(env);
+    env.register_fn("undef!", move |symbol: Value| undef(&weak, &symbol));
+}
+
+/// the symbols bound in the current namespace, sorted
+/// (env-keys) -> (* + - / <= ...)
+fn env_keys(env: &Weak<Env>) -> Value {
+    let mut symbols = env
+        .upgrade()
+        .map_or(vec![], |env| namespace::current(&env).symbols());
+    symbols.sort_by_key(|symbol| symbol.name());
+    symbols.dedup();
+    let empty = Value::Quoted(Expr::List(Rc::from([]), span("")));
+    symbols.into_iter().rev().fold(empty, |rest, symbol| {
+        Value::Pair(Rc::new((symbol_value(symbol), rest)))
+    })
+}
+
+/// the value of a symbol, qualified or bound in the current namespace
+/// (resolve 'inc) -> <fun>
+/// (bound? 'undefined) -> false
+fn resolve(env: &Weak<Env>, symbol: &Value) -> Result<Option<Value>, FelispError> {
+    let Value::Quoted(Expr::Atom(symbol, _)) = symbol else {
+        return Err(FelispError::bad_arg(0, "Symbol", symbol));
+    };
+    let Some(env) = env.upgrade() else {
+        return Ok(None);
+    };
+    Ok(match symbol.qualified() {
+        Some(_) => namespace::lookup(&env.root(), *symbol),
+        None => namespace::current(&env).get(*symbol).ok(),
+    })
+}
+
+/// unbinds a symbol of the current namespace, returning whether it was bound
+/// bindings of defconst can't be removed
+/// (do (def! tmp 1) (undef! 'tmp)) -> true
+fn undef(env: &Weak<Env>, symbol: &Value) -> Result<bool, FelispError> {
+    let Value::Quoted(Expr::Atom(symbol, _)) = symbol else {
+        return Err(FelispError::bad_arg(0, "Symbol", symbol));
+    };
+    let Some(env) = env.upgrade() else {
+        return Ok(false);
+    };
+    Ok(namespace::current(&env).remove(*symbol)?.is_some())
+}
+
+fn symbol_value(symbol: Symbol) -> Value {
+    Value::Quoted(Expr::Atom(symbol, span(symbol.name())))
+}
+
+// made up, as values have no source
+fn span(text: &str) -> Span {
+    Span::new(&Source::new("<env-keys>", text.to_string()), 0, text.len())
+}
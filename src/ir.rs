@@ -0,0 +1,1154 @@
+use std::{collections::HashMap, iter};
+
+use crate::{
+    ast::{Expr, Special},
+    bindings::Bindings,
+    collections,
+    env::{Builtin, NativeFn, Value},
+    error::{FelispError, KEYWORDS},
+    numeric,
+    output::Stream,
+    span::Span,
+    symbol::Symbol,
+    sync::Rc,
+};
+
+/// an expression once analyzed: special forms dispatched, literals folded
+/// and local variables resolved to slots of the enclosing scopes
+#[derive(Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+impl Node {
+    /// the nodes it is made of, including the bodies of the functions it makes
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.kind {
+            NodeKind::Const(_)
+            | NodeKind::Local { .. }
+            | NodeKind::Global(_)
+            | NodeKind::Stream(_)
+            | NodeKind::Ns(_)
+            | NodeKind::Require(_)
+            | NodeKind::Retry => vec![],
+            NodeKind::Def(_, value, _) | NodeKind::Yield(value) => vec![value],
+            NodeKind::Let(_, values, body) | NodeKind::WithOpen(_, values, body) => {
+                values.iter().chain([&**body]).collect()
+            }
+            NodeKind::Do(nodes)
+            | NodeKind::Call(nodes, _)
+            | NodeKind::CallEc(nodes)
+            | NodeKind::Generator(nodes)
+            | NodeKind::WithOutStr(nodes) => nodes.iter().collect(),
+            NodeKind::If(cond, then, otherwise) => [cond, then]
+                .into_iter()
+                .chain(otherwise)
+                .map(|node| &**node)
+                .collect(),
+            NodeKind::Fn(def) => def.arities.iter().map(|arity| &arity.body).collect(),
+            NodeKind::WithHandler(handlers, body) => handlers
+                .iter()
+                .map(|(_, handler)| handler)
+                .chain(body.iter())
+                .collect(),
+            NodeKind::Case(value, table) => [&**value]
+                .into_iter()
+                .chain(&table.branches)
+                .chain(&table.default)
+                .collect(),
+            NodeKind::Binding(ports, body) => ports
+                .iter()
+                .map(|(_, port)| port)
+                .chain(body.iter())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum NodeKind {
+    // literal or quoted data
+    Const(Value),
+    // slot index of the scope depth levels up from the current one
+    Local {
+        depth: usize,
+        index: usize,
+        name: Symbol,
+    },
+    // binding of the global env
+    Global(Symbol),
+    // *out* or *err*, unless bound locally
+    Stream(Stream),
+    // (def! name value), or defonce or defconst
+    Def(Symbol, Rc<Node>, Definition),
+    // (let* (p1 v1 p2 v2 ...) body), vi evaluated then bound by pattern pi
+    // to the next slots of a new scope
+    Let(Rc<Bindings>, Rc<[Node]>, Rc<Node>),
+    // (do e1 e2 ...)
+    Do(Rc<[Node]>),
+    // (if cond then else?)
+    If(Rc<Node>, Rc<Node>, Option<Rc<Node>>),
+    // (fn* (params) body), or (fn* ((params) body) ...) for several arities
+    Fn(Rc<FnDef>),
+    // (f x1 x2 ...), named after the expression in call position
+    Call(Rc<[Node]>, Symbol),
+    // (ns name)
+    Ns(Symbol),
+    // (require 'name)
+    Require(Symbol),
+    // (call/ec f), f alone
+    CallEc(Rc<[Node]>),
+    // (generator e1 e2 ...)
+    Generator(Rc<[Node]>),
+    // (yield value)
+    Yield(Rc<Node>),
+    // (with-handler (kind1 handler1 kind2 handler2 ...) e1 e2 ...)
+    WithHandler(Rc<[(Symbol, Node)]>, Rc<[Node]>),
+    // (retry)
+    Retry,
+    // (case x k1 e1 k2 e2 ... default?)
+    Case(Rc<Node>, Rc<CaseTable>),
+    // (with-out-str e1 e2 ...)
+    WithOutStr(Rc<[Node]>),
+    // (binding (stream1 port1 stream2 port2 ...) e1 e2 ...)
+    Binding(Rc<[(Stream, Node)]>, Rc<[Node]>),
+    // (with-open (p1 r1 p2 r2 ...) body), bound as by let*, then the ri closed
+    // in reverse once the body returns or raises
+    WithOpen(Rc<Bindings>, Rc<[Node]>, Rc<Node>),
+}
+
+/// the branches of a case, looked up by the value of its key expression
+pub struct CaseTable {
+    // index in branches of the branch of each key
+    pub keys: HashMap<Value, usize>,
+    pub branches: Vec<Node>,
+    // evaluated when no key is the value, nil if absent
+    pub default: Option<Node>,
+}
+
+/// how a global is bound
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Definition {
+    // by def!, again each time
+    Var,
+    // by defonce, only while unbound
+    Once,
+    // by defconst, for good
+    Const,
+}
+
+/// the static part of a closure, params bound to slots of a new scope
+pub struct FnDef {
+    // a single one, unless written (fn* ((x) x) ((x y) (+ x y)))
+    pub arities: Rc<[Arity]>,
+    // given as in (fn* fact (n) ...), bound to the function in its body
+    pub name: Option<Symbol>,
+    // the fn* form it was analyzed from
+    pub source: Expr,
+}
+
+/// a (params) body... clause of a fn*
+pub struct Arity {
+    pub params: Rc<Bindings>,
+    pub body: Node,
+}
+
+impl FnDef {
+    /// the arity called with n arguments: the one with n params, or else
+    /// the one with a & taking as many
+    /// with a single arity, it is that one, which reports a wrong count when bound
+    pub fn arity(&self, n: usize) -> Result<&Arity, FelispError> {
+        if let [arity] = &self.arities[..] {
+            return Ok(arity);
+        }
+        let takes = |arity: &&Arity, variadic: bool| {
+            let required = arity.params.patterns.len();
+            match arity.params.rest {
+                None => !variadic && required == n,
+                Some(_) => variadic && required <= n,
+            }
+        };
+        self.arities
+            .iter()
+            .find(|arity| takes(arity, false))
+            .or_else(|| self.arities.iter().find(|arity| takes(arity, true)))
+            .ok_or_else(|| FelispError::ArityError(format!("fn has no arity taking {} args", n)))
+    }
+
+    /// the fewest arguments one of its arities takes
+    pub fn min_arity(&self) -> usize {
+        self.arities
+            .iter()
+            .map(|arity| arity.params.patterns.len())
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// names bound by a let* or fn*, of which the first bound are in scope
+struct Scope {
+    names: Vec<Symbol>,
+    bound: usize,
+}
+
+/// enclosing scopes, innermost last
+#[derive(Default)]
+struct Scopes(Vec<Scope>);
+
+impl Scopes {
+    /// (depth, index) of the innermost binding of symbol, if any
+    /// closures may refer to let* bindings made after them, so that
+    /// (let* (f (fn* (n) (f n))) ...) can recurse
+    fn resolve(&self, symbol: Symbol) -> Option<(usize, usize)> {
+        self.0.iter().rev().enumerate().find_map(|(depth, scope)| {
+            scope.names[..scope.bound]
+                .iter()
+                .rposition(|&name| name == symbol)
+                .or_else(|| match depth {
+                    0 => None,
+                    _ => scope.names[scope.bound..]
+                        .iter()
+                        .position(|&name| name == symbol)
+                        .map(|index| scope.bound + index),
+                })
+                .map(|index| (depth, index))
+        })
+    }
+
+    fn with<T>(&mut self, names: Vec<Symbol>, bound: usize, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.0.push(Scope { names, bound });
+        let result = f(self);
+        self.0.pop();
+        result
+    }
+}
+
+pub fn analyze(expr: &Expr) -> Result<Node, FelispError> {
+    analyze_expr(expr, &mut Scopes::default())
+}
+
+/// expr with the derived forms in it rewritten into the core forms they stand for,
+/// as the analyzer sees them, leaving quoted forms alone
+/// (deftest t (is x)) -> (def! t (fn* () (do (is x))))
+pub fn expand(expr: &Expr) -> Result<Expr, FelispError> {
+    let expand_all =
+        |exprs: &[Expr]| -> Result<Rc<[Expr]>, FelispError> { exprs.iter().map(expand).collect() };
+    match expr {
+        Expr::List(exprs, span) => match exprs.first() {
+            Some(Expr::Special(Special::Quote, _)) => Ok(expr.clone()),
+            Some(Expr::Special(Special::Deftest, _)) => {
+                expand(&expand_deftest(exprs).map_err(|err| err.at(span.clone()))?)
+            }
+            Some(Expr::Special(Special::Defprotocol, _)) => {
+                expand(&expand_defprotocol(exprs).map_err(|err| err.at(span.clone()))?)
+            }
+            Some(Expr::Special(Special::ExtendType, _)) => {
+                expand(&expand_extend_type(exprs).map_err(|err| err.at(span.clone()))?)
+            }
+            Some(Expr::Special(
+                special @ (Special::When | Special::Unless | Special::IfNot),
+                _,
+            )) => expand(&expand_conditional(*special, exprs).map_err(|err| err.at(span.clone()))?),
+            Some(Expr::Special(special @ (Special::ThreadFirst | Special::ThreadLast), _)) => {
+                expand(&expand_threading(*special, exprs).map_err(|err| err.at(span.clone()))?)
+            }
+            _ => Ok(Expr::List(expand_all(exprs)?, span.clone())),
+        },
+        Expr::Vector(exprs, span) => Ok(Expr::Vector(expand_all(exprs)?, span.clone())),
+        Expr::Map(exprs, span) => Ok(Expr::Map(expand_all(exprs)?, span.clone())),
+        _ => Ok(expr.clone()),
+    }
+}
+
+fn analyze_expr(expr: &Expr, scopes: &mut Scopes) -> Result<Node, FelispError> {
+    let span = expr.span().clone();
+    let kind = match expr {
+        Expr::Nil(_) => NodeKind::Const(Value::Nil),
+        Expr::Bool(true, _) => NodeKind::Const(Value::True),
+        Expr::Bool(false, _) => NodeKind::Const(Value::False),
+        Expr::Number(n, _) => NodeKind::Const(Value::Number(*n)),
+        Expr::Numeric(..) => NodeKind::Const(quoted(expr)?),
+        Expr::String(s, _) => NodeKind::Const(Value::String(s.clone())),
+        Expr::Keyword(name, _) => NodeKind::Const(Value::Keyword(*name)),
+        Expr::Special(special, _) => {
+            return Err(FelispError::SyntaxError(format!(
+                "special form '{}' can only be used in call position",
+                special.name()
+            ))
+            .at(span));
+        }
+        Expr::Atom(..) if expr.is_dot() => {
+            return Err(FelispError::SyntaxError(
+                "'.' can only be used in quoted lists".to_string(),
+            )
+            .at(span));
+        }
+        Expr::Atom(symbol, _) => match scopes.resolve(*symbol) {
+            Some((depth, index)) => NodeKind::Local {
+                depth,
+                index,
+                name: *symbol,
+            },
+            None => match Stream::from_name(*symbol) {
+                Some(stream) => NodeKind::Stream(stream),
+                None => NodeKind::Global(*symbol),
+            },
+        },
+        Expr::Vector(exprs, _) => {
+            analyze_literal(exprs, "vector", collections::vector, &span, scopes)?
+        }
+        Expr::Map(exprs, _) => {
+            analyze_literal(exprs, "hash-map", collections::hash_map, &span, scopes)?
+        }
+        // the empty list, which unlike nil is true
+        Expr::List(exprs, _) if exprs.is_empty() => NodeKind::Const(Value::Quoted(expr.clone())),
+        Expr::List(exprs, _) if expr.tagged().is_some() => {
+            analyze_tagged(exprs).map_err(|err| err.at(span.clone()))?
+        }
+        Expr::List(exprs, _) => match exprs[0] {
+            Expr::Special(special, _) => analyze_special(special, exprs, scopes),
+            _ => analyze_call(exprs, scopes),
+        }
+        .map_err(|err| err.at(span.clone()))?,
+    };
+    Ok(Node { kind, span })
+}
+
+fn analyze_all(exprs: &[Expr], scopes: &mut Scopes) -> Result<Rc<[Node]>, FelispError> {
+    exprs
+        .iter()
+        .map(|expr| analyze_expr(expr, scopes))
+        .collect()
+}
+
+/// [a b] and {k v} are folded when all their items are constant,
+/// and otherwise built by calling build once their items are evaluated
+fn analyze_literal(
+    exprs: &[Expr],
+    name: &str,
+    build: Builtin,
+    span: &Span,
+    scopes: &mut Scopes,
+) -> Result<NodeKind, FelispError> {
+    let items = analyze_all(exprs, scopes)?;
+    let consts: Option<Vec<Value>> = items
+        .iter()
+        .map(|item| match &item.kind {
+            NodeKind::Const(value) => Some(value.clone()),
+            _ => None,
+        })
+        .collect();
+    if let Some(values) = consts {
+        return Ok(NodeKind::Const(build(&values)?));
+    }
+    let head = Node {
+        kind: NodeKind::Const(NativeFn::new(name, "& items", build).into()),
+        span: span.clone(),
+    };
+    let exprs = iter::once(head).chain(items.iter().cloned()).collect();
+    Ok(NodeKind::Call(exprs, Symbol::intern(name)))
+}
+
+fn analyze_special(
+    special: Special,
+    exprs: &[Expr],
+    scopes: &mut Scopes,
+) -> Result<NodeKind, FelispError> {
+    match special {
+        Special::Def => analyze_def(exprs, Definition::Var, scopes),
+        Special::Defonce => analyze_def(exprs, Definition::Once, scopes),
+        Special::Defconst => analyze_def(exprs, Definition::Const, scopes),
+        Special::Let => {
+            let (bindings, values, body) = analyze_let(special, exprs, scopes)?;
+            Ok(NodeKind::Let(bindings, values, body))
+        }
+        Special::WithOpen => {
+            let (bindings, values, body) = analyze_let(special, exprs, scopes)?;
+            Ok(NodeKind::WithOpen(bindings, values, body))
+        }
+        Special::Do => Ok(NodeKind::Do(analyze_all(&exprs[1..], scopes)?)),
+        Special::If => analyze_if(exprs, scopes),
+        Special::Quote => analyze_quote(exprs),
+        Special::Fn => analyze_fn(exprs, scopes),
+        Special::Deftest => analyze_deftest(exprs, scopes),
+        Special::Is => analyze_is(exprs, scopes),
+        Special::Ns => Ok(NodeKind::Ns(namespace_arg(exprs)?)),
+        Special::Require => Ok(NodeKind::Require(namespace_arg(exprs)?)),
+        Special::CallEc => analyze_call_ec(exprs, scopes),
+        Special::Generator => Ok(NodeKind::Generator(analyze_all(&exprs[1..], scopes)?)),
+        Special::Yield => analyze_yield(exprs, scopes),
+        Special::Defprotocol => Ok(analyze_expr(&expand_defprotocol(exprs)?, scopes)?.kind),
+        Special::ExtendType => Ok(analyze_expr(&expand_extend_type(exprs)?, scopes)?.kind),
+        Special::WithHandler => analyze_with_handler(exprs, scopes),
+        Special::Retry => analyze_retry(exprs),
+        Special::Case => analyze_case(exprs, scopes),
+        Special::WithOutStr => Ok(NodeKind::WithOutStr(analyze_all(&exprs[1..], scopes)?)),
+        Special::Binding => analyze_binding(exprs, scopes),
+        Special::When | Special::Unless | Special::IfNot => {
+            Ok(analyze_expr(&expand_conditional(special, exprs)?, scopes)?.kind)
+        }
+        Special::ThreadFirst | Special::ThreadLast => {
+            Ok(analyze_expr(&expand_threading(special, exprs)?, scopes)?.kind)
+        }
+    }
+}
+
+/// binds (evaluated) expr to symbol in the global env
+/// (def! symbol expr)
+/// (defonce symbol expr) only evaluates expr while symbol is unbound there
+/// (defconst symbol expr) binds it for good, def! can't bind it again
+fn analyze_def(
+    exprs: &[Expr],
+    definition: Definition,
+    scopes: &mut Scopes,
+) -> Result<NodeKind, FelispError> {
+    let form = exprs[0].name();
+    if exprs.len() != 3 {
+        return Err(FelispError::ArityError(format!(
+            "{} requires 2 arguments",
+            form
+        )));
+    }
+    let name = match &exprs[1] {
+        Expr::Atom(name, _) if name.qualified().is_none() => *name,
+        name => {
+            return Err(FelispError::SyntaxError(format!(
+                "{} expected a symbol to bind got '{}'",
+                form, name
+            ))
+            .at(name.span().clone()));
+        }
+    };
+    let value = analyze_expr(&exprs[2], scopes)?;
+    Ok(NodeKind::Def(name, Rc::new(value), definition))
+}
+
+// the bindings, values and body of a let* or with-open
+type LetParts = (Rc<Bindings>, Rc<[Node]>, Rc<Node>);
+
+/// evaluates the body in a new scope with (evaluated) vi bound to pattern pi,
+/// a symbol or a vector destructuring it, see src/bindings.rs
+/// (let* (p1 v1 p2 v2 ...) body...), or with-open
+fn analyze_let(
+    special: Special,
+    exprs: &[Expr],
+    scopes: &mut Scopes,
+) -> Result<LetParts, FelispError> {
+    if exprs.len() < 2 {
+        return Err(FelispError::ArityError(format!(
+            "{} requires at least 1 argument",
+            special.name()
+        )));
+    }
+    let (bindings, value_exprs) = Bindings::pairs(&exprs[1])?;
+    scopes.with(bindings.names.to_vec(), 0, |scopes| {
+        let values = bindings
+            .patterns
+            .iter()
+            .zip(value_exprs)
+            .map(|(pattern, value)| {
+                let value = analyze_expr(value, scopes)?;
+                scopes.0.last_mut().unwrap().bound += pattern.len();
+                Ok(value)
+            })
+            .collect::<Result<Rc<[Node]>, FelispError>>()?;
+        let body = analyze_body(&exprs[2..], exprs[0].span(), scopes)?;
+        Ok((Rc::new(bindings), values, Rc::new(body)))
+    })
+}
+
+/// the forms of a let* or fn* body, evaluated in turn, nil if there are none
+fn analyze_body(body: &[Expr], head: &Span, scopes: &mut Scopes) -> Result<Node, FelispError> {
+    match body {
+        [expr] => analyze_expr(expr, scopes),
+        _ => {
+            let span = match (body.first(), body.last()) {
+                (Some(first), Some(last)) => {
+                    Span::new(&first.span().source, first.span().start, last.span().end)
+                }
+                _ => head.clone(),
+            };
+            let kind = NodeKind::Do(analyze_all(body, scopes)?);
+            Ok(Node { kind, span })
+        }
+    }
+}
+
+/// (if cond then else?) -> evaluates cond
+/// if it is nil or false, evaluates and returns else (nil if absent)
+/// otherwise evaluates and returns then
+fn analyze_if(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() <= 2 {
+        return Err(FelispError::ArityError(
+            "if requires at least 2 arguments".to_string(),
+        ));
+    }
+    if exprs.len() > 4 {
+        return Err(FelispError::ArityError(
+            "if requires at most 3 arguments".to_string(),
+        ));
+    }
+    let cond = analyze_expr(&exprs[1], scopes)?;
+    let then = analyze_expr(&exprs[2], scopes)?;
+    let otherwise = match exprs.get(3) {
+        Some(expr) => Some(Rc::new(analyze_expr(expr, scopes)?)),
+        None => None,
+    };
+    Ok(NodeKind::If(Rc::new(cond), Rc::new(then), otherwise))
+}
+
+/// returns its argument unevaluated
+/// (quote a) -> a, '(1 2) -> (1 2)
+fn analyze_quote(exprs: &[Expr]) -> Result<NodeKind, FelispError> {
+    if exprs.len() != 2 {
+        return Err(FelispError::ArityError(
+            "quote requires 1 argument".to_string(),
+        ));
+    }
+    Ok(NodeKind::Const(quoted(&exprs[1])?))
+}
+
+/// the value of a quoted form: literals are values already, vectors, maps and lists
+/// those of their quoted items, lists as chains of pairs, while symbols are kept as forms
+/// '[1 a] -> [1 a], '(1 . 2) -> (1 . 2)
+pub fn quoted(expr: &Expr) -> Result<Value, FelispError> {
+    let all =
+        |exprs: &[Expr]| -> Result<Vec<Value>, FelispError> { exprs.iter().map(quoted).collect() };
+    let value = match expr {
+        Expr::Nil(_) => Value::Nil,
+        Expr::Bool(true, _) => Value::True,
+        Expr::Bool(false, _) => Value::False,
+        Expr::Number(n, _) => Value::Number(*n),
+        Expr::Numeric(n, _) => numeric::parse(n).expect("the reader checked it"),
+        Expr::String(s, _) => Value::String(s.clone()),
+        Expr::Keyword(name, _) => Value::Keyword(*name),
+        Expr::Vector(exprs, _) => Value::Vector(all(exprs)?.into_iter().collect()),
+        Expr::Map(exprs, _) => collections::hash_map(&all(exprs)?)?,
+        Expr::List(exprs, span) if !exprs.is_empty() => {
+            let (items, last) = match exprs.len().checked_sub(2) {
+                Some(dot) if exprs[dot].is_dot() => (&exprs[..dot], quoted(&exprs[dot + 1])?),
+                _ => (
+                    &exprs[..],
+                    Value::Quoted(Expr::List(Rc::from([]), span.clone())),
+                ),
+            };
+            all(items)?
+                .into_iter()
+                .rev()
+                .fold(last, |rest, item| Value::Pair(Rc::new((item, rest))))
+        }
+        Expr::Special(..) | Expr::Atom(..) | Expr::List(..) => Value::Quoted(expr.clone()),
+    };
+    Ok(value)
+}
+
+/// returns a lambda that once called, evaluates the body with the given arguments
+/// bound to its params, which may destructure them or take the rest after a &
+/// a name before the params is bound to the lambda in its body, so that it can recurse
+/// (fn* (a) a) -> `<fun>`
+/// ((fn* (a b) (+ a b)) 2 3) -> 5
+/// ((fn* ([a b] & more) more) [1 2] 3 4) -> [3 4]
+/// ((fn* fact (n) (if (<= n 1) 1 (* n (fact (- n 1))))) 5) -> 120
+fn analyze_fn(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() < 2 {
+        return Err(FelispError::ArityError(
+            "fn* requires at least 1 argument".to_string(),
+        ));
+    }
+    let (first, last) = (exprs[0].span(), exprs[exprs.len() - 1].span());
+    let span = Span::new(&first.source, first.start, last.end.max(first.end));
+    let source = Expr::List(exprs.into(), span.clone());
+    let (name, clauses) = match &exprs[1] {
+        Expr::Atom(name, _) if exprs.len() > 2 => (name, &exprs[2..]),
+        _ => return analyze_lambda(&exprs[1..], None, source, exprs[0].span(), scopes),
+    };
+    // as (let* (name (fn* params body...)) name), the let* binding being
+    // seen from the body, as the closures of let* bindings see them
+    let bindings = Expr::List(
+        Rc::from([exprs[1].clone(), Expr::Nil(span.clone())]),
+        span.clone(),
+    );
+    let (bindings, _) = Bindings::pairs(&bindings)?;
+    scopes.with(vec![*name], 0, |scopes| {
+        let kind = analyze_lambda(clauses, Some(*name), source, exprs[0].span(), scopes)?;
+        scopes.0.last_mut().unwrap().bound += 1;
+        let body = analyze_expr(&exprs[1], scopes)?;
+        Ok(NodeKind::Let(
+            Rc::new(bindings),
+            Rc::new([Node { kind, span }]),
+            Rc::new(body),
+        ))
+    })
+}
+
+/// the lambda of the params and body, or clauses, of a fn* after its name if any
+fn analyze_lambda(
+    args: &[Expr],
+    name: Option<Symbol>,
+    source: Expr,
+    head: &Span,
+    scopes: &mut Scopes,
+) -> Result<NodeKind, FelispError> {
+    let clauses = if is_multi_arity(args) {
+        args.iter()
+            .map(|clause| match clause {
+                Expr::List(clause, _) if matches!(clause.first(), Some(Expr::List(..))) => {
+                    Ok(&clause[..])
+                }
+                _ => Err(FelispError::SyntaxError(format!(
+                    "fn* expected a (params body...) clause got '{}'",
+                    clause
+                ))
+                .at(clause.span().clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![args]
+    };
+    let mut arities: Vec<Arity> = vec![];
+    for clause in clauses {
+        let params = Bindings::params(&clause[0])?;
+        let (required, variadic) = (params.patterns.len(), params.rest.is_some());
+        let clash = arities
+            .iter()
+            .any(|arity| match (variadic, &arity.params.rest) {
+                (false, None) => arity.params.patterns.len() == required,
+                (true, Some(_)) => true,
+                _ => false,
+            });
+        if clash {
+            return Err(FelispError::SyntaxError(if variadic {
+                "fn* can have only one arity with &".to_string()
+            } else {
+                format!("fn* has two arities taking {} args", required)
+            })
+            .at(clause[0].span().clone()));
+        }
+        let names = params.names.to_vec();
+        let bound = names.len();
+        let body = scopes.with(names, bound, |scopes| {
+            analyze_body(&clause[1..], head, scopes)
+        })?;
+        arities.push(Arity {
+            params: Rc::new(params),
+            body,
+        });
+    }
+    Ok(NodeKind::Fn(Rc::new(FnDef {
+        arities: arities.into(),
+        name,
+        source,
+    })))
+}
+
+/// whether the arguments of a fn* are clauses of several arities rather than
+/// params and a body: (fn* ((x) x) ((x y) (+ x y)))
+pub fn is_multi_arity(args: &[Expr]) -> bool {
+    match args.first() {
+        Some(Expr::List(clause, _)) => matches!(clause.first(), Some(Expr::List(..))),
+        _ => false,
+    }
+}
+
+/// binds name to a function of no arguments evaluating the body,
+/// which felisp test finds among the top-level forms of a file and calls
+fn analyze_deftest(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    Ok(analyze_expr(&expand_deftest(exprs)?, scopes)?.kind)
+}
+
+/// (deftest name body...) -> (def! name (fn* () (do body...)))
+fn expand_deftest(exprs: &[Expr]) -> Result<Expr, FelispError> {
+    if exprs.len() < 3 {
+        return Err(FelispError::ArityError(
+            "deftest requires at least 2 arguments".to_string(),
+        ));
+    }
+    let span = exprs[0].span().clone();
+    let special = |special| Expr::Special(special, span.clone());
+    let list = |exprs: Vec<Expr>| Expr::List(exprs.into(), span.clone());
+    let body = list(
+        iter::once(special(Special::Do))
+            .chain(exprs[2..].iter().cloned())
+            .collect(),
+    );
+    let value = list(vec![special(Special::Fn), list(vec![]), body]);
+    Ok(list(vec![special(Special::Def), exprs[1].clone(), value]))
+}
+
+/// when, unless and if-not, as the if they stand for
+/// (when c body...) -> (if c (do body...))
+/// (unless c body...) -> (if c nil (do body...))
+/// (if-not c a b) -> (if c b a)
+fn expand_conditional(special: Special, exprs: &[Expr]) -> Result<Expr, FelispError> {
+    let name = special.name();
+    let (min, max) = match special {
+        Special::IfNot => (2, 3),
+        _ => (1, usize::MAX),
+    };
+    if exprs.len() - 1 < min {
+        return Err(FelispError::ArityError(format!(
+            "{} requires at least {} argument{}",
+            name,
+            min,
+            if min == 1 { "" } else { "s" }
+        )));
+    }
+    if exprs.len() - 1 > max {
+        return Err(FelispError::ArityError(format!(
+            "{} requires at most {} arguments",
+            name, max
+        )));
+    }
+    let span = exprs[0].span().clone();
+    let list = |exprs: Vec<Expr>| Expr::List(exprs.into(), span.clone());
+    let nil = Expr::Nil(span.clone());
+    let body = || {
+        list(
+            iter::once(Expr::Special(Special::Do, span.clone()))
+                .chain(exprs[2..].iter().cloned())
+                .collect(),
+        )
+    };
+    let (then, otherwise) = match special {
+        Special::When => (body(), None),
+        Special::Unless => (nil, Some(body())),
+        _ => (exprs.get(3).cloned().unwrap_or(nil), Some(exprs[2].clone())),
+    };
+    let cond = [
+        Expr::Special(Special::If, span.clone()),
+        exprs[1].clone(),
+        then,
+    ];
+    Ok(list(cond.into_iter().chain(otherwise).collect()))
+}
+
+/// -> and ->>, as the nested calls they stand for, threading x through each form
+/// as its first argument or its last, and a symbol f standing for the call (f)
+/// (-> x (f a) g) -> (g (f x a))
+/// (->> x (f a) g) -> (g (f a x))
+fn expand_threading(special: Special, exprs: &[Expr]) -> Result<Expr, FelispError> {
+    let Some(x) = exprs.get(1) else {
+        return Err(FelispError::ArityError(format!(
+            "{} requires at least 1 argument",
+            special.name()
+        )));
+    };
+    let mut threaded = x.clone();
+    for form in &exprs[2..] {
+        let span = form.span().clone();
+        let call = match form {
+            Expr::List(items, _) if !items.is_empty() => {
+                let mut items = items.to_vec();
+                match special {
+                    Special::ThreadFirst => items.insert(1, threaded),
+                    _ => items.push(threaded),
+                }
+                items
+            }
+            _ => vec![form.clone(), threaded],
+        };
+        threaded = Expr::List(call.into(), span);
+    }
+    Ok(threaded)
+}
+
+/// binds the protocol name and a function per method, calling the implementation
+/// for the type of its first argument, see src/protocol.rs
+/// (defprotocol show (render [x])) ->
+/// (do (def! show (make-protocol 'show '(render)))
+///     (def! render (fn* (x) ((protocol-method show 'render x) x)))
+///     show)
+fn expand_defprotocol(exprs: &[Expr]) -> Result<Expr, FelispError> {
+    if exprs.len() < 3 {
+        return Err(FelispError::ArityError(
+            "defprotocol requires at least 2 arguments".to_string(),
+        ));
+    }
+    let span = exprs[0].span().clone();
+    let special = |special| Expr::Special(special, span.clone());
+    let list = |exprs: Vec<Expr>| Expr::List(exprs.into(), span.clone());
+    let atom = |name| Expr::Atom(Symbol::intern(name), span.clone());
+    let quote = |expr| list(vec![special(Special::Quote), expr]);
+    let name = match &exprs[1] {
+        Expr::Atom(name, _) if name.qualified().is_none() => exprs[1].clone(),
+        name => {
+            return Err(FelispError::SyntaxError(format!(
+                "defprotocol expected a symbol to bind got '{}'",
+                name
+            ))
+            .at(name.span().clone()));
+        }
+    };
+    let mut methods = vec![];
+    let mut defs = vec![];
+    for signature in &exprs[2..] {
+        let parsed = match signature {
+            Expr::List(items, _) => match &items[..] {
+                [method @ Expr::Atom(..), params] => method_params(params)
+                    .filter(|params| !params.is_empty())
+                    .map(|params| (method, params)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some((method, params)) = parsed else {
+            return Err(FelispError::SyntaxError(format!(
+                "defprotocol expected a (method [params]) signature got '{}'",
+                signature
+            ))
+            .at(signature.span().clone()));
+        };
+        // errors of the function point at its signature
+        let at = |exprs: Vec<Expr>| Expr::List(exprs.into(), signature.span().clone());
+        let implementation = at(vec![
+            atom("protocol-method"),
+            name.clone(),
+            quote(method.clone()),
+            params[0].clone(),
+        ]);
+        let call = at(iter::once(implementation).chain(params.clone()).collect());
+        let function = at(vec![special(Special::Fn), list(params), call]);
+        defs.push(list(vec![special(Special::Def), method.clone(), function]));
+        methods.push(method.clone());
+    }
+    let protocol = list(vec![
+        atom("make-protocol"),
+        quote(name.clone()),
+        quote(list(methods)),
+    ]);
+    Ok(list(
+        iter::once(special(Special::Do))
+            .chain([list(vec![special(Special::Def), name.clone(), protocol])])
+            .chain(defs)
+            .chain([name])
+            .collect(),
+    ))
+}
+
+/// the symbols of [x y] or (x y), the params of a method of a protocol
+fn method_params(params: &Expr) -> Option<Vec<Expr>> {
+    let (Expr::Vector(params, _) | Expr::List(params, _)) = params else {
+        return None;
+    };
+    params
+        .iter()
+        .map(|param| match param {
+            Expr::Atom(name, _)
+                if name.qualified().is_none() && !param.is_dot() && name.name() != "&" =>
+            {
+                Some(param.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// implements methods of a protocol for a type, see src/protocol.rs
+/// (extend-type :vector show (render [v] body...)) ->
+/// (do (protocol-extend show :vector 'render (fn* (v) body...)) nil)
+fn expand_extend_type(exprs: &[Expr]) -> Result<Expr, FelispError> {
+    if exprs.len() < 4 {
+        return Err(FelispError::ArityError(
+            "extend-type requires at least 3 arguments".to_string(),
+        ));
+    }
+    let span = exprs[0].span().clone();
+    let special = |special| Expr::Special(special, span.clone());
+    let list = |exprs: Vec<Expr>| Expr::List(exprs.into(), span.clone());
+    let (kind, protocol) = (&exprs[1], &exprs[2]);
+    let extends = exprs[3..].iter().map(|implementation| {
+        let Expr::List(implementation, implementation_span) = implementation else {
+            return Err(implementation.clone());
+        };
+        let [method @ Expr::Atom(..), params, body @ ..] = &implementation[..] else {
+            return Err(Expr::List(
+                implementation.clone(),
+                implementation_span.clone(),
+            ));
+        };
+        let (Expr::Vector(params, params_span) | Expr::List(params, params_span)) = params else {
+            return Err(Expr::List(
+                implementation.clone(),
+                implementation_span.clone(),
+            ));
+        };
+        let function = Expr::List(
+            [
+                special(Special::Fn),
+                Expr::List(params.clone(), params_span.clone()),
+            ]
+            .into_iter()
+            .chain(body.iter().cloned())
+            .collect(),
+            implementation_span.clone(),
+        );
+        Ok(list(vec![
+            Expr::Atom(Symbol::intern("protocol-extend"), span.clone()),
+            protocol.clone(),
+            kind.clone(),
+            list(vec![special(Special::Quote), method.clone()]),
+            function,
+        ]))
+    });
+    let extends = extends
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|implementation| {
+            FelispError::SyntaxError(format!(
+                "extend-type expected a (method [params] body...) implementation got '{}'",
+                implementation
+            ))
+            .at(implementation.span().clone())
+        })?;
+    Ok(list(
+        iter::once(special(Special::Do))
+            .chain(extends)
+            .chain([Expr::Nil(span.clone())])
+            .collect(),
+    ))
+}
+
+/// true if expr is, an assertion failure quoting it otherwise
+/// (is (<= 1 2)) -> true
+/// (is (<= 2 1)) -> assertion failed: (<= 2 1)
+fn analyze_is(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() != 2 {
+        return Err(FelispError::ArityError(
+            "is requires 1 argument".to_string(),
+        ));
+    }
+    let span = exprs[1].span().clone();
+    let cond = analyze_expr(&exprs[1], scopes)?;
+    let constant = |value| Node {
+        kind: NodeKind::Const(value),
+        span: span.clone(),
+    };
+    let fail = Node {
+        kind: NodeKind::Call(
+            Rc::new([
+                constant(NativeFn::new("is", "form", assertion_failed).into()),
+                constant(Value::String(span.text().into())),
+            ]),
+            Symbol::intern("is"),
+        ),
+        span: span.clone(),
+    };
+    Ok(NodeKind::If(
+        Rc::new(cond),
+        Rc::new(constant(Value::True)),
+        Some(Rc::new(fail)),
+    ))
+}
+
+fn assertion_failed(args: &[Value]) -> Result<Value, FelispError> {
+    let form = String::try_from(args[0].clone())?;
+    Err(FelispError::AssertionFailed(form))
+}
+
+/// the namespace named by (ns name), (require name) or (require 'name)
+/// ns makes it the one top-level forms are evaluated in from the next one on,
+/// require evaluates its module, see src/namespace.rs
+fn namespace_arg(exprs: &[Expr]) -> Result<Symbol, FelispError> {
+    let special = exprs[0].name();
+    if exprs.len() != 2 {
+        return Err(FelispError::ArityError(format!(
+            "{} requires 1 argument",
+            special
+        )));
+    }
+    let name = match &exprs[1] {
+        Expr::List(quoted, _) if special.name() == "require" => match &quoted[..] {
+            [Expr::Special(Special::Quote, _), name] => name,
+            _ => &exprs[1],
+        },
+        name => name,
+    };
+    match name {
+        Expr::Atom(name, _) if name.qualified().is_none() => Ok(*name),
+        _ => Err(FelispError::SyntaxError(format!(
+            "{} expected a namespace name got '{}'",
+            special, exprs[1]
+        ))),
+    }
+}
+
+/// calls f with an escape continuation k, (k v) returning v from the call/ec
+/// as long as it hasn't returned yet, wherever it is called from
+/// (call/ec (fn* (return) (do (return 1) 2))) -> 1
+fn analyze_call_ec(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() != 2 {
+        return Err(FelispError::ArityError(
+            "call/ec requires 1 argument".to_string(),
+        ));
+    }
+    Ok(NodeKind::CallEc(analyze_all(&exprs[1..], scopes)?))
+}
+
+/// returns value from the call that resumed the generator the yield is in,
+/// which the next call resumes from there, (yield value) itself returning nil
+/// (next (generator (yield 1) (yield 2))) -> 1
+fn analyze_yield(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() != 2 {
+        return Err(FelispError::ArityError(
+            "yield requires 1 argument".to_string(),
+        ));
+    }
+    Ok(NodeKind::Yield(Rc::new(analyze_expr(&exprs[1], scopes)?)))
+}
+
+/// evaluates the body, but if it raises an error of a kind named by a keyword of
+/// error::KEYWORDS, or any kind for :error, returns what the handler for it returns
+/// when called with the error as a map, see FelispError::value
+/// (with-handler (:type-error (fn* (e) 0)) (+ 1 nil)) -> 0
+fn analyze_with_handler(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() < 2 {
+        return Err(FelispError::ArityError(
+            "with-handler requires at least 1 argument".to_string(),
+        ));
+    }
+    let clauses = match &exprs[1] {
+        Expr::List(clauses, _) if clauses.len().is_multiple_of(2) => clauses,
+        clauses => {
+            return Err(FelispError::SyntaxError(format!(
+                "with-handler expected (:kind handler ...) got '{}'",
+                clauses
+            ))
+            .at(clauses.span().clone()));
+        }
+    };
+    let handlers = clauses
+        .chunks(2)
+        .map(|clause| match &clause[0] {
+            Expr::Keyword(kind, _) if kind.name() == "error" || KEYWORDS.contains(&kind.name()) => {
+                Ok((*kind, analyze_expr(&clause[1], scopes)?))
+            }
+            kind => Err(FelispError::SyntaxError(format!(
+                "with-handler expected an error kind got '{}'",
+                kind
+            ))
+            .at(kind.span().clone())),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(NodeKind::WithHandler(
+        handlers,
+        analyze_all(&exprs[2..], scopes)?,
+    ))
+}
+
+/// evaluates the body with the streams redirected to the ports, (evaluated) in turn,
+/// including in the functions it calls, until it returns or escapes
+/// (binding (*out* (open-output "log.txt") *err* *out*) (run))
+fn analyze_binding(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() < 2 {
+        return Err(FelispError::ArityError(
+            "binding requires at least 1 argument".to_string(),
+        ));
+    }
+    let clauses = match &exprs[1] {
+        Expr::List(clauses, _) if clauses.len().is_multiple_of(2) => clauses,
+        clauses => {
+            return Err(FelispError::SyntaxError(format!(
+                "binding expected (*out* port ...) got '{}'",
+                clauses
+            ))
+            .at(clauses.span().clone()));
+        }
+    };
+    let ports = clauses
+        .chunks(2)
+        .map(|clause| {
+            let stream = match &clause[0] {
+                Expr::Atom(name, _) => Stream::from_name(*name),
+                _ => None,
+            };
+            match stream {
+                Some(stream) => Ok((stream, analyze_expr(&clause[1], scopes)?)),
+                None => Err(FelispError::SyntaxError(format!(
+                    "binding expected *out* or *err* got '{}'",
+                    clause[0]
+                ))
+                .at(clause[0].span().clone())),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(NodeKind::Binding(ports, analyze_all(&exprs[2..], scopes)?))
+}
+
+/// in a handler of with-handler, even in a function it calls, evaluates
+/// the body of the with-handler again instead of returning from the handler
+/// (with-handler (:thrown (fn* (e) (retry))) (connect))
+fn analyze_retry(exprs: &[Expr]) -> Result<NodeKind, FelispError> {
+    if exprs.len() != 1 {
+        return Err(FelispError::ArityError(
+            "retry requires 0 arguments".to_string(),
+        ));
+    }
+    Ok(NodeKind::Retry)
+}
+
+/// the branch of the key equal to the value of x, or the default if there is one,
+/// keys being literals not evaluated, and lists of keys sharing their branch
+/// the branch is found in a table built here rather than by testing keys in turn
+/// (case x 1 "one" (2 3) "two or three" "other")
+/// (case op add + sub - :unknown-op)
+fn analyze_case(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    if exprs.len() < 2 {
+        return Err(FelispError::ArityError(
+            "case requires at least 1 argument".to_string(),
+        ));
+    }
+    let value = analyze_expr(&exprs[1], scopes)?;
+    let mut clauses = exprs[2..].chunks_exact(2);
+    let mut table = CaseTable {
+        keys: HashMap::new(),
+        branches: vec![],
+        default: None,
+    };
+    for clause in clauses.by_ref() {
+        let keys = match &clause[0] {
+            Expr::List(keys, _) if !keys.is_empty() => &keys[..],
+            key => std::slice::from_ref(key),
+        };
+        for key in keys {
+            let value = quoted(key)?;
+            if !value.is_hashable() {
+                return Err(FelispError::SyntaxError(format!(
+                    "case keys must be hashable, got '{}'",
+                    key
+                ))
+                .at(key.span().clone()));
+            }
+            if table.keys.insert(value, table.branches.len()).is_some() {
+                return Err(
+                    FelispError::SyntaxError(format!("case has the key '{}' twice", key))
+                        .at(key.span().clone()),
+                );
+            }
+        }
+        table.branches.push(analyze_expr(&clause[1], scopes)?);
+    }
+    if let [default] = clauses.remainder() {
+        table.default = Some(analyze_expr(default, scopes)?);
+    }
+    Ok(NodeKind::Case(Rc::new(value), Rc::new(table)))
+}
+
+/// #tag form -> (#tag 'form), a call of the reader macro bound to #tag
+/// by set-reader-macro! on the unevaluated form
+fn analyze_tagged(exprs: &[Expr]) -> Result<NodeKind, FelispError> {
+    let name = exprs[0].name();
+    let head = Node {
+        kind: NodeKind::Global(name),
+        span: exprs[0].span().clone(),
+    };
+    let form = Node {
+        kind: NodeKind::Const(quoted(&exprs[1])?),
+        span: exprs[1].span().clone(),
+    };
+    Ok(NodeKind::Call(Rc::new([head, form]), name))
+}
+
+/// (f x1 x2 ...) -> (apply f x1 x2 ...)
+fn analyze_call(exprs: &[Expr], scopes: &mut Scopes) -> Result<NodeKind, FelispError> {
+    Ok(NodeKind::Call(analyze_all(exprs, scopes)?, exprs[0].name()))
+}
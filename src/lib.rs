@@ -0,0 +1,59 @@
+pub mod ast;
+pub mod bindings;
+pub mod cache;
+pub mod collections;
+pub mod convert;
+pub mod deterministic;
+#[cfg(feature = "digest")]
+pub mod digest;
+#[cfg(feature = "encoding")]
+pub mod encoding;
+pub mod env;
+pub mod error;
+pub mod eval;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(any(feature = "toml", feature = "yaml", feature = "csv"))]
+pub mod formats;
+pub mod hooks;
+pub mod image;
+#[cfg(feature = "repl")]
+pub mod inspect;
+pub mod interpreter;
+pub mod introspect;
+pub mod ir;
+pub mod lint;
+pub mod logging;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod namespace;
+pub mod native;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod numbers;
+pub mod numeric;
+pub mod optimize;
+pub mod output;
+pub mod printer;
+pub mod profile;
+pub mod protocol;
+pub mod prune;
+#[cfg(feature = "repl")]
+pub mod repl;
+pub mod resource;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod span;
+pub mod symbol;
+pub mod sync;
+#[cfg(feature = "sync")]
+pub mod thread;
+#[cfg(feature = "time")]
+pub mod time;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::FelispError;
+pub use interpreter::Interpreter;
@@ -0,0 +1,419 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    ast::{Expr, Special},
+    bindings::pattern_names,
+    env::Env,
+    ir::{analyze, expand, is_multi_arity},
+    output::Stream,
+    span::Span,
+    symbol::Symbol,
+};
+
+/// a likely mistake, found without evaluating the code
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Warning {
+    /// the message followed by a snippet of the source it is about
+    pub fn report(&self) -> String {
+        format!("{}\n{}", self, self.span.snippet())
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "warning: {}", self.message)
+    }
+}
+
+/// checks the top-level forms of a file, as if evaluated in env, for
+/// malformed special forms, e.g. (if c a b d),
+/// symbols bound neither locally, in env nor by a def! of the file,
+/// let* bindings that are never used,
+/// code that never runs, after a throw or in a branch an if never takes,
+/// and functions looping by calling themselves other than in tail position
+pub fn lint(exprs: &[Expr], env: &Env) -> Vec<Warning> {
+    let mut linter = Linter {
+        env,
+        defined: HashSet::new(),
+        scopes: vec![],
+        warnings: vec![],
+    };
+    for expr in exprs {
+        linter.collect_defs(expr);
+    }
+    for expr in exprs {
+        if let Err(err) = analyze(expr) {
+            let span = err.span().unwrap_or(expr.span()).clone();
+            linter.warn(err.to_string(), &span);
+        }
+        linter.walk(expr);
+    }
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+/// a name bound by a let* or fn*
+struct Binding {
+    name: Symbol,
+    span: Span,
+    used: bool,
+}
+
+struct Linter<'a> {
+    env: &'a Env,
+    // names the file defines with def! or deftest, wherever it does
+    defined: HashSet<Symbol>,
+    // bindings of the enclosing let* and fn*, innermost last
+    scopes: Vec<Vec<Binding>>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, message: String, span: &Span) {
+        self.warnings.push(Warning {
+            message,
+            span: span.clone(),
+        });
+    }
+
+    fn collect_defs(&mut self, expr: &Expr) {
+        match expr {
+            Expr::List(exprs, _) | Expr::Vector(exprs, _) | Expr::Map(exprs, _) => {
+                if let [Expr::Special(special, _), name, ..] = &exprs[..]
+                    && special.defines()
+                {
+                    self.defined.insert(name.name());
+                }
+                // the protocol and its methods
+                if let [Expr::Special(Special::Defprotocol, _), ..] = &exprs[..]
+                    && let Ok(expanded) = expand(expr)
+                {
+                    self.collect_defs(&expanded);
+                }
+                for expr in exprs.iter() {
+                    self.collect_defs(expr);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// marks the innermost binding of name as used, if there is one
+    fn resolve(&mut self, name: Symbol) -> bool {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|binding| binding.name == name));
+        binding.map(|binding| binding.used = true).is_some()
+    }
+
+    fn walk(&mut self, expr: &Expr) {
+        match expr {
+            // qualified symbols are bound by modules, which lint doesn't load
+            Expr::Atom(name, span)
+                if name.qualified().is_none()
+                    && !self.resolve(*name)
+                    && !self.defined.contains(name)
+                    && Stream::from_name(*name).is_none()
+                    && self.env.get(*name).is_err() =>
+            {
+                self.warn(format!("unknown symbol '{}'", name), span);
+            }
+            // the reader macro of a tagged literal may be set at run time,
+            // and its form is data
+            Expr::List(..) if expr.tagged().is_some() => {}
+            Expr::List(exprs, _) => match &exprs[..] {
+                // malformed, which analyze reports
+                [
+                    Expr::Special(Special::Defprotocol | Special::ExtendType, _),
+                    ..,
+                ] => {
+                    if let Ok(expanded) = expand(expr) {
+                        self.walk(&expanded);
+                    }
+                }
+                [Expr::Special(special, _), args @ ..] => self.walk_special(*special, args),
+                _ => self.walk_all(exprs),
+            },
+            Expr::Vector(exprs, _) | Expr::Map(exprs, _) => self.walk_all(exprs),
+            _ => {}
+        }
+    }
+
+    fn walk_all(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.walk(expr);
+        }
+    }
+
+    fn walk_special(&mut self, special: Special, args: &[Expr]) {
+        match (special, args) {
+            (Special::Quote | Special::Ns | Special::Require, _) => {}
+            (Special::Def | Special::Defonce | Special::Defconst, [name, value @ ..]) => {
+                // a named fn* checks itself
+                if let [Expr::List(f, _)] = value
+                    && let [Expr::Special(Special::Fn, _), clauses @ ..] = &f[..]
+                    && !matches!(clauses, [Expr::Atom(..), _, ..])
+                {
+                    self.check_recursion(name.name(), clauses);
+                }
+                self.walk_all(value)
+            }
+            (Special::Deftest, [_, body @ ..])
+            | (Special::Do | Special::Generator | Special::WithOutStr, body) => {
+                self.walk_body(body)
+            }
+            // the resources of a with-open are used by closing them
+            (Special::Let | Special::WithOpen, [Expr::List(bindings, _), body @ ..])
+                if bindings.len().is_multiple_of(2) =>
+            {
+                self.scopes.push(vec![]);
+                for pair in bindings.chunks(2) {
+                    self.walk(&pair[1]);
+                    for name in pattern_names(&pair[0]) {
+                        self.bind(name, special == Special::WithOpen);
+                    }
+                }
+                self.walk_all(body);
+                for binding in self.scopes.pop().unwrap_or_default() {
+                    if !binding.used && !binding.name.name().starts_with('_') {
+                        self.warn(
+                            format!("let* binding '{}' is never used", binding.name),
+                            &binding.span,
+                        );
+                    }
+                }
+            }
+            (Special::Fn, clauses) if is_multi_arity(clauses) => {
+                for clause in clauses {
+                    match clause {
+                        Expr::List(clause, _) => self.walk_special(Special::Fn, clause),
+                        _ => self.walk(clause),
+                    }
+                }
+            }
+            (Special::Fn, [name @ Expr::Atom(..), rest @ ..]) if !rest.is_empty() => {
+                self.check_recursion(name.name(), rest);
+                self.scopes.push(vec![]);
+                self.bind(name, true);
+                self.walk_special(Special::Fn, rest);
+                self.scopes.pop();
+            }
+            (Special::Fn, [Expr::List(params, _), body @ ..]) => {
+                self.scopes.push(vec![]);
+                for param in params.iter().flat_map(pattern_names) {
+                    self.bind(param, true);
+                }
+                self.walk_all(body);
+                self.scopes.pop();
+            }
+            (Special::If, [cond, branches @ ..]) => {
+                self.walk(cond);
+                self.walk_all(branches);
+                let never = match cond {
+                    Expr::Nil(_) | Expr::Bool(false, _) => branches.first().zip(Some("never")),
+                    Expr::Bool(true, _)
+                    | Expr::Number(..)
+                    | Expr::Numeric(..)
+                    | Expr::String(..)
+                    | Expr::Keyword(..) => branches.get(1).zip(Some("always")),
+                    // (), which is true unlike nil
+                    Expr::List(items, _) if items.is_empty() => branches.get(1).zip(Some("always")),
+                    _ => None,
+                };
+                if let Some((branch, always)) = never {
+                    self.warn(
+                        format!("unreachable code, as {} is {} true", cond, always),
+                        branch.span(),
+                    );
+                }
+            }
+            // keys are never evaluated, only the branches and the default
+            (Special::Case, [value, clauses @ ..]) => {
+                self.walk(value);
+                for (i, clause) in clauses.iter().enumerate() {
+                    if i % 2 == 1 || i == clauses.len() - 1 {
+                        self.walk(clause);
+                    }
+                }
+            }
+            // malformed, which analyze reports
+            (_, args) => self.walk_all(args),
+        }
+    }
+
+    /// warns of the calls a function named name makes to itself other than in tail
+    /// position, which each take a frame until they return, when it looks like a loop:
+    /// no evaluation of its body calls it more than once, unlike (+ (fib ...) (fib ...))
+    fn check_recursion(&mut self, name: Symbol, clauses: &[Expr]) {
+        let clauses = match clauses {
+            clauses if is_multi_arity(clauses) => clauses
+                .iter()
+                .filter_map(|clause| match clause {
+                    Expr::List(clause, _) => Some(&clause[..]),
+                    _ => None,
+                })
+                .collect(),
+            clauses => vec![clauses],
+        };
+        let mut calls = vec![];
+        let mut most = 0;
+        for clause in clauses {
+            if let [Expr::List(params, _) | Expr::Vector(params, _), body @ ..] = clause
+                && !params
+                    .iter()
+                    .flat_map(pattern_names)
+                    .any(|p| p.name() == name)
+            {
+                most = most.max(self_calls_in(body, name, true, &mut calls));
+            }
+        }
+        if most > 1 {
+            return;
+        }
+        for (span, tail) in calls {
+            if !tail {
+                self.warn(
+                    format!(
+                        "'{}' calls itself outside tail position, so deep inputs may exceed the max depth",
+                        name
+                    ),
+                    &span,
+                );
+            }
+        }
+    }
+
+    /// forms evaluated in turn, of which those after a throw never are
+    fn walk_body(&mut self, body: &[Expr]) {
+        self.walk_all(body);
+        let throw = body.iter().position(|expr| match expr {
+            Expr::List(exprs, _) => match exprs.first() {
+                Some(Expr::Atom(name, _)) => {
+                    name.name() == "throw" && !self.scopes.iter().flatten().any(|b| b.name == *name)
+                }
+                _ => false,
+            },
+            _ => false,
+        });
+        if let Some(next) = throw.and_then(|i| body.get(i + 1)) {
+            self.warn("unreachable code after throw".to_string(), next.span());
+        }
+    }
+
+    /// binds name in the innermost scope, as used already if it needn't be
+    fn bind(&mut self, name: &Expr, used: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding {
+                name: name.name(),
+                span: name.span().clone(),
+                used,
+            });
+        }
+    }
+}
+
+/// the calls of name in expr, with whether each is in tail position if expr is,
+/// returning the most of them one evaluation of expr makes
+/// calls from functions expr creates are theirs, and shadowed names are others
+fn self_calls(expr: &Expr, name: Symbol, tail: bool, calls: &mut Vec<(Span, bool)>) -> usize {
+    let Expr::List(exprs, span) = expr else {
+        return match expr {
+            Expr::Vector(exprs, _) | Expr::Map(exprs, _) => self_calls_each(exprs, name, calls),
+            _ => 0,
+        };
+    };
+    if expr.tagged().is_some() {
+        return 0;
+    }
+    match &exprs[..] {
+        [Expr::Atom(head, _), args @ ..] if *head == name => {
+            calls.push((span.clone(), tail));
+            1 + self_calls_each(args, name, calls)
+        }
+        [
+            Expr::Special(Special::Quote | Special::Fn | Special::Ns | Special::Require, _),
+            ..,
+        ] => 0,
+        [
+            Expr::Special(
+                Special::When
+                | Special::Unless
+                | Special::IfNot
+                | Special::ThreadFirst
+                | Special::ThreadLast,
+                _,
+            ),
+            ..,
+        ] => match expand(expr) {
+            Ok(expanded) => self_calls(&expanded, name, tail, calls),
+            Err(_) => 0,
+        },
+        [Expr::Special(Special::Do, _), body @ ..] => self_calls_in(body, name, tail, calls),
+        [
+            Expr::Special(Special::Let, _),
+            Expr::List(bindings, _),
+            body @ ..,
+        ] => {
+            let mut count = 0;
+            for pair in bindings.chunks(2) {
+                count += pair
+                    .get(1)
+                    .map_or(0, |value| self_calls(value, name, false, calls));
+                if pattern_names(&pair[0])
+                    .iter()
+                    .any(|bound| bound.name() == name)
+                {
+                    return count;
+                }
+            }
+            count + self_calls_in(body, name, tail, calls)
+        }
+        [Expr::Special(Special::If, _), cond, branches @ ..] => {
+            let count = self_calls(cond, name, false, calls);
+            count
+                + most(
+                    branches
+                        .iter()
+                        .map(|branch| self_calls(branch, name, tail, calls)),
+                )
+        }
+        [Expr::Special(Special::Case, _), value, clauses @ ..] => {
+            let count = self_calls(value, name, false, calls);
+            let branches = clauses
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| i % 2 == 1 || *i == clauses.len() - 1);
+            count + most(branches.map(|(_, branch)| self_calls(branch, name, tail, calls)))
+        }
+        _ => self_calls_each(exprs, name, calls),
+    }
+}
+
+fn most(counts: impl Iterator<Item = usize>) -> usize {
+    counts.max().unwrap_or(0)
+}
+
+/// the calls of name in a body, the last form of which is in tail position if it is
+fn self_calls_in(body: &[Expr], name: Symbol, tail: bool, calls: &mut Vec<(Span, bool)>) -> usize {
+    let last = body.len().saturating_sub(1);
+    body.iter()
+        .enumerate()
+        .map(|(i, expr)| self_calls(expr, name, tail && i == last, calls))
+        .sum()
+}
+
+/// the calls of name in exprs, none of which is in tail position
+fn self_calls_each(exprs: &[Expr], name: Symbol, calls: &mut Vec<(Span, bool)>) -> usize {
+    exprs
+        .iter()
+        .map(|expr| self_calls(expr, name, false, calls))
+        .sum()
+}
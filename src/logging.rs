@@ -0,0 +1,42 @@
+use log::Level;
+
+use crate::{env::Value, error::FelispError};
+
+/// target of the records felisp code logs, which filters like RUST_LOG=felisp=debug select
+pub const TARGET: &str = "felisp";
+
+/// logs its arguments at the error level, through the log facade
+/// (log-error "lost" 3 "items") -> nil
+pub fn log_error(args: &[Value]) -> Result<Value, FelispError> {
+    emit(Level::Error, args)
+}
+
+/// (log-warn "retrying in" 5) -> nil
+pub fn log_warn(args: &[Value]) -> Result<Value, FelispError> {
+    emit(Level::Warn, args)
+}
+
+/// (log-info "started") -> nil
+pub fn log_info(args: &[Value]) -> Result<Value, FelispError> {
+    emit(Level::Info, args)
+}
+
+/// (log-debug "state" {1 2}) -> nil
+pub fn log_debug(args: &[Value]) -> Result<Value, FelispError> {
+    emit(Level::Debug, args)
+}
+
+/// logs the arguments separated by spaces, strings without their quotes
+fn emit(level: Level, args: &[Value]) -> Result<Value, FelispError> {
+    if log::log_enabled!(target: TARGET, level) {
+        let message: Vec<String> = args
+            .iter()
+            .map(|arg| match arg {
+                Value::String(s) => s.to_string(),
+                arg => arg.to_string(),
+            })
+            .collect();
+        log::log!(target: TARGET, level, "{}", message.join(" "));
+    }
+    Ok(Value::Nil)
+}
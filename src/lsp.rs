@@ -0,0 +1,368 @@
+// a minimal language server, speaking JSON-RPC over stdin and stdout:
+// diagnostics from the reader and lint as documents change, the definitions of
+// def!'d symbols, the comments above them on hover and completion of known names
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{Value as Json, json};
+
+use crate::{
+    ast::{Expr, Special, is_delimiter},
+    env::Value,
+    interpreter::{Interpreter, PRELUDE},
+    lint::lint,
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::Rc,
+};
+
+// JSON-RPC error code for requests the server doesn't implement
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// answers the requests read from input on output, until the client exits
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::new();
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// the next message, after its Content-Length header, None at the end of input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+struct Server {
+    // text of the open documents, by uri
+    documents: HashMap<String, String>,
+    // with the builtins and prelude, which lint and completion know of
+    interpreter: Interpreter,
+    exited: bool,
+}
+
+impl Server {
+    fn new() -> Self {
+        let interpreter = Interpreter::new();
+        interpreter
+            .load_prelude()
+            .expect("the prelude evaluates without errors");
+        Self {
+            documents: HashMap::new(),
+            interpreter,
+            exited: false,
+        }
+    }
+
+    /// the replies to message, a response if it is a request,
+    /// and the notifications it leads to
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let id = &message["id"];
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    // the whole text on every change
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "felisp" },
+            }),
+            "shutdown" => Json::Null,
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )];
+            }
+            "textDocument/definition" => self.definition(uri, &params["position"]),
+            "textDocument/hover" => self.hover(uri, &params["position"]),
+            "textDocument/completion" => self.completion(uri),
+            // notifications, like initialized, need no reply
+            _ if id.is_null() => return vec![],
+            method => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("{} is not supported", method),
+                    },
+                })];
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    /// errors of the reader, or the warnings of lint if it could read the document
+    fn diagnostics(&self, uri: &str) -> Json {
+        let text = self.documents.get(uri).cloned().unwrap_or_default();
+        let source = Source::new(uri, text);
+        let diagnostics: Vec<Json> = match Expr::parse_all(&source) {
+            Ok(exprs) => lint(&exprs, self.interpreter.env())
+                .iter()
+                .map(|warning| diagnostic(&warning.span, 2, &warning.message))
+                .collect(),
+            Err(err) => {
+                let span = err.span().cloned().unwrap_or(Span::new(&source, 0, 0));
+                vec![diagnostic(&span, 1, &err.to_string())]
+            }
+        };
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /// where the symbol at position is def!'d in the document, if it is
+    fn definition(&self, uri: &str, position: &Json) -> Json {
+        let Some((text, name)) = self.symbol_at(uri, position) else {
+            return Json::Null;
+        };
+        match definitions(&Source::new(uri, text), name) {
+            Some((name, _)) => json!({ "uri": uri, "range": range(&name) }),
+            None => Json::Null,
+        }
+    }
+
+    /// the first line of the definition of the symbol at position, in the
+    /// document or prelude, and the comments above it
+    fn hover(&self, uri: &str, position: &Json) -> Json {
+        let Some((text, name)) = self.symbol_at(uri, position) else {
+            return Json::Null;
+        };
+        let documented = [(uri, text), ("<prelude>", PRELUDE.to_string())]
+            .into_iter()
+            .find_map(|(uri, text)| definitions(&Source::new(uri, text), name));
+        let doc = match documented {
+            Some((_, form)) => {
+                let code = form.text().lines().next().unwrap_or_default();
+                format!("```lisp\n{}\n```\n{}", code, comments_above(&form))
+            }
+            None if Special::from_name(name.name()).is_some() => {
+                format!("`{}` special form", name)
+            }
+            None => match &self.interpreter.env().get(name) {
+                Ok(Value::Function(f)) => format!("```lisp\n{}\n```\nbuiltin", f.signature()),
+                Ok(value) => format!("`{}` builtin {}", name, value.type_name()),
+                Err(_) => return Json::Null,
+            },
+        };
+        json!({ "contents": { "kind": "markdown", "value": doc } })
+    }
+
+    /// the special forms, builtins, prelude functions and what the document defines
+    fn completion(&self, uri: &str) -> Json {
+        let mut items: HashMap<Symbol, i64> = HashMap::new();
+        for symbol in self.interpreter.env().symbols() {
+            let function = matches!(
+                self.interpreter.env().get(symbol),
+                Ok(Value::Function(_)
+                    | Value::AsyncFunction(_)
+                    | Value::Lambda(_)
+                    | Value::Memoized(_)
+                    | Value::Curried(_))
+            );
+            // LSP CompletionItemKind Function or Variable
+            items.insert(symbol, if function { 3 } else { 6 });
+        }
+        if let Some(text) = self.documents.get(uri)
+            && let Ok(exprs) = Expr::parse_all(&Source::new(uri, text.clone()))
+        {
+            for expr in &exprs {
+                for_each_def(expr, &mut |name, _| {
+                    items.entry(name.name()).or_insert(6);
+                });
+            }
+        }
+        for special in Special::ALL {
+            // Keyword
+            items.insert(Symbol::intern(special.name()), 14);
+        }
+        let mut items: Vec<(Symbol, i64)> = items.into_iter().collect();
+        items.sort_by_key(|(symbol, _)| symbol.name());
+        items
+            .into_iter()
+            .map(|(symbol, kind)| json!({ "label": symbol.name(), "kind": kind }))
+            .collect()
+    }
+
+    /// text of the document and the symbol at position in it
+    fn symbol_at(&self, uri: &str, position: &Json) -> Option<(String, Symbol)> {
+        let text = self.documents.get(uri)?;
+        let offset = offset(text, position)?;
+        let is_symbol = |c: char| !is_delimiter(c) && c.is_ascii_graphic();
+        let start = text[..offset].rfind(|c| !is_symbol(c)).map_or(0, |i| i + 1);
+        let end = text[offset..]
+            .find(|c| !is_symbol(c))
+            .map_or(text.len(), |i| offset + i);
+        let word = &text[start..end];
+        (!word.is_empty()).then(|| (text.clone(), Symbol::intern(word)))
+    }
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// severity 1 for errors, 2 for warnings
+fn diagnostic(span: &Span, severity: u8, message: &str) -> Json {
+    json!({
+        "range": range(span),
+        "severity": severity,
+        "source": "felisp",
+        "message": message,
+    })
+}
+
+fn range(span: &Span) -> Json {
+    let text = &span.source.text;
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// LSP position of a byte offset: its line and UTF-16 column
+fn position(text: &str, offset: usize) -> Json {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// byte offset of an LSP position, if text has its line
+fn offset(text: &str, position: &Json) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let line_start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+/// spans of the name and form of the first def! or deftest of name in source
+fn definitions(source: &Rc<Source>, name: Symbol) -> Option<(Span, Span)> {
+    let exprs = Expr::parse_all(source).ok()?;
+    let mut found = None;
+    for expr in &exprs {
+        for_each_def(expr, &mut |def, form| {
+            if found.is_none() && def.name() == name {
+                found = Some((def.span().clone(), form.span().clone()));
+            }
+        });
+    }
+    found
+}
+
+/// calls f with the name and form of every definition in expr, by def! and the like,
+/// and of the methods of the protocols along with their signatures
+fn for_each_def(expr: &Expr, f: &mut impl FnMut(&Expr, &Expr)) {
+    if let Expr::List(exprs, _) | Expr::Vector(exprs, _) | Expr::Map(exprs, _) = expr {
+        if let [Expr::Special(special, _), name, ..] = &exprs[..]
+            && special.defines()
+        {
+            f(name, expr);
+        }
+        if let [
+            Expr::Special(Special::Defprotocol, _),
+            name,
+            signatures @ ..,
+        ] = &exprs[..]
+        {
+            f(name, expr);
+            for signature in signatures {
+                if let Expr::List(items, _) = signature
+                    && let Some(method) = items.first()
+                {
+                    f(method, signature);
+                }
+            }
+        }
+        for expr in exprs.iter() {
+            for_each_def(expr, f);
+        }
+    }
+}
+
+/// the ; comment lines right above the line span starts on, without the ;
+fn comments_above(span: &Span) -> String {
+    let text = &span.source.text;
+    let line_start = text[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let mut lines: Vec<&str> = text[..line_start]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with(';'))
+        .map(|line| line.trim_start_matches(';').trim())
+        .collect();
+    lines.reverse();
+    lines.join("\n")
+}
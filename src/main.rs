@@ -1,169 +1,1395 @@
-use std::rc::Rc;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use notify::{RecursiveMode, Watcher};
 
-use rustyline::DefaultEditor;
 use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Config as EditorConfig, Editor};
+
+use felisp::ast::{Expr, Special};
+use felisp::deterministic;
+use felisp::env::{Env, Value};
+use felisp::eval::{Limits, eval};
+use felisp::hooks::{StepContext, Trace};
+use felisp::image;
+use felisp::inspect::Inspector;
+use felisp::ir::expand;
+use felisp::lint::lint;
+use felisp::output::Stream;
+use felisp::printer::{self, PrettyPrinter};
+use felisp::profile::Profiler;
+use felisp::prune;
+use felisp::repl::ReplHelper;
+use felisp::span::Source;
+use felisp::symbol::Symbol;
+use felisp::sync::{Lock, Rc, Weak};
+use felisp::{FelispError, Interpreter};
+
+const USAGE: &str = "\
+usage: felisp [OPTIONS] [--image FILE] [-e EXPR | SCRIPT]
+       felisp [OPTIONS] [--image FILE] --json-rpc
+       felisp [OPTIONS] test FILE...
+       felisp fmt [--check] FILE...
+       felisp [OPTIONS] lint FILE...
+       felisp [OPTIONS] watch FILE
+       felisp expand FILE...
+       felisp lsp
+options: --sandbox --optimize --auto-curry --no-history --no-init --no-prelude --no-color --no-cache --time --trace --debug
+         --profile --deterministic [--seed N]";
+
+// exit statuses of sysexits.h, for input that doesn't parse and for other errors
+const EX_DATAERR: u8 = 65;
+const EX_SOFTWARE: u8 = 70;
+
+/// subcommands working on files rather than a script
+#[derive(Clone, Copy)]
+enum Command {
+    // run the deftests of the files
+    Test,
+    // rewrite the files through the pretty-printer
+    Fmt,
+    // check the files for likely mistakes without evaluating them
+    Lint,
+    // print the forms of the files with their derived forms rewritten
+    Expand,
+    // evaluate the file, then the forms that changed each time it does
+    Watch,
+    // serve the language server protocol on stdin and stdout
+    Lsp,
+}
+
+/// what the command line asks for
+#[derive(Default)]
+struct Options {
+    sandbox: bool,
+    optimize: bool,
+    // curry lambdas called with too few arguments, see Interpreter::auto_curry
+    auto_curry: bool,
+    // serve the REPL as JSON-RPC on stdin and stdout, see src/rpc.rs
+    json_rpc: bool,
+    // don't load nor save the REPL history
+    no_history: bool,
+    // don't evaluate the init file
+    no_init: bool,
+    // leave the functions of the prelude undefined
+    no_prelude: bool,
+    // print without ANSI colors, as does setting NO_COLOR
+    no_color: bool,
+    // whether to print in color, see Options::resolve_color
+    color: bool,
+    // parse the files loaded every time, rather than keeping their forms in cache_dir
+    no_cache: bool,
+    // print how long each evaluation took and how many steps it made
+    time: bool,
+    // print every form as it is evaluated, and its value
+    trace: bool,
+    // pause in the debugger at (breakpoint)
+    debug: bool,
+    // time function calls from the start, and print the report at exit
+    profile: bool,
+    // -e: forms to evaluate, printing the value of the last
+    eval: Option<String>,
+    // --image: saved session to evaluate before anything else, see (save-image path)
+    image: Option<String>,
+    script: Option<String>,
+    // felisp test, fmt, lint, expand, watch, lsp: the subcommand and the files it works on
+    command: Option<(Command, Vec<String>)>,
+    // fmt: only tell whether files are formatted, without rewriting them
+    check: bool,
+    // make runs reproducible, see src/deterministic.rs
+    deterministic: bool,
+    // --seed: what random values are generated from when deterministic, 0 by default
+    seed: u64,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sandbox" => options.sandbox = true,
+            "--optimize" => options.optimize = true,
+            "--auto-curry" => options.auto_curry = true,
+            "--json-rpc" => options.json_rpc = true,
+            "--no-history" => options.no_history = true,
+            "--no-init" => options.no_init = true,
+            "--no-prelude" => options.no_prelude = true,
+            "--no-color" => options.no_color = true,
+            "--no-cache" => options.no_cache = true,
+            "--time" => options.time = true,
+            "--trace" => options.trace = true,
+            "--debug" => options.debug = true,
+            "--profile" => options.profile = true,
+            "--check" => options.check = true,
+            "--deterministic" => options.deterministic = true,
+            "--seed" => {
+                let seed = args.next().ok_or(format!("{} requires a number", arg))?;
+                options.seed = seed
+                    .parse()
+                    .map_err(|_| format!("{} requires a number, got {}", arg, seed))?;
+                options.deterministic = true;
+            }
+            "-e" | "--eval" => {
+                options.eval = Some(
+                    args.next()
+                        .ok_or(format!("{} requires an expression", arg))?,
+                )
+            }
+            "--image" => {
+                options.image = Some(args.next().ok_or(format!("{} requires a file", arg))?)
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if let Some((_, files)) = &mut options.command => files.push(arg),
+            "test" if options.script.is_none() => options.command = Some((Command::Test, vec![])),
+            "fmt" if options.script.is_none() => options.command = Some((Command::Fmt, vec![])),
+            "lint" if options.script.is_none() => options.command = Some((Command::Lint, vec![])),
+            "expand" if options.script.is_none() => {
+                options.command = Some((Command::Expand, vec![]))
+            }
+            "watch" if options.script.is_none() => options.command = Some((Command::Watch, vec![])),
+            "lsp" if options.script.is_none() => options.command = Some((Command::Lsp, vec![])),
+            _ if options.script.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => options.script = Some(arg),
+        }
+    }
+    if options.debug && options.json_rpc {
+        return Err(
+            "--debug reads commands from stdin, which --json-rpc reads requests from".into(),
+        );
+    }
+    Ok(options)
+}
+
+fn main() -> ExitCode {
+    // (log-info ...) and the like are shown from the info level on, unless RUST_LOG says otherwise
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let mut options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let config = Config::load();
+    options.resolve_color(&config);
+    if options.deterministic {
+        deterministic::enable(options.seed);
+    }
+    match &options.command {
+        Some((Command::Test, files)) => return run_tests(files, &options),
+        Some((Command::Fmt, files)) => return format_files(files, &options),
+        Some((Command::Lint, files)) => return lint_files(files, &options),
+        Some((Command::Expand, files)) => return expand_files(files, &options),
+        Some((Command::Watch, files)) => return watch_file(files, &options),
+        Some((Command::Lsp, _)) => return serve_lsp(),
+        None => {}
+    }
+    let interpreter = new_interpreter(&options);
+    if options.json_rpc {
+        return serve_rpc(interpreter);
+    }
+    if let Some(source) = &options.eval {
+        let code = run_eval(&interpreter, source, &options);
+        print_profile(&interpreter, &options);
+        return code;
+    }
+    match &options.script {
+        Some(path) => {
+            let code = run_script(&interpreter, path, &options);
+            print_profile(&interpreter, &options);
+            code
+        }
+        None => match repl(&options, config) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {:?}", err);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+/// an interpreter as the options ask
+fn new_interpreter(options: &Options) -> Interpreter {
+    let interpreter = if options.sandbox {
+        Interpreter::sandboxed()
+    } else {
+        Interpreter::new()
+    }
+    .module_path(module_path(options));
+    let traced = Traced::default();
+    let env = Rc::downgrade(interpreter.env());
+    let interpreter = interpreter.on_call({
+        let traced = traced.clone();
+        move |f, args| print_call(&traced, &env, f, args)
+    });
+    let interpreter = if options.optimize {
+        interpreter.optimized()
+    } else {
+        interpreter
+    };
+    let interpreter = if options.auto_curry {
+        interpreter.auto_curry()
+    } else {
+        interpreter
+    };
+    let interpreter = match cache_dir() {
+        Some(dir) if !options.no_cache => interpreter.module_cache(dir),
+        _ => interpreter,
+    };
+    register_trace(interpreter.env(), &traced);
+    register_inspect(interpreter.env(), options);
+    if !options.sandbox {
+        image::register(interpreter.env()).expect("the env is not sandboxed");
+        // threads would escape the limits of a sandbox
+        #[cfg(feature = "sync")]
+        felisp::thread::register(interpreter.env(), interpreter.current_limits());
+        #[cfg(feature = "ffi")]
+        felisp::ffi::register(interpreter.env()).expect("the env is not sandboxed");
+    }
+    if !options.no_prelude {
+        interpreter
+            .load_prelude()
+            .expect("the prelude evaluates without errors");
+    }
+    if let Some(path) = &options.image
+        && let Err(err) = interpreter.eval_file(path)
+    {
+        print_error(&err, options);
+    }
+    // not tracing the prelude
+    let interpreter = if options.trace {
+        interpreter.on_trace(print_trace)
+    } else {
+        interpreter
+    };
+    let interpreter = profiled(interpreter, options);
+    if options.debug {
+        debugged(interpreter)
+    } else {
+        interpreter
+    }
+}
+
+/// binds (profile-start), which starts timing function calls, or starts over,
+/// and (profile-report), printing to *err* how many times each function was called
+/// and how long it took since, as --profile does at exit
+fn profiled(interpreter: Interpreter, options: &Options) -> Interpreter {
+    let profiler = Rc::new(Lock::new(options.profile.then(Profiler::default)));
+    let start = profiler.clone();
+    interpreter.env().register_fn("profile-start", move || {
+        *start.borrow_mut() = Some(Profiler::default());
+    });
+    let report = profiler.clone();
+    // the env holds profile-report, which must not keep it alive
+    let env = Rc::downgrade(interpreter.env());
+    interpreter
+        .env()
+        .register_fn("profile-report", move || -> Result<(), FelispError> {
+            let report = match &*report.borrow() {
+                Some(profiler) => format!("{}\n", profiler),
+                None => "not profiling, see (profile-start)\n".to_string(),
+            };
+            match env.upgrade() {
+                Some(env) => env.print(Stream::Err, &report),
+                None => Ok(()),
+            }
+        });
+    interpreter.on_frame(move |event| {
+        if let Some(profiler) = &mut *profiler.borrow_mut() {
+            profiler.record(event);
+        }
+    })
+}
+
+/// --profile: prints the report of the calls made so far
+fn print_profile(interpreter: &Interpreter, options: &Options) {
+    // called directly, so as not to count it
+    if options.profile
+        && let Ok(Value::Function(report)) = &interpreter.env().get("profile-report")
+    {
+        let _ = report.call(&[]);
+    }
+}
+
+/// --trace: prints each form as its evaluation starts, indented by how many
+/// it is nested in and followed by the depth of its local scope, then its value
+fn print_trace(trace: &Trace) {
+    match trace {
+        Trace::Eval {
+            node,
+            depth,
+            scopes,
+        } => eprintln!(
+            "{}{}  ; scope {}",
+            "  ".repeat(*depth),
+            node.span.text(),
+            scopes
+        ),
+        Trace::Return { value, depth, .. } => eprintln!("{}=> {}", "  ".repeat(*depth), value),
+    }
+}
 
-use crate::ast::Expr;
-use crate::env::{Callback, Env, Value};
+const DEBUG_HELP: &str = "\
+:step, :s or nothing    evaluate the form and pause at the next
+:next or :n             evaluate the form and pause after it
+:continue or :c         run until the next (breakpoint)
+:locals or :l           show the local variables
+:form or :f             show the form about to be evaluated
+:set NAME EXPR          rebind a variable to the value of EXPR
+EXPR                    evaluate EXPR with the local variables in scope";
 
-mod ast;
-mod env;
+/// --debug: where the debugger pauses next
+#[derive(Clone, Copy)]
+enum Stepping {
+    // after the next (breakpoint)
+    Continue,
+    // at the next form
+    Step,
+    // at the next form no deeper than depth, once the current one is evaluated
+    Next(usize),
+}
 
-fn main() -> rustyline::Result<()> {
-    let env = Rc::new(Env::default());
-    let mut rl = DefaultEditor::new()?;
+/// redefines (breakpoint) to pause at the next form in a prompt on stdin,
+/// which can inspect and rebind variables before stepping on
+fn debugged(interpreter: Interpreter) -> Interpreter {
+    let stepping = Rc::new(Lock::new(Stepping::Continue));
+    let breakpoint = stepping.clone();
+    interpreter.env().register_fn("breakpoint", move || {
+        *breakpoint.borrow_mut() = Stepping::Step;
+    });
+    interpreter.on_step(move |step| {
+        let pause = match *stepping.borrow() {
+            Stepping::Continue => false,
+            Stepping::Step => true,
+            Stepping::Next(depth) => step.depth <= depth,
+        };
+        if pause {
+            let next = debug(step);
+            *stepping.borrow_mut() = next;
+        }
+    })
+}
+
+/// prompts for debugger commands until one steps on
+fn debug(step: &StepContext) -> Stepping {
+    eprintln!("{}", step.node.span.snippet());
     loop {
-        let readline = rl.readline(">> ");
-        match readline {
-            Ok(line) => {
-                rl.add_history_entry(line.as_str())?;
-                match Expr::parse(line) {
-                    Ok(expr) => match eval(&expr, env.clone()) {
-                        Ok(output) => println!("{}", output),
-                        Err(err) => println!("eval error: {}", err),
-                    },
-                    Err(err) => println!("parse error: {}", err),
+        eprint!("debug> ");
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).is_err() || line.is_empty() {
+            return Stepping::Continue;
+        }
+        let (command, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "" | ":step" | ":s" => return Stepping::Step,
+            ":next" | ":n" => return Stepping::Next(step.depth),
+            ":continue" | ":c" => return Stepping::Continue,
+            ":locals" | ":l" => {
+                for (name, value) in locals(step) {
+                    eprintln!("{} = {}", name, value);
                 }
             }
-            Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
+            ":form" | ":f" => eprintln!("{}", step.node.span.snippet()),
+            ":help" => eprintln!("{}", DEBUG_HELP),
+            ":set" => match arg.trim().split_once(' ') {
+                Some((name, code)) => match debug_eval(step, code) {
+                    Ok(value) => {
+                        let name = Symbol::intern(name);
+                        if !step
+                            .scope
+                            .is_some_and(|scope| scope.set(name, value.clone()))
+                        {
+                            step.env.set(name, value);
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err.report()),
+                },
+                None => eprintln!("usage: :set NAME EXPR"),
+            },
+            _ if command.starts_with(':') => eprintln!("unknown command {}, see :help", command),
+            _ => match debug_eval(step, line.trim()) {
+                Ok(value) => eprintln!("{}", printer::abbreviate(&value)),
+                Err(err) => eprintln!("{}", err.report()),
+            },
+        }
+    }
+}
+
+/// variables in scope of the form about to be evaluated, outermost first
+fn locals(step: &StepContext) -> Vec<(Symbol, Value)> {
+    let mut scopes = vec![];
+    let mut scope = step.scope;
+    while let Some(inner) = scope {
+        scopes.push(inner);
+        scope = inner.outer();
+    }
+    scopes
+        .iter()
+        .rev()
+        .flat_map(|scope| scope.bindings())
+        .collect()
+}
+
+/// evaluates code in the debugger, with the locals of step as globals
+/// and without the hooks, so as not to pause in it
+fn debug_eval(step: &StepContext, code: &str) -> Result<Value, FelispError> {
+    let env = Env::new(Some(step.env.clone()));
+    for (name, value) in locals(step) {
+        env.set(name, value);
+    }
+    let expr = Expr::parse(code.to_string())?;
+    eval(&expr, Rc::new(env), &Limits::default())
+}
+
+/// functions given to trace, along with the name they are printed by
+type Traced = Rc<Lock<Vec<(Value, String)>>>;
+
+/// binds (trace f), printing each call of f with its arguments, and (untrace f)
+fn register_trace(env: &Rc<Env>, traced: &Traced) {
+    // the env holds trace, which must not keep it alive
+    let weak = Rc::downgrade(env);
+    let list = traced.clone();
+    env.register_fn("trace", move |f: Value| {
+        if !matches!(
+            f,
+            Value::Function(_)
+                | Value::AsyncFunction(_)
+                | Value::Lambda(_)
+                | Value::Memoized(_)
+                | Value::Curried(_)
+        ) {
+            return Err(FelispError::bad_arg(0, "Function", &f));
+        }
+        let name = weak
+            .upgrade()
+            .and_then(|env| {
+                // rather than *1, *2 or *3 when it was just evaluated
+                env.symbols()
+                    .into_iter()
+                    .filter(|&symbol| env.get(symbol).is_ok_and(|value| value == f))
+                    .min_by_key(|symbol| (symbol.name().starts_with('*'), symbol.name()))
+            })
+            .map_or(f.to_string(), |symbol| symbol.to_string());
+        list.borrow_mut().push((f, name));
+        Ok(Value::Nil)
+    });
+    let list = traced.clone();
+    env.register_fn("untrace", move |f: Value| {
+        list.borrow_mut().retain(|(g, _)| *g != f);
+    });
+}
+
+/// binds (inspect v), browsing v as a tree on stdin, which returns v once left,
+/// and fails with --json-rpc, whose requests stdin is for
+fn register_inspect(env: &Rc<Env>, options: &Options) {
+    let rpc = options.json_rpc;
+    env.register_fn("inspect", move |value: Value| {
+        if rpc {
+            let err = "inspect browses values on stdin, which --json-rpc reads requests from";
+            return Err(FelispError::host(io::Error::new(
+                io::ErrorKind::Unsupported,
+                err,
+            )));
+        }
+        Inspector::new(value).run().map_err(FelispError::host)
+    });
+}
+
+/// prints the call to *err* if f is traced
+fn print_call(traced: &Traced, env: &Weak<Env>, f: &Value, args: &[Value]) {
+    if let Some((_, name)) = traced.borrow().iter().find(|(g, _)| g == f)
+        && let Some(env) = env.upgrade()
+    {
+        let call: Vec<String> = [name.clone()]
+            .into_iter()
+            .chain(args.iter().map(Value::to_string))
+            .collect();
+        // as a hook, which has nowhere to return the error of a closed port to
+        let _ = env.print(Stream::Err, &format!("({})\n", call.join(" ")));
+    }
+}
+
+impl Options {
+    /// sets whether to print in color: unless told not to by --no-color, NO_COLOR
+    /// or color = off in config, when stderr, which errors go to, is a terminal
+    fn resolve_color(&mut self, config: &Config) {
+        self.color = !self.no_color
+            && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            && config.color
+            && io::stderr().is_terminal();
+    }
+}
+
+/// prints the report of err to stderr, in red with its kind in bold if in color
+fn print_error(err: &FelispError, options: &Options) {
+    let report = err.report();
+    if !options.color {
+        eprintln!("{}", report);
+        return;
+    }
+    match report.strip_prefix(err.kind()) {
+        Some(rest) => eprintln!("\x1b[1;31m{}\x1b[22m{}\x1b[0m", err.kind(), rest),
+        None => eprintln!("\x1b[31m{}\x1b[0m", report),
+    }
+}
+
+/// calls f, returning its result and how long it took and how many steps
+/// interpreter made meanwhile, as its stats count them
+fn timed<T>(interpreter: &Interpreter, f: impl FnOnce() -> T) -> (T, String) {
+    let before = interpreter.stats().evals;
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    let steps = interpreter.stats().evals - before;
+    let timing = format!("; {:?}, {} steps", elapsed, steps);
+    (result, timing)
+}
+
+/// evaluates the forms given with -e and prints the value of the last
+fn run_eval(interpreter: &Interpreter, source: &str, options: &Options) -> ExitCode {
+    let source = Source::new("<eval>", source.to_string());
+    let (result, timing) = timed(interpreter, || interpreter.eval_source(&source));
+    if options.time {
+        eprintln!("{}", timing);
+    }
+    match result {
+        Ok(value) => {
+            println!("{}", printer::abbreviate(&value));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            print_error(&err, options);
+            exit_code(&err)
+        }
+    }
+}
+
+/// evaluates every form of the script, stopping at the first error
+fn run_script(interpreter: &Interpreter, path: &str, options: &Options) -> ExitCode {
+    let (result, timing) = timed(interpreter, || interpreter.eval_file(path));
+    if options.time {
+        eprintln!("{}", timing);
+    }
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            print_error(&err, options);
+            exit_code(&err)
+        }
+    }
+}
+
+/// the exit status of a script or -e failing with err, after sysexits.h:
+/// 65 when it doesn't parse, 1 for an uncaught (throw v) and 70 for other errors
+fn exit_code(err: &FelispError) -> ExitCode {
+    match err.keyword() {
+        Some("parse-error") => ExitCode::from(EX_DATAERR),
+        Some("thrown") => ExitCode::FAILURE,
+        _ => ExitCode::from(EX_SOFTWARE),
+    }
+}
+
+/// felisp test: evaluates each file in a fresh interpreter then calls the functions
+/// its top-level deftest forms defined, reporting those that fail
+fn run_tests(files: &[String], options: &Options) -> ExitCode {
+    let (mut passed, mut failed) = (0, 0);
+    for path in files {
+        let interpreter = new_interpreter(options);
+        let exprs = match Source::read(path).and_then(|source| Expr::parse_all(&source)) {
+            Ok(exprs) => exprs,
+            Err(err) => {
+                println!("FAIL {}", path);
+                print_error(&err, options);
+                failed += 1;
+                continue;
             }
-            Err(ReadlineError::Eof) => {
-                println!("CTRL-D");
+        };
+        let mut tests = vec![];
+        for expr in &exprs {
+            if let Err(err) = interpreter.eval(expr) {
+                println!("FAIL {}", path);
+                print_error(&err, options);
+                failed += 1;
+                tests.clear();
                 break;
             }
+            if let Expr::List(items, _) = expr
+                && let [Expr::Special(Special::Deftest, _), name, ..] = &items[..]
+            {
+                tests.push(name.name());
+            }
+        }
+        for name in tests {
+            match interpreter.eval_source(&Source::new("<test>", format!("({})", name))) {
+                Ok(_) => passed += 1,
+                Err(err) => {
+                    println!("FAIL {} {}", path, name);
+                    print_error(&err, options);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// rewrites each file through the pretty-printer, or with --check only lists
+/// those that aren't formatted, failing if there are any
+fn format_files(files: &[String], options: &Options) -> ExitCode {
+    let mut failed = false;
+    for path in files {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
             Err(err) => {
-                println!("Error: {:?}", err);
-                break;
+                eprintln!("can't read {}: {}", path, err);
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match format_source(path, &text) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                print_error(&err, options);
+                failed = true;
+                continue;
             }
+        };
+        if formatted == text {
+            continue;
+        }
+        if options.check {
+            println!("{} is not formatted", path);
+            failed = true;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("can't write {}: {}", path, err);
+            failed = true;
         }
     }
-    Ok(())
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// text formatted, its top-level forms separated by a blank line where they were
+fn format_source(path: &str, text: &str) -> Result<String, FelispError> {
+    // a #! line is kept as is
+    let (shebang, code) = match text.split_once('\n') {
+        Some((first, rest)) if first.starts_with("#!") => (&text[..first.len() + 1], rest),
+        _ => ("", text),
+    };
+    let source = Source::new(path, code.to_string());
+    let exprs = Expr::parse_all(&source)?;
+    let printer = PrettyPrinter::default();
+    let mut out = shebang.to_string();
+    let mut end = 0;
+    for expr in &exprs {
+        let span = expr.span();
+        out += &between(&code[end..span.start], end > 0);
+        out += &printer.print_expr(expr);
+        end = span.end;
+    }
+    // and the comments after the last form, ending in a single line break
+    out += &between(&code[end..], end > 0);
+    out.truncate(out.trim_end_matches('\n').len());
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// what goes between two top-level forms, or before the first one:
+/// the comments between them, each on a line of its own,
+/// and an empty line wherever there were empty lines
+fn between(text: &str, after_form: bool) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut out = String::new();
+    // whether anything was printed before, and an empty line since
+    let mut printed = after_form;
+    let mut empty = false;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            // the first and last are the ends of the lines of the forms
+            empty |= i > 0 && i + 1 < lines.len();
+            continue;
+        }
+        if printed {
+            out += if empty { "\n\n" } else { "\n" };
+        }
+        out += line;
+        printed = true;
+        empty = false;
+    }
+    if printed {
+        out += if empty { "\n\n" } else { "\n" };
+    }
+    out
 }
 
-fn eval(expr: &Expr, env: Rc<Env>) -> Result<Value, String> {
-    match expr {
-        Expr::Atom(symbol) => env.get(symbol),
-        Expr::List(exprs) if exprs.is_empty() => Ok(Value::Nil),
-        Expr::List(exprs) => {
-            let first_value = eval(&exprs[0], env.clone())?;
-            match first_value {
-                Value::Def => eval_def(&env, exprs),
-                Value::Let => eval_let(&env, exprs),
-                Value::Do => eval_do(&env, exprs),
-                Value::If => eval_if(&env, exprs),
-                Value::Quote => eval_quote(&env, exprs),
-                Value::Fn => eval_fn(&env, exprs),
-                Value::Function(f) => eval_function(env, exprs, f),
-                _ => Err(format!("unknown symbol '{}'", first_value)),
+/// prints the warnings of lint for each file, as if evaluated by the REPL,
+/// failing if there are any
+fn lint_files(files: &[String], options: &Options) -> ExitCode {
+    let interpreter = new_interpreter(options);
+    let mut warnings = 0;
+    let mut failed = false;
+    for path in files {
+        match Source::read(path).and_then(|source| Expr::parse_all(&source)) {
+            Ok(exprs) => {
+                for warning in lint(&exprs, interpreter.env()) {
+                    println!("{}", warning.report());
+                    warnings += 1;
+                }
+            }
+            Err(err) => {
+                print_error(&err, options);
+                failed = true;
             }
         }
     }
+    if warnings > 0 {
+        println!(
+            "{} warning{}",
+            warnings,
+            if warnings == 1 { "" } else { "s" }
+        );
+    }
+    if failed || warnings > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-/// updates the current env with (evaluated) expr bound to symbol
-/// (def! symbol expr)
-fn eval_def(env: &Rc<Env>, exprs: &Vec<Expr>) -> Result<Value, String> {
-    if exprs.len() != 3 {
-        return Err(format!("def! requires 2 arguments"));
+/// prints the top-level forms of each file, pretty-printed, with the derived
+/// forms like deftest rewritten into the core forms they stand for,
+/// without evaluating them
+fn expand_files(files: &[String], options: &Options) -> ExitCode {
+    let printer = PrettyPrinter::default();
+    let mut failed = false;
+    for path in files {
+        let expanded = Source::read(path).and_then(|source| {
+            Expr::parse_all(&source)?
+                .iter()
+                .map(expand)
+                .collect::<Result<Vec<Expr>, FelispError>>()
+        });
+        match expanded {
+            Ok(exprs) => {
+                for expr in exprs {
+                    println!("{}", printer.print_expr(&expr));
+                }
+            }
+            Err(err) => {
+                print_error(&err, options);
+                failed = true;
+            }
+        }
     }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
 
-    let key = exprs[1].to_string();
-    let val = eval(&exprs[2], env.clone())?;
-    env.set(&key, val.clone());
-    Ok(val)
+/// felisp watch: evaluates the file, then each time it changes the top-level forms
+/// that did, so that what the others defined, and the state it holds, is kept
+fn watch_file(files: &[String], options: &Options) -> ExitCode {
+    let [path] = files else {
+        eprintln!("watch requires 1 file\n{}", USAGE);
+        return ExitCode::from(2);
+    };
+    let path = Path::new(path);
+    let (sender, receiver) = mpsc::channel();
+    // the directory rather than the file, which editors often replace when saving
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let watcher = notify::recommended_watcher(sender).and_then(|mut watcher| {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map(|()| watcher)
+    });
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            eprintln!("watch: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let interpreter = new_interpreter(options);
+    let mut evaluated = HashSet::new();
+    eprintln!("watching {}, Ctrl-C to stop", path.display());
+    loop {
+        reload(&interpreter, path, &mut evaluated, options);
+        if !wait_for_change(&receiver, path) {
+            return ExitCode::FAILURE;
+        }
+    }
 }
 
-/// evaluates expr in a new env with (evaluated) vi bound to (symbol) ki
-/// (let* (k1 v1 k2 v2 ...) expr)
-fn eval_let(env: &Rc<Env>, exprs: &Vec<Expr>) -> Result<Value, String> {
-    if exprs.len() != 3 {
-        return Err(format!("let* requires 2 arguments"));
+/// waits until the file is written to, and the editor done writing it,
+/// false if the watcher stopped
+fn wait_for_change(receiver: &mpsc::Receiver<notify::Result<notify::Event>>, path: &Path) -> bool {
+    let changed = |event: &notify::Result<notify::Event>| {
+        event.as_ref().is_ok_and(|event| {
+            (event.kind.is_modify() || event.kind.is_create())
+                && event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name() == path.file_name())
+        })
+    };
+    loop {
+        match receiver.recv() {
+            Ok(event) if changed(&event) => break,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
     }
-    match &exprs[1] {
-        Expr::List(keypairs) if keypairs.len() % 2 == 0 => {
-            let env = Rc::new(Env::new(Some(env.clone())));
-            for keypair in keypairs.chunks(2) {
-                let key = keypair[0].to_string();
-                let val = eval(&keypair[1], env.clone())?;
-                env.set(&key, val.clone());
+    while receiver.recv_timeout(Duration::from_millis(50)).is_ok() {}
+    true
+}
+
+/// evaluates the top-level forms of the file not among those evaluated,
+/// which become those it has now, those that failed left out to be retried
+fn reload(
+    interpreter: &Interpreter,
+    path: &Path,
+    evaluated: &mut HashSet<String>,
+    options: &Options,
+) {
+    let exprs = match Source::read(path).and_then(|source| Expr::parse_all(&source)) {
+        Ok(exprs) => exprs,
+        Err(err) => return print_error(&err, options),
+    };
+    let mut forms = HashSet::new();
+    let mut defined = vec![];
+    for expr in &exprs {
+        // as printed, so that changes of layout or comments don't count
+        let form = expr.to_string();
+        if !evaluated.contains(&form) {
+            if let Err(err) = interpreter.eval(expr) {
+                print_error(&err, options);
+                continue;
             }
-            Ok(eval(&exprs[2], env)?)
+            if let Expr::List(exprs, _) = expr
+                && let [Expr::Special(special, _), name, ..] = &exprs[..]
+                && special.defines()
+            {
+                defined.push(name.to_string());
+            }
+        }
+        forms.insert(form);
+    }
+    if !evaluated.is_empty() && !defined.is_empty() {
+        eprintln!("reloaded {}", defined.join(", "));
+    }
+    *evaluated = forms;
+}
+
+#[cfg(feature = "lsp")]
+fn serve_lsp() -> ExitCode {
+    match felisp::lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("lsp: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(feature = "lsp"))]
+fn serve_lsp() -> ExitCode {
+    eprintln!("felisp was built without the lsp feature");
+    ExitCode::FAILURE
+}
+
+#[cfg(feature = "rpc")]
+fn serve_rpc(interpreter: Interpreter) -> ExitCode {
+    match felisp::rpc::serve(interpreter, io::stdin().lock(), io::stdout()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("json-rpc: {}", err);
+            ExitCode::FAILURE
         }
-        _ => Err(format!("let* expected key-value pairs got '{}'", exprs[1])),
     }
 }
 
-/// evaluates all arguments sequentially, returning the last
-/// (do 1 2 3) -> 3
-fn eval_do(env: &Rc<Env>, exprs: &Vec<Expr>) -> Result<Value, String> {
-    let mut output = Value::Nil;
-    for expr in &exprs[1..] {
-        output = eval(expr, env.clone())?;
+#[cfg(not(feature = "rpc"))]
+fn serve_rpc(_: Interpreter) -> ExitCode {
+    eprintln!("felisp was built without the rpc feature");
+    ExitCode::FAILURE
+}
+
+/// $XDG_STATE_HOME/felisp/history if set, ~/.felisp_history otherwise
+fn history_path() -> Option<PathBuf> {
+    match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("felisp").join("history")),
+        None => env::home_dir().map(|home| home.join(".felisp_history")),
     }
-    Ok(output)
 }
 
-/// (if cond then else?) -> evaluates cond
-/// if it is nil or false, evaluates and returns else (nil if absent)
-/// otherwise evaluates and returns then
-fn eval_if(env: &Rc<Env>, exprs: &Vec<Expr>) -> Result<Value, String> {
-    if exprs.len() <= 2 {
-        return Err(format!("if* requires at least 2 arguments"));
+/// where require looks for modules: the directory of the script, or the current one,
+/// then those of $FELISP_PATH
+fn module_path(options: &Options) -> Vec<PathBuf> {
+    let script = match &options.command {
+        Some((Command::Watch, files)) => files.first(),
+        _ => options.script.as_ref(),
+    };
+    let dir = script
+        .and_then(|script| Path::new(script).parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or(PathBuf::from("."), Path::to_path_buf);
+    let mut path = vec![dir];
+    if let Some(dirs) = env::var_os("FELISP_PATH") {
+        path.extend(env::split_paths(&dirs));
     }
-    match eval(&exprs[1], env.clone())? {
-        Value::Nil | Value::False if exprs.len() == 3 => Ok(Value::Nil),
-        Value::Nil | Value::False if exprs.len() == 4 => Ok(eval(&exprs[3], env.clone())?),
-        _ if exprs.len() <= 4 => Ok(eval(&exprs[2], env.clone())?),
-        _ => Err(format!("if* requires at most 3 arguments")),
+    path
+}
+
+/// $XDG_CACHE_HOME/felisp if set, ~/.cache/felisp otherwise
+fn cache_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("felisp")),
+        None => env::home_dir().map(|home| home.join(".cache").join("felisp")),
     }
 }
 
-/// (f x1 x2 ...) -> (apply f x1 x2 ...)
-fn eval_function(env: Rc<Env>, exprs: &Vec<Expr>, f: Callback) -> Result<Value, String> {
-    let mut args = vec![];
-    for expr in &exprs[1..] {
-        let value = eval(expr, env.clone())?;
-        args.push(value);
+/// $XDG_CONFIG_HOME/felisp if set, ~/.config/felisp otherwise
+fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("felisp")),
+        None => env::home_dir().map(|home| home.join(".config").join("felisp")),
     }
-    Ok(f(&args)?)
 }
 
-/// (quote a b) -> (a b)
-fn eval_quote(_env: &Env, exprs: &[Expr]) -> Result<Value, String> {
-    Ok(Value::Quoted(Expr::List(Vec::from(&exprs[1..]))))
+/// REPL settings, read from the config file of config_dir, lines like
+/// prompt = "λ> "
+/// color = off
+/// history-size = 1000
+struct Config {
+    prompt: String,
+    // whether to print in color, errors and the line being edited
+    color: bool,
+    // how many lines of history to keep
+    history_size: usize,
 }
 
-/// returns a lambda that once called, evaluates the body with the given arguments
-/// (fn* (a) a) -> `<fun>`
-/// ((fn* (a b) (+ a b)) 2 3) -> 5
-fn eval_fn(env: &Rc<Env>, exprs: &Vec<Expr>) -> Result<Value, String> {
-    if exprs.len() != 3 {
-        return Err(format!("fn* requires 2 arguments"));
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prompt: ">> ".to_string(),
+            color: true,
+            history_size: 100,
+        }
     }
-    if let Expr::List(bindings) = &exprs[1] {
-        let cloned_env = env.clone();
-        let cloned_bindings = bindings.clone();
-        let cloned_body = exprs[2].clone();
-        let cb: Callback = Rc::new(move |args| {
-            if args.len() != cloned_bindings.len() {
-                return Err(format!(
-                    "fn required {} args but given {}",
-                    cloned_bindings.len(),
-                    args.len()
-                ));
+}
+
+impl Config {
+    /// the settings of the config file if there is one, the defaults otherwise,
+    /// warning about the lines it can't make sense of
+    fn load() -> Self {
+        let mut config = Config::default();
+        let Some(path) = config_dir().map(|dir| dir.join("config")) else {
+            return config;
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return config;
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
             }
-            let env = Rc::new(Env::new(Some(cloned_env.clone())));
-            for (binding, arg) in cloned_bindings.iter().zip(args) {
-                let key = binding.to_string();
-                env.set(&key, arg.clone());
+            if let Err(err) = config.set(line) {
+                eprintln!("{}:{}: {}", path.display(), i + 1, err);
             }
-            let output = eval(&cloned_body, env)?;
-            Ok(output)
-        });
-        Ok(Value::Function(cb))
-    } else {
-        Err(format!("fn* expected bindings got '{}'", exprs[1]))
+        }
+        config
+    }
+
+    /// applies a setting = value line
+    fn set(&mut self, line: &str) -> Result<(), String> {
+        let (setting, value) = line
+            .split_once('=')
+            .ok_or(format!("expected setting = value, got '{}'", line))?;
+        let value = value.trim();
+        match setting.trim() {
+            "prompt" => {
+                self.prompt = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value)
+                    .to_string()
+            }
+            "color" => {
+                self.color = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(format!("expected on or off, got '{}'", value)),
+                }
+            }
+            "history-size" => {
+                self.history_size = value
+                    .parse()
+                    .map_err(|_| format!("expected a number, got '{}'", value))?
+            }
+            setting => return Err(format!("unknown setting {}", setting)),
+        }
+        Ok(())
+    }
+}
+
+/// evaluates the init file of config_dir into the env of interpreter,
+/// unless there is none or --no-init
+fn load_init(interpreter: &Interpreter, options: &Options) {
+    if options.no_init {
+        return;
+    }
+    let Some(path) = config_dir().map(|dir| dir.join("init.lisp")) else {
+        return;
+    };
+    if path.exists()
+        && let Err(err) = interpreter.eval_file(&path)
+    {
+        print_error(&err, options);
+    }
+}
+
+const HELP: &str = "\
+:help                   show this help
+:quit                   leave the REPL
+:env                    list the bindings of the env
+:load FILE              evaluate the forms of FILE
+:paste                  read lines up to one of :end, then evaluate each form in them
+:reset                  start over with a fresh env, loading the init file
+:prune ROOT...          remove the bindings made since startup that the ROOTs don't reach
+:type EXPR              show the type of the value of EXPR, as type-of names it
+:expand EXPR            show EXPR with its derived forms rewritten, unevaluated
+:time on|off            show how long each evaluation takes
+:pretty                 show how values are printed
+:pretty on|off          print values over several lines, or on one
+:pretty width N         break values wider than N columns
+:pretty depth N|none    print collections nested deeper than N as ...
+:pretty items N|none    print only the first N items of collections";
+
+/// what lives across the lines of a REPL session
+struct Session {
+    interpreter: Interpreter,
+    printer: PrettyPrinter,
+    // whether values go through printer rather than on one line
+    pretty: bool,
+    // whether to show how long each evaluation takes
+    time: bool,
+    // set by Ctrl-C to abort the evaluation
+    interrupt: Arc<AtomicBool>,
+    config: Config,
+    // bound once the session started, which :prune keeps
+    baseline: Vec<Symbol>,
+}
+
+impl Session {
+    fn print(&self, value: &Value) {
+        if self.pretty {
+            println!("{}", self.printer.print(value));
+        } else {
+            let printer = PrettyPrinter {
+                width: usize::MAX,
+                ..self.printer
+            };
+            println!("{}", printer.print(value));
+        }
+    }
+
+    fn helper(&self, options: &Options) -> ReplHelper {
+        let mut helper = ReplHelper::new(self.interpreter.env().clone());
+        if !options.color {
+            helper = helper.without_color();
+        }
+        if !io::stdin().is_terminal() {
+            helper = helper.lenient();
+        }
+        helper
     }
 }
+
+/// runs a colon command, returning whether to leave the REPL
+fn run_command(command: &str, session: &mut Session, options: &Options) -> bool {
+    let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+    let arg = arg.trim();
+    let interpreter = &mut session.interpreter;
+    match name {
+        "help" => println!("{}", HELP),
+        "quit" | "q" => return true,
+        "env" => {
+            let mut symbols = interpreter.env().symbols();
+            symbols.sort_by_key(|symbol| symbol.name());
+            symbols.dedup();
+            for symbol in symbols {
+                if let Ok(value) = interpreter.env().get(symbol) {
+                    println!("{} {}", symbol, value);
+                }
+            }
+        }
+        "load" => match interpreter.eval_file(arg) {
+            Ok(value) => session.print(&value),
+            Err(err) => print_error(&err, options),
+        },
+        "reset" => {
+            *interpreter = new_interpreter(options).interruptible(session.interrupt.clone());
+            load_init(interpreter, options);
+            forget_results(interpreter);
+            session.baseline = interpreter.env().symbols();
+        }
+        "prune" => {
+            let mut roots: Vec<Symbol> = arg.split_whitespace().map(Symbol::intern).collect();
+            roots.extend(["*1", "*2", "*3", "*e"].map(Symbol::intern));
+            roots.extend(&session.baseline);
+            let pruned = prune::prune(interpreter.env(), &roots);
+            let names: Vec<&str> = pruned.iter().map(|symbol| symbol.name()).collect();
+            match names.len() {
+                0 => println!("nothing to prune"),
+                1 => println!("pruned 1 binding: {}", names[0]),
+                n => println!("pruned {} bindings: {}", n, names.join(" ")),
+            }
+        }
+        "type" => match interpreter.eval_str(arg) {
+            Ok(value) => println!(":{}", value.type_keyword()),
+            Err(err) => print_error(&err, options),
+        },
+        "expand" => match Expr::parse(arg.to_string()).and_then(|expr| expand(&expr)) {
+            Ok(expr) => println!("{}", session.printer.print_expr(&expr)),
+            Err(err) => print_error(&err, options),
+        },
+        "time" => match arg {
+            "on" => session.time = true,
+            "off" => session.time = false,
+            _ => println!("expected on or off, see :help"),
+        },
+        "pretty" => {
+            if let Err(err) = set_pretty(arg, session) {
+                println!("{}, see :help", err);
+            }
+        }
+        _ => println!("unknown command :{}, see :help", name),
+    }
+    false
+}
+
+/// :pretty, changing or showing how the REPL prints values
+fn set_pretty(arg: &str, session: &mut Session) -> Result<(), String> {
+    let (setting, value) = arg.split_once(' ').unwrap_or((arg, ""));
+    let value = value.trim();
+    // N, or none for no limit
+    let limit = || match value {
+        "none" => Ok(None),
+        _ => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("expected a number or none, got '{}'", value)),
+    };
+    let printer = &mut session.printer;
+    match setting {
+        "" => {
+            let show = |limit: Option<usize>| limit.map_or("none".to_string(), |n| n.to_string());
+            println!(
+                "pretty {}, width {}, depth {}, items {}",
+                if session.pretty { "on" } else { "off" },
+                printer.width,
+                show(printer.max_depth),
+                show(printer.max_items)
+            );
+        }
+        "on" => session.pretty = true,
+        "off" => session.pretty = false,
+        "width" => {
+            printer.width = value
+                .parse()
+                .map_err(|_| format!("expected a number, got '{}'", value))?
+        }
+        "depth" => printer.max_depth = limit()?,
+        "items" => printer.max_items = limit()?,
+        _ => return Err(format!("unknown setting {}", setting)),
+    }
+    Ok(())
+}
+
+/// binds *1, *2 and *3 to nil, like *e, until there are results
+fn forget_results(interpreter: &Interpreter) {
+    for name in ["*1", "*2", "*3", "*e"] {
+        interpreter.env().set(name, Value::Nil);
+    }
+}
+
+/// binds the last value to *1, shifting the previous ones to *2 and *3,
+/// or the last error to *e, the thrown value or else its message
+fn remember(interpreter: &Interpreter, result: &Result<Value, FelispError>) {
+    let env = interpreter.env();
+    match result {
+        Ok(value) => {
+            env.set("*3", env.get("*2").unwrap_or(Value::Nil));
+            env.set("*2", env.get("*1").unwrap_or(Value::Nil));
+            env.set("*1", value.clone());
+        }
+        Err(err) => match err.thrown() {
+            Some(value) => env.set("*e", value.clone()),
+            None => env.set("*e", Value::from(err.to_string())),
+        },
+    }
+}
+
+/// evaluates the form read, printing its value or error, and remembers the result
+fn evaluate(
+    session: &mut Session,
+    options: &Options,
+    read: impl FnOnce() -> Result<Expr, FelispError>,
+) {
+    // in case Ctrl-C came after the last evaluation was done
+    session.interrupt.store(false, Ordering::Relaxed);
+    let (result, timing) = timed(&session.interpreter, || {
+        read().and_then(|expr| session.interpreter.eval(&expr))
+    });
+    match &result {
+        Ok(output) => session.print(output),
+        Err(err) => print_error(err, options),
+    }
+    if session.time {
+        println!("{}", timing);
+    }
+    remember(&session.interpreter, &result);
+}
+
+/// :paste, reading lines as they are up to one of :end or the end of input,
+/// then evaluating each form they hold in turn, as if typed one after the other
+fn paste(
+    rl: &mut Editor<ReplHelper, DefaultHistory>,
+    session: &mut Session,
+    options: &Options,
+) -> rustyline::Result<()> {
+    println!("pasting, end with a line of :end or Ctrl-D");
+    // whose validator would hold lines with open brackets back
+    rl.set_helper(None);
+    let mut text = String::new();
+    let read = loop {
+        match rl.readline("") {
+            Ok(line) if line.trim() == ":end" => break Ok(()),
+            Ok(line) => {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            Err(ReadlineError::Eof) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    rl.set_helper(Some(session.helper(options)));
+    match read {
+        Ok(()) => {}
+        Err(ReadlineError::Interrupted) => {
+            println!("CTRL-C, nothing pasted");
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    }
+    rl.add_history_entry(text.trim_end())?;
+    match Expr::parse_all(&Source::new("<paste>", text)) {
+        Ok(exprs) => {
+            for expr in exprs {
+                evaluate(session, options, || Ok(expr));
+            }
+        }
+        Err(err) => print_error(&err, options),
+    }
+    Ok(())
+}
+
+fn repl(options: &Options, config: Config) -> rustyline::Result<()> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let mut session = Session {
+        interpreter: new_interpreter(options).interruptible(interrupt.clone()),
+        printer: PrettyPrinter::default(),
+        pretty: true,
+        time: options.time,
+        interrupt,
+        config,
+        baseline: vec![],
+    };
+    load_init(&session.interpreter, options);
+    forget_results(&session.interpreter);
+    session.baseline = session.interpreter.env().symbols();
+    let history = history_path().filter(|_| !options.no_history);
+    let editor_config = EditorConfig::builder()
+        .max_history_size(session.config.history_size)?
+        .build();
+    let mut rl: Editor<ReplHelper, DefaultHistory> = Editor::with_config(editor_config)?;
+    // Ctrl-C while editing a line is read by rustyline, this is while evaluating
+    // installed after the editor, which otherwise takes SIGINT over
+    let flag = session.interrupt.clone();
+    if let Err(err) = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed)) {
+        eprintln!("Ctrl-C won't interrupt evaluations: {}", err);
+    }
+    rl.set_helper(Some(session.helper(options)));
+    if let Some(path) = &history {
+        // there is none yet on first run
+        let _ = rl.load_history(path);
+    }
+    loop {
+        let readline = rl.readline(&session.config.prompt);
+        match readline {
+            Ok(line) => {
+                rl.add_history_entry(line.as_str())?;
+                if line.trim() == ":paste" {
+                    paste(&mut rl, &mut session, options)?;
+                    continue;
+                }
+                if let Some(command) = line.trim().strip_prefix(':') {
+                    if run_command(command, &mut session, options) {
+                        break;
+                    }
+                    // :reset replaces the env
+                    rl.set_helper(Some(session.helper(options)));
+                    continue;
+                }
+                evaluate(&mut session, options, || Expr::parse(line));
+            }
+            Err(ReadlineError::Interrupted) => {
+                println!("CTRL-C");
+                break;
+            }
+            Err(ReadlineError::Eof) => {
+                println!("CTRL-D");
+                break;
+            }
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break;
+            }
+        }
+    }
+    print_profile(&session.interpreter, options);
+    if let Some(path) = &history {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        rl.save_history(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::Expr,
+    cache,
+    env::{Env, Value},
+    error::FelispError,
+    eval::{Limits, run},
+    hooks::Hooks,
+    ir::analyze,
+    symbol::{Symbol, SymbolMap},
+    sync::Rc,
+};
+
+/// the namespace of the outermost env itself, where the REPL starts
+pub const USER: &str = "user";
+
+/// namespaces made by ns and require, which the outermost env keeps track of
+pub struct Namespaces {
+    // envs of the namespaces other than user, nested in the outermost env
+    envs: SymbolMap<Rc<Env>>,
+    // namespace top-level forms are evaluated in, user if None
+    current: Option<Symbol>,
+    // directories require looks for modules in, in turn
+    path: Vec<PathBuf>,
+    // directory keeping the parsed forms of the files loaded, see src/cache.rs
+    cache: Option<PathBuf>,
+    // modules require already loaded, which it doesn't load again
+    loaded: HashSet<Symbol>,
+    // modules being loaded, innermost last
+    loading: Vec<Symbol>,
+}
+
+impl Default for Namespaces {
+    fn default() -> Self {
+        Self {
+            envs: SymbolMap::default(),
+            current: None,
+            path: vec![PathBuf::from(".")],
+            cache: None,
+            loaded: HashSet::new(),
+            loading: vec![],
+        }
+    }
+}
+
+/// the env of the current namespace, which top-level forms are evaluated in
+pub fn current(env: &Rc<Env>) -> Rc<Env> {
+    let root = env.root();
+    let current = root.namespaces().borrow().current;
+    match current {
+        Some(name) => namespace_env(&root, name),
+        None => root,
+    }
+}
+
+/// the name of the current namespace
+pub fn current_name(env: &Env) -> Symbol {
+    let current = env.namespaces().borrow().current;
+    current.unwrap_or_else(|| Symbol::intern(USER))
+}
+
+/// the value of a qualified symbol, bound by a def! in its namespace
+/// my.utils/helper -> helper of my.utils
+pub(crate) fn lookup(root: &Env, symbol: Symbol) -> Option<Value> {
+    let (namespace, name) = symbol.qualified()?;
+    let env = root.namespaces().borrow().envs.get(&namespace).cloned()?;
+    env.get_own(name)
+}
+
+/// makes name the namespace of the top-level forms evaluated next, made on first use
+/// (ns my.utils) -> nil
+pub fn enter(env: &Rc<Env>, name: Symbol) {
+    let root = env.root();
+    namespace_env(&root, name);
+    root.namespaces().borrow_mut().current = (name.name() != USER).then_some(name);
+}
+
+/// directories require looks for modules in, in turn, . by default
+pub fn set_path(env: &Env, path: Vec<PathBuf>) {
+    env.namespaces().borrow_mut().path = path;
+}
+
+/// keeps the parsed forms of the files loaded in dir, or none if None
+pub fn set_cache(env: &Env, dir: Option<PathBuf>) {
+    env.namespaces().borrow_mut().cache = dir;
+}
+
+/// the forms of the file at path, through the cache if there is one
+pub fn read_forms(env: &Env, path: &Path) -> Result<Vec<Expr>, FelispError> {
+    let cache = env.namespaces().borrow().cache.clone();
+    cache::read_forms(path, cache.as_deref())
+}
+
+/// evaluates the module of namespace name in it, unless it already was
+/// my.utils is read from my/utils.lisp, in the first directory of the path having it
+/// (require 'my.utils) -> nil
+pub fn require(
+    env: &Rc<Env>,
+    name: Symbol,
+    limits: &Limits,
+    hooks: &Hooks,
+) -> Result<(), FelispError> {
+    let root = env.root();
+    if root.is_sandboxed() {
+        return Err(FelispError::Sandboxed("require".to_string()));
+    }
+    let (file, previous) = {
+        let namespaces = root.namespaces().borrow();
+        if namespaces.loaded.contains(&name) {
+            return Ok(());
+        }
+        if let Some(i) = namespaces.loading.iter().position(|&n| n == name) {
+            let cycle: Vec<String> = namespaces.loading[i..]
+                .iter()
+                .chain([&name])
+                .map(Symbol::to_string)
+                .collect();
+            return Err(FelispError::ModuleError(format!(
+                "cyclic require: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        (module_file(&namespaces.path, name)?, namespaces.current)
+    };
+    // all analyzed first, so that a malformed form leaves the module unevaluated
+    let nodes = read_forms(&root, &file)?
+        .iter()
+        .map(analyze)
+        .collect::<Result<Vec<_>, FelispError>>()?;
+    enter(&root, name);
+    root.namespaces().borrow_mut().loading.push(name);
+    let result = nodes
+        .into_iter()
+        .try_for_each(|node| run(node, current(&root), limits, hooks).map(drop));
+    let mut namespaces = root.namespaces().borrow_mut();
+    namespaces.loading.pop();
+    namespaces.current = previous;
+    if result.is_ok() {
+        namespaces.loaded.insert(name);
+    }
+    result
+}
+
+/// the env of namespace name, made on first use
+fn namespace_env(root: &Rc<Env>, name: Symbol) -> Rc<Env> {
+    if name.name() == USER {
+        return root.clone();
+    }
+    root.namespaces()
+        .borrow_mut()
+        .envs
+        .entry(name)
+        .or_insert_with(|| Rc::new(Env::new(Some(root.clone()))))
+        .clone()
+}
+
+/// the file of the module of namespace name, in the first directory of path having it
+fn module_file(path: &[PathBuf], name: Symbol) -> Result<PathBuf, FelispError> {
+    let relative: PathBuf = format!("{}.lisp", name.name().replace('.', "/")).into();
+    path.iter()
+        .map(|dir| dir.join(&relative))
+        .find(|file| file.is_file())
+        .ok_or_else(|| {
+            let dirs: Vec<String> = path.iter().map(|dir| dir.display().to_string()).collect();
+            FelispError::ModuleError(format!(
+                "no {} for {} in {}",
+                relative.display(),
+                name,
+                dirs.join(", ")
+            ))
+        })
+}
@@ -0,0 +1,107 @@
+// rust functions as builtins: arguments are converted from values with
+// TryFrom and results into values with Into, arity checked on the way
+// (register_fn "add" |a: i64, b: i64| a + b) -> (add 1 2) -> 3
+
+use std::any::type_name;
+
+use crate::{
+    env::{NativeFn, Value},
+    error::FelispError,
+    symbol::Symbol,
+    sync::MaybeSync,
+};
+
+/// what a native function may return: a value or a result of one
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, FelispError>;
+}
+
+impl<T: Into<Value>> IntoResult for T {
+    fn into_result(self) -> Result<Value, FelispError> {
+        Ok(self.into())
+    }
+}
+
+/// host errors are converted with From, or wrapped with FelispError::host
+impl<T: Into<Value>, E: Into<FelispError>> IntoResult for Result<T, E> {
+    fn into_result(self) -> Result<Value, FelispError> {
+        self.map(Into::into).map_err(Into::into)
+    }
+}
+
+/// rust function of Args that can be wrapped into a NativeFn named name,
+/// whose parameters are named after their types: (add i64 i64)
+pub trait IntoNativeFn<Args> {
+    fn into_native_fn(self, name: Symbol) -> NativeFn;
+}
+
+/// converts the arg-th argument, blaming it for type errors
+fn arg<T>(args: &[Value], i: usize) -> Result<T, FelispError>
+where
+    T: TryFrom<Value>,
+    FelispError: From<T::Error>,
+{
+    T::try_from(args[i].clone()).map_err(|err| match FelispError::from(err) {
+        FelispError::TypeError { expected, got, .. } => FelispError::TypeError {
+            expected,
+            got,
+            arg: Some(i),
+            function: None,
+        },
+        err => err,
+    })
+}
+
+/// a type name without the paths of the types in it
+/// alloc::vec::Vec<felisp::env::Value> -> Vec<Value>
+fn short_type_name(name: &str) -> String {
+    let mut short = String::new();
+    // where the identifier being copied starts in short
+    let mut start = 0;
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(start);
+        } else {
+            short.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                start = short.len();
+            }
+        }
+    }
+    short
+}
+
+macro_rules! impl_native_fn {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + MaybeSync + 'static,
+            R: IntoResult,
+            $($arg: TryFrom<Value>, FelispError: From<$arg::Error>,)*
+        {
+            #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+            fn into_native_fn(self, name: Symbol) -> NativeFn {
+                let params: Vec<String> = vec![$(short_type_name(type_name::<$arg>())),*];
+                // as many arguments as parameters, which NativeFn::call checks
+                NativeFn::new(name, &params.join(" "), move |args: &[Value]| {
+                    let mut i = 0;
+                    $(
+                        let $arg: $arg = arg(args, i)?;
+                        i += 1;
+                    )*
+                    self($($arg),*).into_result()
+                })
+            }
+        }
+    };
+}
+
+impl_native_fn!();
+impl_native_fn!(A);
+impl_native_fn!(A, B);
+impl_native_fn!(A, B, C);
+impl_native_fn!(A, B, C, D);
+impl_native_fn!(A, B, C, D, E);
+impl_native_fn!(A, B, C, D, E, G);
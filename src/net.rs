@@ -0,0 +1,118 @@
+// TCP sockets, as resources: connections read and written as strings,
+// or vectors of bytes, blocking until the other end answers
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    env::{Builtin, Value},
+    error::FelispError,
+    resource::{self, Resource},
+};
+
+/// builtins reaching the network, left out of sandboxed envs
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[
+    ("tcp-connect", "host port", tcp_connect),
+    ("tcp-listen", "port", tcp_listen),
+    ("tcp-accept", "listener", tcp_accept),
+    ("socket-read", "socket n", socket_read),
+    ("socket-write", "socket s", socket_write),
+];
+
+/// a connection to host on port
+/// (tcp-connect "example.com" 80) -> <TcpStream>
+fn tcp_connect(args: &[Value]) -> Result<Value, FelispError> {
+    arity("tcp-connect", args, 2)?;
+    let Value::String(host) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    let port = port(args, 1)?;
+    let stream = TcpStream::connect((&**host, port)).map_err(FelispError::host)?;
+    Ok(Resource::value(stream))
+}
+
+/// a socket listening on port of every interface, 0 for any free one
+/// (tcp-listen 8080) -> <TcpListener>
+fn tcp_listen(args: &[Value]) -> Result<Value, FelispError> {
+    arity("tcp-listen", args, 1)?;
+    let port = port(args, 0)?;
+    let listener = TcpListener::bind(("0.0.0.0", port)).map_err(FelispError::host)?;
+    Ok(Resource::value(listener))
+}
+
+/// the next connection to the listener, waiting for one
+/// (tcp-accept (tcp-listen 8080)) -> <TcpStream>
+fn tcp_accept(args: &[Value]) -> Result<Value, FelispError> {
+    arity("tcp-accept", args, 1)?;
+    let accepted = resource::with_arg(args, 0, "TcpListener", TcpListener::accept)?;
+    let (stream, _) = accepted.map_err(FelispError::host)?;
+    Ok(Resource::value(stream))
+}
+
+/// at most n bytes from the connection, as a string with invalid UTF-8 replaced,
+/// waiting for some, nil once the other end closed it
+/// (socket-read s 1024) -> "HTTP/1.0 200 OK..."
+fn socket_read(args: &[Value]) -> Result<Value, FelispError> {
+    arity("socket-read", args, 2)?;
+    let n = match &args[1] {
+        Value::Number(n) if *n > 0 => *n as usize,
+        n => return Err(FelispError::bad_arg(1, "positive Number", n)),
+    };
+    let mut buf = vec![0; n];
+    let read = stream(args, |mut stream| stream.read(&mut buf))?.map_err(FelispError::host)?;
+    if read == 0 {
+        return Ok(Value::Nil);
+    }
+    Ok(Value::from(
+        String::from_utf8_lossy(&buf[..read]).into_owned(),
+    ))
+}
+
+/// writes all of data, a string or a vector of bytes, returning how many bytes it has
+/// (socket-write s "GET / HTTP/1.0\r\n\r\n") -> 18
+fn socket_write(args: &[Value]) -> Result<Value, FelispError> {
+    arity("socket-write", args, 2)?;
+    let bytes = match &args[1] {
+        Value::String(s) => s.as_bytes().to_vec(),
+        Value::Vector(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Number(n) => u8::try_from(*n).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| FelispError::bad_arg(1, "String or bytes", &args[1]))?,
+        data => return Err(FelispError::bad_arg(1, "String or bytes", data)),
+    };
+    stream(args, |mut stream| stream.write_all(&bytes))?.map_err(FelispError::host)?;
+    Ok(Value::Number(bytes.len() as i64))
+}
+
+fn arity(name: &str, args: &[Value], n: usize) -> Result<(), FelispError> {
+    if args.len() != n {
+        return Err(FelispError::ArityError(format!(
+            "{} requires {} arguments but given {}",
+            name,
+            n,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+fn port(args: &[Value], i: usize) -> Result<u16, FelispError> {
+    match &args[i] {
+        Value::Number(n) => {
+            u16::try_from(*n).map_err(|_| FelispError::bad_arg(i, "port", &args[i]))
+        }
+        port => Err(FelispError::bad_arg(i, "port", port)),
+    }
+}
+
+/// what f returns for the connection args start with, read and written through
+/// a shared reference, failing if it is closed
+fn stream<R>(args: &[Value], f: impl FnOnce(&TcpStream) -> R) -> Result<R, FelispError> {
+    resource::with_arg(args, 0, "TcpStream", f)
+}
@@ -0,0 +1,88 @@
+use crate::{env::Value, error::FelispError, numeric};
+
+// conversions between numbers and strings, for code handling input:
+// malformed strings give nil rather than an error
+
+fn arity(name: &str, args: &[Value]) -> Result<(), FelispError> {
+    if !(1..=2).contains(&args.len()) {
+        return Err(FelispError::ArityError(format!(
+            "{} requires 1 to 2 arguments but given {}",
+            name,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+/// args[i] as a base from 2 to 36, 10 when absent
+fn base(args: &[Value], i: usize) -> Result<u32, FelispError> {
+    match args.get(i) {
+        None => Ok(10),
+        Some(Value::Number(n)) if (2..=36).contains(n) => Ok(*n as u32),
+        Some(base) => Err(FelispError::bad_arg(i, "base from 2 to 36", base)),
+    }
+}
+
+/// the integer s writes in base, with an optional sign, nil if it writes none
+/// (parse-int "-42") -> -42
+/// (parse-int "ff" 16) -> 255
+/// (parse-int "ffffffffffffffff" 16) -> 18446744073709551615
+/// (parse-int "4x") -> nil
+pub fn parse_int(args: &[Value]) -> Result<Value, FelispError> {
+    arity("parse-int", args)?;
+    let Value::String(s) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    let base = base(args, 1)?;
+    Ok(numeric::parse_integer(s, base).unwrap_or(Value::Nil))
+}
+
+/// the number s writes, as a float, nil if it writes none
+/// (parse-float "1.5") -> 1.5
+/// (parse-float "-2") -> -2.0
+/// (parse-float "1.5x") -> nil
+pub fn parse_float(args: &[Value]) -> Result<Value, FelispError> {
+    let Value::String(s) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    let number = numeric::parse(s);
+    Ok(number.map_or(Value::Nil, |n| Value::from(numeric::to_f64(&n))))
+}
+
+/// n written in base, with lower-case digits past 9, ratios and floats only in base 10
+/// (number->string 255 16) -> "ff"
+/// (number->string -5 2) -> "-101"
+/// (number->string 1/3) -> "1/3"
+pub fn number_to_string(args: &[Value]) -> Result<Value, FelispError> {
+    arity("number->string", args)?;
+    let base = base(args, 1)?;
+    let n = match &args[0] {
+        Value::Number(n) => *n,
+        Value::BigInt(n) => return Ok(Value::from(n.to_str_radix(base))),
+        Value::Ratio(_) | Value::Float(_) if base == 10 => {
+            return Ok(Value::from(args[0].to_string()));
+        }
+        Value::Ratio(_) | Value::Float(_) => {
+            return Err(FelispError::bad_arg(
+                1,
+                "base 10 for ratios and floats",
+                &args[1],
+            ));
+        }
+        _ => return Err(FelispError::bad_arg(0, "Number", &args[0])),
+    };
+    let mut digits = vec![];
+    let mut rest = n.unsigned_abs();
+    loop {
+        let digit = (rest % u64::from(base)) as u32;
+        digits.push(char::from_digit(digit, base).unwrap_or('?'));
+        rest /= u64::from(base);
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    Ok(Value::from(digits.into_iter().rev().collect::<String>()))
+}
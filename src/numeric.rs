@@ -0,0 +1,337 @@
+// the number tower, from the most exact level to the least: ints, i64 in Value::Number,
+// bigints past them, ratios of two integers and floats
+//
+// contagion: arithmetic and comparison on numbers of two levels first raise the lower
+// to the higher, so (+ 1 1/2) adds ratios and (+ 1/2 0.5) floats, then their results
+// fall back to the lowest level holding them exactly: ints overflow into bigints and
+// bigints that fit are ints again, dividing integers gives a ratio unless it is exact,
+// and ratios of denominator 1 are integers; floats never go back to exact numbers
+//
+// the rules live here and nowhere else, builtins on numbers going through Num
+
+use std::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::{env::Value, error::FelispError, sync::Rc};
+
+/// a number of any level, as arithmetic works on it
+#[derive(Clone)]
+enum Num {
+    Int(i64),
+    Big(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+}
+
+impl Num {
+    fn of(value: &Value) -> Option<Num> {
+        match value {
+            Value::Number(n) => Some(Num::Int(*n)),
+            Value::BigInt(n) => Some(Num::Big((**n).clone())),
+            Value::Ratio(r) => Some(Num::Ratio((**r).clone())),
+            Value::Float(x) => Some(Num::Float(*x)),
+            _ => None,
+        }
+    }
+
+    fn arg(args: &[Value], i: usize) -> Result<Num, FelispError> {
+        Num::of(&args[i]).ok_or_else(|| FelispError::bad_arg(i, "Number", &args[i]))
+    }
+
+    /// position in the tower, ints being 0
+    fn level(&self) -> u8 {
+        match self {
+            Num::Int(_) => 0,
+            Num::Big(_) => 1,
+            Num::Ratio(_) => 2,
+            Num::Float(_) => 3,
+        }
+    }
+
+    /// the same number at level, which is never below its own
+    fn raise(self, level: u8) -> Num {
+        match (self, level) {
+            (n, level) if n.level() == level => n,
+            (Num::Int(n), 1) => Num::Big(n.into()),
+            (Num::Int(n), 2) => Num::Ratio(BigInt::from(n).into()),
+            (Num::Big(n), 2) => Num::Ratio(n.into()),
+            (n, _) => Num::Float(n.to_f64()),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Num::Int(n) => *n as f64,
+            Num::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Num::Ratio(r) => r.to_f64().unwrap_or(f64::NAN),
+            Num::Float(x) => *x,
+        }
+    }
+
+    /// the value at the lowest level holding the number exactly
+    fn into_value(self) -> Value {
+        match self {
+            Num::Int(n) => Value::Number(n),
+            Num::Big(n) => match n.to_i64() {
+                Some(n) => Value::Number(n),
+                None => Value::BigInt(Rc::new(n)),
+            },
+            Num::Ratio(r) if r.is_integer() => Num::Big(r.to_integer()).into_value(),
+            Num::Ratio(r) => Value::Ratio(Rc::new(r)),
+            Num::Float(x) => Value::Float(x),
+        }
+    }
+}
+
+/// a and b raised to the higher of their levels
+fn contagion(a: Num, b: Num) -> (Num, Num) {
+    let level = a.level().max(b.level());
+    (a.raise(level), b.raise(level))
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// a op b, failing with what it expected of b
+fn apply(op: Op, a: Num, b: Num) -> Result<Num, &'static str> {
+    Ok(match contagion(a, b) {
+        (Num::Int(x), Num::Int(y)) => {
+            let exact = match op {
+                Op::Add => x.checked_add(y),
+                Op::Sub => x.checked_sub(y),
+                Op::Mul => x.checked_mul(y),
+                Op::Div if y != 0 && x.checked_rem(y) == Some(0) => x.checked_div(y),
+                Op::Div => None,
+            };
+            match exact {
+                Some(n) => Num::Int(n),
+                None => return apply(op, Num::Big(x.into()), Num::Big(y.into())),
+            }
+        }
+        (Num::Big(x), Num::Big(y)) => match op {
+            Op::Add => Num::Big(x + y),
+            Op::Sub => Num::Big(x - y),
+            Op::Mul => Num::Big(x * y),
+            Op::Div if y.is_zero() => return Err("non-zero Number"),
+            Op::Div => Num::Ratio(BigRational::new(x, y)),
+        },
+        (Num::Ratio(x), Num::Ratio(y)) => match op {
+            Op::Add => Num::Ratio(x + y),
+            Op::Sub => Num::Ratio(x - y),
+            Op::Mul => Num::Ratio(x * y),
+            Op::Div if y.is_zero() => return Err("non-zero Number"),
+            Op::Div => Num::Ratio(x / y),
+        },
+        // as IEEE 754 has it, dividing by zero gives an infinity or NaN
+        (Num::Float(x), Num::Float(y)) => Num::Float(match op {
+            Op::Add => x + y,
+            Op::Sub => x - y,
+            Op::Mul => x * y,
+            Op::Div => x / y,
+        }),
+        _ => unreachable!("contagion raises both to the same level"),
+    })
+}
+
+/// how a and b compare as numbers, None if either is NaN
+fn compare(a: Num, b: Num) -> Option<Ordering> {
+    match contagion(a, b) {
+        (Num::Int(x), Num::Int(y)) => Some(x.cmp(&y)),
+        (Num::Big(x), Num::Big(y)) => Some(x.cmp(&y)),
+        (Num::Ratio(x), Num::Ratio(y)) => Some(x.cmp(&y)),
+        (Num::Float(x), Num::Float(y)) => x.partial_cmp(&y),
+        _ => unreachable!("contagion raises both to the same level"),
+    }
+}
+
+/// the order of numbers among values: by magnitude, then by level for equal ones,
+/// 1 before 1.0, and floats by f64::total_cmp, so that NaN has a place
+pub fn total_cmp(a: &Value, b: &Value) -> Ordering {
+    let (Some(x), Some(y)) = (Num::of(a), Num::of(b)) else {
+        unreachable!("only numbers are compared here")
+    };
+    let levels = x.level().cmp(&y.level());
+    let ordering = match contagion(x, y) {
+        (Num::Float(x), Num::Float(y)) => x.total_cmp(&y),
+        (x, y) => compare(x, y).unwrap_or(Ordering::Equal),
+    };
+    ordering.then(levels)
+}
+
+/// the number s writes: an integer, a ratio like 1/2 or -3/4, or a float like 1.5,
+/// -0.25 or 1e-3, nil when it writes none
+/// integers are read as ints or bigints and ratios in lowest terms, or as integers
+pub fn parse(s: &str) -> Option<Value> {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if is_digits(digits) {
+        return Some(Num::Big(s.parse().ok()?).into_value());
+    }
+    if let Some((numer, denom)) = digits.split_once('/') {
+        if !is_digits(numer) || !is_digits(denom) {
+            return None;
+        }
+        let (numer, denom): (BigInt, BigInt) =
+            (s.split_once('/')?.0.parse().ok()?, denom.parse().ok()?);
+        if denom.is_zero() {
+            return None;
+        }
+        return Some(Num::Ratio(BigRational::new(numer, denom)).into_value());
+    }
+    // digits, then a fraction, an exponent or both
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (digits, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let exponent_ok = exponent.is_none_or(|e| is_digits(e.strip_prefix(['-', '+']).unwrap_or(e)));
+    if !is_digits(whole) || !fraction.is_none_or(is_digits) || !exponent_ok {
+        return None;
+    }
+    if fraction.is_none() && exponent.is_none() {
+        return None;
+    }
+    s.parse().ok().map(Value::Float)
+}
+
+/// the integer s writes in base, with an optional sign, an int or a bigint past them,
+/// None when it writes none
+pub fn parse_integer(s: &str, base: u32) -> Option<Value> {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    // which BigInt would skip
+    if digits.is_empty() || digits.contains('_') {
+        return None;
+    }
+    Some(Num::Big(BigInt::parse_bytes(s.as_bytes(), base)?).into_value())
+}
+
+/// any number as a float, rounded if need be
+pub fn to_f64(value: &Value) -> Option<f64> {
+    Num::of(value).map(|n| n.to_f64())
+}
+
+/// whether s is a number literal other than an int, read by Expr::Numeric
+pub fn is_literal(s: &str) -> bool {
+    s.parse::<i64>().is_err() && parse(s).is_some()
+}
+
+/// (+ 1 2 3) -> 6, (+ 1 1/2) -> 3/2, (+ 1 0.5) -> 1.5, (+) -> 0
+pub fn add(args: &[Value]) -> Result<Value, FelispError> {
+    fold("+", args, Op::Add, 0)
+}
+
+/// (- 10 1 2) -> 7, (- 5) -> -5
+pub fn sub(args: &[Value]) -> Result<Value, FelispError> {
+    fold("-", args, Op::Sub, 0)
+}
+
+/// (* 2 3 4) -> 24, (* 4611686018427387904 2) -> 9223372036854775808, (*) -> 1
+pub fn mul(args: &[Value]) -> Result<Value, FelispError> {
+    fold("*", args, Op::Mul, 1)
+}
+
+/// exact unless given floats, integers dividing into a ratio if need be
+/// (/ 12 2 3) -> 2, (/ 1 2) -> 1/2, (/ 2) -> 1/2, (/ 1.0 4) -> 0.25
+pub fn div(args: &[Value]) -> Result<Value, FelispError> {
+    fold("/", args, Op::Div, 1)
+}
+
+/// folds op left over the numbers of args, from the first
+/// subtraction and division require an argument, applied to identity when alone:
+/// (- 5) is (- 0 5), while (+) and (*) return identity
+fn fold(name: &str, args: &[Value], op: Op, identity: i64) -> Result<Value, FelispError> {
+    let inverse = matches!(op, Op::Sub | Op::Div);
+    let (mut total, rest) = match args.len() {
+        0 if inverse => {
+            return Err(FelispError::ArityError(format!(
+                "{} requires at least 1 argument but given 0",
+                name
+            )));
+        }
+        0 => (Num::Int(identity), 0),
+        1 if inverse => (Num::Int(identity), 0),
+        _ => (Num::arg(args, 0)?, 1),
+    };
+    for i in rest..args.len() {
+        total = apply(op, total, Num::arg(args, i)?)
+            .map_err(|expected| FelispError::bad_arg(i, expected, &args[i]))?;
+    }
+    Ok(total.into_value())
+}
+
+/// (< 1 2 3) -> true, (< 1 1) -> false, (< 1/2 0.6) -> true
+pub fn lt(args: &[Value]) -> Result<Value, FelispError> {
+    chain("<", args, Ordering::is_lt)
+}
+
+/// (<= 1 2) -> true, (<= 1/2 0.5) -> true
+pub fn leq(args: &[Value]) -> Result<Value, FelispError> {
+    chain("<=", args, Ordering::is_le)
+}
+
+/// (> 3 2 1) -> true
+pub fn gt(args: &[Value]) -> Result<Value, FelispError> {
+    chain(">", args, Ordering::is_gt)
+}
+
+/// (>= 2 2 1) -> true
+pub fn geq(args: &[Value]) -> Result<Value, FelispError> {
+    chain(">=", args, Ordering::is_ge)
+}
+
+/// (= 1 1.0 2/2) -> true, (= 1 2) -> false
+pub fn eq(args: &[Value]) -> Result<Value, FelispError> {
+    chain("=", args, Ordering::is_eq)
+}
+
+/// whether each number of args is in the relation holds with the next, whatever
+/// their levels, false when one is NaN, and true when alone
+fn chain(name: &str, args: &[Value], holds: fn(Ordering) -> bool) -> Result<Value, FelispError> {
+    if args.is_empty() {
+        return Err(FelispError::ArityError(format!(
+            "{} requires at least 1 argument but given 0",
+            name
+        )));
+    }
+    let numbers = (0..args.len())
+        .map(|i| Num::arg(args, i))
+        .collect::<Result<Vec<Num>, FelispError>>()?;
+    let holds = numbers
+        .windows(2)
+        .all(|pair| compare(pair[0].clone(), pair[1].clone()).is_some_and(holds));
+    Ok(Value::from(holds))
+}
+
+/// how a and b compare as compare has it if both are numbers: as <= orders them,
+/// so that 1 and 1.0 are equal, and NaN where total_cmp puts it
+pub fn order(a: &Value, b: &Value) -> Option<Ordering> {
+    let (x, y) = (Num::of(a)?, Num::of(b)?);
+    Some(compare(x, y).unwrap_or_else(|| total_cmp(a, b)))
+}
+
+/// integer division, truncating towards zero
+/// (quot 7 2) -> 3, (quot -7 2) -> -3
+pub fn quot(args: &[Value]) -> Result<Value, FelispError> {
+    let integer = |i: usize| match Num::arg(args, i)? {
+        n @ (Num::Int(_) | Num::Big(_)) => Ok(n.raise(1)),
+        _ => Err(FelispError::bad_arg(i, "integer", &args[i])),
+    };
+    let (Num::Big(x), Num::Big(y)) = (integer(0)?, integer(1)?) else {
+        unreachable!("integers are raised to bigints")
+    };
+    if y.is_zero() {
+        return Err(FelispError::bad_arg(1, "non-zero Number", &args[1]));
+    }
+    Ok(Num::Big(x / y).into_value())
+}
@@ -0,0 +1,217 @@
+use crate::{
+    env::{Env, Value},
+    ir::{Arity, CaseTable, FnDef, Node, NodeKind},
+    span::Span,
+    symbol::Symbol,
+    sync::Rc,
+};
+
+// builtins without side effects, whose calls on constants can be made at analysis time
+const PURE: &[&str] = &[
+    "+",
+    "-",
+    "*",
+    "/",
+    "<",
+    "<=",
+    ">",
+    ">=",
+    "=",
+    "==",
+    "quot",
+    "nil?",
+    "type-of",
+    "str",
+    "vector",
+    "hash-map",
+    "get",
+    "nth",
+    "vec",
+    "assoc",
+    "dissoc",
+    "conj",
+    "count",
+    "cons",
+    "list",
+    "list*",
+    "car",
+    "cdr",
+    "sort",
+    "compare",
+    "partition",
+    "zip",
+    "frequencies",
+];
+
+/// rewrites node into a cheaper equivalent:
+/// calls of pure builtins on constants are folded, (do x) becomes x,
+/// and if or case on a constant becomes the branch it takes
+/// quoted forms need no work here, the analyzer already made them constants
+///
+/// builtins are looked up in env as it is now, so code that rebinds them
+/// with def! before calling them in the same form may see the original;
+/// calls in the bodies of functions and generators are left alone, as they run
+/// later, when the names called may be bound to something else
+pub fn optimize(node: Node, env: &Env) -> Node {
+    rewrite(node, Some(env))
+}
+
+/// optimize, folding calls only if given the env builtins are looked up in
+fn rewrite(node: Node, env: Option<&Env>) -> Node {
+    let span = node.span;
+    let kind = match node.kind {
+        NodeKind::Def(key, value, definition) => {
+            NodeKind::Def(key, Rc::new(optimize_rc(value, env)), definition)
+        }
+        NodeKind::Let(names, values, body) => NodeKind::Let(
+            names,
+            optimize_all(&values, env),
+            Rc::new(optimize_rc(body, env)),
+        ),
+        NodeKind::WithOpen(names, values, body) => NodeKind::WithOpen(
+            names,
+            optimize_all(&values, env),
+            Rc::new(optimize_rc(body, env)),
+        ),
+        NodeKind::Do(exprs) => return optimize_do(&exprs, span, env),
+        NodeKind::If(cond, then, otherwise) => {
+            let cond = optimize_rc(cond, env);
+            let then = optimize_rc(then, env);
+            let otherwise = otherwise.map(|otherwise| optimize_rc(otherwise, env));
+            match (&cond.kind, otherwise) {
+                (NodeKind::Const(Value::Nil | Value::False), None) => NodeKind::Const(Value::Nil),
+                (NodeKind::Const(Value::Nil | Value::False), Some(otherwise)) => return otherwise,
+                (NodeKind::Const(_), _) => return then,
+                (_, otherwise) => {
+                    NodeKind::If(Rc::new(cond), Rc::new(then), otherwise.map(Rc::new))
+                }
+            }
+        }
+        NodeKind::Case(value, table) => {
+            let value = optimize_rc(value, env);
+            let branches: Vec<Node> = table
+                .branches
+                .iter()
+                .map(|branch| rewrite(branch.clone(), env))
+                .collect();
+            let default = table.default.clone().map(|default| rewrite(default, env));
+            if let NodeKind::Const(value) = &value.kind {
+                return match table.keys.get(value) {
+                    Some(&index) => branches[index].clone(),
+                    None => default.unwrap_or(Node {
+                        kind: NodeKind::Const(Value::Nil),
+                        span,
+                    }),
+                };
+            }
+            NodeKind::Case(
+                Rc::new(value),
+                Rc::new(CaseTable {
+                    keys: table.keys.clone(),
+                    branches,
+                    default,
+                }),
+            )
+        }
+        NodeKind::Fn(def) => NodeKind::Fn(Rc::new(FnDef {
+            arities: def
+                .arities
+                .iter()
+                .map(|arity| Arity {
+                    params: arity.params.clone(),
+                    body: rewrite(arity.body.clone(), None),
+                })
+                .collect(),
+            name: def.name,
+            source: def.source.clone(),
+        })),
+        NodeKind::Call(exprs, name) => {
+            let exprs = optimize_all(&exprs, env);
+            match fold_call(&exprs, env) {
+                Some(value) => NodeKind::Const(value),
+                None => NodeKind::Call(exprs, name),
+            }
+        }
+        NodeKind::CallEc(exprs) => NodeKind::CallEc(optimize_all(&exprs, env)),
+        NodeKind::Generator(body) => NodeKind::Generator(optimize_all(&body, None)),
+        NodeKind::WithOutStr(body) => NodeKind::WithOutStr(optimize_all(&body, env)),
+        NodeKind::Binding(ports, body) => NodeKind::Binding(
+            ports
+                .iter()
+                .map(|(stream, port)| (*stream, rewrite(port.clone(), env)))
+                .collect(),
+            optimize_all(&body, env),
+        ),
+        NodeKind::Yield(value) => NodeKind::Yield(Rc::new(optimize_rc(value, env))),
+        NodeKind::WithHandler(handlers, body) => NodeKind::WithHandler(
+            handlers
+                .iter()
+                .map(|(kind, handler)| (*kind, rewrite(handler.clone(), env)))
+                .collect(),
+            optimize_all(&body, env),
+        ),
+        kind => kind,
+    };
+    Node { kind, span }
+}
+
+fn optimize_rc(node: Rc<Node>, env: Option<&Env>) -> Node {
+    rewrite(Rc::unwrap_or_clone(node), env)
+}
+
+fn optimize_all(nodes: &[Node], env: Option<&Env>) -> Rc<[Node]> {
+    nodes
+        .iter()
+        .map(|node| rewrite(node.clone(), env))
+        .collect()
+}
+
+/// (do) -> nil, (do x) -> x
+/// constants before the last expression are dropped, their value being unused
+fn optimize_do(exprs: &[Node], span: Span, env: Option<&Env>) -> Node {
+    let last = exprs.len().saturating_sub(1);
+    let mut exprs: Vec<Node> = exprs
+        .iter()
+        .enumerate()
+        .map(|(i, expr)| (i, rewrite(expr.clone(), env)))
+        .filter(|(i, expr)| *i == last || !matches!(expr.kind, NodeKind::Const(_)))
+        .map(|(_, expr)| expr)
+        .collect();
+    match exprs.len() {
+        0 => Node {
+            kind: NodeKind::Const(Value::Nil),
+            span,
+        },
+        1 => exprs.pop().unwrap(),
+        _ => Node {
+            kind: NodeKind::Do(exprs.into()),
+            span,
+        },
+    }
+}
+
+/// value of a call to a pure builtin with constant arguments,
+/// unless it fails, in which case it is left for the evaluator to report
+fn fold_call(exprs: &[Node], env: Option<&Env>) -> Option<Value> {
+    let NodeKind::Global(symbol) = exprs[0].kind else {
+        return None;
+    };
+    if !PURE.iter().any(|&name| Symbol::intern(name) == symbol) {
+        return None;
+    }
+    // not folding calls to what the program bound to the name of a builtin
+    let Ok(Value::Function(f)) = &env?.get(symbol) else {
+        return None;
+    };
+    if !f.is_builtin(symbol) {
+        return None;
+    }
+    let args = exprs[1..]
+        .iter()
+        .map(|expr| match &expr.kind {
+            NodeKind::Const(value) => Some(value.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<Value>>>()?;
+    f.call(&args).ok()
+}
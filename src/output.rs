@@ -0,0 +1,239 @@
+// the ports printing goes to, held by the outermost env: println and prn write to
+// *out*, stdout unless redirected, and eprintln to *err*, stderr unless redirected
+// (binding (*out* port) body...) redirects a stream for as long as its body runs,
+// and with-out-str to a string port, whose contents it returns
+
+use std::io::{self, Write};
+#[cfg(feature = "io")]
+use std::{any::Any, fs::File};
+
+#[cfg(feature = "io")]
+use crate::env::Builtin;
+
+use crate::{
+    env::{NativeFn, Value},
+    error::FelispError,
+    symbol::Symbol,
+    sync::{Lock, MaybeSend, Rc},
+};
+
+/// builtins opening ports, left out of sandboxed envs
+#[cfg(feature = "io")]
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[("open-output", "path", open_output)];
+
+/// the streams printing goes to, which binding redirects
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stream {
+    // *out*, which println and prn write to
+    Out,
+    // *err*, which eprintln writes to
+    Err,
+}
+
+impl Stream {
+    pub fn from_name(name: Symbol) -> Option<Stream> {
+        match name.name() {
+            "*out*" => Some(Stream::Out),
+            "*err*" => Some(Stream::Err),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Out => "*out*",
+            Stream::Err => "*err*",
+        }
+    }
+}
+
+/// somewhere to write text to, a native value printed <Port>
+pub struct Port {
+    sink: Lock<Sink>,
+}
+
+enum Sink {
+    Stdout,
+    Stderr,
+    // what with-out-str returns
+    String(String),
+    #[cfg(feature = "io")]
+    File(File),
+    // a file port once closed, which writing to fails
+    #[cfg(feature = "io")]
+    Closed,
+    // given by the host, see Interpreter::redirect_out
+    Host(HostSink),
+}
+
+#[cfg(not(feature = "sync"))]
+type HostSink = Box<dyn FnMut(&str)>;
+#[cfg(feature = "sync")]
+type HostSink = Box<dyn FnMut(&str) + Send>;
+
+impl Port {
+    /// a port value calling f with the text written to it
+    pub fn host(f: impl FnMut(&str) + MaybeSend + 'static) -> Value {
+        Port::value(Sink::Host(Box::new(f)))
+    }
+
+    pub(crate) fn string() -> Value {
+        Port::value(Sink::String(String::new()))
+    }
+
+    fn value(sink: Sink) -> Value {
+        Value::native(Port {
+            sink: Lock::new(sink),
+        })
+    }
+
+    pub fn write(&self, s: &str) -> Result<(), FelispError> {
+        let result = match &mut *self.sink.borrow_mut() {
+            Sink::Stdout => write_flushed(&mut io::stdout().lock(), s),
+            Sink::Stderr => write_flushed(&mut io::stderr().lock(), s),
+            Sink::String(buffer) => {
+                buffer.push_str(s);
+                Ok(())
+            }
+            #[cfg(feature = "io")]
+            Sink::File(file) => file.write_all(s.as_bytes()),
+            #[cfg(feature = "io")]
+            Sink::Closed => Err(io::Error::other("write to a closed port")),
+            Sink::Host(f) => {
+                f(s);
+                Ok(())
+            }
+        };
+        result.map_err(FelispError::host)
+    }
+
+    /// what was written to a string port, None for the others
+    pub fn contents(&self) -> Option<String> {
+        match &*self.sink.borrow() {
+            Sink::String(buffer) => Some(buffer.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn write_flushed(writer: &mut impl Write, s: &str) -> io::Result<()> {
+    writer.write_all(s.as_bytes())?;
+    writer.flush()
+}
+
+/// the ports of each stream, the one it is redirected to last
+pub struct Output {
+    out: Lock<Vec<Value>>,
+    err: Lock<Vec<Value>>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            out: Lock::new(vec![Port::value(Sink::Stdout)]),
+            err: Lock::new(vec![Port::value(Sink::Stderr)]),
+        }
+    }
+}
+
+impl Output {
+    fn ports(&self, stream: Stream) -> &Lock<Vec<Value>> {
+        match stream {
+            Stream::Out => &self.out,
+            Stream::Err => &self.err,
+        }
+    }
+
+    /// the port stream is redirected to, the value of *out* or *err*
+    pub fn current(&self, stream: Stream) -> Value {
+        let ports = self.ports(stream).borrow();
+        ports.last().expect("streams keep their first port").clone()
+    }
+
+    pub fn write(&self, stream: Stream, s: &str) -> Result<(), FelispError> {
+        let port = self.current(stream);
+        port.downcast_native::<Port>()
+            .expect("only ports are redirected to")
+            .write(s)
+    }
+
+    /// replaces the port stream goes to when it isn't redirected
+    pub fn set(&self, stream: Stream, port: Value) {
+        self.ports(stream).borrow_mut()[0] = port;
+    }
+
+    /// redirects stream to port, until it is released
+    pub(crate) fn redirect(&self, stream: Stream, port: Value) {
+        self.ports(stream).borrow_mut().push(port);
+    }
+
+    pub(crate) fn release(&self, stream: Stream, port: &Value) {
+        let mut ports = self.ports(stream).borrow_mut();
+        if let Some(index) = ports.iter().rposition(|p| p == port)
+            && index > 0
+        {
+            ports.remove(index);
+        }
+    }
+}
+
+/// println, prn and eprintln, writing to the streams of output
+pub fn builtins(output: &Rc<Output>) -> [(&'static str, Value); 3] {
+    let printer = |name: &'static str, stream, readably| {
+        let output = Rc::clone(output);
+        let f = move |args: &[Value]| print_line(&output, stream, args, readably);
+        (name, NativeFn::new(name, "& xs", f).into())
+    };
+    [
+        printer("println", Stream::Out, false),
+        printer("prn", Stream::Out, true),
+        printer("eprintln", Stream::Err, false),
+    ]
+}
+
+/// the arguments separated by spaces then a newline, strings in quotes if readably
+/// (println "a" 1) prints a 1, (prn "a" 1) prints "a" 1
+fn print_line(
+    output: &Output,
+    stream: Stream,
+    args: &[Value],
+    readably: bool,
+) -> Result<Value, FelispError> {
+    let line: Vec<String> = args
+        .iter()
+        .map(|arg| match arg {
+            Value::String(s) if !readably => s.to_string(),
+            arg => arg.to_string(),
+        })
+        .collect();
+    output.write(stream, &format!("{}\n", line.join(" ")))?;
+    Ok(Value::Nil)
+}
+
+/// a port writing to the file at path, created or truncated, closed by close!
+#[cfg(feature = "io")]
+/// (binding (*out* (open-output "log.txt")) (println "logged")) -> nil
+fn open_output(args: &[Value]) -> Result<Value, FelispError> {
+    let Value::String(path) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    let file = File::create(&**path).map_err(FelispError::host)?;
+    let port = Port {
+        sink: Lock::new(Sink::File(file)),
+    };
+    Ok(Value::closable(port, "Port", close_file))
+}
+
+/// closes the file of a port, which (close! port) does, see src/resource.rs
+#[cfg(feature = "io")]
+fn close_file(port: &dyn Any) -> bool {
+    let Some(port) = port.downcast_ref::<Port>() else {
+        return false;
+    };
+    let mut sink = port.sink.borrow_mut();
+    let open = matches!(*sink, Sink::File(_));
+    if open {
+        *sink = Sink::Closed;
+    }
+    open
+}
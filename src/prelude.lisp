@@ -0,0 +1,158 @@
+; functions derived from the builtins, so that those can stay few
+; evaluated in the root env by Interpreter::load_prelude
+
+; (not nil) -> true
+(def! not (fn* (x) (if x false true)))
+
+; true unless x is nil or false, as if sees it
+; (boolean 0) -> true, (boolean nil) -> false
+(def! boolean (fn* (x) (if x true false)))
+
+; (truthy? ()) -> true
+(def! truthy? boolean)
+
+; (inc 1) -> 2
+(def! inc (fn* (n) (+ n 1)))
+
+; (dec 1) -> 0
+(def! dec (fn* (n) (- n 1)))
+
+; (neg 1) -> -1
+(def! neg (fn* (n) (- n)))
+
+; (zero? 0) -> true
+(def! zero? (fn* (n) (if (<= n 0) (<= 0 n) false)))
+
+; (empty? ()) -> true, (empty? nil) -> true
+(def! empty? (fn* (coll) (zero? (count coll))))
+
+; (first [1 2 3]) -> 1, (first '(1 2 3)) -> 1
+(def! first (fn* (coll) (nth coll 0)))
+
+; (second [1 2 3]) -> 2
+(def! second (fn* (coll) (nth coll 1)))
+
+; (last [1 2 3]) -> 3
+(def! last (fn* (coll) (nth coll (dec (count coll)))))
+
+; (identity 1) -> 1
+(def! identity (fn* (x) x))
+
+; a function returning x, whatever it is called with
+; ((constantly 1) 2 3) -> 1
+(def! constantly (fn* (x) (fn* (& _) x)))
+
+; a function returning true when f returns nil or false
+; ((complement zero?) 1) -> true
+(def! complement
+  (fn* (f)
+    (fn* (& args) (not (apply f args)))))
+
+; a function returning a vector of what each of fs returns
+; ((juxt inc dec) 1) -> [2 0]
+(def! juxt
+  (fn* (& fs)
+    (fn* (& args)
+      (let* (step (fn* (i out)
+                    (if (<= (count fs) i)
+                      out
+                      (step (inc i) (conj out (apply (get fs i) args))))))
+        (step 0 [])))))
+
+; the items of coll in vectors by what f returns for them
+; (group-by zero? [0 1 0]) -> {true [0 0] false [1]}
+(def! group-by
+  (fn* (f coll)
+    (let* (items (vec coll)
+           step (fn* (i groups)
+                  (if (<= (count items) i)
+                    groups
+                    (let* (x (get items i) k (f x))
+                      (step (inc i) (assoc groups k (conj (get groups k []) x)))))))
+      (step 0 {}))))
+
+; whether pred holds for every item of coll, stopping at the first it doesn't
+; (every? zero? [0 0]) -> true, (every? zero? '(0 1)) -> false
+(def! every?
+  (fn* (pred coll)
+    (let* (items (vec coll)
+           step (fn* (i)
+                  (if (<= (count items) i)
+                    true
+                    (if (pred (get items i)) (step (inc i)) false))))
+      (step 0))))
+
+; the first true value pred returns for an item of coll, nil if none
+; (some (fn* (n) (if (zero? n) "zero")) [1 0]) -> "zero"
+(def! some
+  (fn* (pred coll)
+    (let* (items (vec coll)
+           step (fn* (i)
+                  (if (<= (count items) i)
+                    nil
+                    (let* (found (pred (get items i)))
+                      (if found found (step (inc i)))))))
+      (step 0))))
+
+; the first item of coll for which pred holds, nil if none
+; (find zero? [1 0 2]) -> 0
+(def! find
+  (fn* (pred coll)
+    (let* (items (vec coll)
+           step (fn* (i)
+                  (if (<= (count items) i)
+                    nil
+                    (if (pred (get items i)) (get items i) (step (inc i))))))
+      (step 0))))
+
+; the items of coll in the order opts gives them: by what its :key function returns
+; for them, compared by its :by function, true when a goes before b, else ascending
+; equal items keep their order, and lists give lists, vectors and maps vectors
+; (sort [1 3 2] {:by >}) -> [3 2 1], (sort '([1 2] [3]) {:key count}) -> ([3] [1 2])
+(def! sort
+  (let* (ascending sort)
+    (fn*
+      ((coll) (ascending coll))
+      ((coll opts)
+        (let* (key (get opts :key (fn* (x) x))
+               before? (get opts :by (fn* (a b) (< (compare a b) 0)))
+               merge (fn* (xs ys)
+                       (let* (step (fn* (i j out)
+                                     (if (if (<= (count ys) j)
+                                           true
+                                           (if (<= (count xs) i)
+                                             false
+                                             (not (before? (key (get ys j)) (key (get xs i))))))
+                                       (if (<= (count xs) i)
+                                         out
+                                         (step (inc i) j (conj out (get xs i))))
+                                       (step i (inc j) (conj out (get ys j))))))
+                         (step 0 0 [])))
+               pass (fn* (runs i out)
+                      (if (<= (count runs) (inc i))
+                        (if (<= (count runs) i) out (conj out (get runs i)))
+                        (pass runs (+ i 2) (conj out (merge (get runs i) (get runs (inc i)))))))
+               sorted (fn* (runs) (if (<= (count runs) 1) (get runs 0 []) (sorted (pass runs 0 []))))
+               as-list (fn* (items i out)
+                         (if (< i 0) out (as-list items (dec i) (cons (get items i) out))))
+               items (sorted (zip coll)))
+          (case (type-of coll)
+            :vector items
+            :map items
+            (as-list items (dec (count items)) ())))))))
+
+; the next value of a generator, nil once its body returned
+; (next (generator (yield 1))) -> 1
+(def! next (fn* (g) (g)))
+
+; the form an error handled by with-handler was raised from, pretty-printed
+; (with-handler (:error error-form) (+ 1 nil)) -> "(+ 1 nil)"
+(def! error-form (fn* (e) (get e :form)))
+
+; where that form is, as source:line:column
+; (with-handler (:error error-location) (+ 1 nil)) -> "<repl>:1:39"
+(def! error-location (fn* (e) (get e :location)))
+
+; the names of the local variables visible there, innermost first
+; (let* (x 1) (with-handler (:error error-locals) (+ x nil))) -> [x]
+(def! error-locals (fn* (e) (get e :locals)))
@@ -0,0 +1,540 @@
+use std::fmt;
+
+use crate::{
+    ast::{self, Expr, Special, raw_string_hashes, write_seq},
+    deterministic,
+    env::Value,
+    sync::Rc,
+};
+
+// stands for what was left out of a value too big to print
+const ELLIPSIS: &str = "...";
+// how deep the printer recurses when not limited to a depth, printing what is
+// nested deeper as Display does, in full and without recursing
+const MAX_NESTING: usize = 256;
+
+/// prints values on several lines when they don't fit in width,
+/// aligned after the opening bracket, one map entry per line,
+/// and as many vector items per line as fit:
+/// [[1 2 3] 4 5
+///  {"a" 1
+///   "b" 2}]
+/// collections and lists nested deeper than max_depth print as ...,
+/// and only their first max_items items are printed, so that
+/// huge values generated by a program print in bounded time
+#[derive(Debug, Clone, Copy)]
+pub struct PrettyPrinter {
+    pub width: usize,
+    pub max_depth: Option<usize>,
+    pub max_items: Option<usize>,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            max_depth: Some(16),
+            max_items: Some(100),
+        }
+    }
+}
+
+impl PrettyPrinter {
+    pub fn print(&self, value: &Value) -> String {
+        self.pretty(value, 0, 0)
+    }
+
+    /// expr as source code, which unlike values is never abbreviated:
+    /// special forms keep what they define or test on their first line
+    /// and indent the rest by 2, calls align their arguments after the first
+    /// (def! sum
+    ///   (fn* (a b)
+    ///     (+ a
+    ///        b)))
+    /// strings, 'form and #"...{form}..." read from source print as written there
+    pub fn print_expr(&self, expr: &Expr) -> String {
+        self.code(expr, 0)
+    }
+
+    /// value on one line, abbreviated
+    fn flat(&self, value: &Value, depth: usize) -> String {
+        if depth >= MAX_NESTING {
+            return value.to_string();
+        }
+        match self.items(value, depth) {
+            Some((open, items, close)) => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|item| match item {
+                        Item::Value(value) => self.flat(value, depth + 1),
+                        Item::Entry(k, v) => {
+                            format!("{} {}", self.flat(k, depth + 1), self.flat(v, depth + 1))
+                        }
+                        Item::Tail(last) => format!(". {}", self.flat(last, depth + 1)),
+                        Item::Ellipsis => ELLIPSIS.to_string(),
+                    })
+                    .collect();
+                format!("{}{}{}", open, items.join(" "), close)
+            }
+            None if self.too_deep(value, depth) => ELLIPSIS.to_string(),
+            None => value.to_string(),
+        }
+    }
+
+    /// value starting at column indent, broken over lines if needed
+    fn pretty(&self, value: &Value, depth: usize, indent: usize) -> String {
+        let flat = self.flat(value, depth);
+        if indent + flat.chars().count() <= self.width || depth >= MAX_NESTING {
+            return flat;
+        }
+        let Some((open, items, close)) = self.items(value, depth) else {
+            return flat;
+        };
+        let indent = indent + open.len();
+        let separator = format!("\n{}", " ".repeat(indent));
+        let mut out = open.to_string();
+        // column after the last item printed, None before the first
+        let mut column = None;
+        for item in &items {
+            let printed = match item {
+                Item::Value(value) => self.pretty(value, depth + 1, indent),
+                Item::Entry(k, v) => {
+                    let k = self.flat(k, depth + 1);
+                    let v = self.pretty(v, depth + 1, indent + k.chars().count() + 1);
+                    format!("{} {}", k, v)
+                }
+                Item::Tail(last) => format!(". {}", self.pretty(last, depth + 1, indent + 2)),
+                Item::Ellipsis => ELLIPSIS.to_string(),
+            };
+            let len = printed.chars().count();
+            // items that fit on one line are packed together, entries are not
+            column = match column {
+                Some(at)
+                    if !matches!(item, Item::Entry(..))
+                        && !printed.contains('\n')
+                        && at + 1 + len <= self.width =>
+                {
+                    out.push(' ');
+                    Some(at + 1 + len)
+                }
+                Some(_) => {
+                    out.push_str(&separator);
+                    Some(indent + len)
+                }
+                None => Some(indent + len),
+            };
+            out.push_str(&printed);
+        }
+        out.push_str(close);
+        out
+    }
+
+    /// expr starting at column indent, broken over lines if needed,
+    /// and always if it has comments, each on a line of its own before
+    /// the form it comes before, or the closing bracket
+    fn code(&self, expr: &Expr, indent: usize) -> String {
+        let flat = Written(expr).to_string();
+        if written(expr).is_some()
+            || indent + flat.chars().count() <= self.width && !commented(expr)
+        {
+            return flat;
+        }
+        let comments = comments_in(expr).unwrap_or_default();
+        if let Some(form) = quoted(expr) {
+            let lines = comment_lines(before(&comments, 1), indent + 1);
+            return format!("'{}{}", lines, self.code(form, indent + 1));
+        }
+        if let Some((tag, form)) = expr.tagged() {
+            let tag = format!("#{} ", tag);
+            let lines = comment_lines(before(&comments, 1), indent + tag.len());
+            return format!("{}{}{}", tag, lines, self.code(form, indent + tag.len()));
+        }
+        match expr {
+            Expr::List(exprs, _) => match exprs.first() {
+                Some(Expr::Special(special, _)) => {
+                    let kept = match special {
+                        // (fn* name (params)
+                        Special::Fn if matches!(exprs.get(1), Some(Expr::Atom(..))) => 2,
+                        Special::Def
+                        | Special::Defonce
+                        | Special::Defconst
+                        | Special::Let
+                        | Special::Fn
+                        | Special::If
+                        | Special::Deftest
+                        | Special::Ns
+                        | Special::Require
+                        | Special::CallEc
+                        | Special::Yield
+                        | Special::Defprotocol
+                        | Special::WithHandler
+                        | Special::Case
+                        | Special::Binding
+                        | Special::WithOpen
+                        | Special::When
+                        | Special::Unless
+                        | Special::IfNot
+                        | Special::ThreadFirst
+                        | Special::ThreadLast => 1,
+                        Special::ExtendType => 2,
+                        Special::Do
+                        | Special::Quote
+                        | Special::Is
+                        | Special::Generator
+                        | Special::Retry
+                        | Special::WithOutStr => 0,
+                    };
+                    self.code_seq(exprs, &comments, kept + 1, indent + 2, indent)
+                }
+                Some(head) if exprs.len() > 1 && before(&comments, 0).is_empty() => {
+                    let head = self.code(head, indent + 1);
+                    // arguments under the first one, unless the function spans lines
+                    let align = if head.contains('\n') {
+                        indent + 1
+                    } else {
+                        indent + 2 + head.chars().count()
+                    };
+                    self.code_seq(exprs, &comments, 2, align, indent)
+                }
+                _ => self.code_seq(exprs, &comments, 1, indent + 1, indent),
+            },
+            Expr::Vector(exprs, _) => self.code_packed(exprs, &comments, indent),
+            Expr::Map(exprs, _) => self.code_pairs("{", exprs, &comments, "}", indent),
+            _ => flat,
+        }
+    }
+
+    /// [items], as many per line as fit, those spanning lines on their own
+    fn code_packed(&self, exprs: &[Expr], comments: &[Vec<&str>], indent: usize) -> String {
+        let align = indent + 1;
+        let mut out = "[".to_string();
+        // column after the last item printed, None before the first
+        let mut column = None;
+        for (i, expr) in exprs.iter().enumerate() {
+            let lines = before(comments, i);
+            let flat = Written(expr).to_string();
+            let len = flat.chars().count();
+            column = match column {
+                Some(at) if at + 1 + len <= self.width && lines.is_empty() && !commented(expr) => {
+                    out.push(' ');
+                    out += &flat;
+                    Some(at + 1 + len)
+                }
+                _ => {
+                    if column.is_some() {
+                        out += &format!("\n{}", " ".repeat(align));
+                    }
+                    out += &comment_lines(lines, align);
+                    let printed = self.code(expr, align);
+                    let end = last_line_len(&printed, align);
+                    out += &printed;
+                    // nothing goes after an item spanning lines
+                    Some(if printed.contains('\n') {
+                        self.width
+                    } else {
+                        end
+                    })
+                }
+            };
+        }
+        out + &closing(
+            before(comments, exprs.len()),
+            !exprs.is_empty(),
+            align,
+            indent,
+        ) + "]"
+    }
+
+    /// open, then a key and its value per line, as in maps and let* bindings
+    fn code_pairs(
+        &self,
+        open: &str,
+        exprs: &[Expr],
+        comments: &[Vec<&str>],
+        close: &str,
+        indent: usize,
+    ) -> String {
+        let align = indent + open.len();
+        let mut out = open.to_string();
+        for (i, pair) in exprs.chunks(2).enumerate() {
+            if i > 0 {
+                out += &format!("\n{}", " ".repeat(align));
+            }
+            out += &comment_lines(before(comments, 2 * i), align);
+            let k = self.code(&pair[0], align);
+            out += &k;
+            if let Some(v) = pair.get(1) {
+                let lines = before(comments, 2 * i + 1);
+                let column = if lines.is_empty() {
+                    out.push(' ');
+                    last_line_len(&k, align) + 1
+                } else {
+                    // under the key, indented
+                    out += &format!("\n{}", " ".repeat(align + 2));
+                    out += &comment_lines(lines, align + 2);
+                    align + 2
+                };
+                out += &self.code(v, column);
+            }
+        }
+        out + &closing(
+            before(comments, exprs.len()),
+            !exprs.is_empty(),
+            align,
+            indent,
+        ) + close
+    }
+
+    /// (, the first kept exprs on its line, then one expr per line at column align
+    fn code_seq(
+        &self,
+        exprs: &[Expr],
+        comments: &[Vec<&str>],
+        kept: usize,
+        align: usize,
+        indent: usize,
+    ) -> String {
+        let mut out = "(".to_string();
+        let mut column = indent + 1;
+        for (i, expr) in exprs.iter().enumerate() {
+            let lines = before(comments, i);
+            if i >= kept || i > 0 && !lines.is_empty() {
+                out += &format!("\n{}", " ".repeat(align));
+                column = align;
+            } else if i > 0 {
+                out.push(' ');
+                column += 1;
+            }
+            out += &comment_lines(lines, column);
+            let printed = match expr {
+                Expr::Vector(bindings, _)
+                    if i == 1
+                        && matches!(exprs[0], Expr::Special(Special::Let, _))
+                        && (column + Written(expr).to_string().chars().count() > self.width
+                            || commented(expr)) =>
+                {
+                    let comments = comments_in(expr).unwrap_or_default();
+                    self.code_pairs("[", bindings, &comments, "]", column)
+                }
+                _ => self.code(expr, column),
+            };
+            column = last_line_len(&printed, column);
+            out += &printed;
+        }
+        out + &closing(
+            before(comments, exprs.len()),
+            !exprs.is_empty(),
+            align,
+            indent,
+        ) + ")"
+    }
+
+    fn too_deep(&self, value: &Value, depth: usize) -> bool {
+        matches!(value, Value::Vector(_) | Value::Map(_) | Value::Pair(_))
+            && self.max_depth.is_some_and(|max| depth >= max)
+    }
+
+    /// brackets and items to print of a collection, if value is one not too deep
+    fn items<'v>(&self, value: &'v Value, depth: usize) -> Option<(&str, Vec<Item<'v>>, &str)> {
+        if self.too_deep(value, depth) {
+            return None;
+        }
+        let max = self.max_items.unwrap_or(usize::MAX);
+        let (open, mut items, len, close) = match value {
+            Value::Vector(values) => (
+                "[",
+                values.iter().take(max).map(Item::Value).collect::<Vec<_>>(),
+                values.len(),
+                "]",
+            ),
+            Value::Map(entries) => (
+                "{",
+                deterministic::entries(entries)
+                    .into_iter()
+                    .take(max)
+                    .map(|(k, v)| Item::Entry(k, v))
+                    .collect(),
+                entries.len(),
+                "}",
+            ),
+            // walked only as far as printed, as lists can be too long to count
+            Value::Pair(_) => {
+                let mut items = vec![];
+                let mut rest = value;
+                loop {
+                    match rest {
+                        Value::Pair(_) if items.len() == max => {
+                            items.push(Item::Ellipsis);
+                            break;
+                        }
+                        Value::Pair(pair) => {
+                            items.push(Item::Value(&pair.0));
+                            rest = &pair.1;
+                        }
+                        Value::Nil => break,
+                        Value::Quoted(Expr::List(exprs, _)) if exprs.is_empty() => break,
+                        last => {
+                            items.push(Item::Tail(last));
+                            break;
+                        }
+                    }
+                }
+                return Some(("(", items, ")"));
+            }
+            _ => return None,
+        };
+        if len > max {
+            items.push(Item::Ellipsis);
+        }
+        Some((open, items, close))
+    }
+}
+
+/// value on one line, abbreviated as the default printer does,
+/// for error messages, which may be given values of any size
+pub fn abbreviate(value: &Value) -> String {
+    PrettyPrinter {
+        width: usize::MAX,
+        ..PrettyPrinter::default()
+    }
+    .print(value)
+}
+
+/// expr on one line, as print_expr writes it
+struct Written<'e>(&'e Expr);
+
+impl fmt::Display for Written<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(text) = written(self.0) {
+            return write!(f, "{}", text);
+        }
+        if let Some(form) = quoted(self.0) {
+            return write!(f, "'{}", Written(form));
+        }
+        match self.0 {
+            Expr::List(exprs, _) => match self.0.tagged() {
+                Some((tag, form)) => write!(f, "#{} {}", tag, Written(form)),
+                None => write_seq(f, "(", exprs.iter().map(Written), ")"),
+            },
+            Expr::Vector(exprs, _) => write_seq(f, "[", exprs.iter().map(Written), "]"),
+            Expr::Map(exprs, _) => write_seq(f, "{", exprs.iter().map(Written), "}"),
+            expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+/// the source text of a string or interpolated string, which reading rewrites:
+/// escapes are read, raw strings aren't told from others, and #"hi {s}" reads as
+/// (str "hi " s), none for other forms and those not read from source
+fn written(expr: &Expr) -> Option<&str> {
+    let text = expr.span().text();
+    let literal = match expr {
+        Expr::String(..) => text.starts_with('"') || raw_string_hashes(text).is_some(),
+        Expr::List(..) => text.starts_with("#\""),
+        _ => false,
+    };
+    // a form made rather than read may have the span of another
+    (literal && Expr::parse(text.to_string()).is_ok_and(|read| read == *expr)).then_some(text)
+}
+
+/// the ; comments before each form in a list, vector, map, 'form or #tag form
+/// read from source, and last those before its closing bracket,
+/// none if it has none or wasn't read
+fn comments_in(expr: &Expr) -> Option<Vec<Vec<&str>>> {
+    let (Expr::List(exprs, span) | Expr::Vector(exprs, span) | Expr::Map(exprs, span)) = expr
+    else {
+        return None;
+    };
+    // brackets, or the ' or #tag that is the first form
+    let bracket = match span.text().chars().next() {
+        Some('(' | '[' | '{') => 1,
+        Some('\'' | '#') if written(expr).is_none() => 0,
+        _ => return None,
+    };
+    let text = &span.source.text;
+    let mut start = span.start + bracket;
+    let mut comments = vec![];
+    for expr in exprs.iter() {
+        let at = expr.span();
+        if !Rc::ptr_eq(&at.source, &span.source) || at.start < start || at.end > span.end {
+            return None;
+        }
+        comments.push(ast::comments(text.get(start..at.start)?)?);
+        start = at.end;
+    }
+    comments.push(ast::comments(
+        text.get(start..span.end.checked_sub(bracket)?)?,
+    )?);
+    comments
+        .iter()
+        .any(|lines| !lines.is_empty())
+        .then_some(comments)
+}
+
+/// whether expr has comments in it, which keep it from being printed on one line
+fn commented(expr: &Expr) -> bool {
+    match expr {
+        _ if written(expr).is_some() => false,
+        Expr::List(exprs, _) | Expr::Vector(exprs, _) | Expr::Map(exprs, _) => {
+            comments_in(expr).is_some() || exprs.iter().any(commented)
+        }
+        _ => false,
+    }
+}
+
+/// the comments before the i-th form
+fn before<'c>(comments: &'c [Vec<&'c str>], i: usize) -> &'c [&'c str] {
+    comments.get(i).map_or(&[], Vec::as_slice)
+}
+
+/// comments each on its line, starting at column, the next line as well
+fn comment_lines(comments: &[&str], column: usize) -> String {
+    let separator = format!("\n{}", " ".repeat(column));
+    comments
+        .iter()
+        .map(|line| format!("{}{}", line, separator))
+        .collect()
+}
+
+/// what goes before the closing bracket: the comments before it on lines of their own
+/// at column align, after the forms if there are any, then a line for the bracket
+fn closing(comments: &[&str], after_forms: bool, align: usize, indent: usize) -> String {
+    if comments.is_empty() {
+        return String::new();
+    }
+    let separator = format!("\n{}", " ".repeat(align));
+    let mut out = if after_forms {
+        separator.clone()
+    } else {
+        String::new()
+    };
+    out += &comments.join(&separator);
+    out + "\n" + &" ".repeat(indent)
+}
+
+/// the form of a 'form, which reads as (quote form)
+fn quoted(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::List(exprs, _) => match &exprs[..] {
+            [Expr::Special(Special::Quote, span), form] if span.text() == "'" => Some(form),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// column after printing text starting at column
+fn last_line_len(text: &str, column: usize) -> usize {
+    match text.rsplit_once('\n') {
+        Some((_, last)) => last.chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+enum Item<'v> {
+    Value(&'v Value),
+    Entry(&'v Value, &'v Value),
+    // the last cdr of an improper list, printed after a dot
+    Tail(&'v Value),
+    Ellipsis,
+}
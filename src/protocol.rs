@@ -0,0 +1,140 @@
+// protocols: named sets of methods, generic functions calling the implementation
+// extend-type gave for the type-of their first argument, or else for :default
+// (defprotocol show (render [x])) and (extend-type :vector show (render [v] ...))
+// expand into calls of the builtins below, see ir::expand_defprotocol
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::Expr,
+    env::{Builtin, TYPE_KEYWORDS, Value},
+    error::FelispError,
+    printer,
+    symbol::Symbol,
+    sync::Lock,
+};
+
+/// builtins defprotocol and extend-type expand into, and satisfies?
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[
+    ("make-protocol", "name methods", make_protocol),
+    ("protocol-extend", "protocol type method f", protocol_extend),
+    ("protocol-method", "protocol method x", protocol_method),
+    ("satisfies?", "protocol x", satisfies),
+];
+
+/// the methods of a protocol, and their implementations by type
+pub struct Protocol {
+    name: Symbol,
+    methods: Vec<Symbol>,
+    // keyed by method and type keyword, extended as extend-type is evaluated
+    impls: Lock<HashMap<(Symbol, Symbol), Value>>,
+}
+
+/// a protocol without implementations
+/// (make-protocol 'show '(render)) -> <Protocol>
+fn make_protocol(args: &[Value]) -> Result<Value, FelispError> {
+    arity("make-protocol", args, 2)?;
+    let name = symbol(args, 0)?;
+    let methods = args[1]
+        .list_items()
+        .ok_or_else(|| FelispError::bad_arg(1, "list of symbols", &args[1]))?
+        .iter()
+        .map(|method| match method {
+            Value::Quoted(Expr::Atom(method, _)) => Ok(*method),
+            _ => Err(FelispError::bad_arg(1, "list of symbols", &args[1])),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Value::native(Protocol {
+        name,
+        methods,
+        impls: Lock::default(),
+    }))
+}
+
+/// implements method of protocol for type with f
+/// (protocol-extend show :vector 'render (fn* (v) ...)) -> nil
+fn protocol_extend(args: &[Value]) -> Result<Value, FelispError> {
+    arity("protocol-extend", args, 4)?;
+    let protocol = protocol(args, 0)?;
+    let kind = match &args[1] {
+        Value::Keyword(kind) if *kind == default() || TYPE_KEYWORDS.contains(&kind.name()) => *kind,
+        kind => return Err(FelispError::bad_arg(1, "type keyword or :default", kind)),
+    };
+    let method = symbol(args, 2)?;
+    if !protocol.methods.contains(&method) {
+        return Err(FelispError::bad_arg(
+            2,
+            &format!("method of {}", protocol.name),
+            &args[2],
+        ));
+    }
+    protocol
+        .impls
+        .borrow_mut()
+        .insert((method, kind), args[3].clone());
+    Ok(Value::Nil)
+}
+
+/// the implementation of method of protocol for the type of x
+/// (protocol-method show 'render [1]) -> <fun>
+fn protocol_method(args: &[Value]) -> Result<Value, FelispError> {
+    arity("protocol-method", args, 3)?;
+    let protocol = protocol(args, 0)?;
+    let method = symbol(args, 1)?;
+    let impls = protocol.impls.borrow();
+    let kind = Symbol::intern(args[2].type_keyword());
+    match impls
+        .get(&(method, kind))
+        .or_else(|| impls.get(&(method, default())))
+    {
+        Some(f) => Ok(f.clone()),
+        None => Err(FelispError::TypeError {
+            expected: format!(":{} to implement {} of {}", kind, method, protocol.name),
+            got: printer::abbreviate(&args[2]),
+            arg: None,
+            function: None,
+        }),
+    }
+}
+
+/// whether the type of x implements every method of protocol
+/// (satisfies? show [1]) -> true
+fn satisfies(args: &[Value]) -> Result<Value, FelispError> {
+    arity("satisfies?", args, 2)?;
+    let protocol = protocol(args, 0)?;
+    let impls = protocol.impls.borrow();
+    let kind = Symbol::intern(args[1].type_keyword());
+    Ok(Value::from(protocol.methods.iter().all(|&method| {
+        impls.contains_key(&(method, kind)) || impls.contains_key(&(method, default()))
+    })))
+}
+
+fn arity(name: &str, args: &[Value], n: usize) -> Result<(), FelispError> {
+    if args.len() != n {
+        return Err(FelispError::ArityError(format!(
+            "{} requires {} arguments but given {}",
+            name,
+            n,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+fn protocol(args: &[Value], i: usize) -> Result<&Protocol, FelispError> {
+    args[i]
+        .downcast_native()
+        .ok_or_else(|| FelispError::bad_arg(i, "Protocol", &args[i]))
+}
+
+fn symbol(args: &[Value], i: usize) -> Result<Symbol, FelispError> {
+    match &args[i] {
+        Value::Quoted(Expr::Atom(symbol, _)) => Ok(*symbol),
+        arg => Err(FelispError::bad_arg(i, "Symbol", arg)),
+    }
+}
+
+// the type of the implementations used for types without their own
+fn default() -> Symbol {
+    Symbol::intern("default")
+}
@@ -0,0 +1,124 @@
+// pruning: removing the bindings of an env that nothing kept reaches, so that long
+// REPL sessions release what their intermediate def!s hold
+// a binding reaches the values it holds, and the globals the functions and generators
+// among them look up, as well as the local variables they captured
+// host values and builtins are opaque, reaching nothing, and neither are the names
+// code looks up itself, as with resolve, nor the values generators made since starting
+
+use std::collections::HashSet;
+
+use crate::{
+    env::{Env, Scope, Value},
+    ir::{Node, NodeKind},
+    symbol::Symbol,
+    sync::Rc,
+};
+
+/// removes the bindings of env that the roots don't reach, returning their names, sorted
+/// bindings of defconst and reader macros are kept, as are those they reach
+/// (prune(&env, &[Symbol::intern("main")])) -> [scratch tmp]
+pub fn prune(env: &Env, roots: &[Symbol]) -> Vec<Symbol> {
+    let symbols = env.symbols();
+    let kept = symbols
+        .iter()
+        .copied()
+        .filter(|&symbol| env.is_constant(symbol) || symbol.name().starts_with('#'));
+    let reached = reachable(env, roots.iter().copied().chain(kept));
+    let mut pruned: Vec<Symbol> = symbols
+        .into_iter()
+        .filter(|symbol| !reached.contains(symbol))
+        .filter(|&symbol| env.remove(symbol).is_ok_and(|value| value.is_some()))
+        .collect();
+    pruned.sort_by_key(|symbol| symbol.name());
+    pruned
+}
+
+/// the symbols bound in env that the roots reach, themselves included
+pub fn reachable(env: &Env, roots: impl IntoIterator<Item = Symbol>) -> HashSet<Symbol> {
+    let mut reached = HashSet::new();
+    let mut symbols: Vec<Symbol> = roots.into_iter().collect();
+    let mut values = vec![];
+    // functions are often reached several times, each looked into once
+    let mut seen = HashSet::new();
+    loop {
+        if let Some(symbol) = symbols.pop() {
+            if reached.insert(symbol)
+                && let Some(value) = env.get_own(symbol)
+            {
+                values.push(value);
+            }
+        } else if let Some(value) = values.pop() {
+            references(&value, &mut seen, &mut symbols, &mut values);
+        } else {
+            return reached;
+        }
+    }
+}
+
+/// pushes the symbols and values value refers to
+fn references(
+    value: &Value,
+    seen: &mut HashSet<*const ()>,
+    symbols: &mut Vec<Symbol>,
+    values: &mut Vec<Value>,
+) {
+    let shared = match value {
+        Value::Lambda(f) => Some(Rc::as_ptr(f).cast()),
+        Value::Memoized(f) => Some(Rc::as_ptr(f).cast()),
+        Value::Curried(f) => Some(Rc::as_ptr(f).cast()),
+        Value::Generator(g) => Some(Rc::as_ptr(g).cast()),
+        _ => None,
+    };
+    if shared.is_some_and(|ptr| !seen.insert(ptr)) {
+        return;
+    }
+    match value {
+        Value::Pair(pair) => values.extend([pair.0.clone(), pair.1.clone()]),
+        Value::Vector(items) => values.extend(items.iter().cloned()),
+        Value::Map(entries) => {
+            values.extend(entries.iter().flat_map(|(k, v)| [k.clone(), v.clone()]))
+        }
+        Value::Lambda(lambda) => {
+            for arity in lambda.def.arities.iter() {
+                globals(&arity.body, symbols, values);
+            }
+            captured(lambda.scope.as_ref(), values);
+        }
+        Value::Memoized(memo) => {
+            values.push(memo.f().clone());
+            values.extend(memo.cached());
+        }
+        Value::Curried(curried) => {
+            let (f, args) = curried.parts();
+            values.push(f.clone());
+            values.extend(args.iter().cloned());
+        }
+        Value::Generator(generator) => {
+            for node in generator.body() {
+                globals(node, symbols, values);
+            }
+            captured(generator.scope(), values);
+        }
+        _ => {}
+    }
+}
+
+/// pushes the values of the variables of scope and the ones it is nested in
+fn captured(mut scope: Option<&Rc<Scope>>, values: &mut Vec<Value>) {
+    while let Some(variables) = scope {
+        values.extend(variables.bindings().into_iter().map(|(_, value)| value));
+        scope = variables.outer();
+    }
+}
+
+/// pushes the globals node looks up, and the constants it holds
+fn globals(node: &Node, symbols: &mut Vec<Symbol>, values: &mut Vec<Value>) {
+    match &node.kind {
+        NodeKind::Global(symbol) => symbols.push(*symbol),
+        NodeKind::Const(value) => values.push(value.clone()),
+        _ => {}
+    }
+    for child in node.children() {
+        globals(child, symbols, values);
+    }
+}
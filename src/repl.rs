@@ -0,0 +1,299 @@
+// line editing support for the REPL: completion of the symbols bound in its env,
+// syntax highlighting, bracket matching and continuation of unbalanced input
+
+use std::{borrow::Cow, ops::Range};
+
+use rustyline::{
+    Context, Helper,
+    completion::Completer,
+    highlight::{CmdKind, Highlighter},
+    hint::Hinter,
+    validate::{ValidationContext, ValidationResult, Validator},
+};
+
+use crate::{
+    ast::{Special, is_delimiter, raw_string_hashes, raw_string_len},
+    env::Env,
+    sync::Rc,
+};
+
+/// rustyline helper completing symbols from the live env
+pub struct ReplHelper {
+    env: Rc<Env>,
+    // whether to highlight the line being edited
+    color: bool,
+    // whether to let lines with unbalanced closers through, see lenient
+    lenient: bool,
+}
+
+impl ReplHelper {
+    pub fn new(env: Rc<Env>) -> Self {
+        Self {
+            env,
+            color: true,
+            lenient: false,
+        }
+    }
+
+    /// leaves the line being edited as typed
+    pub fn without_color(mut self) -> Self {
+        self.color = false;
+        self
+    }
+
+    /// hands lines with unbalanced closers to the reader, which reports them,
+    /// instead of asking for them to be fixed, for input that isn't typed
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    /// special forms and bound symbols starting with the word before the cursor
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| c.is_whitespace() || is_delimiter(c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        let mut candidates: Vec<String> = Special::ALL
+            .iter()
+            .map(|special| special.name())
+            .chain(self.env.symbols().into_iter().map(|symbol| symbol.name()))
+            .filter(|name| name.starts_with(prefix))
+            .map(str::to_string)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    /// colors strings, numbers, special forms and comments, the bracket
+    /// under or before the cursor along with its match, and unmatched closers
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.color {
+            return Cow::Borrowed(line);
+        }
+        let tokens = tokens(line);
+        let brackets = Brackets::new(&tokens, line);
+        let cursor = tokens
+            .iter()
+            .filter(|(token, _)| matches!(token, Token::Open | Token::Close))
+            .map(|(_, range)| range.start)
+            .find(|&start| start == pos || start + 1 == pos);
+        let matched = cursor.and_then(|start| brackets.matching(start));
+        let mut out = String::new();
+        let mut end = 0;
+        for (token, range) in tokens {
+            out += &line[end..range.start];
+            let color = match token {
+                Token::Open | Token::Close if brackets.unmatched.contains(&range.start) => {
+                    Some(RED)
+                }
+                Token::Open | Token::Close
+                    if matched.is_some()
+                        && (cursor == Some(range.start) || matched == Some(range.start)) =>
+                {
+                    Some(BRACKET)
+                }
+                Token::String | Token::Unterminated => Some(GREEN),
+                Token::Number => Some(CYAN),
+                Token::Keyword => Some(MAGENTA),
+                Token::Comment => Some(GREY),
+                _ => None,
+            };
+            match color {
+                Some(color) => out += &format!("{}{}{}", color, &line[range.clone()], RESET),
+                None => out += &line[range.clone()],
+            }
+            end = range.end;
+        }
+        out += &line[end..];
+        Cow::Owned(out)
+    }
+
+    fn highlight_char(&self, _: &str, _: usize, kind: CmdKind) -> bool {
+        // cursor moves change which brackets are matched
+        self.color && kind != CmdKind::ForcedRefresh
+    }
+}
+
+impl Validator for ReplHelper {
+    /// asks for more lines while brackets or strings are left open
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let line = ctx.input();
+        let tokens = tokens(line);
+        let brackets = Brackets::new(&tokens, line);
+        if let Some(&i) = brackets.unmatched.first()
+            && !self.lenient
+        {
+            let c = &line[i..i + 1];
+            return Ok(ValidationResult::Invalid(Some(format!(
+                " unbalanced '{}'",
+                c
+            ))));
+        }
+        let unterminated = tokens
+            .last()
+            .is_some_and(|(token, _)| *token == Token::Unterminated);
+        if unterminated || (!brackets.open.is_empty() && brackets.unmatched.is_empty()) {
+            return Ok(ValidationResult::Incomplete);
+        }
+        Ok(ValidationResult::Valid(None))
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const GREY: &str = "\x1b[90m";
+const BRACKET: &str = "\x1b[1;34m";
+
+/// what a piece of the line is, as far as highlighting goes
+#[derive(Clone, Copy, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    String,
+    // string missing its closing quote
+    Unterminated,
+    Comment,
+    Number,
+    // special forms and literals
+    Keyword,
+    Symbol,
+}
+
+/// tokens of line and their byte ranges, a lenient version of the reader
+fn tokens(line: &str) -> Vec<(Token, Range<usize>)> {
+    let mut chars = line.char_indices().peekable();
+    let mut tokens = vec![];
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' | '[' | '{' => Token::Open,
+            ')' | ']' | '}' => Token::Close,
+            ';' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                Token::Comment
+            }
+            'r' if raw_string_hashes(&line[start..]).is_some() => {
+                let end = raw_string_len(&line[start..]).map(|len| start + len);
+                while chars
+                    .next_if(|&(i, _)| end.is_none_or(|end| i < end))
+                    .is_some()
+                {}
+                match end {
+                    Some(_) => Token::String,
+                    None => Token::Unterminated,
+                }
+            }
+            '"' => {
+                let mut token = Token::Unterminated;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => {
+                            token = Token::String;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                token
+            }
+            _ => {
+                while chars
+                    .next_if(|&(_, c)| !c.is_whitespace() && !is_delimiter(c))
+                    .is_some()
+                {}
+                let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+                let atom = &line[start..end];
+                if atom.parse::<i64>().is_ok() {
+                    Token::Number
+                } else if Special::from_name(atom).is_some()
+                    || matches!(atom, "nil" | "true" | "false")
+                {
+                    Token::Keyword
+                } else {
+                    Token::Symbol
+                }
+            }
+        };
+        let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+        tokens.push((token, start..end));
+    }
+    tokens
+}
+
+/// brackets of a line paired up by offset
+struct Brackets {
+    pairs: Vec<(usize, usize)>,
+    // closers without a matching opener
+    unmatched: Vec<usize>,
+    // openers not closed yet
+    open: Vec<usize>,
+}
+
+impl Brackets {
+    fn new(tokens: &[(Token, Range<usize>)], line: &str) -> Self {
+        let mut brackets = Brackets {
+            pairs: vec![],
+            unmatched: vec![],
+            open: vec![],
+        };
+        for (token, range) in tokens {
+            let c = line.as_bytes()[range.start];
+            match token {
+                Token::Open => brackets.open.push(range.start),
+                Token::Close => match brackets.open.last() {
+                    Some(&open) if closer(line.as_bytes()[open]) == c => {
+                        brackets.open.pop();
+                        brackets.pairs.push((open, range.start));
+                    }
+                    _ => brackets.unmatched.push(range.start),
+                },
+                _ => {}
+            }
+        }
+        brackets
+    }
+
+    /// offset of the bracket paired with the one at offset i
+    fn matching(&self, i: usize) -> Option<usize> {
+        self.pairs.iter().find_map(|&(open, close)| match i {
+            _ if i == open => Some(close),
+            _ if i == close => Some(open),
+            _ => None,
+        })
+    }
+}
+
+fn closer(open: u8) -> u8 {
+    match open {
+        b'(' => b')',
+        b'[' => b']',
+        _ => b'}',
+    }
+}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,98 @@
+// resources: native values holding a handle of the host, a socket, a file or a library,
+// which (close! r) releases, or else dropping the last reference to the value
+// (with-open (r (tcp-connect ...)) body...) closes r once its body returns or raises
+
+use std::any::{Any, type_name};
+
+use crate::{
+    env::{Builtin, Value},
+    error::FelispError,
+    sync::{Lock, MaybeSync},
+};
+
+/// builtins of every env, as closing takes nothing from the code
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[("close!", "r", close)];
+
+/// a handle of the host and how to release it, printed as <T>
+pub struct Resource<T> {
+    // None once closed
+    handle: Lock<Option<T>>,
+    finalizer: fn(T),
+}
+
+impl<T: Any + MaybeSync> Resource<T> {
+    /// a value holding handle, released by dropping it
+    pub fn value(handle: T) -> Value {
+        Self::with_finalizer(handle, drop)
+    }
+
+    /// a value holding handle, released by finalizer, e.g. a C function freeing it
+    pub fn with_finalizer(handle: T, finalizer: fn(T)) -> Value {
+        let resource = Resource {
+            handle: Lock::new(Some(handle)),
+            finalizer,
+        };
+        let name = type_name::<T>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        Value::closable(resource, name, close_any::<T>)
+    }
+}
+
+// the close of Native, for a Resource<T>
+fn close_any<T: Any>(value: &dyn Any) -> bool {
+    value
+        .downcast_ref::<Resource<T>>()
+        .is_some_and(Resource::close)
+}
+
+impl<T> Resource<T> {
+    /// what f returns for the handle, None once it is closed
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.handle.borrow().as_ref().map(f)
+    }
+
+    /// releases the handle, false if it already was
+    pub fn close(&self) -> bool {
+        let handle = self.handle.borrow_mut().take();
+        match handle {
+            Some(handle) => {
+                (self.finalizer)(handle);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T> Drop for Resource<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// what f returns for the handle of type T args[i] holds, failing if it is closed
+pub fn with_arg<T: Any, R>(
+    args: &[Value],
+    i: usize,
+    expected: &str,
+    f: impl FnOnce(&T) -> R,
+) -> Result<R, FelispError> {
+    let resource = args[i]
+        .downcast_native::<Resource<T>>()
+        .ok_or_else(|| FelispError::bad_arg(i, expected, &args[i]))?;
+    resource
+        .with(f)
+        .ok_or_else(|| FelispError::bad_arg(i, &format!("open {}", expected), &args[i]))
+}
+
+/// releases the handle of r, returning false if it already was
+/// (close! (tcp-connect "example.com" 80)) -> true
+fn close(args: &[Value]) -> Result<Value, FelispError> {
+    match &args[0] {
+        Value::Native(native) => native
+            .close()
+            .map(Value::from)
+            .ok_or_else(|| FelispError::bad_arg(0, "resource", &args[0])),
+        r => Err(FelispError::bad_arg(0, "resource", r)),
+    }
+}
@@ -0,0 +1,183 @@
+// a REPL for programs rather than people, as felisp --json-rpc serves it: JSON-RPC 2.0
+// messages over stdin and stdout, one per line, for editors and notebooks to drive the
+// interpreter with instead of scraping the prompt
+// {"jsonrpc":"2.0","id":1,"method":"eval","params":{"code":"(println 1) (+ 1 2)"}}
+// is answered by {"jsonrpc":"2.0","method":"out","params":{"id":1,"text":"1\n"}},
+// then {"jsonrpc":"2.0","id":1,"result":{"value":"3"}}
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::{Value as Json, json};
+
+use crate::{
+    ast::Special,
+    error::FelispError,
+    interpreter::Interpreter,
+    printer,
+    span::Source,
+    sync::{Lock, MaybeSend, Rc},
+};
+
+// JSON-RPC error codes for lines that aren't JSON, messages that aren't requests,
+// methods the server doesn't implement and missing params
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// and for code raising an error, the first of those left to servers
+const EVAL_ERROR: i64 = -32000;
+
+/// answers the requests read from input on output, until the end of input or exit
+/// what the code prints to *out* and *err* is sent as it does, in out and err
+/// notifications naming the request being answered
+pub fn serve(
+    interpreter: Interpreter,
+    input: impl BufRead,
+    output: impl Write + MaybeSend + 'static,
+) -> io::Result<()> {
+    let channel = Rc::new(Lock::new(Channel {
+        output,
+        id: Json::Null,
+    }));
+    let printer = |method: &'static str| {
+        let channel = Rc::clone(&channel);
+        move |text: &str| {
+            let mut channel = channel.borrow_mut();
+            let params = json!({ "id": channel.id, "text": text });
+            // should writing fail, writing the response fails too, which ends the session
+            let _ = channel.send(&notification(method, params));
+        }
+    };
+    let interpreter = interpreter
+        .redirect_out(printer("out"))
+        .redirect_err(printer("err"));
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match serde_json::from_str::<Json>(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                let reply = error(&Json::Null, PARSE_ERROR, &err.to_string(), Json::Null);
+                channel.borrow_mut().send(&reply)?;
+                continue;
+            }
+        };
+        let id = message["id"].clone();
+        let Some(method) = message["method"].as_str() else {
+            let reply = error(&id, INVALID_REQUEST, "a request needs a method", Json::Null);
+            channel.borrow_mut().send(&reply)?;
+            continue;
+        };
+        channel.borrow_mut().id = id.clone();
+        // not holding the channel while evaluating, which prints to it
+        let result = answer(&interpreter, method, &message["params"]);
+        let mut channel = channel.borrow_mut();
+        // notifications need no reply
+        if !id.is_null() {
+            let reply = match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message, data)) => error(&id, code, &message, data),
+            };
+            channel.send(&reply)?;
+        }
+        channel.id = Json::Null;
+        if method == "exit" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// where replies go, and the request being answered, which printing is part of
+struct Channel<W> {
+    output: W,
+    id: Json,
+}
+
+impl<W: Write> Channel<W> {
+    fn send(&mut self, message: &Json) -> io::Result<()> {
+        writeln!(self.output, "{}", message)?;
+        self.output.flush()
+    }
+}
+
+/// the result of a request, or the code, message and data of its error
+/// eval: {"code": "(+ 1 2)", "file": "scratch.lisp"} -> {"value": "3"}, file being optional
+/// complete: {"prefix": "with-"} -> ["with-handler", "with-open", "with-out-str"]
+/// exit: ends the session once answered
+fn answer(
+    interpreter: &Interpreter,
+    method: &str,
+    params: &Json,
+) -> Result<Json, (i64, String, Json)> {
+    match method {
+        "eval" => {
+            let Some(code) = params["code"].as_str() else {
+                let message = "eval requires the code to evaluate, as a string";
+                return Err((INVALID_PARAMS, message.to_string(), Json::Null));
+            };
+            let file = params["file"].as_str().unwrap_or("<rpc>");
+            match interpreter.eval_source(&Source::new(file, code.to_string())) {
+                Ok(value) => Ok(json!({ "value": printer::abbreviate(&value) })),
+                Err(err) => Err((EVAL_ERROR, err.to_string(), error_data(&err))),
+            }
+        }
+        "complete" => {
+            let prefix = params["prefix"].as_str().unwrap_or_default();
+            Ok(json!(completions(interpreter, prefix)))
+        }
+        "exit" => Ok(Json::Null),
+        method => Err((
+            METHOD_NOT_FOUND,
+            format!("{} is not supported", method),
+            Json::Null,
+        )),
+    }
+}
+
+/// special forms and bound symbols starting with prefix, sorted
+fn completions(interpreter: &Interpreter, prefix: &str) -> Vec<&'static str> {
+    let mut candidates: Vec<&str> = Special::ALL
+        .iter()
+        .map(|special| special.name())
+        .chain(
+            interpreter
+                .current_env()
+                .symbols()
+                .into_iter()
+                .map(|symbol| symbol.name()),
+        )
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// the kind of err, as with-handler names it, its report as the REPL prints it,
+/// and where it was raised, if known
+/// {"kind": "type-error", "report": "type error: ...", "file": "<rpc>", "line": 1, "column": 4}
+fn error_data(err: &FelispError) -> Json {
+    let mut data = json!({ "kind": err.keyword(), "report": err.report() });
+    if let Some(span) = err.span() {
+        let (line, column) = span.source.line_col(span.start);
+        data["file"] = json!(span.source.name);
+        data["line"] = json!(line);
+        data["column"] = json!(column);
+    }
+    data
+}
+
+fn error(id: &Json, code: i64, message: &str, data: Json) -> Json {
+    let mut error = json!({ "code": code, "message": message });
+    if !data.is_null() {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
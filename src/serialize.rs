@@ -0,0 +1,110 @@
+// values as serde data: nil as unit, booleans, integers, floats, strings, keywords as
+// their names, bigints and ratios as the strings they are written as, vectors and proper lists as sequences and maps as maps, so they can go to and from json, yaml, ...
+// functions and quoted forms are code rather than data and can't be serialized,
+// neither can native values of the host
+
+use std::fmt;
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser,
+};
+
+use crate::{deterministic, env::Value, sync::Rc};
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Nil => serializer.serialize_unit(),
+            Value::True => serializer.serialize_bool(true),
+            Value::False => serializer.serialize_bool(false),
+            Value::Number(n) => serializer.serialize_i64(*n),
+            Value::Float(x) => serializer.serialize_f64(*x),
+            Value::BigInt(_) | Value::Ratio(_) => serializer.serialize_str(&self.to_string()),
+            Value::String(s) => serializer.serialize_str(s),
+            // {:name "felisp"} -> {"name": "felisp"}, read back with string keys
+            Value::Keyword(name) => serializer.serialize_str(name.name()),
+            Value::Vector(items) => serializer.collect_seq(items),
+            Value::Pair(_) => match self.list_items() {
+                Some(items) => serializer.collect_seq(items),
+                None => Err(ser::Error::custom(format!("can't serialize '{}'", self))),
+            },
+            Value::Map(entries) => serializer.collect_map(deterministic::entries(entries)),
+            Value::Quoted(_)
+            | Value::Function(_)
+            | Value::AsyncFunction(_)
+            | Value::Lambda(_)
+            | Value::Memoized(_)
+            | Value::Curried(_)
+            | Value::Native(_)
+            | Value::Generator(_) => Err(ser::Error::custom(format!("can't serialize '{}'", self))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "nil, a boolean, a number, a string, a sequence or a map")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Value, E> {
+        Ok(if b { Value::True } else { Value::False })
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Number(n))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Value, E> {
+        Ok(i64::try_from(n)
+            .map(Value::Number)
+            .unwrap_or_else(|_| Value::BigInt(Rc::new(n.into()))))
+    }
+
+    fn visit_f64<E: de::Error>(self, x: f64) -> Result<Value, E> {
+        Ok(Value::Float(x))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.into()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = im::Vector::new();
+        while let Some(item) = seq.next_element()? {
+            items.push_back(item);
+        }
+        Ok(Value::Vector(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = im::HashMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            entries.insert(key, value);
+        }
+        Ok(Value::Map(entries))
+    }
+}
@@ -0,0 +1,120 @@
+use std::{fmt, fs, hash, io, path::Path};
+
+use crate::{error::FelispError, sync::Rc};
+
+/// a named piece of source code, shared by every span pointing into it
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: String) -> Rc<Source> {
+        Rc::new(Self {
+            name: name.to_string(),
+            text,
+        })
+    }
+
+    /// the file at path, named after it
+    /// a first line starting with #!, as in executable scripts, is skipped
+    pub fn read(path: impl AsRef<Path>) -> Result<Rc<Source>, FelispError> {
+        let path = path.as_ref();
+        let mut text = fs::read_to_string(path).map_err(|err| {
+            FelispError::host(io::Error::new(
+                err.kind(),
+                format!("can't read {}: {}", path.display(), err),
+            ))
+        })?;
+        if text.starts_with("#!") {
+            // made a comment, so that spans still point at the right place
+            text.replace_range(..2, ";!");
+        }
+        Ok(Source::new(&path.display().to_string(), text))
+    }
+
+    /// 1-based (line, column) of a byte offset
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (line, col)
+    }
+}
+
+/// byte range [start, end) of an expression within its source
+/// spans never take part in comparing or hashing expressions
+#[derive(Clone)]
+pub struct Span {
+    pub source: Rc<Source>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(source: &Rc<Source>, start: usize, end: usize) -> Self {
+        Self {
+            source: source.clone(),
+            start,
+            end,
+        }
+    }
+
+    /// source code of the expression
+    pub fn text(&self) -> &str {
+        self.source
+            .text
+            .get(self.start..self.end)
+            .unwrap_or_default()
+    }
+
+    /// renders the line holding the span with a caret underneath
+    ///  --> <repl>:1:6
+    ///   |
+    /// 1 | (+ 1 x)
+    ///   |      ^
+    pub fn snippet(&self) -> String {
+        let text = &self.source.text;
+        let (line, col) = self.source.line_col(self.start);
+        let line_start = text[..self.start.min(text.len())]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line_end = text[line_start..]
+            .find('\n')
+            .map_or(text.len(), |i| line_start + i);
+        let code = &text[line_start..line_end];
+        let width = text[self.start.min(line_end)..self.end.clamp(self.start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            self.source.name,
+            line,
+            col,
+            line,
+            code,
+            " ".repeat(col - 1),
+            "^".repeat(width),
+        )
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl hash::Hash for Span {
+    fn hash<H: hash::Hasher>(&self, _state: &mut H) {}
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}..{}", self.source.name, self.start, self.end)
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasherDefault, Hasher},
+    sync::{Mutex, OnceLock},
+};
+
+/// interned symbol: a small id standing for a name, cheap to copy, compare and hash
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// process-wide table of interned names, which live as long as the program
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Mutex::default)
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        let mut interner = interner().lock().unwrap();
+        if let Some(&symbol) = interner.ids.get(name) {
+            return symbol;
+        }
+        let name: &'static str = Box::leak(name.into());
+        let symbol = Symbol(interner.names.len() as u32);
+        interner.names.push(name);
+        interner.ids.insert(name, symbol);
+        symbol
+    }
+
+    pub fn name(self) -> &'static str {
+        interner().lock().unwrap().names[self.0 as usize]
+    }
+
+    /// the namespace and name of a qualified symbol
+    /// my.utils/helper -> (my.utils, helper)
+    pub fn qualified(self) -> Option<(Symbol, Symbol)> {
+        let (namespace, name) = self.name().split_once('/')?;
+        if namespace.is_empty() || name.is_empty() {
+            return None;
+        }
+        Some((Symbol::intern(namespace), Symbol::intern(name)))
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.name(), self.0)
+    }
+}
+
+/// symbols are already unique small integers, spreading them is all the hashing needed
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 << 8) | byte as u64;
+        }
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.0 = (n as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
@@ -0,0 +1,67 @@
+// shared ownership and interior mutability, single threaded by default
+// with the sync feature, Rc stands for Arc and Lock for a Mutex
+// so that values, envs and interpreters can be sent across threads
+
+use std::ops::{Deref, DerefMut};
+
+#[cfg(not(feature = "sync"))]
+pub use std::rc::{Rc, Weak};
+#[cfg(feature = "sync")]
+pub use std::sync::{Arc as Rc, Weak};
+
+/// a RefCell, or a Mutex with the sync feature
+#[derive(Default)]
+pub struct Lock<T> {
+    #[cfg(not(feature = "sync"))]
+    inner: std::cell::RefCell<T>,
+    #[cfg(feature = "sync")]
+    inner: std::sync::Mutex<T>,
+}
+
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: value.into(),
+        }
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub fn borrow(&self) -> impl Deref<Target = T> + '_ {
+        self.inner.borrow()
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub fn borrow_mut(&self) -> impl DerefMut<Target = T> + '_ {
+        self.inner.borrow_mut()
+    }
+
+    #[cfg(feature = "sync")]
+    pub fn borrow(&self) -> impl Deref<Target = T> + '_ {
+        self.inner.lock().unwrap()
+    }
+
+    #[cfg(feature = "sync")]
+    pub fn borrow_mut(&self) -> impl DerefMut<Target = T> + '_ {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// Send with the sync feature, implemented by everything otherwise
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "sync"))]
+impl<T> MaybeSend for T {}
+#[cfg(feature = "sync")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "sync")]
+impl<T: Send> MaybeSend for T {}
+
+/// Send + Sync with the sync feature, implemented by everything otherwise
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "sync"))]
+impl<T> MaybeSync for T {}
+#[cfg(feature = "sync")]
+pub trait MaybeSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync> MaybeSync for T {}
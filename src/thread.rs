@@ -0,0 +1,134 @@
+// threads and channels, with the sync feature making values and envs Send:
+// spawned functions run on threads of their own, sharing the global env,
+// and pass values along channels
+
+use std::{
+    io, mem,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    env::{Env, Value},
+    error::FelispError,
+    eval::{Limits, run},
+    hooks::Hooks,
+    ir::{Node, NodeKind},
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::{Lock, Rc},
+};
+
+/// a spawned thread, printed as <Thread>
+pub struct Thread {
+    state: Lock<ThreadState>,
+}
+
+enum ThreadState {
+    Running(JoinHandle<Result<Value, FelispError>>),
+    // what its function returned, kept for every join
+    Joined(Result<Value, FelispError>),
+}
+
+/// an unbounded queue of values between threads, printed as <Channel>
+pub struct Channel {
+    sender: Sender<Value>,
+    receiver: Lock<Receiver<Value>>,
+}
+
+/// binds spawn, join, chan, send! and recv! in env
+/// spawned functions are evaluated within limits, each thread with a budget of its own
+pub fn register(env: &Env, limits: &Limits) {
+    let limits = limits.clone();
+    env.register_fn("spawn", move |f: Value| spawn(f, limits.clone()));
+    env.register_fn("join", join);
+    env.register_fn("chan", || {
+        let (sender, receiver) = mpsc::channel();
+        Value::native(Channel {
+            sender,
+            receiver: Lock::new(receiver),
+        })
+    });
+    env.register_fn("send!", send);
+    env.register_fn("recv!", recv);
+}
+
+/// calls f without arguments on a new thread
+/// (spawn (fn* () (send! c 1))) -> <Thread>
+fn spawn(f: Value, limits: Limits) -> Result<Value, FelispError> {
+    if !matches!(
+        f,
+        Value::Function(_)
+            | Value::AsyncFunction(_)
+            | Value::Lambda(_)
+            | Value::Memoized(_)
+            | Value::Curried(_)
+    ) {
+        return Err(FelispError::bad_arg(0, "Function", &f));
+    }
+    let source = Source::new("<spawn>", "(f)".to_string());
+    let span = Span::new(&source, 0, source.text.len());
+    let head = Node {
+        kind: NodeKind::Const(f),
+        span: Span::new(&source, 1, 2),
+    };
+    let call = Node {
+        kind: NodeKind::Call(Rc::from([head]), Symbol::intern("spawn")),
+        span,
+    };
+    // f is called with the env it closes over, the call itself looks nothing up
+    let env = Rc::new(Env::new(None));
+    let handle = thread::spawn(move || run(call, env, &limits, &Hooks::default()));
+    Ok(Value::native(Thread {
+        state: Lock::new(ThreadState::Running(handle)),
+    }))
+}
+
+/// waits for the thread to return, returning what its function did,
+/// or raising the error it did
+/// (join (spawn (fn* () 1))) -> 1
+fn join(thread: Value) -> Result<Value, FelispError> {
+    let Some(thread) = thread.downcast_native::<Thread>() else {
+        return Err(FelispError::bad_arg(0, "Thread", &thread));
+    };
+    let mut state = thread.state.borrow_mut();
+    if let ThreadState::Running(_) = &*state {
+        let ThreadState::Running(handle) =
+            mem::replace(&mut *state, ThreadState::Joined(Ok(Value::Nil)))
+        else {
+            unreachable!("running")
+        };
+        let result = handle.join().unwrap_or_else(|_| {
+            Err(FelispError::host(io::Error::other(
+                "spawned thread panicked",
+            )))
+        });
+        *state = ThreadState::Joined(result);
+    }
+    match &*state {
+        ThreadState::Joined(result) => result.clone(),
+        ThreadState::Running(_) => unreachable!("joined above"),
+    }
+}
+
+/// queues v on the channel, without waiting for it to be received
+/// (send! c 1) -> 1
+fn send(channel: Value, value: Value) -> Result<Value, FelispError> {
+    let Some(chan) = channel.downcast_native::<Channel>() else {
+        return Err(FelispError::bad_arg(0, "Channel", &channel));
+    };
+    // the channel holds a receiver, so it can't be disconnected
+    let _ = chan.sender.send(value.clone());
+    Ok(value)
+}
+
+/// takes the oldest value queued on the channel, waiting for one if there are none
+/// (recv! c) -> 1
+fn recv(channel: Value) -> Result<Value, FelispError> {
+    let Some(chan) = channel.downcast_native::<Channel>() else {
+        return Err(FelispError::bad_arg(0, "Channel", &channel));
+    };
+    let receiver = chan.receiver.borrow();
+    // nor can it be while receiving, as the channel holds a sender
+    Ok(receiver.recv().unwrap_or(Value::Nil))
+}
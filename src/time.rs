@@ -0,0 +1,125 @@
+// dates and times, in UTC: timestamps are maps of their fields, keyed by keywords,
+// with :epoch-ms the milliseconds since 1970 the others are computed from
+// formats are those of strftime, see chrono::format::strftime
+
+use chrono::{
+    DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike, Utc,
+    format::{Item, StrftimeItems},
+};
+
+use crate::{
+    deterministic,
+    env::{Builtin, Value},
+    error::FelispError,
+    symbol::Symbol,
+};
+
+/// builtins of the time feature, safe to give to any code
+pub const BUILTINS: &[(&str, &str, Builtin)] = &[
+    ("now", "", now),
+    ("format-time", "t fmt", format_time),
+    ("parse-time", "s fmt", parse_time),
+];
+
+/// the current time
+/// (now) -> {:epoch-ms 1760608800000 :year 2025 :month 10 :day 16 :hour 10 ...}
+fn now(args: &[Value]) -> Result<Value, FelispError> {
+    if !args.is_empty() {
+        return Err(FelispError::ArityError(format!(
+            "now requires 0 arguments but given {}",
+            args.len()
+        )));
+    }
+    let time = deterministic::now_ms().map_or_else(Utc::now, |ms| {
+        DateTime::from_timestamp_millis(ms).expect("the logical clock is in range")
+    });
+    Ok(timestamp(time))
+}
+
+/// t, a timestamp or milliseconds since 1970, written after fmt
+/// (format-time (now) "%Y-%m-%d") -> "2025-10-16"
+fn format_time(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 2 {
+        return Err(FelispError::ArityError(format!(
+            "format-time requires 2 arguments but given {}",
+            args.len()
+        )));
+    }
+    let epoch_ms = match &args[0] {
+        Value::Number(ms) => Some(*ms),
+        Value::Map(fields) => match fields.get(&field("epoch-ms")) {
+            Some(Value::Number(ms)) => Some(*ms),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Some(time) = epoch_ms.and_then(DateTime::from_timestamp_millis) else {
+        return Err(FelispError::bad_arg(0, "timestamp", &args[0]));
+    };
+    let items = format_items(args, 1)?;
+    Ok(Value::from(
+        time.format_with_items(items.iter()).to_string(),
+    ))
+}
+
+/// the timestamp s writes after fmt, nil if it doesn't
+/// times without a zone are taken as UTC, dates without a time at midnight
+/// (parse-time "2025-10-16" "%Y-%m-%d") -> {:epoch-ms 1760572800000 ...}
+fn parse_time(args: &[Value]) -> Result<Value, FelispError> {
+    if args.len() != 2 {
+        return Err(FelispError::ArityError(format!(
+            "parse-time requires 2 arguments but given {}",
+            args.len()
+        )));
+    }
+    let Value::String(s) = &args[0] else {
+        return Err(FelispError::bad_arg(0, "String", &args[0]));
+    };
+    format_items(args, 1)?;
+    let Value::String(fmt) = &args[1] else {
+        unreachable!("checked by format_items")
+    };
+    let time = DateTime::parse_from_str(s, fmt)
+        .map(|time| time.to_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(s, fmt).map(|time| time.and_utc()))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, fmt)
+                .map(|date| date.and_time(Default::default()).and_utc())
+        });
+    Ok(time.map_or(Value::Nil, timestamp))
+}
+
+/// the strftime format at args[i], checked to be one
+fn format_items(args: &[Value], i: usize) -> Result<Vec<Item<'_>>, FelispError> {
+    let Value::String(fmt) = &args[i] else {
+        return Err(FelispError::bad_arg(i, "String", &args[i]));
+    };
+    let items: Vec<Item> = StrftimeItems::new(fmt).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(FelispError::bad_arg(i, "strftime format", &args[i]));
+    }
+    Ok(items)
+}
+
+fn timestamp(time: DateTime<Utc>) -> Value {
+    let fields = [
+        ("epoch-ms", time.timestamp_millis()),
+        ("year", i64::from(time.year())),
+        ("month", i64::from(time.month())),
+        ("day", i64::from(time.day())),
+        ("hour", i64::from(time.hour())),
+        ("minute", i64::from(time.minute())),
+        ("second", i64::from(time.second())),
+        ("millisecond", i64::from(time.timestamp_subsec_millis())),
+    ];
+    Value::Map(
+        fields
+            .into_iter()
+            .map(|(name, n)| (field(name), Value::Number(n)))
+            .collect(),
+    )
+}
+
+fn field(name: &str) -> Value {
+    Value::Keyword(Symbol::intern(name))
+}
@@ -0,0 +1,46 @@
+// glue for an in-browser playground, built with
+// wasm-pack build --target web --no-default-features --features wasm
+// timeouts are not available there: the clock can't be read on wasm32-unknown-unknown
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Interpreter, printer};
+
+/// an interpreter whose definitions persist from one eval to the next
+/// with the prelude loaded
+#[wasm_bindgen]
+pub struct Playground {
+    interpreter: Interpreter,
+}
+
+impl Default for Playground {
+    fn default() -> Self {
+        let interpreter = Interpreter::new();
+        interpreter
+            .load_prelude()
+            .expect("the prelude evaluates without errors");
+        Self { interpreter }
+    }
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the printed value of source, or the report of the error it raised
+    pub fn eval_str(&self, source: &str) -> Result<String, String> {
+        self.interpreter
+            .eval_str(source)
+            .map(|value| printer::abbreviate(&value))
+            .map_err(|err| err.report())
+    }
+}
+
+/// evaluates source in a fresh interpreter
+#[wasm_bindgen]
+pub fn eval_str(source: &str) -> Result<String, String> {
+    Playground::new().eval_str(source)
+}
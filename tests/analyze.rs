@@ -0,0 +1,115 @@
+// what the analyzer makes of expressions before they are evaluated: special forms
+// dispatched, literals folded and variables resolved to the slots they are bound to,
+// and what the optimizer then rewrites them into
+
+use felisp::{
+    Interpreter,
+    ast::Expr,
+    env::Value,
+    ir::{Node, NodeKind, analyze},
+    optimize::optimize,
+};
+
+fn analyzed(code: &str) -> Node {
+    analyze(&Expr::parse(code.to_string()).unwrap()).unwrap()
+}
+
+/// the variables node refers to, in order, as name@depth.index for locals
+/// and the bare name for globals
+fn variables(node: &Node) -> Vec<String> {
+    let mut found = match &node.kind {
+        NodeKind::Local { depth, index, name } => vec![format!("{}@{}.{}", name, depth, index)],
+        NodeKind::Global(name) => vec![name.to_string()],
+        _ => vec![],
+    };
+    for child in node.children() {
+        found.extend(variables(child));
+    }
+    found
+}
+
+#[test]
+fn locals_are_resolved_to_slots() {
+    let node = analyzed("(fn* (a b) (let* (c 1 d a) (+ a b c d)))");
+    assert_eq!(
+        variables(&node),
+        ["a@1.0", "+", "a@1.0", "b@1.1", "c@0.0", "d@0.1"]
+    );
+    // the innermost binding of a name shadows the others
+    let node = analyzed("(fn* (x) (let* (x 1) (fn* (y x) x)))");
+    assert_eq!(variables(&node), ["x@0.1"]);
+    // closures may refer to let* bindings made after them, to recurse
+    let node = analyzed("(let* (f (fn* (n) (f n))) f)");
+    assert_eq!(variables(&node), ["f@1.0", "n@0.0", "f@0.0"]);
+}
+
+#[test]
+fn literals_are_folded() {
+    for (code, value) in [
+        ("'(1 2)", "(1 2)"),
+        ("[1 :a \"s\"]", "[1 :a \"s\"]"),
+        ("{:a 1}", "{:a 1}"),
+    ] {
+        let NodeKind::Const(folded) = analyzed(code).kind else {
+            panic!("{} was not folded", code);
+        };
+        assert_eq!(folded.to_string(), value);
+    }
+    // but not those evaluating something
+    assert!(!matches!(analyzed("[1 x]").kind, NodeKind::Const(_)));
+    assert!(matches!(analyzed("nil").kind, NodeKind::Const(Value::Nil)));
+}
+
+#[test]
+fn malformed_forms_fail_before_anything_runs() {
+    let expr = Expr::parse("(do (println 1) (let* x 1))".to_string()).unwrap();
+    let err = analyze(&expr).err().unwrap();
+    assert_eq!(err.kind(), "syntax error");
+}
+
+/// node analyzed from code, then optimized against the builtins of a fresh env
+fn optimized(code: &str) -> Node {
+    let interpreter = Interpreter::new();
+    optimize(analyzed(code), interpreter.env())
+}
+
+fn folded(code: &str) -> Option<String> {
+    match optimized(code).kind {
+        NodeKind::Const(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[test]
+fn constants_are_folded() {
+    assert_eq!(folded("(+ 1 (* 2 3))").as_deref(), Some("7"));
+    assert_eq!(folded("(if (<= 1 2) :yes (f))").as_deref(), Some(":yes"));
+    assert_eq!(
+        folded("(case (count [1 2]) 2 :two :other)").as_deref(),
+        Some(":two")
+    );
+    assert_eq!(folded("(do 1 2 (str \"a\" 3))").as_deref(), Some("\"a3\""));
+    // calls which fail are left for the evaluator to report
+    assert_eq!(folded("(+ 1 nil)"), None);
+    // as are those of functions that aren't pure builtins, or on variables
+    assert_eq!(folded("(println 1)"), None);
+    assert_eq!(folded("(f 1)"), None);
+    assert_eq!(folded("(+ x 1)"), None);
+    // and those in the bodies of functions, which may be called once + is rebound
+    let node = optimized("(fn* () (+ 1 2))");
+    let NodeKind::Fn(def) = &node.kind else {
+        panic!("expected a function");
+    };
+    assert!(matches!(def.arities[0].body.kind, NodeKind::Call(..)));
+}
+
+#[test]
+fn only_builtins_bound_to_their_own_name_are_folded() {
+    let interpreter = Interpreter::new();
+    interpreter.eval_str("(def! + -)").unwrap();
+    let node = optimize(analyzed("(+ 1 2)"), interpreter.env());
+    assert!(matches!(node.kind, NodeKind::Call(..)));
+    interpreter.eval_str("(def! plus *)").unwrap();
+    let node = optimize(analyzed("(plus 2 3)"), interpreter.env());
+    assert!(matches!(node.kind, NodeKind::Call(..)));
+}
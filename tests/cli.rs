@@ -0,0 +1,704 @@
+// runs the felisp command on scripts written to a scratch directory, checking the
+// exit status and what it prints to stdout and stderr
+
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use regex::Regex;
+
+/// a directory of its own for each test, emptied when it starts
+fn scratch(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("felisp-cli-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// felisp run in dir, which is its home too, so that it keeps its files there
+fn command(dir: &PathBuf) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_felisp"));
+    command
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env_remove("XDG_STATE_HOME")
+        .env_remove("XDG_CONFIG_HOME");
+    command
+}
+
+/// what felisp run in dir with args exits with and prints
+fn felisp(dir: &PathBuf, args: &[&str]) -> Output {
+    command(dir)
+        .args(["--no-history", "--no-init", "--no-color"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// what the REPL run by command prints for the lines typed on stdin
+fn type_lines(mut command: Command, lines: &[&str]) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for line in lines {
+        writeln!(stdin, "{}", line).unwrap();
+    }
+    drop(stdin);
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn scripts_run_every_form_and_stop_at_the_first_error() {
+    let dir = scratch("scripts");
+    fs::write(
+        dir.join("ok.lisp"),
+        "(def! x 2)\n(println \"x is\" x)\n(* x 3)\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("bad.lisp"),
+        "(println \"before\")\n(car 5)\n(println \"after\")\n",
+    )
+    .unwrap();
+    let output = felisp(&dir, &["ok.lisp"]);
+    assert!(output.status.success());
+    // the value of the last form isn't printed
+    assert_eq!(stdout(&output), "x is 2\n");
+    let output = felisp(&dir, &["bad.lisp"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "before\n");
+    assert!(
+        stderr(&output)
+            .starts_with("type error: car: expected Pair but got '5' (arg 1)\n --> bad.lisp:2:6\n")
+    );
+    let output = felisp(&dir, &["missing.lisp"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).starts_with("can't read missing.lisp"));
+}
+
+#[test]
+fn eval_prints_the_value_of_the_last_expression() {
+    let dir = scratch("eval");
+    let output = felisp(&dir, &["-e", "(def! x 2) (println \"x is\" x) (* x 3)"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "x is 2\n6\n");
+    let output = felisp(&dir, &["--eval", "(str \"a\" 1)"]);
+    assert_eq!(stdout(&output), "\"a1\"\n");
+    let output = felisp(&dir, &["-e", "(car 5)"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains(" --> <eval>:1:6\n"));
+    let output = felisp(&dir, &["-e"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("-e requires an expression\nusage: felisp"));
+}
+
+#[test]
+fn history_is_kept_across_sessions() {
+    let dir = scratch("history");
+    let mut repl = command(&dir);
+    repl.args(["--no-init", "--no-color"]);
+    type_lines(repl, &["(+ 1 2)", "(def! y 5)"]);
+    let history = dir.join(".felisp_history");
+    assert_eq!(
+        fs::read_to_string(&history).unwrap(),
+        "#V2\n(+ 1 2)\n(def! y 5)\n"
+    );
+    let mut repl = command(&dir);
+    repl.args(["--no-init", "--no-color", "--no-history"]);
+    type_lines(repl, &["(* 2 3)"]);
+    assert_eq!(
+        fs::read_to_string(&history).unwrap(),
+        "#V2\n(+ 1 2)\n(def! y 5)\n"
+    );
+    let mut repl = command(&dir);
+    repl.args(["--no-init", "--no-color"])
+        .env("XDG_STATE_HOME", dir.join("state"));
+    type_lines(repl, &["(* 2 3)"]);
+    let history = dir.join("state").join("felisp").join("history");
+    assert_eq!(fs::read_to_string(&history).unwrap(), "#V2\n(* 2 3)\n");
+}
+
+#[test]
+fn the_repl_reads_on_while_brackets_and_strings_are_open() {
+    let dir = scratch("continuation");
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(repl, &["(+ 1", "  2)", "\"a", "b\"", "(+ 1 2))"]);
+    assert_eq!(stdout(&output), "3\n\"a\\nb\"\nCTRL-D\n");
+    // closers typed too many aren't held back, but reported
+    assert!(stderr(&output).starts_with("parse error: unexpected trailing input\n"));
+}
+
+#[test]
+fn meta_commands_are_run_by_the_repl() {
+    let dir = scratch("commands");
+    fs::write(dir.join("lib.lisp"), "(def! from-lib 7)\n(* from-lib 2)\n").unwrap();
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(
+        repl,
+        &[
+            ":help",
+            "(def! zz 1)",
+            ":env",
+            ":type [1]",
+            ":load lib.lisp",
+            "from-lib",
+            ":reset",
+            "zz",
+            ":bogus",
+            ":quit",
+            "(println \"not evaluated\")",
+        ],
+    );
+    let stdout = stdout(&output);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with(":help"));
+    let help = lines
+        .iter()
+        .take_while(|line| line.starts_with(':'))
+        .count();
+    let rest = &lines[help..];
+    assert_eq!(rest[0], "1");
+    assert!(rest.contains(&"zz 1") && rest.contains(&"car <fun>"));
+    let env = rest
+        .iter()
+        .skip(1)
+        .take_while(|line| line.contains(' '))
+        .count();
+    assert_eq!(
+        rest[1 + env..],
+        [":vector", "14", "7", "unknown command :bogus, see :help"]
+    );
+    assert!(stderr(&output).starts_with("unknown symbol 'zz'"));
+}
+
+#[test]
+fn the_repl_binds_the_last_results_and_error() {
+    let dir = scratch("results");
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(
+        repl,
+        &[
+            "*1",
+            "1",
+            "2",
+            "3",
+            "(vector *1 *2 *3)",
+            "(car 5)",
+            "*1",
+            "*e",
+            ":type 4",
+            "*2",
+        ],
+    );
+    // errors and commands leave the results alone
+    assert_eq!(
+        stdout(&output).lines().collect::<Vec<_>>(),
+        [
+            "nil",
+            "1",
+            "2",
+            "3",
+            "[3 2 1]",
+            "[3 2 1]",
+            "\"type error: car: expected Pair but got '5' (arg 1)\"",
+            ":int",
+            "[3 2 1]",
+            "CTRL-D",
+        ]
+    );
+}
+
+#[test]
+fn pretty_printing_is_set_by_a_command() {
+    let dir = scratch("pretty");
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(
+        repl,
+        &[
+            "(def! v [[1 2 3] 4 5 {\"a\" 1}])",
+            ":pretty width 12",
+            "v",
+            ":pretty depth 1",
+            "v",
+            ":pretty items 2",
+            ":pretty",
+            "v",
+            ":pretty off",
+            ":pretty width x",
+            ":pretty items none",
+            ":pretty depth none",
+            "v",
+            ":pretty on",
+            "v",
+        ],
+    );
+    assert_eq!(
+        stdout(&output).lines().collect::<Vec<_>>(),
+        [
+            "[[1 2 3] 4 5 {\"a\" 1}]",
+            "[[1 2 3] 4 5",
+            " {\"a\" 1}]",
+            "[... 4 5 ...]",
+            "pretty on, width 12, depth 1, items 2",
+            "[... 4 ...]",
+            "expected a number, got 'x', see :help",
+            "[[1 2 3] 4 5 {\"a\" 1}]",
+            "[[1 2 3] 4 5",
+            " {\"a\" 1}]",
+            "CTRL-D",
+        ]
+    );
+}
+
+#[test]
+fn timing_is_printed_after_each_evaluation() {
+    let timing = Regex::new(r"^; [0-9.]+(ns|µs|ms|s), 3 steps$").unwrap();
+    let dir = scratch("timing");
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(
+        repl,
+        &[
+            "(+ 1 2)",
+            ":time on",
+            "(* 2 3)",
+            ":time off",
+            "4",
+            ":time maybe",
+        ],
+    );
+    let printed = stdout(&output);
+    let lines: Vec<&str> = printed.lines().collect();
+    assert_eq!(lines[..2], ["3", "6"]);
+    assert!(timing.is_match(lines[2]), "{}", lines[2]);
+    assert_eq!(lines[3..], ["4", "expected on or off, see :help", "CTRL-D"]);
+    let output = felisp(&dir, &["--time", "-e", "(+ 1 2)"]);
+    assert_eq!(stdout(&output), "3\n");
+    assert!(timing.is_match(stderr(&output).trim_end()));
+}
+
+#[test]
+fn the_init_file_and_config_are_read_at_startup() {
+    let dir = scratch("init");
+    let config = dir.join(".config").join("felisp");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(config.join("init.lisp"), "(def! greeting \"hi\")\n").unwrap();
+    fs::write(
+        config.join("config"),
+        "# settings\nhistory-size = 1\ncolor = off\nbogus = 1\n",
+    )
+    .unwrap();
+    let mut repl = command(&dir);
+    repl.arg("--no-color");
+    let output = type_lines(repl, &["greeting", "(+ 1 2)"]);
+    assert_eq!(stdout(&output), "\"hi\"\n3\nCTRL-D\n");
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "{}:4: unknown setting bogus\n",
+            config.join("config").display()
+        )
+    );
+    // only the last line is kept
+    let history = fs::read_to_string(dir.join(".felisp_history")).unwrap();
+    assert_eq!(history, "#V2\n(+ 1 2)\n");
+    let mut repl = command(&dir);
+    repl.args(["--no-color", "--no-history", "--no-init"]);
+    let output = type_lines(repl, &["greeting"]);
+    assert!(stderr(&output).contains("unknown symbol 'greeting'"));
+    // nor is it read by scripts
+    let output = command(&dir).args(["-e", "greeting"]).output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn the_prelude_can_be_left_out() {
+    let dir = scratch("prelude");
+    let output = felisp(&dir, &["-e", "(inc 1)"]);
+    assert_eq!(stdout(&output), "2\n");
+    let output = felisp(&dir, &["--no-prelude", "-e", "(inc 1)"]);
+    assert!(stderr(&output).starts_with("unknown symbol 'inc'"));
+    let output = felisp(&dir, &["--no-prelude", "-e", "(+ 1 2)"]);
+    assert_eq!(stdout(&output), "3\n");
+}
+
+#[test]
+fn tracing_prints_each_form_and_call() {
+    let dir = scratch("trace");
+    let output = felisp(
+        &dir,
+        &["--trace", "-e", "(do (def! f (fn* (x) (+ x 1))) (f 2))"],
+    );
+    assert_eq!(stdout(&output), "3\n");
+    assert_eq!(
+        stderr(&output),
+        "\
+(do (def! f (fn* (x) (+ x 1))) (f 2))  ; scope 0
+  (def! f (fn* (x) (+ x 1)))  ; scope 0
+    (fn* (x) (+ x 1))  ; scope 0
+    => <fun>
+  => <fun>
+  (f 2)  ; scope 0
+    f  ; scope 0
+    => <fun>
+    2  ; scope 0
+    => 2
+    (+ x 1)  ; scope 1
+      +  ; scope 1
+      => <fun>
+      x  ; scope 1
+      => 2
+      1  ; scope 1
+      => 1
+    => 3
+  => 3
+=> 3
+"
+    );
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(
+        repl,
+        &[
+            "(def! f (fn* (x) (* x 2)))",
+            "(trace f)",
+            "(+ 1 (f 3))",
+            "(untrace f)",
+            "(f 3)",
+        ],
+    );
+    assert_eq!(stdout(&output), "<fun>\nnil\n7\nnil\n6\nCTRL-D\n");
+    assert_eq!(stderr(&output), "(f 3)\n");
+}
+
+#[test]
+fn errors_are_colored_only_on_a_terminal() {
+    let dir = scratch("color");
+    // the kind of the error in bold, the whole report in red
+    let colored = "\x1b[1;31mtype error\x1b[22m: car: expected Pair but got '5' (arg 1)";
+    let output = command(&dir)
+        .args(["--no-history", "--no-init", "-e", "(car 5)"])
+        .output()
+        .unwrap();
+    assert!(stderr(&output).starts_with("type error: car"));
+    // script, of util-linux, runs felisp with a terminal for stderr, which it copies to stdout
+    if cfg!(target_os = "linux") {
+        let on_terminal = |args: &str, no_color: &str| {
+            let felisp = env!("CARGO_BIN_EXE_felisp");
+            let command = format!("'{}' --no-history --no-init {} -e '(car 5)'", felisp, args);
+            let output = Command::new("script")
+                .args(["-qec", &command, "/dev/null"])
+                .current_dir(&dir)
+                .env("HOME", &dir)
+                .env("NO_COLOR", no_color)
+                .output()
+                .unwrap();
+            stdout(&output)
+        };
+        assert!(on_terminal("", "").starts_with(colored));
+        assert!(on_terminal("", "1").starts_with("type error: car"));
+        assert!(on_terminal("--no-color", "").starts_with("type error: car"));
+        // or by the config
+        let config = dir.join(".config").join("felisp");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("config"), "color = off\n").unwrap();
+        assert!(on_terminal("", "").starts_with("type error: car"));
+        fs::write(config.join("config"), "color = on\n").unwrap();
+        assert!(on_terminal("", "").starts_with(colored));
+    }
+}
+
+#[test]
+fn scripts_can_start_with_a_shebang() {
+    let dir = scratch("shebang");
+    let script = dir.join("hello");
+    let text = format!(
+        "#!{}\n(println \"hello\")\n(car 5)\n",
+        env!("CARGO_BIN_EXE_felisp")
+    );
+    fs::write(&script, text).unwrap();
+    let output = felisp(&dir, &["hello"]);
+    assert_eq!(stdout(&output), "hello\n");
+    // lines are counted from the #! line
+    assert!(stderr(&output).contains(" --> hello:3:6\n"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        // run as a program, which the kernel hands to felisp
+        let output = Command::new(&script).current_dir(&dir).output().unwrap();
+        assert_eq!(stdout(&output), "hello\n");
+    }
+    // only files have one, not code given otherwise
+    let output = felisp(&dir, &["-e", "#!felisp\n1"]);
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_interrupts_the_evaluation_rather_than_the_repl() {
+    let dir = scratch("interrupt");
+    let mut child = command(&dir)
+        .args(["--no-history", "--no-init", "--no-color"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "(def! spin (fn* (n) (spin (+ n 1))))\n(spin 0)").unwrap();
+    // what is typed while it runs is read once it is interrupted
+    writeln!(stdin, "(+ 1 2)").unwrap();
+    drop(stdin);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(stdout(&output), "<fun>\n3\nCTRL-D\n");
+    assert!(stderr(&output).starts_with("interrupted\n"));
+}
+
+#[test]
+fn test_runs_the_deftests_of_each_file() {
+    let dir = scratch("test");
+    fs::write(
+        dir.join("a.lisp"),
+        "\
+(def! double (fn* (x) (* 2 x)))
+(deftest doubles (is (<= (double 2) 4)) (is (<= 4 (double 2))))
+(deftest fails (is (<= (double 3) 5)))
+(deftest errs (car 5))
+",
+    )
+    .unwrap();
+    fs::write(dir.join("b.lisp"), "(deftest ok (is true))\n").unwrap();
+    fs::write(dir.join("c.lisp"), "(+ 1\n").unwrap();
+    fs::write(dir.join("d.lisp"), "(car 5)\n(deftest never (is true))\n").unwrap();
+    let output = felisp(&dir, &["test", "a.lisp", "b.lisp", "c.lisp", "d.lisp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "FAIL a.lisp fails\nFAIL a.lisp errs\nFAIL c.lisp\nFAIL d.lisp\n2 passed, 4 failed\n"
+    );
+    // with the failing form of each
+    let stderr = stderr(&output);
+    assert!(stderr.starts_with("assertion failed: (<= (double 3) 5)\n --> a.lisp:3:20\n"));
+    assert!(
+        stderr.contains("type error: car: expected Pair but got '5' (arg 1)\n --> a.lisp:4:20\n")
+    );
+    assert!(stderr.contains("parse error: expected ')'\n --> c.lisp:2:1\n"));
+    let output = felisp(&dir, &["test", "b.lisp"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "1 passed, 0 failed\n");
+}
+
+#[test]
+fn fmt_writes_literals_back_as_they_were() {
+    let dir = scratch("fmt");
+    let formatted = r##"#!/usr/bin/env felisp
+(def! path r"C:\Users\me\notes.txt")
+(def! pattern r#"^"(\w+)"$"#)
+
+(def! greet
+  (fn* (name)
+    (println #"hi {name}, \{braces} and {(count name)} letters" "a\\b")))
+(def! names '(ada grace "linus" 'turing))
+"##;
+    fs::write(dir.join("formatted.lisp"), formatted).unwrap();
+    let output = felisp(&dir, &["fmt", "--check", "formatted.lisp"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    let output = felisp(&dir, &["fmt", "formatted.lisp"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("formatted.lisp")).unwrap(),
+        formatted
+    );
+    // the same forms, laid out otherwise
+    fs::write(
+        dir.join("messy.lisp"),
+        r##"#!/usr/bin/env felisp
+(def!   path r"C:\Users\me\notes.txt")
+(def! pattern    r#"^"(\w+)"$"#)
+
+(def! greet (fn* (name) (println #"hi {name}, \{braces} and {(count name)} letters" "a\\b")))
+(def! names '(ada   grace "linus" 'turing))
+"##,
+    )
+    .unwrap();
+    let output = felisp(&dir, &["fmt", "--check", "messy.lisp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "messy.lisp is not formatted\n");
+    let output = felisp(&dir, &["fmt", "messy.lisp"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("messy.lisp")).unwrap(),
+        formatted
+    );
+}
+
+#[test]
+fn fmt_keeps_comments_before_the_form_after_them() {
+    let dir = scratch("fmt-comments");
+    fs::write(
+        dir.join("a.lisp"),
+        "\
+;; a header
+;; of two lines
+
+;; x
+(def! x 1) ; one
+(def! add (fn* (a b) ; a and b
+  ;; summed
+  (+ a b ; no more
+  )))
+[1 2 ;; three
+ 3]
+
+;; at the end
+
+",
+    )
+    .unwrap();
+    let formatted = "\
+;; a header
+;; of two lines
+
+;; x
+(def! x 1)
+; one
+(def! add
+  (fn* (a b)
+    ; a and b
+    ;; summed
+    (+ a
+       b
+       ; no more
+    )))
+[1 2
+ ;; three
+ 3]
+
+;; at the end
+";
+    let output = felisp(&dir, &["fmt", "a.lisp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(dir.join("a.lisp")).unwrap(), formatted);
+    let output = felisp(&dir, &["fmt", "--check", "a.lisp"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    // only comments
+    fs::write(dir.join("b.lisp"), "; nothing yet\n").unwrap();
+    let output = felisp(&dir, &["fmt", "--check", "b.lisp"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+}
+
+#[test]
+fn inspect_reads_commands_until_q() {
+    let dir = scratch("inspect");
+    let mut repl = command(&dir);
+    repl.args(["--no-history", "--no-init", "--no-color"]);
+    let output = type_lines(
+        repl,
+        &["(inspect [1 [2 3]])", "2", "x", "*", "-", "q", "(+ 1 2)"],
+    );
+    let stdout = stdout(&output);
+    let collapsed = "0 - :vector (2) [1 [2 3]]\n1     0 :int 1\n2   + 1 :vector (2) [2 3]\n";
+    let expanded = "\
+0 - :vector (2) [1 [2 3]]
+1     0 :int 1
+2   - 1 :vector (2) [2 3]
+3       0 :int 2
+4       1 :int 3
+";
+    // the value inspected is returned
+    let rest = "[1 [2 3]]\n3\nCTRL-D\n";
+    assert_eq!(
+        stdout,
+        [
+            collapsed,
+            expanded,
+            "expected a line number, see ?\n",
+            expanded,
+            collapsed,
+            rest
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn deterministic_runs_are_reproducible() {
+    let dir = scratch("deterministic");
+    let map = "{:e 5 :c 1 :a 2 :b 3 :d 4 \"x\" 1 2 3}";
+    // maps are printed in the order of their keys
+    let output = felisp(&dir, &["--deterministic", "-e", map]);
+    assert_eq!(stdout(&output), "{2 3 \"x\" 1 :a 2 :b 3 :c 1 :d 4 :e 5}\n");
+    let output = felisp(&dir, &["--seed", "x", "-e", "1"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("--seed requires a number, got x\n"));
+}
+
+#[cfg(all(feature = "time", feature = "digest"))]
+#[test]
+fn deterministic_runs_take_time_and_randomness_from_the_seed() {
+    let dir = scratch("seeded");
+    let code = "(println (uuid) (get (now) :epoch-ms) (get (now) :epoch-ms))";
+    let run = |args: &[&str]| stdout(&felisp(&dir, &[args, &["-e", code]].concat()));
+    let seeded = run(&["--seed", "7"]);
+    assert_eq!(seeded, run(&["--seed", "7"]));
+    // a millisecond per call of now, from 1970
+    assert!(seeded.ends_with(" 0 1\nnil\n"));
+    assert_ne!(seeded, run(&["--seed", "8"]));
+    assert_ne!(run(&[]), run(&[]));
+}
+
+#[test]
+fn failing_scripts_exit_with_the_class_of_their_error() {
+    let dir = scratch("status");
+    fs::write(dir.join("ok.lisp"), "(+ 1 2)\n").unwrap();
+    fs::write(dir.join("unparsed.lisp"), "(+ 1\n").unwrap();
+    fs::write(dir.join("failing.lisp"), "(car 5)\n").unwrap();
+    fs::write(dir.join("thrown.lisp"), "(throw 1)\n").unwrap();
+    let status = |args: &[&str]| {
+        let output = felisp(&dir, args);
+        // errors go to stderr, leaving stdout to what the code prints
+        assert!(output.status.success() || output.stdout.is_empty());
+        output.status.code()
+    };
+    assert_eq!(status(&["ok.lisp"]), Some(0));
+    assert_eq!(status(&["unparsed.lisp"]), Some(65));
+    assert_eq!(status(&["failing.lisp"]), Some(70));
+    assert_eq!(status(&["thrown.lisp"]), Some(1));
+    assert_eq!(status(&["-e", "(+ 1"]), Some(65));
+    assert_eq!(status(&["-e", "(car 5)"]), Some(70));
+    assert_eq!(status(&["-e", "(throw :oops)"]), Some(1));
+    // caught, it is no error
+    assert_eq!(
+        status(&["-e", "(with-handler (:thrown (fn* (e) 0)) (throw 1))"]),
+        Some(0)
+    );
+    let output = felisp(&dir, &["thrown.lisp"]);
+    assert!(stderr(&output).starts_with("uncaught exception: 1\n --> thrown.lisp:1:1\n"));
+}
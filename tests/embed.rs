@@ -0,0 +1,980 @@
+// embeds felisp as a host program does, through Interpreter and Env,
+// checking what the embedding API promises
+
+use std::{
+    pin::{Pin, pin},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use felisp::{
+    Interpreter,
+    ast::Expr,
+    env::{AsyncCallback, Env, Value},
+    error::FelispError,
+    eval::Limits,
+    hooks::{FrameEvent, Trace},
+    image,
+    printer::{self, PrettyPrinter},
+    span::Source,
+    symbol::Symbol,
+    sync::Rc,
+};
+
+// calls itself forever, without growing the stack
+const LOOP: &str = "(do (def! f (fn* () (f))) (f))";
+
+#[test]
+fn limits_apply_to_any_interpreter() {
+    let limits = Limits {
+        max_steps: Some(1000),
+        ..Limits::default()
+    };
+    for interpreter in [
+        Interpreter::new().limits(limits.clone()),
+        Interpreter::sandboxed().limits(limits.clone()),
+    ] {
+        assert_eq!(interpreter.current_limits().max_steps, Some(1000));
+        let err = interpreter.eval_str(LOOP).unwrap_err();
+        assert_eq!(err.kind(), "max steps exceeded");
+    }
+    let interpreter = Interpreter::sandboxed().limits(Limits {
+        timeout: Some(Duration::from_millis(20)),
+        ..Limits::default()
+    });
+    assert_eq!(interpreter.eval_str(LOOP).unwrap_err().kind(), "timed out");
+}
+
+#[test]
+fn limits_replace_those_given_before() {
+    let interpreter = Interpreter::with_limits(100, 1000, Duration::from_secs(1)).limits(Limits {
+        max_steps: Some(10),
+        ..Limits::default()
+    });
+    assert_eq!(interpreter.current_limits().timeout, None);
+    let err = interpreter
+        .eval_str("(+ 1 (+ 2 (+ 3 (+ 4 5))))")
+        .unwrap_err();
+    assert_eq!(err.kind(), "max steps exceeded");
+}
+
+#[test]
+fn sandboxes_refuse_io_to_the_code() {
+    let interpreter = Interpreter::sandboxed();
+    assert_eq!(interpreter.eval_str("(+ 1 2)").unwrap(), Value::from(3));
+    for code in ["(open-output \"x\")", "(tcp-connect \"localhost\" 80)"] {
+        let err = interpreter.eval_str(code).unwrap_err();
+        assert_eq!(err.kind(), "unknown symbol", "{}", code);
+    }
+    let err = interpreter.env().set_io("read", Value::Nil).unwrap_err();
+    assert_eq!(err.kind(), "sandboxed");
+}
+
+#[test]
+fn sandboxes_trust_the_host() {
+    let interpreter = Interpreter::sandboxed();
+    interpreter.env().register_fn("double", |n: i64| n * 2);
+    interpreter.env().set("answer", Value::from(42));
+    assert_eq!(
+        interpreter.eval_str("(double answer)").unwrap(),
+        Value::from(84)
+    );
+    assert!(Interpreter::new().env().set_io("read", Value::Nil).is_ok());
+}
+
+#[test]
+fn inlined_arguments_spend_steps() {
+    // a step for the call, one for each argument of +, evaluated inline, and one returning
+    let sum = |max_steps| {
+        Interpreter::new()
+            .limits(Limits {
+                max_steps: Some(max_steps),
+                ..Limits::default()
+            })
+            .eval_str("(+ 1 2 3 4)")
+    };
+    assert_eq!(sum(5).unwrap_err().kind(), "max steps exceeded");
+    assert_eq!(sum(6).unwrap(), Value::from(10));
+}
+
+#[test]
+fn errors_are_structured() {
+    let interpreter = Interpreter::new();
+    let err = interpreter.eval_str("(+ 1 nil)").unwrap_err();
+    let FelispError::TypeError {
+        expected,
+        got,
+        arg,
+        function,
+    } = err.cause()
+    else {
+        panic!("expected a type error but got {:?}", err);
+    };
+    assert_eq!(
+        (expected.as_str(), got.as_str(), *arg),
+        ("Number", "nil", Some(1))
+    );
+    assert_eq!(function.map(|function| function.name()), Some("+"));
+    assert_eq!(
+        (err.kind(), err.keyword()),
+        ("type error", Some("type-error"))
+    );
+    let err = interpreter.eval_str("nope").unwrap_err();
+    assert!(matches!(err.cause(), FelispError::UnknownSymbol(name) if name == "nope"));
+    let err = interpreter.eval_str("((fn* (x) x))").unwrap_err();
+    assert!(matches!(err.cause(), FelispError::ArityError(_)));
+    let err = interpreter.eval_str("(throw [1 2])").unwrap_err();
+    assert_eq!(err.thrown(), Some(&interpreter.eval_str("[1 2]").unwrap()));
+    assert_eq!(err.to_string(), "uncaught exception: [1 2]");
+}
+
+#[test]
+fn reports_show_the_line_raising_the_error() {
+    let code = format!("(do{}\n  (car 1))", "\n  1".repeat(9));
+    let source = Source::new("script.lisp", code);
+    let err = Interpreter::new().eval_source(&source).unwrap_err();
+    let span = err.span().unwrap();
+    assert_eq!(span.source.line_col(span.start), (11, 8));
+    assert_eq!(
+        err.report(),
+        [
+            "type error: car: expected Pair but got '1' (arg 1)",
+            "  --> script.lisp:11:8",
+            "   |",
+            "11 |   (car 1))",
+            "   |        ^",
+            "backtrace:",
+            "  at car (script.lisp:11:4)",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn backtraces_name_the_calls_unwound_through() {
+    let code =
+        "(do (def! inner (fn* (x) (car x))) (def! outer (fn* (x) (+ 1 (inner x)))) (outer 1))";
+    let err = Interpreter::new().eval_str(code).unwrap_err();
+    let names: Vec<&str> = err
+        .backtrace()
+        .iter()
+        .map(|frame| frame.name.name())
+        .collect();
+    assert_eq!(names, ["car", "inner", "outer"]);
+}
+
+#[test]
+fn deep_recursion_runs_on_a_small_stack() {
+    // the evaluator keeps its own stack of continuations rather than recursing,
+    // so calls nest as deep as max_depth allows whatever the stack of the thread
+    let sum = std::thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(|| {
+            let interpreter = Interpreter::new().limits(Limits {
+                max_depth: 1_000_000,
+                ..Limits::default()
+            });
+            let code = "(do (def! sum-to (fn* (n) (if (<= n 0) 0 (+ n (sum-to (- n 1)))))) (sum-to 100000))";
+            // values stay on the thread that made them
+            match interpreter.eval_str(code) {
+                Ok(value) => Ok(value.to_string()),
+                Err(err) => Err(err.to_string()),
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(sum.as_deref(), Ok("5000050000"));
+}
+
+#[test]
+fn depth_limits_nesting_rather_than_steps() {
+    let interpreter = Interpreter::new().limits(Limits {
+        max_depth: 50,
+        ..Limits::default()
+    });
+    let sum_to = "(def! sum-to (fn* (n) (if (<= n 0) 0 (+ n (sum-to (- n 1))))))";
+    interpreter.eval_str(sum_to).unwrap();
+    assert_eq!(
+        interpreter.eval_str("(sum-to 10)").unwrap(),
+        Value::from(55)
+    );
+    let err = interpreter.eval_str("(sum-to 100)").unwrap_err();
+    assert_eq!(
+        (err.kind(), err.keyword()),
+        ("max depth exceeded", Some("depth-exceeded"))
+    );
+    // which handlers catch
+    let handled = "(with-handler (:error (fn* (e) (get e :kind))) (sum-to 100))";
+    assert_eq!(
+        interpreter.eval_str(handled).unwrap(),
+        Value::Keyword(Symbol::intern("depth-exceeded"))
+    );
+    // tail calls don't nest
+    let count_down = "(do (def! down (fn* (n) (if (<= n 0) :done (down (- n 1))))) (down 1000))";
+    assert!(interpreter.eval_str(count_down).is_ok());
+    // unlike running out of steps
+    let interpreter = interpreter.limits(Limits {
+        max_steps: Some(1000),
+        ..Limits::default()
+    });
+    let err = interpreter
+        .eval_str("(with-handler (:error (fn* (e) :caught)) (sum-to 10000))")
+        .unwrap_err();
+    assert_eq!(err.kind(), "max steps exceeded");
+}
+
+#[test]
+fn steps_are_counted_per_evaluation() {
+    let interpreter = Interpreter::new().limits(Limits {
+        max_steps: Some(100),
+        ..Limits::default()
+    });
+    for _ in 0..100 {
+        assert_eq!(
+            interpreter.eval_str("(+ 1 (* 2 3))").unwrap(),
+            Value::from(7)
+        );
+    }
+}
+
+#[test]
+fn deadlines_are_given_per_evaluation() {
+    let interpreter = Interpreter::new();
+    let expr = Expr::parse(LOOP.to_string()).unwrap();
+    let err = interpreter
+        .eval_with_deadline(&expr, Duration::from_millis(20))
+        .unwrap_err();
+    assert_eq!((err.kind(), err.keyword()), ("timed out", None));
+    // the shorter of the deadline and the interpreter's own timeout applies
+    let interpreter = interpreter.limits(Limits {
+        timeout: Some(Duration::from_millis(20)),
+        ..Limits::default()
+    });
+    let err = interpreter
+        .eval_with_deadline(&expr, Duration::from_secs(3600))
+        .unwrap_err();
+    assert_eq!(err.kind(), "timed out");
+}
+
+#[test]
+fn interrupts_abort_the_evaluation_and_are_cleared() {
+    let flag = Arc::new(AtomicBool::new(false));
+    let interpreter = Interpreter::new().interruptible(Arc::clone(&flag));
+    let setter = {
+        let flag = Arc::clone(&flag);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            flag.store(true, Ordering::Relaxed);
+        })
+    };
+    let err = interpreter.eval_str(LOOP).unwrap_err();
+    setter.join().unwrap();
+    assert_eq!((err.kind(), err.keyword()), ("interrupted", None));
+    assert!(!flag.load(Ordering::Relaxed));
+    assert_eq!(interpreter.eval_str("(+ 1 2)").unwrap(), Value::from(3));
+}
+
+#[test]
+fn symbols_are_interned() {
+    let symbol = Symbol::intern("interned-name");
+    assert_eq!(symbol, Symbol::from("interned-name"));
+    assert_ne!(symbol, Symbol::intern("interned-names"));
+    assert_eq!(symbol.name(), "interned-name");
+    let qualified = Symbol::intern("my.utils/helper").qualified();
+    assert_eq!(
+        qualified,
+        Some((Symbol::intern("my.utils"), Symbol::intern("helper")))
+    );
+    // envs are keyed by symbol, whether bound by the code or the host
+    let interpreter = Interpreter::new();
+    interpreter.eval_str("(def! interned-name 1)").unwrap();
+    assert_eq!(interpreter.env().get(symbol).unwrap(), Value::from(1));
+    assert!(interpreter.env().symbols().contains(&symbol));
+    interpreter.env().set(symbol, Value::from(2));
+    assert_eq!(
+        interpreter.eval_str("interned-name").unwrap(),
+        Value::from(2)
+    );
+    // and keywords are symbols too
+    let keyword = interpreter.eval_str(":interned-name").unwrap();
+    assert_eq!(keyword, Value::Keyword(symbol));
+}
+
+#[test]
+fn collections_share_structure() {
+    let interpreter = Interpreter::new();
+    let code = "(do (def! v [1 2 3]) (def! m {:a v}) [v (conj v 4) m (assoc m :b 2)])";
+    let values = interpreter.eval_str(code).unwrap();
+    let Value::Vector(values) = &values else {
+        panic!("expected a vector");
+    };
+    let (Value::Vector(v), Value::Vector(w)) = (&values[0], &values[1]) else {
+        panic!("expected vectors");
+    };
+    let (Value::Map(m), Value::Map(n)) = (&values[2], &values[3]) else {
+        panic!("expected maps");
+    };
+    assert_eq!((v.len(), w.len(), m.len(), n.len()), (3, 4, 1, 2));
+    // the vector bound to v is the one in m, not a copy of it
+    let Value::Vector(in_m) = &m[&Value::Keyword(Symbol::intern("a"))] else {
+        panic!("expected a vector");
+    };
+    assert!(v.ptr_eq(in_m));
+    let bound = interpreter.eval_str("v").unwrap();
+    let Value::Vector(bound) = &bound else {
+        panic!("expected a vector");
+    };
+    assert!(bound.ptr_eq(v));
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn interpreters_move_between_threads() {
+    let interpreter = Interpreter::new();
+    interpreter.eval_str("(def! x 20)").unwrap();
+    let interpreter = std::thread::spawn(move || {
+        assert_eq!(interpreter.eval_str("(+ x 1)").unwrap(), Value::from(21));
+        interpreter
+    })
+    .join()
+    .unwrap();
+    // and so do the values they make
+    let value = interpreter.eval_str("[x {:a x}]").unwrap();
+    let printed = std::thread::spawn(move || value.to_string())
+        .join()
+        .unwrap();
+    assert_eq!(printed, "[20 {:a 20}]");
+}
+
+/// what future returns, polled until it is ready, as an executor would
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// a future pending the first time it is polled, to suspend what awaits it
+struct Later(bool);
+
+impl Future for Later {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[test]
+fn async_host_functions_are_awaited() {
+    let interpreter = Interpreter::new();
+    let echo: AsyncCallback = Rc::new(|args: Vec<Value>| {
+        Box::pin(async move {
+            Later(false).await;
+            Ok(args[0].clone())
+        })
+    });
+    interpreter.env().set("echo", Value::AsyncFunction(echo));
+    let failing: AsyncCallback = Rc::new(|args: Vec<Value>| {
+        Box::pin(async move { Err(FelispError::Thrown(args[0].clone())) })
+    });
+    interpreter.env().set("fail", Value::AsyncFunction(failing));
+    let eval =
+        |code: &str| block_on(interpreter.eval_async(&Expr::parse(code.to_string()).unwrap()));
+    assert_eq!(eval("(+ 1 (echo 2))").unwrap(), Value::from(3));
+    // from the bodies of functions, however deep
+    let code = "(do (def! f (fn* (n) (if (<= n 0) (echo :done) (f (- n 1))))) [(f 100) (echo 1)])";
+    assert_eq!(eval(code).unwrap().to_string(), "[:done 1]");
+    // the errors they return are raised where they were called
+    let err = eval("(fail :no)").unwrap_err();
+    assert_eq!(err.thrown(), Some(&interpreter.eval_str(":no").unwrap()));
+    let caught = eval("(with-handler (:thrown (fn* (e) (get e :value))) (fail :no))");
+    assert_eq!(caught.unwrap().to_string(), ":no");
+    // and only eval_async can call them
+    let err = interpreter.eval_str("(echo 1)").unwrap_err();
+    assert_eq!(err.kind(), "async call");
+}
+
+#[test]
+#[cfg(feature = "wasm")]
+fn playgrounds_keep_their_definitions() {
+    use felisp::wasm::{self, Playground};
+
+    let playground = Playground::new();
+    assert_eq!(playground.eval_str("(def! x 20)").as_deref(), Ok("20"));
+    assert_eq!(
+        playground.eval_str("((juxt inc dec) x)").as_deref(),
+        Ok("[21 19]")
+    );
+    let report = playground.eval_str("(car x)").unwrap_err();
+    assert!(
+        report.starts_with("type error: car: expected Pair but got '20' (arg 1)\n"),
+        "{}",
+        report
+    );
+    // eval_str starts afresh each time
+    assert_eq!(wasm::eval_str("(+ 1 2)").as_deref(), Ok("3"));
+    assert!(
+        wasm::eval_str("x")
+            .unwrap_err()
+            .starts_with("unknown symbol 'x'")
+    );
+}
+
+#[test]
+fn envs_are_built_from_what_the_host_gives() {
+    let mut calls = 0;
+    let env = Env::builder()
+        .defaults()
+        .builtin("double", |args: &[Value]| match args {
+            [Value::Number(n)] => Ok(Value::Number(n * 2)),
+            _ => Err(FelispError::ArityError(
+                "double requires 1 number".to_string(),
+            )),
+        })
+        .register_fn("shout", |s: String| s.to_uppercase())
+        .builtin_mut("next-id", move |_: &[Value]| {
+            calls += 1;
+            Ok(Value::from(calls))
+        })
+        .value("answer", Value::from(42))
+        .build();
+    let interpreter = Interpreter::with_env(env);
+    assert_eq!(
+        interpreter.eval_str("(double answer)").unwrap(),
+        Value::from(84)
+    );
+    assert_eq!(
+        interpreter.eval_str("(shout \"hi\")").unwrap(),
+        Value::from("HI")
+    );
+    assert_eq!(
+        interpreter
+            .eval_str("[(next-id) (next-id)]")
+            .unwrap()
+            .to_string(),
+        "[1 2]"
+    );
+    // without defaults there are only the bindings given, and the special forms
+    let bare = Interpreter::with_env(Env::builder().value("x", Value::from(1)).build());
+    assert_eq!(bare.eval_str("(if x (do x) nil)").unwrap(), Value::from(1));
+    assert_eq!(
+        bare.eval_str("(+ x 1)").unwrap_err().kind(),
+        "unknown symbol"
+    );
+}
+
+#[test]
+fn built_envs_look_up_what_they_lack_in_their_parent() {
+    let parent = Rc::new(Env::builder().defaults().value("x", Value::from(1)).build());
+    let child = Env::builder()
+        .parent(Rc::clone(&parent))
+        .value("y", Value::from(2))
+        .build();
+    let interpreter = Interpreter::with_env(child);
+    assert_eq!(interpreter.eval_str("(+ x y)").unwrap(), Value::from(3));
+    interpreter.eval_str("(def! z 3)").unwrap();
+    assert!(parent.get_own("z").is_none());
+    assert!(parent.get("y").is_err());
+    assert!(interpreter.env().symbols().contains(&Symbol::intern("x")));
+    // and the namespaces made are the child's own
+    interpreter.eval_str("(ns built.child)").unwrap();
+    interpreter.eval_str("(def! w 4)").unwrap();
+    assert_eq!(interpreter.eval_str("(+ x y w)").unwrap(), Value::from(7));
+    assert_eq!(
+        Interpreter::with_env(Env::builder().parent(parent).build())
+            .eval_str("x")
+            .unwrap(),
+        Value::from(1)
+    );
+    let sandboxed = Rc::new(Env::sandboxed());
+    assert!(Env::builder().parent(sandboxed).build().is_sandboxed());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn values_go_to_and_from_json() {
+    use serde_json::json;
+
+    let interpreter = Interpreter::new();
+    let code = "{:name \"felisp\" :tags [1 2.5 nil true] :list '(1 :a) :half 1/2 \
+                :big 123456789012345678901234567890}";
+    let value = interpreter.eval_str(code).unwrap();
+    let json = serde_json::to_value(&value).unwrap();
+    assert_eq!(
+        json,
+        json!({
+            "name": "felisp",
+            "tags": [1, 2.5, null, true],
+            "list": [1, "a"],
+            "half": "1/2",
+            "big": "123456789012345678901234567890",
+        })
+    );
+    // read back with keyword keys as strings and lists as vectors
+    let read: Value =
+        serde_json::from_value(json!({"n": [1, u64::MAX, -2, "s", {"x": null}]})).unwrap();
+    let expected = interpreter
+        .eval_str("{\"n\" [1 18446744073709551615 -2 \"s\" {\"x\" nil}]}")
+        .unwrap();
+    assert_eq!(read, expected);
+    // code can't be serialized
+    for code in ["+", "(fn* (x) x)", "'(a b)", "(cons 1 2)"] {
+        let value = interpreter.eval_str(code).unwrap();
+        assert!(serde_json::to_string(&value).is_err(), "{}", code);
+    }
+}
+
+#[test]
+fn rust_values_convert_to_and_from_values() {
+    let interpreter = Interpreter::new();
+    let eval = |code: &str| interpreter.eval_str(code).unwrap();
+    assert_eq!(Value::from(()), Value::Nil);
+    assert_eq!(Value::from(true), eval("true"));
+    assert_eq!(Value::from(-3), eval("-3"));
+    assert_eq!(Value::from(0.5), eval("0.5"));
+    assert_eq!(Value::from("s"), eval("\"s\""));
+    assert_eq!(Value::from(String::from("s")), eval("\"s\""));
+    assert_eq!(Value::from(None::<i64>), Value::Nil);
+    assert_eq!(Value::from(Some(1)), eval("1"));
+    assert_eq!(Value::from(vec![1, 2]), eval("[1 2]"));
+    let map: Value = [("a", 1), ("b", 2)].into_iter().collect();
+    assert_eq!(map, eval("{\"a\" 1 \"b\" 2}"));
+
+    // and back, failing with type errors
+    assert!(bool::try_from(eval("0")).unwrap());
+    assert!(!bool::try_from(eval("nil")).unwrap());
+    assert_eq!(i64::try_from(eval("42")).unwrap(), 42);
+    assert_eq!(f64::try_from(eval("1/2")).unwrap(), 0.5);
+    assert_eq!(f64::try_from(eval("2")).unwrap(), 2.0);
+    assert_eq!(String::try_from(eval("\"s\"")).unwrap(), "s");
+    assert_eq!(Vec::<i64>::try_from(eval("[1 2]")).unwrap(), [1, 2]);
+    assert_eq!(
+        Vec::<i64>::try_from(eval("nil")).unwrap(),
+        Vec::<i64>::new()
+    );
+    let err = i64::try_from(eval("\"1\"")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type error: expected Number but got '\"1\"'"
+    );
+    let err = Vec::<String>::try_from(eval("[\"a\" 1]")).unwrap_err();
+    assert_eq!(err.to_string(), "type error: expected String but got '1'");
+}
+
+#[test]
+fn rust_functions_are_registered_with_their_signature() {
+    let interpreter = Interpreter::new();
+    let env = interpreter.env();
+    env.register_fn("add", |a: i64, b: i64| a + b);
+    env.register_fn("mean", |xs: Vec<f64>| {
+        (!xs.is_empty()).then(|| xs.iter().sum::<f64>() / xs.len() as f64)
+    });
+    env.register_fn("parse", |s: String| {
+        s.parse::<i64>().map_err(FelispError::host)
+    });
+    env.register_fn("answer", || 42);
+    let eval = |code: &str| interpreter.eval_str(code);
+    assert_eq!(eval("(add 1 2)").unwrap(), Value::from(3));
+    assert_eq!(eval("(mean [1 2])").unwrap(), Value::from(1.5));
+    assert_eq!(eval("(mean [])").unwrap(), Value::Nil);
+    assert_eq!(eval("(parse \"12\")").unwrap(), Value::from(12));
+    assert_eq!(eval("(answer)").unwrap(), Value::from(42));
+    // the arguments are counted and converted, blaming the one that isn't right
+    let err = eval("(add 1)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "arity error: add requires 2 arguments but given 1"
+    );
+    let err = eval("(add 1 \"2\")").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type error: add: expected Number but got '\"2\"' (arg 2)"
+    );
+    let err = eval("(parse \"x\")").unwrap_err();
+    assert_eq!(
+        (err.kind(), err.to_string().as_str()),
+        ("host error", "invalid digit found in string")
+    );
+    // and the parameters are named after their types
+    let signature = |name: &str| match &env.get(name).unwrap() {
+        Value::Function(f) => f.signature(),
+        value => panic!("expected a function but got {}", value),
+    };
+    assert_eq!(signature("add"), "(add i64 i64)");
+    assert_eq!(signature("mean"), "(mean Vec<f64>)");
+}
+
+#[test]
+fn stateful_callbacks_keep_and_release_their_state() {
+    let interpreter = Interpreter::new();
+    let mut next = 0;
+    interpreter.env().register_mut("next-id", move |_| {
+        next += 1;
+        Ok(Value::from(next))
+    });
+    // a resource the host shares with the callback owning it
+    let log = Arc::new(Mutex::new(vec![]));
+    let mut lines = 0;
+    interpreter.env().register_mut("log!", {
+        let log = Arc::clone(&log);
+        move |args| {
+            lines += 1;
+            log.lock().unwrap().push(format!("{} {}", lines, args[0]));
+            Ok(Value::Nil)
+        }
+    });
+    let code = "(do (log! (next-id)) (log! :b) (log! (next-id)) [(next-id) (next-id)])";
+    assert_eq!(interpreter.eval_str(code).unwrap().to_string(), "[3 4]");
+    assert_eq!(*log.lock().unwrap(), ["1 1", "2 :b", "3 2"]);
+    // the state lives as long as the env binding it
+    assert_eq!(Arc::strong_count(&log), 2);
+    drop(interpreter);
+    assert_eq!(Arc::strong_count(&log), 1);
+}
+
+/// a host value the code passes around without seeing into
+struct Connection {
+    url: String,
+}
+
+#[test]
+fn native_values_are_opaque_to_the_code() {
+    let interpreter = Interpreter::new();
+    interpreter
+        .env()
+        .register_fn("connect", |url: String| Value::native(Connection { url }));
+    interpreter.env().register_fn("url", |conn: Value| {
+        match conn.downcast_native::<Connection>() {
+            Some(conn) => Ok(conn.url.clone()),
+            None => Err(FelispError::bad_arg(0, "Connection", &conn)),
+        }
+    });
+    let eval = |code: &str| interpreter.eval_str(code);
+    eval("(def! conn (connect \"db://local\"))").unwrap();
+    assert_eq!(eval("(url conn)").unwrap(), Value::from("db://local"));
+    assert_eq!(
+        eval("[conn (type-of conn)]").unwrap().to_string(),
+        "[<Connection> :native]"
+    );
+    // they are equal to themselves only, and can be keys
+    assert_eq!(eval("conn").unwrap(), eval("conn").unwrap());
+    assert_ne!(
+        eval("conn").unwrap(),
+        eval("(connect \"db://local\")").unwrap()
+    );
+    assert_eq!(eval("(get {conn 1} conn)").unwrap(), Value::from(1));
+    let err = eval("(url 1)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type error: url: expected Connection but got '1' (arg 1)"
+    );
+    assert!(eval("conn").unwrap().downcast_native::<String>().is_none());
+}
+
+/// a log hooks append to, shared with the test reading it
+#[derive(Clone, Default)]
+struct Log(Arc<Mutex<Vec<String>>>);
+
+impl Log {
+    fn push(&self, line: String) {
+        self.0.lock().unwrap().push(line);
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+#[test]
+fn hooks_are_told_of_calls_frames_and_errors() {
+    let (calls, frames, errors) = (Log::default(), Log::default(), Log::default());
+    let interpreter = Interpreter::new()
+        .on_call({
+            let calls = calls.clone();
+            move |_, args| {
+                let args: Vec<String> = args.iter().map(Value::to_string).collect();
+                calls.push(args.join(" "));
+            }
+        })
+        .on_frame({
+            let frames = frames.clone();
+            move |event| match event {
+                FrameEvent::Enter { frame, .. } => frames.push(format!("enter {}", frame.name)),
+                FrameEvent::Exit { frame } => frames.push(format!("exit {}", frame.name)),
+            }
+        })
+        .on_error({
+            let errors = errors.clone();
+            move |err| errors.push(err.kind().to_string())
+        });
+    interpreter
+        .eval_str("(do (def! f (fn* (x) (* 2 (+ x 1)))) (f 2))")
+        .unwrap();
+    assert_eq!(calls.take(), ["2", "2 1", "2 3"]);
+    assert_eq!(
+        frames.take(),
+        [
+            "enter f", "enter +", "exit +", "enter *", "exit *", "exit f"
+        ]
+    );
+    assert!(errors.take().is_empty());
+    interpreter.eval_str("(f nil)").unwrap_err();
+    assert_eq!(frames.take(), ["enter f", "enter +"]);
+    assert_eq!(errors.take(), ["type error"]);
+    assert_eq!(interpreter.stats().calls, 5);
+}
+
+#[test]
+fn hooks_are_told_of_each_node() {
+    let (traces, steps, evals) = (Log::default(), Log::default(), Log::default());
+    let interpreter = Interpreter::new()
+        .on_trace({
+            let traces = traces.clone();
+            move |trace| match trace {
+                Trace::Eval { node, depth, .. } => {
+                    traces.push(format!("{}> {}", depth, node.span.text()))
+                }
+                Trace::Return { value, depth, .. } => traces.push(format!("{}< {}", depth, value)),
+            }
+        })
+        .on_step({
+            let steps = steps.clone();
+            move |step| {
+                let locals = step.scope.map_or(vec![], |scope| scope.bindings());
+                let locals: Vec<String> = locals
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                steps.push(format!("{} [{}]", step.node.span.text(), locals.join(" ")));
+            }
+        })
+        .on_eval({
+            let evals = evals.clone();
+            move |node, _| evals.push(node.span.text().to_string())
+        });
+    interpreter.eval_str("(let* (a 1) (+ a 2))").unwrap();
+    assert_eq!(
+        traces.take(),
+        [
+            "0> (let* (a 1) (+ a 2))",
+            "1> 1",
+            "1< 1",
+            "1> (+ a 2)",
+            "2> +",
+            "2< <fun>",
+            "2> a",
+            "2< 1",
+            "2> 2",
+            "2< 2",
+            "1< 3",
+            "0< 3"
+        ]
+    );
+    assert_eq!(
+        steps.take(),
+        [
+            "(let* (a 1) (+ a 2)) []",
+            "1 []",
+            "(+ a 2) [a=1]",
+            "+ [a=1]",
+            "a [a=1]",
+            "2 [a=1]"
+        ]
+    );
+    assert_eq!(
+        evals.take(),
+        ["(let* (a 1) (+ a 2))", "1", "(+ a 2)", "+", "a", "2"]
+    );
+}
+
+#[test]
+fn equal_values_hash_alike() {
+    use std::hash::{BuildHasher, RandomState};
+    let interpreter = Interpreter::new();
+    let hasher = RandomState::new();
+    let hash = |code| hasher.hash_one(interpreter.eval_str(code).unwrap());
+    assert_eq!(hash("{:a 1 :b [2 3]}"), hash("{:b [2 3] :a 1}"));
+    assert_eq!(hash("(quote (1 2))"), hash("'(1 2)"));
+    assert_ne!(hash("[1 2]"), hash("'(1 2)"));
+    let mut values = vec![Value::from("a"), Value::Number(2), Value::Nil, Value::True];
+    values.sort();
+    assert_eq!(
+        values,
+        [Value::Nil, Value::True, Value::Number(2), Value::from("a")]
+    );
+}
+
+#[test]
+fn values_are_pretty_printed_within_limits() {
+    let interpreter = Interpreter::new();
+    let value = interpreter.eval_str("[[1 2 3] 4 5 {\"a\" [6 7]}]").unwrap();
+    let printer = PrettyPrinter {
+        width: 12,
+        ..PrettyPrinter::default()
+    };
+    assert_eq!(printer.print(&value), "[[1 2 3] 4 5\n {\"a\" [6 7]}]");
+    let printer = PrettyPrinter {
+        max_depth: Some(1),
+        max_items: Some(3),
+        ..printer
+    };
+    assert_eq!(printer.print(&value), "[... 4 5 ...]");
+    // as errors print values, on one line
+    assert_eq!(printer::abbreviate(&value), "[[1 2 3] 4 5 {\"a\" [6 7]}]");
+}
+
+#[test]
+fn the_prelude_is_loaded_on_request() {
+    let interpreter = Interpreter::new();
+    let err = interpreter.eval_str("(inc 1)").unwrap_err();
+    assert!(matches!(err.cause(), FelispError::UnknownSymbol(name) if name == "inc"));
+    interpreter.load_prelude().unwrap();
+    assert_eq!(interpreter.eval_str("(inc 1)").unwrap(), Value::Number(2));
+    // its functions report errors where they are called from
+    let err = interpreter.eval_str("(inc nil)").unwrap_err();
+    assert!(err.report().contains("at inc (<repl>:1:2)"));
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn sockets_talk_to_the_host() {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+    // answers what it reads in capitals, then closes the connection
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = server.local_addr().unwrap().port();
+    let answering = std::thread::spawn(move || {
+        let (mut stream, _) = server.accept().unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        stream.write_all(&buf.to_ascii_uppercase()).unwrap();
+    });
+    let interpreter = Interpreter::new();
+    let code = format!(
+        "(do (def! s (tcp-connect \"127.0.0.1\" {})) (socket-write s \"ping\"))",
+        port
+    );
+    assert_eq!(interpreter.eval_str(&code).unwrap(), Value::Number(4));
+    answering.join().unwrap();
+    assert_eq!(
+        interpreter.eval_str("(socket-read s 16)").unwrap(),
+        Value::from("PING")
+    );
+    assert_eq!(
+        interpreter.eval_str("(socket-read s 16)").unwrap(),
+        Value::Nil
+    );
+    interpreter.eval_str("(close! s)").unwrap();
+    let err = interpreter.eval_str("(socket-read s 16)").unwrap_err();
+    assert_eq!(err.kind(), "type error");
+    // and the other way round, the host connecting to a listener
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let listen = format!("(def! l (tcp-listen {}))", port);
+    interpreter.eval_str(&listen).unwrap();
+    let connecting = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(b"hi").unwrap();
+    });
+    let read = interpreter
+        .eval_str("(socket-read (tcp-accept l) 16)")
+        .unwrap();
+    connecting.join().unwrap();
+    assert_eq!(read, Value::from("hi"));
+    let err = Interpreter::sandboxed()
+        .eval_str("(tcp-listen 0)")
+        .unwrap_err();
+    assert!(matches!(err.cause(), FelispError::UnknownSymbol(name) if name == "tcp-listen"));
+}
+
+#[test]
+fn snapshots_tell_what_code_bound() {
+    let interpreter = Interpreter::new();
+    interpreter.eval_str("(def! kept 1)").unwrap();
+    let before = interpreter.env().snapshot();
+    interpreter
+        .eval_str("(do (def! b 2) (def! a 3) (def! kept 1))")
+        .unwrap();
+    // rebinding to an equal value is no change
+    assert_eq!(
+        interpreter.env().diff(&before),
+        [
+            (Symbol::intern("a"), Value::Number(3)),
+            (Symbol::intern("b"), Value::Number(2))
+        ]
+    );
+    interpreter.eval_str("(def! kept 4)").unwrap();
+    let diff = interpreter.env().diff(&before);
+    assert_eq!(diff.len(), 3);
+    assert_eq!(diff[2], (Symbol::intern("kept"), Value::Number(4)));
+}
+
+#[test]
+fn scoped_evaluations_leave_the_env_alone() {
+    let interpreter = Interpreter::new();
+    interpreter.eval_str("(def! base 10)").unwrap();
+    let defined = interpreter.scoped(|scoped| {
+        scoped.eval_str("(def! user-fn (fn* (x) (+ base x)))")?;
+        scoped.eval_str("(def! result (user-fn 5))")?;
+        Ok::<_, FelispError>(scoped.current_env().diff(&Default::default()))
+    });
+    let names: Vec<String> = defined
+        .unwrap()
+        .iter()
+        .map(|(symbol, value)| format!("{} {}", symbol, value))
+        .collect();
+    assert_eq!(names, ["result 15", "user-fn <fun>"]);
+    let err = interpreter.eval_str("result").unwrap_err();
+    assert!(matches!(err.cause(), FelispError::UnknownSymbol(name) if name == "result"));
+    assert_eq!(interpreter.eval_str("base").unwrap(), Value::Number(10));
+}
+
+#[test]
+fn images_save_long_lists() {
+    let path = std::env::temp_dir().join(format!("felisp-image-{}.flp", std::process::id()));
+    let interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            "(do (def! build (fn* (n out) (if (<= n 0) out (build (- n 1) (cons n out)))))
+                 (def! long (build 200000 ()))
+                 (def! dotted (cons 1 (cons 2 3)))
+                 (def! nested [{:a (build 3 nil)}]))",
+        )
+        .unwrap();
+    assert_eq!(image::save(interpreter.env(), &path).unwrap(), 4);
+    let image = std::fs::read_to_string(&path).unwrap();
+    assert!(image.contains("(def! dotted (list* 1 2 3))\n"));
+    assert!(image.contains("(def! nested [{:a (list* 1 2 3 nil)}])\n"));
+    let loaded = Interpreter::new();
+    loaded.eval_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    for name in ["long", "dotted", "nested"] {
+        assert_eq!(
+            loaded.eval_str(name).unwrap(),
+            interpreter.eval_str(name).unwrap()
+        );
+    }
+    assert_eq!(
+        loaded.eval_str("(count long)").unwrap(),
+        Value::Number(200000)
+    );
+}
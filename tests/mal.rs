@@ -0,0 +1,218 @@
+// runs the Make-A-Lisp step tests of tests/mal against the REPL, as mal's
+// runtest.py does: each form is typed on stdin, and what the REPL prints for it
+// is checked against the lines that follow it in the file:
+// ;=>value for the printed value, ;/regex for each line printed before it
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    thread,
+};
+
+use regex::Regex;
+
+// evaluated after every form, so that the output can be split per form
+const MARKER: &str = "\"--end of form--\"";
+
+/// a form and what the REPL should print for it
+struct Case {
+    line: usize,
+    form: String,
+    // ;/ lines, matching the first lines printed
+    printed: Vec<String>,
+    // ;=> line, the last line printed
+    value: Option<String>,
+}
+
+fn parse(text: &str) -> Vec<Case> {
+    let mut cases: Vec<Case> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(value) = trimmed.strip_prefix(";=>") {
+            if let Some(case) = cases.last_mut() {
+                case.value = Some(value.to_string());
+            }
+        } else if let Some(regex) = trimmed.strip_prefix(";/") {
+            if let Some(case) = cases.last_mut() {
+                case.printed.push(regex.to_string());
+            }
+        } else if !trimmed.is_empty() && !trimmed.starts_with(";;") {
+            cases.push(Case {
+                line: i + 1,
+                form: line.to_string(),
+                printed: vec![],
+                value: None,
+            });
+        }
+    }
+    cases
+}
+
+/// what the REPL started with options prints for each form, stdout and stderr interleaved
+fn run(cases: &[Case], options: &[&str]) -> io::Result<Vec<Vec<String>>> {
+    let (mut reader, writer) = io::pipe()?;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_felisp"))
+        .args(["--no-history", "--no-init", "--no-color"])
+        .args(options)
+        .stdin(Stdio::piped())
+        .stdout(writer.try_clone()?)
+        .stderr(writer)
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input: String = cases
+        .iter()
+        .map(|case| format!("{}\n{}\n", case.form, MARKER))
+        .collect();
+    // written from another thread so that the REPL never blocks on a full pipe
+    let feeder = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let mut output = String::new();
+    reader.read_to_string(&mut output)?;
+    feeder.join().unwrap()?;
+    child.wait()?;
+    Ok(output
+        .split(MARKER)
+        .map(|printed| {
+            printed
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .collect())
+}
+
+/// runs the cases of tests/mal/name.mal, failing with all those that don't pass
+fn check(name: &str) {
+    check_with(name, &[]);
+}
+
+/// check, with the REPL started with options
+fn check_with(name: &str, options: &[&str]) {
+    let path = format!("{}/tests/mal/{}.mal", env!("CARGO_MANIFEST_DIR"), name);
+    let cases = parse(&fs::read_to_string(&path).unwrap());
+    let outputs = run(&cases, options).unwrap();
+    let mut failures = vec![];
+    for (case, printed) in cases.iter().zip(&outputs) {
+        let mut ok = case.printed.len() <= printed.len();
+        for (regex, line) in case.printed.iter().zip(printed) {
+            ok &= Regex::new(&format!("^(?:{})$", regex))
+                .unwrap()
+                .is_match(line);
+        }
+        if let Some(value) = &case.value {
+            ok &= printed.last() == Some(value);
+        }
+        if !ok {
+            failures.push(format!(
+                "{}:{}: {}\n  expected {:?} {:?}\n  got {:?}",
+                name, case.line, case.form, case.printed, case.value, printed
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn step1_read_print() {
+    check("step1_read_print");
+}
+
+#[test]
+fn step2_eval() {
+    check("step2_eval");
+}
+
+#[test]
+fn step3_env() {
+    check("step3_env");
+}
+
+#[test]
+fn step4_if_fn_do() {
+    check("step4_if_fn_do");
+}
+
+#[test]
+fn step7_quote() {
+    check("step7_quote");
+}
+
+#[test]
+fn step9_try() {
+    check("step9_try");
+}
+
+#[test]
+fn errors() {
+    check("errors");
+}
+
+#[test]
+#[cfg(feature = "ffi")]
+fn ffi() {
+    check("ffi");
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn threads() {
+    check("threads");
+}
+
+#[test]
+#[cfg(feature = "time")]
+fn time() {
+    check("time");
+}
+
+#[test]
+#[cfg(feature = "digest")]
+fn digest() {
+    check("digest");
+}
+
+#[test]
+#[cfg(feature = "encoding")]
+fn encoding() {
+    check("encoding");
+}
+
+#[test]
+#[cfg(feature = "toml")]
+fn toml() {
+    check("toml");
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn yaml() {
+    check("yaml");
+}
+
+#[test]
+#[cfg(feature = "csv")]
+fn csv() {
+    check("csv");
+}
+
+#[test]
+fn optimize() {
+    check("optimize");
+}
+
+#[test]
+fn optimized() {
+    check_with("optimize", &["--optimize"]);
+}
+
+#[test]
+fn sandbox() {
+    check_with("sandbox", &["--sandbox"]);
+}
+
+#[test]
+fn auto_curry() {
+    check_with("auto_curry", &["--auto-curry"]);
+}
@@ -0,0 +1,291 @@
+// properties of the reader: what it reads prints back to source it reads the same,
+// and it fails with an error, rather than a panic, on anything else
+
+use std::{env, fs};
+
+use felisp::{
+    ast::{Expr, MAX_NESTING, Special},
+    cache::read_forms,
+    numeric,
+    printer::PrettyPrinter,
+    span::{Source, Span},
+    symbol::Symbol,
+    sync::Rc,
+};
+use proptest::prelude::*;
+
+fn span() -> Span {
+    Span::new(&Source::new("<generated>", String::new()), 0, 0)
+}
+
+/// symbols the reader doesn't take for a literal or a special form
+fn symbol() -> impl Strategy<Value = Symbol> {
+    "[a-zA-Z*+!?<>=/_-][a-zA-Z0-9*+!?<>=/_.'-]{0,12}"
+        .prop_filter("reads as a literal", |name| {
+            !matches!(name.as_str(), "nil" | "true" | "false")
+                && name.parse::<i64>().is_err()
+                && !numeric::is_literal(name)
+                && Special::from_name(name).is_none()
+        })
+        .prop_map(|name| Symbol::intern(&name))
+}
+
+fn expr() -> impl Strategy<Value = Expr> {
+    let leaf = prop_oneof![
+        Just(Expr::Nil(span())),
+        any::<bool>().prop_map(|b| Expr::Bool(b, span())),
+        any::<i64>().prop_map(|n| Expr::Number(n, span())),
+        "-?[0-9]{19,30}|-?[0-9]{1,9}/[1-9][0-9]{0,5}|-?[0-9]{1,9}\\.[0-9]{1,9}(e-?[0-9]{1,2})?"
+            .prop_filter("reads as an int", |n| numeric::is_literal(n))
+            .prop_map(|n| Expr::Numeric(n.into(), span())),
+        any::<String>().prop_map(|s| Expr::String(s.into(), span())),
+        proptest::sample::select(Special::ALL.to_vec()).prop_map(|s| Expr::Special(s, span())),
+        symbol().prop_map(|s| Expr::Atom(s, span())),
+        symbol().prop_map(|s| Expr::Keyword(s, span())),
+    ];
+    leaf.prop_recursive(8, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8)
+                .prop_map(|exprs| Expr::List(exprs.into(), span())),
+            prop::collection::vec(inner.clone(), 0..8)
+                .prop_map(|exprs| Expr::Vector(exprs.into(), span())),
+            prop::collection::vec((inner.clone(), inner), 0..4).prop_map(|entries| {
+                let exprs: Vec<Expr> = entries.into_iter().flat_map(|(k, v)| [k, v]).collect();
+                Expr::Map(exprs.into(), span())
+            }),
+        ]
+    })
+}
+
+/// source made of bits of felisp syntax and arbitrary characters
+fn source() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        prop_oneof![
+            Just("(".to_string()),
+            Just(")".to_string()),
+            Just("[".to_string()),
+            Just("]".to_string()),
+            Just("{".to_string()),
+            Just("}".to_string()),
+            Just("\"".to_string()),
+            Just("\\".to_string()),
+            Just(";".to_string()),
+            Just("#".to_string()),
+            Just(" ".to_string()),
+            Just("\n".to_string()),
+            "[a-z0-9*+-]{1,4}",
+            any::<char>().prop_map(String::from),
+        ],
+        0..32,
+    )
+    .prop_map(|parts| parts.concat())
+}
+
+proptest! {
+    #[test]
+    fn printed_exprs_read_back(expr in expr()) {
+        let printed = expr.to_string();
+        prop_assert_eq!(Expr::parse(printed.clone()).ok(), Some(expr), "reading {}", printed);
+    }
+
+    #[test]
+    fn formatted_exprs_read_back(expr in expr(), width in 0..100usize) {
+        let printer = PrettyPrinter { width, ..PrettyPrinter::default() };
+        let formatted = printer.print_expr(&expr);
+        prop_assert_eq!(Expr::parse(formatted.clone()).ok(), Some(expr), "reading {}", formatted);
+    }
+
+    #[test]
+    fn cached_forms_are_the_ones_read(exprs in prop::collection::vec(expr(), 0..8)) {
+        let dir = env::temp_dir().join(format!("felisp-cache-test-{}", std::process::id()));
+        let file = dir.join("forms.lisp");
+        fs::create_dir_all(&dir).unwrap();
+        let text: Vec<String> = exprs.iter().map(Expr::to_string).collect();
+        fs::write(&file, text.join("\n")).unwrap();
+        let read = read_forms(&file, None).unwrap();
+        let spans = |exprs: &[Expr]| -> Vec<(usize, usize)> {
+            exprs.iter().map(|expr| (expr.span().start, expr.span().end)).collect()
+        };
+        // the first read fills the cache, the second reads from it
+        for _ in 0..2 {
+            let cached = read_forms(&file, Some(&dir.join("cache"))).unwrap();
+            prop_assert_eq!(&cached, &read);
+            prop_assert_eq!(spans(&cached), spans(&read));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn printing_what_was_read_is_stable(source in source()) {
+        if let Ok(expr) = Expr::parse(source) {
+            let printed = expr.to_string();
+            let reread = Expr::parse(printed.clone()).ok();
+            prop_assert_eq!(reread.as_ref().map(Expr::to_string), Some(printed));
+            prop_assert_eq!(reread, Some(expr));
+        }
+    }
+
+    #[test]
+    fn raw_strings_read_as_written(s in any::<String>(), hashes in 0..3usize) {
+        let closing = format!("\"{}", "#".repeat(hashes));
+        prop_assume!(!s.contains(&closing));
+        let raw = format!("r{}\"{}{}", "#".repeat(hashes), s, closing);
+        prop_assert_eq!(Expr::parse(raw).ok(), Some(Expr::String(s.into(), span())));
+    }
+
+    #[test]
+    fn arbitrary_source_does_not_panic(text in any::<String>()) {
+        if let Err(err) = Expr::parse_all(&Source::new("<generated>", text)) {
+            err.report();
+        }
+    }
+
+    #[test]
+    fn control_characters_do_not_panic(text in "[\\x00-\\x1f\\x7f()\\[\\]{}\"\\\\;a ]{0,64}") {
+        if let Err(err) = Expr::parse_all(&Source::new("<generated>", text)) {
+            err.report();
+        }
+    }
+}
+
+#[test]
+fn deep_nesting_is_an_error() {
+    for (open, close) in [("(", ")"), ("[", "]"), ("{", "}")] {
+        let text = format!("{}{}", open.repeat(100_000), close.repeat(100_000));
+        let err = Expr::parse(text).unwrap_err();
+        assert!(err.to_string().contains("nested deeper than"), "{}", err);
+        err.report();
+    }
+    let text = format!("{}{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING));
+    let expr = Expr::parse(text.clone()).unwrap();
+    assert_eq!(expr.to_string(), text);
+}
+
+#[test]
+fn huge_atoms_read() {
+    let name = "a".repeat(1 << 20);
+    let expr = Expr::parse(name.clone()).unwrap();
+    assert_eq!(expr, Expr::Atom(Symbol::intern(&name), span()));
+    let string = format!("\"{}\"", "\\n".repeat(1 << 20));
+    assert_eq!(Expr::parse(string.clone()).unwrap().to_string(), string);
+    let number = "9".repeat(1 << 10);
+    assert!(Expr::parse(number).is_ok());
+}
+
+#[test]
+fn errors_point_at_whole_characters() {
+    for text in ["λ", "(λ)", "\"\\λ\"", "{1 λ}", "(1 2 ☃"] {
+        let err = Expr::parse(text.to_string()).unwrap_err();
+        let span = err.span().unwrap();
+        assert!(
+            text.is_char_boundary(span.start) && text.is_char_boundary(span.end.min(text.len()))
+        );
+        err.report();
+    }
+}
+
+#[test]
+fn clones_share_what_was_read() {
+    let expr = Expr::parse("(f \"a long string\" [1 2])".to_string()).unwrap();
+    let copy = expr.clone();
+    let (Expr::List(items, span), Expr::List(copied, copied_span)) = (&expr, &copy) else {
+        panic!("expected a list but read {}", expr);
+    };
+    assert!(Rc::ptr_eq(items, copied));
+    assert!(Rc::ptr_eq(&span.source, &copied_span.source));
+    let (Expr::String(string, _), Expr::String(copied, _)) = (&items[1], &copied[1]) else {
+        panic!("expected a string but read {}", items[1]);
+    };
+    assert!(Rc::ptr_eq(string, copied));
+    // every node points at the one source it was read from
+    assert!(Rc::ptr_eq(&items[2].span().source, &span.source));
+}
+
+#[test]
+fn literals_and_special_forms_are_read_as_such() {
+    let read = |text: &str| Expr::parse(text.to_string()).unwrap();
+    assert_eq!(read("nil"), Expr::Nil(span()));
+    assert_eq!(read("false"), Expr::Bool(false, span()));
+    assert_eq!(read("-42"), Expr::Number(-42, span()));
+    assert_eq!(read("1/2"), Expr::Numeric("1/2".into(), span()));
+    assert_eq!(read("1e3"), Expr::Numeric("1e3".into(), span()));
+    let big = "123456789012345678901234567890";
+    assert_eq!(read(big), Expr::Numeric(big.into(), span()));
+    assert_eq!(read(":a"), Expr::Keyword(Symbol::intern("a"), span()));
+    assert_eq!(read("\"a\""), Expr::String("a".into(), span()));
+    for special in Special::ALL {
+        assert_eq!(read(special.name()), Expr::Special(special, span()));
+    }
+    // only whole atoms are
+    for name in ["-", "nil?", "def!x", "1+", "-x"] {
+        assert_eq!(read(name), Expr::Atom(Symbol::intern(name), span()));
+    }
+}
+
+#[test]
+fn formatting_keeps_literals_as_written() {
+    let format = |text: &str, width| {
+        let printer = PrettyPrinter {
+            width,
+            ..PrettyPrinter::default()
+        };
+        printer.print_expr(&Expr::parse(text.to_string()).unwrap())
+    };
+    for text in [
+        r#"#"hi {s}""#,
+        r#"#"x is {x}, \{y} is {(+ y 1)}""#,
+        r#"r"C:\dir\file""#,
+        r###"r#"say "hi""#"###,
+        "\"two\nlines\"",
+        "'x",
+        "'(a 'b)",
+        r#"(println #"hi {name}" r"\d+" '[a b])"#,
+    ] {
+        assert_eq!(format(text, 80), text);
+    }
+    // and when broken over lines
+    assert_eq!(
+        format(
+            r#"(def! greet (fn* (name) (println #"hi {name}" r"\d+" '(a b c))))"#,
+            30
+        ),
+        r#"(def! greet
+  (fn* (name)
+    (println #"hi {name}"
+             r"\d+"
+             '(a b c))))"#
+    );
+    assert_eq!(
+        format("'(alpha beta gamma)", 10),
+        "'(alpha beta\n        gamma)"
+    );
+    // quote written out stays so
+    assert_eq!(format("(quote x)", 80), "(quote x)");
+}
+
+#[test]
+fn formatting_keeps_comments() {
+    let format =
+        |text: &str| PrettyPrinter::default().print_expr(&Expr::parse(text.to_string()).unwrap());
+    assert_eq!(format("(f a ; about a\n b)"), "(f a\n   ; about a\n   b)");
+    assert_eq!(
+        format("(do ;; first\n 1\n 2)"),
+        "(do\n  ;; first\n  1\n  2)"
+    );
+    assert_eq!(format("{:a 1 ; a\n :b 2}"), "{:a 1\n ; a\n :b 2}");
+    assert_eq!(format("'(a ; b\n)"), "'(a\n  ; b\n )");
+    // what is formatted reads as what was
+    for text in [
+        "(f a ; about a\n b)",
+        "{:a ; a\n 1}",
+        "#date ; when\n \"2024-01-01\"",
+        "' ; q\n x",
+    ] {
+        let formatted = format(text);
+        assert_eq!(
+            Expr::parse(formatted.clone()).unwrap(),
+            Expr::parse(text.to_string()).unwrap()
+        );
+        assert_eq!(format(&formatted), formatted, "formatting {}", formatted);
+    }
+}
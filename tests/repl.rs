@@ -0,0 +1,118 @@
+// the line editing of the REPL: completion, highlighting and continuation of the
+// line being typed, as ReplHelper does it for rustyline
+
+use rustyline::{Context, completion::Completer, highlight::Highlighter, history::DefaultHistory};
+
+use felisp::{Interpreter, inspect::Inspector, repl::ReplHelper};
+
+/// what pressing Tab at the end of line completes, and where the completed word starts
+fn complete(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+    let history = DefaultHistory::new();
+    helper
+        .complete(line, line.len(), &Context::new(&history))
+        .unwrap()
+}
+
+#[test]
+fn completes_special_forms_builtins_and_definitions() {
+    let interpreter = Interpreter::new();
+    interpreter.eval_str("(def! my-total 1)").unwrap();
+    let helper = ReplHelper::new(interpreter.env().clone());
+    assert_eq!(complete(&helper, "my-"), (0, vec!["my-total".to_string()]));
+    assert_eq!(
+        complete(&helper, "(+ 1 [(car my-t"),
+        (11, vec!["my-total".to_string()])
+    );
+    let (_, candidates) = complete(&helper, "(de");
+    assert!(candidates.contains(&"def!".to_string()));
+    assert!(candidates.contains(&"defonce".to_string()));
+    let (_, candidates) = complete(&helper, "(hash-m");
+    assert_eq!(candidates, ["hash-map"]);
+    // definitions made after the helper was created are completed too
+    interpreter.eval_str("(def! my-count 2)").unwrap();
+    assert_eq!(complete(&helper, "(my-").1, ["my-count", "my-total"]);
+    assert!(complete(&helper, "(zzz").1.is_empty());
+}
+
+/// line as highlighted with the cursor at pos, its colors spelled out
+fn highlighted(helper: &ReplHelper, line: &str, pos: usize) -> String {
+    [
+        ("\x1b[0m", ">"),
+        ("\x1b[31m", "<red "),
+        ("\x1b[32m", "<green "),
+        ("\x1b[35m", "<magenta "),
+        ("\x1b[36m", "<cyan "),
+        ("\x1b[90m", "<grey "),
+        ("\x1b[1;34m", "<bracket "),
+    ]
+    .into_iter()
+    .fold(
+        helper.highlight(line, pos).into_owned(),
+        |line, (code, tag)| line.replace(code, tag),
+    )
+}
+
+#[test]
+fn highlights_tokens_and_the_brackets_at_the_cursor() {
+    let helper = ReplHelper::new(Interpreter::new().env().clone());
+    assert_eq!(
+        highlighted(&helper, "(def! s \"a\" 1) ; c", 18),
+        "(<magenta def!> s <green \"a\"> <cyan 1>) <grey ; c>"
+    );
+    // the bracket under the cursor and its match
+    assert_eq!(
+        highlighted(&helper, "(+ (* 2 3) 1)", 3),
+        "(+ <bracket (>* <cyan 2> <cyan 3><bracket )> <cyan 1>)"
+    );
+    // or just before it
+    assert_eq!(
+        highlighted(&helper, "(+ (* 2 3) 1)", 13),
+        "<bracket (>+ (* <cyan 2> <cyan 3>) <cyan 1><bracket )>"
+    );
+    assert_eq!(highlighted(&helper, "(+ 1))", 6), "(+ <cyan 1>)<red )>");
+    assert_eq!(highlighted(&helper, "\"abc", 4), "<green \"abc>");
+    assert_eq!(highlighted(&helper, "nil x", 5), "<magenta nil> x");
+    let helper = helper.without_color();
+    assert_eq!(highlighted(&helper, "(+ 1)", 0), "(+ 1)");
+}
+
+#[test]
+fn inspectors_expand_and_collapse_lines() {
+    let interpreter = Interpreter::new();
+    let value = interpreter.eval_str("[1 [2 3] {:a \"xy\"}]").unwrap();
+    let mut inspector = Inspector::new(value);
+    assert_eq!(
+        inspector.render(),
+        "\
+0 - :vector (3) [1 [2 3] {:a \"xy\"}]
+1     0 :int 1
+2   + 1 :vector (2) [2 3]
+3   + 2 :map (1) {:a \"xy\"}"
+    );
+    assert!(inspector.toggle(2) && inspector.toggle(5));
+    assert_eq!(
+        inspector.render(),
+        "\
+0 - :vector (3) [1 [2 3] {:a \"xy\"}]
+1     0 :int 1
+2   - 1 :vector (2) [2 3]
+3       0 :int 2
+4       1 :int 3
+5   - 2 :map (1) {:a \"xy\"}
+6       :a :string (2) \"xy\""
+    );
+    // lines without children, or past the last, don't toggle
+    assert!(!inspector.toggle(1) && !inspector.toggle(7));
+    assert!(inspector.toggle(2));
+    assert_eq!(inspector.render().lines().count(), 5);
+    let pair = interpreter.eval_str("(cons 1 2)").unwrap();
+    assert_eq!(
+        Inspector::new(pair).render(),
+        "0 - :pair (2) (1 . 2)\n1     car :int 1\n2     cdr :int 2"
+    );
+    // lines are numbered to the same width
+    let long = interpreter.eval_str("[0 1 2 3 4 5 6 7 8 9 10]").unwrap();
+    let rendered = Inspector::new(long).render();
+    assert!(rendered.starts_with(" 0 - :vector (11) [0 1 2 3 4 5 6 7 ...]\n 1     0 :int 0\n"));
+    assert!(rendered.ends_with("\n11     10 :int 10"));
+}
@@ -0,0 +1,133 @@
+// drives felisp --json-rpc as an editor would, a request per line on stdin,
+// checking the lines it answers on stdout, whose objects have their keys sorted
+
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// the lines felisp --json-rpc writes to stdout for requests
+fn serve(requests: &[&str]) -> io::Result<Vec<String>> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_felisp"))
+        .args(["--no-history", "--no-init", "--json-rpc"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(format!("{}\n", requests.join("\n")).as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    Ok(stdout.lines().map(str::to_string).collect())
+}
+
+#[test]
+fn eval_sends_output_then_value() {
+    let lines = serve(&[
+        r#"{"jsonrpc":"2.0","id":1,"method":"eval","params":{"code":"(def! x 2) (println \"x\" x) (* x 3)"}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"eval","params":{"code":"(eprintln x) x"}}"#,
+    ])
+    .unwrap();
+    assert_eq!(
+        lines,
+        [
+            r#"{"jsonrpc":"2.0","method":"out","params":{"id":1,"text":"x 2\n"}}"#,
+            r#"{"id":1,"jsonrpc":"2.0","result":{"value":"6"}}"#,
+            r#"{"jsonrpc":"2.0","method":"err","params":{"id":2,"text":"2\n"}}"#,
+            r#"{"id":2,"jsonrpc":"2.0","result":{"value":"2"}}"#,
+        ]
+    );
+}
+
+#[test]
+fn values_are_abbreviated() {
+    let lines = serve(&[
+        r#"{"jsonrpc":"2.0","id":1,"method":"eval","params":{"code":"(def! nest (fn* (n acc) (if (<= n 0) acc (nest (- n 1) [acc])))) (nest 100000 1)"}}"#,
+    ])
+    .unwrap();
+    let value = format!("{}...{}", "[".repeat(16), "]".repeat(16));
+    assert_eq!(
+        lines,
+        [format!(
+            r#"{{"id":1,"jsonrpc":"2.0","result":{{"value":"{}"}}}}"#,
+            value
+        )]
+    );
+}
+
+#[test]
+fn errors_are_structured() {
+    let lines = serve(&[
+        r#"{"id":1,"method":"eval","params":{"code":"(+ 1 nil)"}}"#,
+        r#"{"id":2,"method":"eval","params":{"code":"(+ 1"}}"#,
+        "not json",
+        r#"{"id":3,"method":"eval"}"#,
+        r#"{"id":4,"method":"load"}"#,
+        r#"{"id":5,"params":{}}"#,
+    ])
+    .unwrap();
+    assert_eq!(lines.len(), 6);
+    assert!(lines[0].starts_with(r#"{"error":{"code":-32000,"data":{"column":6,"file":"<rpc>","kind":"type-error","line":1,"report":"type error: +: expected Number but got 'nil' (arg 2)"#));
+    assert!(lines[0].ends_with(r#""message":"type error: +: expected Number but got 'nil' (arg 2)"},"id":1,"jsonrpc":"2.0"}"#));
+    assert!(lines[1].contains(r#""kind":"parse-error""#));
+    assert!(lines[2].starts_with(r#"{"error":{"code":-32700,"#));
+    assert!(lines[2].ends_with(r#""id":null,"jsonrpc":"2.0"}"#));
+    assert!(lines[3].starts_with(r#"{"error":{"code":-32602,"#));
+    assert_eq!(
+        lines[4],
+        r#"{"error":{"code":-32601,"message":"load is not supported"},"id":4,"jsonrpc":"2.0"}"#
+    );
+    assert!(lines[5].starts_with(r#"{"error":{"code":-32600,"#));
+}
+
+#[test]
+fn complete_and_exit() {
+    let lines = serve(&[
+        r#"{"id":1,"method":"eval","params":{"code":"(def! with-x 1)"}}"#,
+        r#"{"id":2,"method":"complete","params":{"prefix":"with-"}}"#,
+        r#"{"method":"exit"}"#,
+        r#"{"id":3,"method":"eval","params":{"code":"1"}}"#,
+    ])
+    .unwrap();
+    assert_eq!(
+        lines,
+        [
+            r#"{"id":1,"jsonrpc":"2.0","result":{"value":"1"}}"#,
+            r#"{"id":2,"jsonrpc":"2.0","result":["with-handler","with-open","with-out-str","with-x"]}"#,
+        ]
+    );
+}
+
+#[test]
+fn tools_keep_to_the_protocol() {
+    let lines = serve(&[
+        r#"{"id":1,"method":"eval","params":{"code":"(def! f (fn* (x) x)) (trace f) (f 1)"}}"#,
+        r#"{"id":2,"method":"eval","params":{"code":"(profile-start) (f 2) (profile-report)"}}"#,
+        r#"{"id":3,"method":"eval","params":{"code":"(inspect [1 2])"}}"#,
+        r#"{"id":4,"method":"eval","params":{"code":"(breakpoint) 4"}}"#,
+    ])
+    .unwrap();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(
+        lines[..3],
+        [
+            r#"{"jsonrpc":"2.0","method":"err","params":{"id":1,"text":"(f 1)\n"}}"#,
+            r#"{"id":1,"jsonrpc":"2.0","result":{"value":"1"}}"#,
+            r#"{"jsonrpc":"2.0","method":"err","params":{"id":2,"text":"(f 2)\n"}}"#,
+        ]
+    );
+    assert!(lines[3].starts_with(
+        r#"{"jsonrpc":"2.0","method":"err","params":{"id":2,"text":"   calls   inclusive   exclusive  function\n"#
+    ));
+    assert_eq!(
+        lines[4],
+        r#"{"id":2,"jsonrpc":"2.0","result":{"value":"nil"}}"#
+    );
+    assert!(lines[5].starts_with(r#"{"error":{"code":-32000,"#));
+    assert!(lines[5].ends_with(r#""message":"inspect browses values on stdin, which --json-rpc reads requests from"},"id":3,"jsonrpc":"2.0"}"#));
+    assert_eq!(
+        lines[6],
+        r#"{"id":4,"jsonrpc":"2.0","result":{"value":"4"}}"#
+    );
+}